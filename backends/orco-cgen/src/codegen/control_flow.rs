@@ -1,65 +1,85 @@
 use super::{Codegen, oc};
 use std::fmt::Write as _;
 
-impl oc::AcfCodegen for &mut Codegen<'_> {
+// Pending values are flushed before any control flow,
+// so they are not evaluated in another block or loop iteration
+
+impl oc::AcfCodegen for Codegen<'_> {
     fn alloc_label(&mut self) -> oc::Label {
         self.next_label_id += 1;
         oc::Label(self.next_label_id - 1)
     }
 
     fn label(&mut self, label: oc::Label) {
+        oc::BodyCodegen::flush(self);
         writeln!(&mut self.body, "label{}:", label.0).unwrap();
+        self.line_mapping = None;
     }
 
     fn jump(&mut self, label: oc::Label) {
+        oc::BodyCodegen::flush(self);
         self.line(format_args!("goto label{};", label.0));
     }
 
     fn cjump(&mut self, condition: oc::Value, label: oc::Label) {
         let condition = self.use_value(condition).expression;
+        oc::BodyCodegen::flush(self);
         self.line(format_args!("if ({condition}) goto label{};", label.0));
     }
 }
 
-impl oc::BcfCodegen for &mut Codegen<'_> {
+impl oc::BcfCodegen for Codegen<'_> {
     fn if_(&mut self, condition: oc::Value) {
         let condition = self.use_value(condition).expression;
+        oc::BodyCodegen::flush(self);
         self.line(format_args!("if ({condition}) {{"));
         self.indent += 1;
+        self.open_scope();
     }
 
     fn else_(&mut self) {
+        oc::BodyCodegen::flush(self);
+        self.close_scope();
         self.indent -= 1;
         self.line(format_args!("}} else {{"));
         self.indent += 1;
+        self.open_scope();
     }
 
     fn end(&mut self) {
+        oc::BodyCodegen::flush(self);
+        self.close_scope();
         self.indent -= 1;
         self.line(format_args!("}}"));
     }
 
     fn loop_(&mut self) {
+        oc::BodyCodegen::flush(self);
         self.line(format_args!("while (true) {{"));
         self.indent += 1;
+        self.open_scope();
     }
 
     fn break_(&mut self) {
+        oc::BodyCodegen::flush(self);
         self.line(format_args!("break;"));
     }
 
     fn continue_(&mut self) {
+        oc::BodyCodegen::flush(self);
         self.line(format_args!("continue;"));
     }
 
     // This is very unnecessary, but I think it looks cleaner :)
     fn cbreak(&mut self, condition: oc::Value) {
         let condition = self.use_value(condition).expression;
+        oc::BodyCodegen::flush(self);
         self.line(format_args!("if ({condition}) break;"));
     }
 
     fn ccontinue(&mut self, condition: oc::Value) {
         let condition = self.use_value(condition).expression;
+        oc::BodyCodegen::flush(self);
         self.line(format_args!("if ({condition}) continue;"));
     }
 }
@@ -1,35 +1,48 @@
-use super::{ValueInfo, oc};
+use super::{Effects, ValueInfo, oc};
 
-impl oc::Intrinsics for &mut super::Codegen<'_> {
+/// Effects of a value computed from `operands`
+fn effects(operands: &[&ValueInfo]) -> Effects {
+    let mut effects = Effects::default();
+    for operand in operands {
+        effects.merge(&operand.effects);
+    }
+    effects
+}
+
+impl oc::Intrinsics for super::Codegen<'_> {
     fn add(&mut self, a: oc::Value, b: oc::Value) -> oc::Value {
         let (a, b) = (self.use_value(a), self.use_value(b));
         assert_eq!(a.ty, b.ty, "can't add values of different types");
-        self.mk_value(ValueInfo::new(
-            format!("({} + {})", a.expression, b.expression),
-            a.ty,
-        ))
+        let effects = effects(&[&a, &b]);
+        self.mk_value(
+            ValueInfo::new(format!("({} + {})", a.expression, b.expression), a.ty)
+                .with_effects(effects),
+        )
     }
 
     fn mul(&mut self, a: oc::Value, b: oc::Value) -> oc::Value {
         let (a, b) = (self.use_value(a), self.use_value(b));
         assert_eq!(a.ty, b.ty, "can't add values of different types");
-        self.mk_value(ValueInfo::new(
-            format!("({} * {})", a.expression, b.expression),
-            a.ty,
-        ))
+        let effects = effects(&[&a, &b]);
+        self.mk_value(
+            ValueInfo::new(format!("({} * {})", a.expression, b.expression), a.ty)
+                .with_effects(effects),
+        )
     }
 
     fn eq(&mut self, a: oc::Value, b: oc::Value) -> oc::Value {
         let (a, b) = (self.use_value(a), self.use_value(b));
         assert_eq!(a.ty, b.ty, "can't eq values of different types");
-        self.mk_value(ValueInfo::new(
-            format!("({} == {})", a.expression, b.expression),
-            a.ty,
-        ))
+        let effects = effects(&[&a, &b]);
+        self.mk_value(
+            ValueInfo::new(format!("({} == {})", a.expression, b.expression), a.ty)
+                .with_effects(effects),
+        )
     }
 
     fn not(&mut self, a: oc::Value) -> oc::Value {
         let a = self.use_value(a);
-        self.mk_value(ValueInfo::new(format!("!{}", a.expression), a.ty))
+        let effects = effects(&[&a]);
+        self.mk_value(ValueInfo::new(format!("!{}", a.expression), a.ty).with_effects(effects))
     }
 }
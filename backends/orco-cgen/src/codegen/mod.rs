@@ -4,8 +4,9 @@ use std::collections::{HashMap, HashSet};
 
 mod control_flow;
 mod intrinsics;
+mod scope;
 mod value;
-use value::ValueInfo;
+use value::{Effects, Location, ValueInfo};
 
 /// Implementation of [`oc::BodyCodegen`]
 pub struct Codegen<'a> {
@@ -18,6 +19,14 @@ pub struct Codegen<'a> {
     body: String,
     /// Current indentation level
     indent: usize,
+    /// See [`oc::BodyCodegen::set_location`]
+    location: Option<orco::attrs::SourceLocation>,
+    /// Source file and line the next line of the body is attributed to,
+    /// because of the last `#line` directive
+    line_mapping: Option<(orco::sinter::IStr, u32)>,
+    /// Open C blocks, the first one is the function body
+    scopes: Vec<scope::Scope>,
+    next_scope_id: usize,
 
     /// A variable info list. Variables never get removed,
     /// this can be indexed using [`oc::Variable::0`] directly
@@ -28,54 +37,92 @@ pub struct Codegen<'a> {
     /// removed whenever values get used
     values: HashMap<usize, ValueInfo>,
     next_value_id: usize,
+    /// Variables, which had their address taken.
+    /// Writes through pointers can change them
+    escaped: HashSet<usize>,
     /// ID of the next label for ACF (see [`orco::codegen::AcfCodegen`]).
     next_label_id: usize,
+    /// See [`oc::BodyCodegen::abort`]
+    aborted: bool,
 }
 
 struct VariableInfo {
     name: String,
     ty: orco::Type,
+    /// ID of the scope this variable is declared in, [None] if it wasn't used yet
+    scope: Option<usize>,
+    /// See [`oc::BodyCodegen::storage_dead`]
+    live: bool,
 }
 
 impl<'a> Codegen<'a> {
-    #[allow(missing_docs)]
-    pub fn new(ctx: &'a Backend, name: orco::Symbol) -> Self {
+    /// Start defining a function. `name` must already include generics,
+    /// see [`Backend::generic_name`]. Fails if the function is not declared
+    /// or is already defined
+    pub fn new(ctx: &'a Backend, name: orco::Symbol) -> Result<Self, orco::Error> {
+        // Look up before constructing, so an unfinished function isn't defined on drop
+        let signature = ctx
+            .functions
+            .pin()
+            .get(&name)
+            .cloned()
+            .ok_or(orco::Error::Undeclared(
+                orco::error::SymbolKind::Function,
+                name,
+            ))?;
+        if !ctx.defined.pin().insert(name) {
+            return Err(orco::Error::AlreadyDefined(name, Vec::new()));
+        }
+
         let mut this = Self {
             backend: ctx,
             name,
 
             body: "{\n".to_owned(),
             indent: 1,
+            location: None,
+            line_mapping: None,
+            scopes: Vec::new(),
+            next_scope_id: 0,
 
             variables: Vec::new(),
             variable_names: HashSet::new(),
             values: HashMap::new(),
             next_value_id: 0,
+            escaped: HashSet::new(),
             next_label_id: 0,
+            aborted: false,
         };
 
-        let signature = ctx
-            .functions
-            .get_sync(&this.name)
-            .unwrap_or_else(|| panic!("trying to codegen an undeclared function {}", this.name));
-        this.body = format!(
+        this.body = signature
+            .attrs
+            .location
+            .as_ref()
+            .map(|location| format!("{}\n", crate::symbols::FmtLineDirective(location)))
+            .unwrap_or_default();
+        this.body += &format!(
             "{} {{\n",
             crate::symbols::FmtFunction {
-                name: &crate::symname(name), // FIXME: Generics
+                backend: ctx,
+                name: &ctx.cname(name),
                 signature: &signature,
                 name_all_args: true
             }
         );
 
+        this.open_scope();
+
         for (idx, (name, ty)) in signature.params.iter().enumerate() {
             let name = name.clone().unwrap_or_else(|| format!("arg{idx}"));
             this.variables.push(VariableInfo {
                 ty: ty.clone(),
                 name,
+                scope: Some(scope::ARGUMENTS),
+                live: true,
             });
         }
 
-        this
+        Ok(this)
     }
 
     /// Adds indent to the body
@@ -85,11 +132,28 @@ impl<'a> Codegen<'a> {
         }
     }
 
-    /// Add a line to the source code
+    /// Add a line to the source code.
+    /// Emits a `#line` directive first, if the line would be attributed to a wrong location
     pub fn line(&mut self, args: std::fmt::Arguments<'_>) {
+        if let Some(location) = &self.location
+            && self.line_mapping != Some((location.file, location.line))
+        {
+            use std::fmt::Write as _;
+            writeln!(
+                &mut self.body,
+                "{}",
+                crate::symbols::FmtLineDirective(location)
+            )
+            .unwrap();
+            self.line_mapping = Some((location.file, location.line));
+        }
+
         self.indent();
         std::fmt::write(&mut self.body, args).unwrap();
         self.body.push('\n');
+        if let Some((_, line)) = &mut self.line_mapping {
+            *line += 1;
+        }
     }
 }
 
@@ -100,14 +164,57 @@ impl oc::BodyCodegen for Codegen<'_> {
         }
     }
 
+    fn set_location(&mut self, location: Option<orco::attrs::SourceLocation>) {
+        self.location = location;
+    }
+
     fn type_of(&self, id: usize) -> orco::Type {
         self.values[&id].ty.clone()
     }
 
+    fn variable_type(&self, variable: oc::Variable) -> orco::Type {
+        self.variables[variable.0].ty.clone()
+    }
+
+    fn global_type(&self, name: orco::Symbol, generics: &[orco::Type]) -> orco::Type {
+        let name = self.backend.generic_name(name, generics);
+        if let Some(signature) = self.backend.functions.pin().get(&name) {
+            return signature.ptr_type();
+        }
+        match self.backend.globals.pin().get(&name) {
+            Some(ty) => ty.clone(),
+            None => panic!("undeclared symbol {name}"),
+        }
+    }
+
+    fn symbol_kind(
+        &self,
+        name: orco::Symbol,
+        generics: &[orco::Type],
+    ) -> Option<orco::error::SymbolKind> {
+        // Functions are stored by their specialized names, globals aren't generic
+        let specialized = self.backend.generic_name(name, generics);
+        if self.backend.functions.pin().contains_key(&specialized) {
+            Some(orco::error::SymbolKind::Function)
+        } else if self.backend.globals.pin().contains_key(&name) {
+            Some(orco::error::SymbolKind::Global)
+        } else {
+            None
+        }
+    }
+
+    fn inline_type(&self, ty: orco::Type) -> orco::Type {
+        let guard = self.backend.types.guard();
+        self.backend.inline_type_aliases(&guard, &ty, true).clone()
+    }
+
     fn declare_var(&mut self, mut ty: orco::Type, name: Option<&str>) -> oc::Variable {
-        self.backend.intern_type(&mut ty, false);
+        self.backend.intern_type(&mut ty, None);
         let id = self.variables.len();
-        let mut name = name.map_or_else(|| format!("var{id}"), |name| crate::symname(name.into())); // TODO: Not ideal
+        let mut name = name.map_or_else(
+            || format!("var{id}"),
+            |name| self.backend.cname(name.into()),
+        ); // TODO: Not ideal
         if self.variable_names.contains(&name) {
             for disambiguator in 1.. {
                 let disambiguated = format!("{name}{disambiguator}");
@@ -119,53 +226,106 @@ impl oc::BodyCodegen for Codegen<'_> {
         }
         self.variable_names.insert(name.clone());
 
-        if !matches!(&ty, orco::Type::Struct { fields } if fields.is_empty()) {
-            self.line(format_args!(
-                "{};",
-                crate::types::FmtType {
-                    ty: &ty,
-                    constant: false,
-                    name: Some(&name),
-                }
-            ));
-        }
-
-        self.variables.push(VariableInfo { name, ty });
+        // Declaration is placed once the variable is used, see [`Self::use_variable`]
+        self.variables.push(VariableInfo {
+            name,
+            ty,
+            scope: None,
+            live: true,
+        });
         oc::Variable(id)
     }
 
+    fn storage_live(&mut self, variable: oc::Variable) {
+        self.variables[variable.0].live = true;
+        self.use_variable(variable.0);
+    }
+
+    fn storage_dead(&mut self, variable: oc::Variable) {
+        // Pending values can't read the variable after it's dead
+        self.before_write(Location::Variable(variable.0));
+        self.variables[variable.0].live = false;
+    }
+
     fn assign(&mut self, target: oc::Place, value: oc::Value) {
-        let target = self.place(target).expression;
+        let (target, location) = self.place(target);
         let value = self.use_value(value).expression;
-        self.line(format_args!("{target} = {value};"));
+        self.before_write(location);
+        self.line(format_args!("{} = {value};", target.expression));
+    }
+
+    fn flush(&mut self) {
+        self.materialize_if(|effects| !effects.is_pure());
+    }
+
+    fn abort(&mut self) {
+        self.aborted = true;
+    }
+
+    fn dup(&mut self, value: oc::Value) -> (oc::Value, oc::Value) {
+        let value = self.use_value(value);
+        if value.constant {
+            let copy = ValueInfo::constant(value.expression.clone(), value.ty.clone());
+            return (self.mk_value(value), self.mk_value(copy));
+        }
+
+        let ty = value.ty.clone();
+        let value = self.mk_value(value);
+        let tmp = self.mk_tmp(value);
+        let name = self.variables[tmp.0].name.clone();
+        (
+            self.mk_value(ValueInfo::new(name.clone(), ty.clone())),
+            self.mk_value(ValueInfo::new(name, ty)),
+        )
     }
 
     fn iconst(&mut self, value: i128, size: orco::types::IntegerSize) -> oc::Value {
-        self.mk_value(ValueInfo::new(value.to_string(), orco::Type::Integer(size))) // TODO: Literal sizes
+        self.mk_value(ValueInfo::constant(
+            value.to_string(),
+            orco::Type::Integer(size),
+        )) // TODO: Literal sizes
     }
 
     fn uconst(&mut self, value: u128, size: orco::types::IntegerSize) -> oc::Value {
-        self.mk_value(ValueInfo::new(
+        self.mk_value(ValueInfo::constant(
             value.to_string(),
             orco::Type::Unsigned(size),
         )) // TODO: Literal sizes
     }
 
     fn fconst(&mut self, value: f64, size: u16) -> oc::Value {
-        self.mk_value(ValueInfo::new(value.to_string(), orco::Type::Float(size))) // TODO: Literal sizes
+        self.mk_value(ValueInfo::constant(
+            value.to_string(),
+            orco::Type::Float(size),
+        )) // TODO: Literal sizes
     }
 
     fn bconst(&mut self, value: bool) -> oc::Value {
-        self.mk_value(ValueInfo::new(value.to_string(), orco::Type::Bool))
+        self.mk_value(ValueInfo::constant(value.to_string(), orco::Type::Bool))
     }
 
     fn read(&mut self, place: oc::Place) -> oc::Value {
-        let place = self.place(place);
+        let (mut place, location) = self.place(place);
+        match location {
+            Location::Variable(variable) => {
+                place.effects.variables.insert(variable);
+            }
+            Location::Memory => place.effects.memory = true,
+            Location::Constant => (),
+        }
+
+        // Pending calls could change memory this value reads
+        if place.effects.conflicts(Location::Memory, &self.escaped) {
+            self.materialize_if(|effects| effects.call);
+        }
         self.mk_value(place)
     }
 
     fn reference(&mut self, place: oc::Place, mutable: bool) -> oc::Value {
-        let mut place = self.place(place);
+        let (mut place, location) = self.place(place);
+        if let Location::Variable(variable) = location {
+            self.escaped.insert(variable);
+        }
         place.expression.insert(0, '&');
         place.ty = orco::Type::Ptr(Box::new(place.ty), mutable);
         self.mk_value(place)
@@ -185,6 +345,8 @@ impl oc::BodyCodegen for Codegen<'_> {
         };
 
         let mut call = func.expression;
+        let mut effects = func.effects;
+        effects.call = true;
         call.push('(');
         for (idx, arg) in args.into_iter().enumerate() {
             let arg = self.use_value(arg);
@@ -192,11 +354,14 @@ impl oc::BodyCodegen for Codegen<'_> {
                 call.push_str(", ");
             }
             call.push_str(&arg.expression);
+            effects.merge(&arg.effects);
         }
         call.push(')');
 
+        // A call can write to any memory
+        self.before_write(Location::Memory);
         match ty {
-            Some(rt) => Some(self.mk_value(ValueInfo::new(call, *rt))),
+            Some(rt) => Some(self.mk_value(ValueInfo::new(call, *rt).with_effects(effects))),
             None => {
                 self.line(format_args!("{call};"));
                 None
@@ -205,29 +370,25 @@ impl oc::BodyCodegen for Codegen<'_> {
     }
 
     fn return_(&mut self, value: Option<oc::Value>) {
+        let value = value.map(|value| self.use_value(value).expression);
+        self.materialize_if(|effects| effects.call);
         if let Some(value) = value {
-            let value = self.use_value(value).expression;
             self.line(format_args!("return {value};"));
         } else {
             self.line(format_args!("return;"));
         }
     }
-
-    fn intrinsics(&mut self) -> impl oc::Intrinsics + '_ {
-        self
-    }
-
-    fn acf(&mut self) -> impl oc::AcfCodegen + '_ {
-        self
-    }
-
-    fn bcf(&mut self) -> impl oc::BcfCodegen + '_ {
-        self
-    }
 }
 
 impl std::ops::Drop for Codegen<'_> {
     fn drop(&mut self) {
+        if self.aborted {
+            self.backend.defined.pin().remove(&self.name);
+            return;
+        }
+        // Unused calls still have to happen
+        self.materialize_if(|effects| effects.call);
+        self.close_scope();
         self.body.push('}');
         self.backend.define(std::mem::take(&mut self.body));
     }
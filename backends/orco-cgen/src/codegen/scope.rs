@@ -0,0 +1,98 @@
+use super::Codegen;
+
+/// Scope of function arguments, which are never declared
+pub(super) const ARGUMENTS: usize = usize::MAX;
+
+/// A C block, variables get declared at it's start
+pub(super) struct Scope {
+    pub(super) id: usize,
+    /// Position in the body where declarations should be inserted
+    start: usize,
+    indent: usize,
+    /// Variables to declare in this scope
+    variables: Vec<usize>,
+}
+
+impl Codegen<'_> {
+    /// Start a new scope at the current position of the body.
+    /// Should be called right after a line opening a C block
+    pub(super) fn open_scope(&mut self) {
+        self.scopes.push(Scope {
+            id: self.next_scope_id,
+            start: self.body.len(),
+            indent: self.indent,
+            variables: Vec::new(),
+        });
+        self.next_scope_id += 1;
+        // Declarations will be inserted here, so line directives before it can't be relied on
+        self.line_mapping = None;
+    }
+
+    /// Close the innermost scope and insert declarations of it's variables.
+    /// Variables that are still live move to the parent scope,
+    /// since they might be used after the block ends
+    pub(super) fn close_scope(&mut self) {
+        let scope = self
+            .scopes
+            .pop()
+            .expect("closing a scope while none are open");
+        let mut declarations = String::new();
+        for variable in scope.variables {
+            if self.variables[variable].live
+                && let Some(parent) = self.scopes.last_mut()
+            {
+                parent.variables.push(variable);
+                self.variables[variable].scope = Some(parent.id);
+                continue;
+            }
+
+            if let Some(declaration) = self.declaration(variable) {
+                for _ in 0..scope.indent {
+                    declarations.push_str("  ");
+                }
+                declarations.push_str(&declaration);
+                declarations.push('\n');
+            }
+        }
+        self.body.insert_str(scope.start, &declarations);
+    }
+
+    /// Make sure a variable will be declared in a scope, where it's used
+    pub(super) fn use_variable(&mut self, variable: usize) {
+        let scope = self.variables[variable].scope;
+        if scope.is_some_and(|scope| {
+            scope == ARGUMENTS || self.scopes.iter().any(|open| open.id == scope)
+        }) {
+            return;
+        }
+
+        let current = self
+            .scopes
+            .last_mut()
+            .expect("using a variable while no scopes are open");
+        current.variables.push(variable);
+        self.variables[variable].scope = Some(current.id);
+    }
+
+    /// C declaration of a variable, [None] if it doesn't need one (zero-sized)
+    fn declaration(&self, variable: usize) -> Option<String> {
+        let variable = &self.variables[variable];
+        let guard = self.backend.types.guard();
+        if matches!(
+            self.backend.inline_type_aliases(&guard, &variable.ty, true),
+            orco::Type::Struct { fields } if fields.is_empty()
+        ) {
+            return None;
+        }
+
+        Some(format!(
+            "{};",
+            crate::types::FmtType {
+                backend: self.backend,
+                ty: &variable.ty,
+                constant: false,
+                name: Some(&variable.name),
+            }
+        ))
+    }
+}
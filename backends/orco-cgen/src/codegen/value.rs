@@ -1,19 +1,132 @@
 use super::oc;
+use std::collections::HashSet;
 
 pub(super) struct ValueInfo {
     /// Expression for this value, will either be placed whenever
     /// the value is used or placed in the code whenever the value is flushed.
     pub(super) expression: String,
     pub(super) ty: orco::Type,
+    /// Constants can be duplicated without a temporary, see [`oc::BodyCodegen::dup`]
+    pub(super) constant: bool,
+    /// State this value depends on, see [`Effects`]
+    pub(super) effects: Effects,
 }
 
 impl ValueInfo {
     pub(super) fn new(expression: String, ty: orco::Type) -> Self {
-        Self { expression, ty }
+        Self {
+            expression,
+            ty,
+            constant: false,
+            effects: Effects::default(),
+        }
+    }
+
+    pub(super) fn constant(expression: String, ty: orco::Type) -> Self {
+        Self {
+            expression,
+            ty,
+            constant: true,
+            effects: Effects::default(),
+        }
+    }
+
+    /// Set effects of this value
+    pub(super) fn with_effects(mut self, effects: Effects) -> Self {
+        self.effects = effects;
+        self
+    }
+}
+
+/// What a pending value reads or does. Used to decide which values
+/// have to be materialized before a statement, see [`oc::BodyCodegen`] sequencing rules
+#[derive(Clone, Debug, Default)]
+pub(super) struct Effects {
+    /// Variables this value reads
+    pub(super) variables: HashSet<usize>,
+    /// Whether this value reads memory through a pointer
+    pub(super) memory: bool,
+    /// Whether this value contains a call
+    pub(super) call: bool,
+}
+
+impl Effects {
+    /// Add effects of an operand
+    pub(super) fn merge(&mut self, other: &Effects) {
+        self.variables.extend(&other.variables);
+        self.memory |= other.memory;
+        self.call |= other.call;
+    }
+
+    /// Whether statements can't change the result of this value
+    pub(super) fn is_pure(&self) -> bool {
+        self.variables.is_empty() && !self.memory && !self.call
+    }
+
+    /// Whether writing to `location` could change the result of this value.
+    /// Pending calls conflict with anything, so that they happen in order.
+    /// `escaped` are variables which had their address taken
+    pub(super) fn conflicts(&self, location: Location, escaped: &HashSet<usize>) -> bool {
+        self.call
+            || match location {
+                Location::Variable(variable) => {
+                    self.variables.contains(&variable)
+                        || (self.memory && escaped.contains(&variable))
+                }
+                Location::Memory => {
+                    self.memory
+                        || self
+                            .variables
+                            .iter()
+                            .any(|variable| escaped.contains(variable))
+                }
+                Location::Constant => false,
+            }
     }
 }
 
+/// Where a place is stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Location {
+    Variable(usize),
+    /// Behind a pointer
+    Memory,
+    /// Can't be changed, f.e. a function
+    Constant,
+}
+
 impl super::Codegen<'_> {
+    /// Materialize pending values, for which `conflicts` returns true.
+    /// Values are materialized in the order they were created
+    pub(super) fn materialize_if(&mut self, conflicts: impl Fn(&Effects) -> bool) {
+        let mut ids = self
+            .values
+            .iter()
+            .filter(|(_, value)| conflicts(&value.effects))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+
+        for id in ids {
+            let value = self.values.remove(&id).unwrap();
+            let ty = value.ty.clone();
+            let expression = value.expression.clone();
+            let tmp = oc::BodyCodegen::declare_var(self, ty.clone(), None);
+            self.use_variable(tmp.0);
+            let name = self.variables[tmp.0].name.clone();
+            self.line(format_args!("{name} = {expression};"));
+            // Temporaries are never written again, so reading them is pure
+            self.values.insert(id, ValueInfo::new(name, ty));
+        }
+    }
+
+    /// Materialize pending values before writing to `location`
+    pub(super) fn before_write(&mut self, location: Location) {
+        let escaped = std::mem::take(&mut self.escaped);
+        self.materialize_if(|effects| effects.conflicts(location, &escaped));
+        self.escaped = escaped;
+    }
+
     /// Make a value and put it in [`Self::values`]
     pub(super) fn mk_value(&mut self, value: ValueInfo) -> oc::Value {
         let id = oc::Value(self.next_value_id);
@@ -33,40 +146,75 @@ impl super::Codegen<'_> {
         })
     }
 
-    /// Convert [`oc::Place`] to a value (C code string + type)
-    pub(super) fn place(&mut self, place: oc::Place) -> ValueInfo {
+    /// Convert [`oc::Place`] to a value (C code string + type) and it's location.
+    /// Effects of the value only include computing the address of the place
+    pub(super) fn place(&mut self, place: oc::Place) -> (ValueInfo, Location) {
         match place {
             oc::Place::Variable(variable) => {
-                let variable = &self.variables[variable.0];
-                ValueInfo::new(variable.name.clone(), variable.ty.clone())
+                self.use_variable(variable.0);
+                let info = &self.variables[variable.0];
+                (
+                    ValueInfo::new(info.name.clone(), info.ty.clone()),
+                    Location::Variable(variable.0),
+                )
+            }
+            oc::Place::Global(name, generics) => {
+                let name = self.backend.generic_name(name, &generics);
+                // Global variables can be written to (even by calls), functions can't
+                let location = match self.backend.globals.pin().contains_key(&name) {
+                    true => Location::Memory,
+                    false => Location::Constant,
+                };
+                (
+                    ValueInfo::new(
+                        self.backend.cname(name),
+                        oc::BodyCodegen::global_type(self, name, &[]),
+                    ),
+                    location,
+                )
             }
-            oc::Place::Global(name) => ValueInfo::new(
-                crate::symname(name),
-                if let Some(signature) = self.backend.functions.get_sync(&name) {
-                    signature.ptr_type()
-                } else {
-                    panic!("undeclared symbol {name}")
-                },
-            ),
             oc::Place::Deref(value) => {
                 let value = self.use_value(value);
-                ValueInfo::new(
-                    format!("(*{})", value.expression),
-                    match self.backend.inline_type_aliases(value.ty, false) {
-                        orco::Type::Ptr(ty, _) => *ty,
-                        ty => panic!("trying to dereference a non-pointer type {ty:#?}"),
-                    },
+                (
+                    ValueInfo::new(
+                        format!("(*{})", value.expression),
+                        match oc::BodyCodegen::inline_type(self, value.ty) {
+                            orco::Type::Ptr(ty, _) => *ty,
+                            ty => panic!("trying to dereference a non-pointer type {ty:#?}"),
+                        },
+                    )
+                    .with_effects(value.effects),
+                    Location::Memory,
                 )
             }
             oc::Place::Field(place, idx) => {
-                let place = self.place(*place);
-                let mut fields = match self.backend.inline_type_aliases(place.ty.clone(), true) {
+                let (place, location) = self.place(*place);
+                let mut fields = match oc::BodyCodegen::inline_type(self, place.ty.clone()) {
                     orco::Type::Struct { fields } => fields,
                     ty => panic!("trying to access field #{idx} on a non-struct type {ty:#?}"),
                 };
                 let (name, ty) = fields.swap_remove(idx);
                 let name = name.unwrap_or_else(|| format!("_{idx}"));
-                ValueInfo::new(format!("{}.{name}", place.expression), ty)
+                (
+                    ValueInfo::new(format!("{}.{name}", place.expression), ty)
+                        .with_effects(place.effects),
+                    location,
+                )
+            }
+            oc::Place::Index(place, index) => {
+                let (place, location) = self.place(*place);
+                let index = self.use_value(index);
+                let ty = match oc::BodyCodegen::inline_type(self, place.ty.clone()) {
+                    orco::Type::Array(ty, _) => *ty,
+                    ty => panic!("trying to index a non-array type {ty:#?}"),
+                };
+                let mut effects = place.effects;
+                effects.merge(&index.effects);
+                (
+                    ValueInfo::new(format!("{}[{}]", place.expression, index.expression), ty)
+                        .with_effects(effects),
+                    location,
+                )
             }
         }
     }
@@ -15,11 +15,11 @@ mod type_names;
 /// Symbol container types
 pub mod symbols;
 
-// /// Code generation, used to generate function bodies.
-// pub mod codegen;
-// pub use codegen::Codegen;
+/// Code generation, used to generate function bodies.
+pub mod codegen;
+pub use codegen::Codegen;
 
-use papaya::HashMap;
+use papaya::{HashMap, HashSet};
 
 /// Root backend struct
 #[derive(Debug, Default)]
@@ -27,9 +27,13 @@ pub struct Backend {
     /// Type aliases
     pub types: HashMap<orco::Symbol, orco::Type>,
     /// Interned types
-    interned: HashMap<orco::Type, orco::Symbol>,
+    interned: HashMap<orco::types::IType, orco::Symbol>,
     /// Function declarations
     pub functions: HashMap<orco::Symbol, orco::types::FunctionSignature>,
+    /// Global variables
+    pub globals: HashMap<orco::Symbol, orco::Type>,
+    /// Functions that are defined (or being defined), see [`Codegen::new`]
+    defined: HashSet<orco::Symbol>,
     /// Definitions
     definitions: std::sync::Mutex<Vec<String>>,
 }
@@ -46,13 +50,15 @@ impl Backend {
         self.definitions.lock().unwrap().push(code);
     }
 
-    /// Get the name of the symbol used in generated C code ("mangling")
+    /// Get the name of the symbol used in generated C code, see [`orco::Symbol::mangle`].
+    /// Bare names (foreign items and interned anonymous types) are only made into valid identifiers
     pub fn cname(&self, name: orco::Symbol) -> String {
-        // Take only the method name, not the path
-        // FIXME: conflicts...
+        if !name.is_bare() {
+            return name.mangle();
+        }
+
         let mut new_name = String::new();
-        for split in name.split([',', '<', '>', '{', '}']) {
-            let split = &split[split.rfind([':', '.']).map_or(0, |i| i + 1)..];
+        for split in name.name.split([',', '<', '>', '{', '}']) {
             if !split.is_empty() {
                 match new_name.chars().last() {
                     None | Some('_') => (),
@@ -79,8 +85,13 @@ impl orco::DeclarationBackend for Backend {
         mut params: Vec<(Option<String>, orco::Type)>,
         mut return_type: Option<orco::Type>,
         attrs: orco::attrs::FunctionAttributes,
-    ) {
+    ) -> Result<(), orco::Error> {
         let name = self.generic_name(name, &generics);
+        let already_declared =
+            || orco::Error::AlreadyDeclared(orco::error::SymbolKind::Function, name, Vec::new());
+        if self.globals.pin().contains_key(&name) {
+            return Err(already_declared());
+        }
         for (_, ty) in &mut params {
             self.intern_type(ty, None);
         }
@@ -97,29 +108,66 @@ impl orco::DeclarationBackend for Backend {
                     attrs,
                 },
             )
-            .unwrap_or_else(|_| panic!("function {name} is already declared"));
+            .map_err(|_| already_declared())?;
+        Ok(())
     }
 
-    fn type_(&self, name: orco::Symbol, generics: Vec<orco::Type>, mut ty: orco::Type) {
+    fn type_(
+        &self,
+        name: orco::Symbol,
+        generics: Vec<orco::Type>,
+        mut ty: orco::Type,
+    ) -> Result<(), orco::Error> {
         let name = self.generic_name(name, &generics);
+        let already_declared =
+            || orco::Error::AlreadyDeclared(orco::error::SymbolKind::Type, name, Vec::new());
+        if self.types.pin().contains_key(&name) {
+            return Err(already_declared());
+        }
         self.intern_type(&mut ty, Some(name));
         self.types
             .pin()
             .try_insert(name, ty)
-            .unwrap_or_else(|_| panic!("type {name} is already declared"));
+            .map_err(|_| already_declared())?;
+        Ok(())
+    }
+
+    fn global(&self, name: orco::Symbol, mut ty: orco::Type) -> Result<(), orco::Error> {
+        let already_declared =
+            || orco::Error::AlreadyDeclared(orco::error::SymbolKind::Global, name, Vec::new());
+        if self.functions.pin().contains_key(&name) {
+            return Err(already_declared());
+        }
+        self.intern_type(&mut ty, None);
+        self.globals
+            .pin()
+            .try_insert(name, ty)
+            .map_err(|_| already_declared())?;
+        Ok(())
     }
 }
 
-// impl orco::CodegenBackend for crate::Backend {
-//     fn cg_function(
-//         &self,
-//         name: orco::Symbol,
-//         generics: Vec<orco::Type>,
-//     ) -> impl orco::codegen::BodyCodegen {
-//         let name = self.generic_name(name, &generics);
-//         codegen::Codegen::new(self, name)
-//     }
-// }
+impl Backend {
+    /// Define a function, see [`orco::CodegenBackend::cg_function`].
+    /// Returns a concrete [Codegen], so no dynamic dispatch is involved
+    pub fn codegen(
+        &self,
+        name: orco::Symbol,
+        generics: &[orco::Type],
+    ) -> Result<Codegen<'_>, orco::Error> {
+        Codegen::new(self, self.generic_name(name, generics))
+    }
+}
+
+impl orco::CodegenBackend for Backend {
+    fn cg_function(
+        &self,
+        name: orco::Symbol,
+        generics: Vec<orco::Type>,
+    ) -> Result<Box<dyn orco::codegen::BodyCodegen + '_>, orco::Error> {
+        Ok(Box::new(self.codegen(name, &generics)?))
+    }
+}
 
 /// Adds all symbols this type uses into `dependencies`
 fn type_dependencies(backend: &Backend, ty: &orco::Type, dependencies: &mut Vec<orco::Symbol>) {
@@ -168,31 +216,37 @@ impl std::fmt::Display for Backend {
             }
         }
 
-        fn topsort(name: orco::Symbol, sorter: &mut TopSorter) {
+        fn topsort(name: orco::Symbol, sorter: &mut TopSorter) -> Result<(), orco::Error> {
             use std::collections::hash_map::Entry;
             match sorter.visited.entry(name) {
                 Entry::Occupied(finished) => {
                     if *finished.get() {
-                        return;
+                        return Ok(());
                     }
-                    panic!(
-                        "type dependency cycle detected on {name}, possibly an infinitely-recursive type",
-                    );
+                    return Err(orco::Error::DependencyCycle(name));
                 }
                 Entry::Vacant(entry) => entry.insert(false),
             };
 
             let deps = sorter.deps.remove(&name);
             for dep in deps.into_iter().flat_map(Vec::into_iter) {
-                topsort(dep, sorter);
+                topsort(dep, sorter)?;
             }
 
             sorter.visited.insert(name, true);
             sorter.order.push(name);
+            Ok(())
         }
 
+        // Types in a cycle are skipped, C compiler will report the error
         for (name, _) in types.iter() {
-            topsort(*name, &mut sorter);
+            if let Err(err) = topsort(*name, &mut sorter) {
+                writeln!(f, "#error {:?}", err.to_string())?;
+                sorter
+                    .visited
+                    .values_mut()
+                    .for_each(|finished| *finished = true);
+            }
         }
         writeln!(f)?;
 
@@ -230,6 +284,22 @@ impl std::fmt::Display for Backend {
 
         writeln!(f)?;
 
+        // Tentative definitions, zero-initialized
+        for (name, ty) in self.globals.pin().iter() {
+            writeln!(
+                f,
+                "{};",
+                FmtType {
+                    backend: self,
+                    ty,
+                    constant: false,
+                    name: Some(&self.cname(*name))
+                }
+            )?;
+        }
+
+        writeln!(f)?;
+
         for def in self.definitions.lock().unwrap().iter() {
             writeln!(f, "{def}\n")?;
         }
@@ -23,12 +23,18 @@ impl std::fmt::Display for FmtFunction<'_> {
         } = *self;
 
         use orco::attrs as oa;
+        if signature.attrs.internal {
+            write!(f, "static ")?;
+        }
         match signature.attrs.inlining {
             oa::Inlining::Never => write!(f, "__attribute__ ((noinline)) ")?,
             oa::Inlining::Auto => (),
             oa::Inlining::Hint => write!(f, "inline ")?,
             oa::Inlining::Always => write!(f, "__attribute__ ((always_inline)) ")?,
         }
+        if signature.attrs.destructor {
+            write!(f, "__attribute__ ((destructor)) ")?;
+        }
 
         let mut sig_noret = name.to_owned();
 
@@ -68,3 +74,14 @@ impl std::fmt::Display for FmtFunction<'_> {
         .fmt(f)
     }
 }
+
+/// Formats a `#line` directive, so the next line of C code
+/// is attributed to the source location
+pub struct FmtLineDirective<'a>(pub &'a orco::attrs::SourceLocation);
+
+impl std::fmt::Display for FmtLineDirective<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let file = self.0.file.replace('\\', "\\\\").replace('"', "\\\"");
+        write!(f, "#line {} \"{file}\"", self.0.line)
+    }
+}
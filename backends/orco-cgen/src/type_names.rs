@@ -61,16 +61,18 @@ impl super::Backend {
         match ty {
             Type::Struct { .. } => {
                 let interned = self.interned.pin();
-                if let Some(name) = interned.get(ty) {
+                let key = orco::types::IType::from(&*ty);
+                if let Some(name) = interned.get(&key) {
                     *ty = orco::Type::Symbol(*name, Vec::new());
                 } else {
                     if let Some(name) = named {
-                        interned.insert(ty.clone(), name);
+                        interned.insert(key, name);
                     } else {
                         use orco::DeclarationBackend as _;
                         let name = ty.to_string().into();
                         let ty = core::mem::replace(ty, Type::Symbol(name, Vec::new()));
-                        self.type_(name, Vec::new(), ty);
+                        // Same struct might be interned by another thread, that's fine
+                        let _ = self.type_(name, Vec::new(), ty);
                     }
                 }
             }
@@ -78,18 +80,14 @@ impl super::Backend {
         }
     }
 
-    /// Embed generics in the symbol name (also handles disambiguation and interning)
+    /// Embed generics in the symbol (also handles disambiguation and interning)
     pub fn generic_name(&self, name: orco::Symbol, generics: &[orco::Type]) -> orco::Symbol {
-        if generics.is_empty() {
-            return name;
-        }
-
         for ty in generics {
             if ty.has_params() {
                 panic!("generic params are not supported (encountered {ty})");
             }
         }
 
-        format!("{name}{}", orco::types::fmt_generics(generics)).into()
+        name.with_generics(generics)
     }
 }
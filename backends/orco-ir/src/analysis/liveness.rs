@@ -0,0 +1,101 @@
+use super::Cfg;
+use crate::ir::{self, Place, Statement};
+use std::collections::HashSet;
+
+/// Variable a statement overwrites completely, making its old value dead
+fn killed(statement: &Statement) -> Option<usize> {
+    match statement {
+        Statement::Assign(Place::Variable(variable), _)
+        | Statement::StorageLive(variable)
+        | Statement::StorageDead(variable) => Some(variable.0),
+        _ => None,
+    }
+}
+
+/// Variables a statement reads
+fn reads(statement: &Statement, f: &mut impl FnMut(usize)) {
+    statement.visit_reads(&mut |place| {
+        if let Some(variable) = place.root_variable() {
+            f(variable.0);
+        }
+    });
+}
+
+/// Updates `live` (variables live after the statement) to variables live before it
+fn transfer(statement: &Statement, live: &mut HashSet<usize>) {
+    if let Some(variable) = killed(statement) {
+        live.remove(&variable);
+    }
+    reads(statement, &mut |variable| {
+        live.insert(variable);
+    });
+}
+
+/// Variables (indices into [`ir::Body::variables`]) which are live
+/// (their value can still be read) at the start and at the end of each block.
+/// Only direct reads and writes are tracked: variables which had their address taken
+/// (see [`ir::Body::escaped_variables`]) can be accessed through pointers at any time
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Liveness {
+    #[allow(missing_docs)]
+    pub live_in: Vec<HashSet<usize>>,
+    #[allow(missing_docs)]
+    pub live_out: Vec<HashSet<usize>>,
+}
+
+impl Liveness {
+    #[allow(missing_docs)]
+    #[must_use]
+    pub fn new(body: &ir::Body, cfg: &Cfg) -> Self {
+        let mut liveness = Self {
+            live_in: vec![HashSet::new(); cfg.blocks.len()],
+            live_out: vec![HashSet::new(); cfg.blocks.len()],
+        };
+
+        // Going backwards converges faster
+        let mut order = cfg.reverse_postorder();
+        order.reverse();
+        let reachable = order.iter().copied().collect::<HashSet<_>>();
+        order.extend((0..cfg.blocks.len()).filter(|block| !reachable.contains(block)));
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in &order {
+                let mut live = cfg.blocks[block]
+                    .successors
+                    .iter()
+                    .filter_map(|successor| liveness.live_in.get(*successor))
+                    .flatten()
+                    .copied()
+                    .collect::<HashSet<_>>();
+                liveness.live_out[block].clone_from(&live);
+                for statement in body.statements[cfg.blocks[block].statements.clone()]
+                    .iter()
+                    .rev()
+                {
+                    transfer(statement, &mut live);
+                }
+                if live != liveness.live_in[block] {
+                    liveness.live_in[block] = live;
+                    changed = true;
+                }
+            }
+        }
+        liveness
+    }
+
+    /// Variables live after each statement
+    #[must_use]
+    pub fn statement_live_out(&self, body: &ir::Body, cfg: &Cfg) -> Vec<HashSet<usize>> {
+        let mut live_out = vec![HashSet::new(); body.statements.len()];
+        for (block, data) in cfg.blocks.iter().enumerate() {
+            let mut live = self.live_out[block].clone();
+            for idx in data.statements.clone().rev() {
+                live_out[idx].clone_from(&live);
+                transfer(&body.statements[idx], &mut live);
+            }
+        }
+        live_out
+    }
+}
@@ -0,0 +1,142 @@
+use crate::ir;
+use std::ops::Range;
+
+pub use orco::graph::{Dominators, Loop, Loops};
+
+mod liveness;
+pub use liveness::Liveness;
+
+/// Basic block: a run of statements control enters only through the first one
+/// and leaves only through the last one
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+    /// Indices into [`ir::Body::statements`]
+    pub statements: Range<usize>,
+    /// Blocks control can go to after this one, [`Cfg::exit`] stands for leaving the function
+    pub successors: Vec<usize>,
+    /// Blocks control can come from
+    pub predecessors: Vec<usize>,
+}
+
+/// Control flow graph of an [`ir::Body`], built from both ACF and BCF statements
+/// (see [`ir::Body::successors`]). Block `0` is the entry
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cfg {
+    #[allow(missing_docs)]
+    pub blocks: Vec<Block>,
+    /// Block each statement belongs to
+    pub statement_blocks: Vec<usize>,
+}
+
+impl Cfg {
+    /// Build the graph. Panics on malformed BCF, like [`ir::Body::successors`]
+    #[must_use]
+    pub fn new(body: &ir::Body) -> Self {
+        let successors = body.successors();
+        let len = body.statements.len();
+
+        // A block starts at the entry, at every statement control can come to
+        // from somewhere else than the previous statement and after every branch
+        let mut predecessors = vec![0; len + 1];
+        let mut leaders = vec![false; len + 1];
+        leaders[0] = true;
+        for (idx, successors) in successors.iter().enumerate() {
+            for successor in successors {
+                predecessors[*successor] += 1;
+            }
+            if successors.as_slice() != [idx + 1] {
+                leaders[idx + 1] = true;
+                for successor in successors {
+                    leaders[*successor] = true;
+                }
+            }
+        }
+
+        let mut cfg = Self::default();
+        for idx in 0..len {
+            if leaders[idx] || predecessors[idx] != 1 {
+                cfg.blocks.push(Block {
+                    statements: idx..idx,
+                    successors: Vec::new(),
+                    predecessors: Vec::new(),
+                });
+            }
+            cfg.blocks.last_mut().unwrap().statements.end = idx + 1;
+            cfg.statement_blocks.push(cfg.blocks.len() - 1);
+        }
+
+        let exit = cfg.exit();
+        for block in 0..cfg.blocks.len() {
+            let last = cfg.blocks[block].statements.end - 1;
+            let mut block_successors = Vec::new();
+            for successor in &successors[last] {
+                let successor = cfg
+                    .statement_blocks
+                    .get(*successor)
+                    .copied()
+                    .unwrap_or(exit);
+                if !block_successors.contains(&successor) {
+                    block_successors.push(successor);
+                }
+            }
+            for successor in &block_successors {
+                if *successor != exit {
+                    cfg.blocks[*successor].predecessors.push(block);
+                }
+            }
+            cfg.blocks[block].successors = block_successors;
+        }
+        cfg
+    }
+
+    /// Block index that stands for leaving the function
+    #[must_use]
+    pub fn exit(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Blocks reachable from the entry, see [`orco::graph::reverse_postorder`]
+    #[must_use]
+    pub fn reverse_postorder(&self) -> Vec<usize> {
+        orco::graph::reverse_postorder(self, 0)
+    }
+
+    /// Dominator tree, see [Dominators]
+    #[must_use]
+    pub fn dominators(&self) -> Dominators {
+        Dominators::new(self, 0)
+    }
+}
+
+/// Successors can be [`Cfg::exit`], which is outside of the graph
+impl orco::graph::Graph for Cfg {
+    fn node_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    fn successors(&self, node: usize) -> &[usize] {
+        &self.blocks[node].successors
+    }
+}
+
+impl ir::Body {
+    /// Shortcut for [`Cfg::new`]
+    #[must_use]
+    pub fn cfg(&self) -> Cfg {
+        Cfg::new(self)
+    }
+
+    /// Attributes set by [`ir::Statement::LoopAttributes`] in the header block of a loop
+    /// (the last one wins), defaults if there are none
+    #[must_use]
+    pub fn loop_attributes(&self, cfg: &Cfg, data: &Loop) -> orco::attrs::LoopAttributes {
+        self.statements[cfg.blocks[data.header].statements.clone()]
+            .iter()
+            .rev()
+            .find_map(|statement| match statement {
+                ir::Statement::LoopAttributes(attrs) => Some(attrs.clone()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,1146 @@
+//! Layout: [MAGIC], [VERSION] (little endian `u16`), then a stream of items
+//! (see [Item]), each starting with a tag byte, terminated with a zero byte.
+//!
+//! Integers are LEB128 varints (signed ones zigzag-encoded), floats are raw IEEE bits.
+//! Strings and symbols are written once, later occurrences refer to the first one
+//! by index: `0` is followed by the value itself, `n + 1` refers to the `n`th value.
+//!
+//! Only declarations and definitions are stored, monomorphization has to be redone.
+use crate::{Store, ir};
+use orco::Type;
+use orco::codegen as oc;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/// Magic bytes every encoded [Store] starts with
+pub const MAGIC: &[u8; 6] = b"ORCOIR";
+
+/// Schema version, bumped on every incompatible change of the encoding
+pub const VERSION: u16 = 3;
+
+/// Error produced when decoding a [Store]
+#[derive(Debug)]
+pub enum DecodeError {
+    #[allow(missing_docs)]
+    Io(std::io::Error),
+    /// Input doesn't start with [MAGIC]
+    BadMagic,
+    /// Input was encoded with another schema version, see [VERSION]
+    UnsupportedVersion(u16),
+    /// Input is malformed
+    Invalid(String),
+    /// A decoded item was rejected by the store
+    Declaration(orco::Error),
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Io(err) => write!(f, "{err}"),
+            DecodeError::BadMagic => write!(f, "not an encoded orco IR store"),
+            DecodeError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported schema version {version}, expected version {VERSION}"
+            ),
+            DecodeError::Invalid(message) => write!(f, "malformed input: {message}"),
+            DecodeError::Declaration(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<std::io::Error> for DecodeError {
+    fn from(err: std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::UnexpectedEof => Self::Invalid("unexpected end of input".into()),
+            _ => Self::Io(err),
+        }
+    }
+}
+
+type DResult<T> = Result<T, DecodeError>;
+
+fn invalid<T>(message: impl Into<String>) -> DResult<T> {
+    Err(DecodeError::Invalid(message.into()))
+}
+
+/// One top-level entry of an encoded [Store]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Item {
+    /// See [`orco::DeclarationBackend::type_`]
+    Type {
+        #[allow(missing_docs)]
+        name: orco::Symbol,
+        #[allow(missing_docs)]
+        generics: Vec<Type>,
+        #[allow(missing_docs)]
+        ty: Type,
+    },
+    /// See [`orco::DeclarationBackend::global`]
+    Global {
+        #[allow(missing_docs)]
+        name: orco::Symbol,
+        #[allow(missing_docs)]
+        ty: Type,
+    },
+    /// See [`orco::DeclarationBackend::function`]
+    Function {
+        #[allow(missing_docs)]
+        name: orco::Symbol,
+        #[allow(missing_docs)]
+        generic_params: Vec<Type>,
+        #[allow(missing_docs)]
+        signature: orco::types::FunctionSignature,
+    },
+    /// A specialization of a function declared before
+    Body {
+        #[allow(missing_docs)]
+        function: orco::Symbol,
+        #[allow(missing_docs)]
+        generics: Vec<Type>,
+        #[allow(missing_docs)]
+        body: ir::Body,
+    },
+}
+
+impl Item {
+    /// Add this item to a store
+    pub fn insert_into(self, store: &Store) -> Result<(), orco::Error> {
+        use orco::DeclarationBackend as _;
+        match self {
+            Item::Type { name, generics, ty } => store.type_(name, generics, ty),
+            Item::Global { name, ty } => store.global(name, ty),
+            Item::Function {
+                name,
+                generic_params,
+                signature,
+            } => store.function(
+                name,
+                generic_params,
+                signature.params,
+                signature.return_type,
+                signature.attrs,
+            ),
+            Item::Body {
+                function,
+                generics,
+                body,
+            } => {
+                let functions = store.functions.pin();
+                let decl = functions.get(&function).ok_or(orco::Error::Undeclared(
+                    orco::error::SymbolKind::Function,
+                    function,
+                ))?;
+                match decl
+                    .bodies
+                    .pin()
+                    .try_insert(crate::generics::intern(&generics), body)
+                {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(orco::Error::AlreadyDefined(function, generics)),
+                }
+            }
+        }
+    }
+}
+
+/// Encode a store. Items are sorted, so the output is deterministic
+pub fn write(store: &Store, writer: impl Write) -> std::io::Result<()> {
+    let mut writer = Writer::new(writer)?;
+
+    let types = store.types.pin();
+    let mut types = types.iter().collect::<Vec<_>>();
+    types.sort_unstable_by_key(|(name, _)| **name);
+    for (name, specs) in types {
+        let specs = specs.pin();
+        let mut specs = specs.iter().collect::<Vec<_>>();
+        specs.sort_unstable();
+        for (generics, ty) in specs {
+            writer.write(&Item::Type {
+                name: *name,
+                generics: generics.iter().map(|ty| ty.to_type()).collect(),
+                ty: ty.to_type(),
+            })?;
+        }
+    }
+
+    let globals = store.globals.pin();
+    let mut globals = globals.iter().collect::<Vec<_>>();
+    globals.sort_unstable();
+    for (name, ty) in globals {
+        writer.write(&Item::Global {
+            name: *name,
+            ty: ty.clone(),
+        })?;
+    }
+
+    let functions = store.functions.pin();
+    let mut functions = functions.iter().collect::<Vec<_>>();
+    functions.sort_unstable_by_key(|(name, _)| **name);
+    for (name, decl) in functions {
+        writer.write(&Item::Function {
+            name: *name,
+            generic_params: decl.generic_params.clone(),
+            signature: decl.signature.clone(),
+        })?;
+
+        let bodies = decl.bodies.pin();
+        let mut bodies = bodies.iter().collect::<Vec<_>>();
+        bodies.sort_unstable_by_key(|(generics, _)| *generics);
+        for (generics, body) in bodies {
+            // Bodies are cloned one at a time, not the whole store
+            writer.write(&Item::Body {
+                function: *name,
+                generics: generics.iter().map(|ty| ty.to_type()).collect(),
+                body: body.clone(),
+            })?;
+        }
+    }
+
+    writer.finish()?;
+    Ok(())
+}
+
+/// Decode a store, inserting items as they are read (see [Reader])
+pub fn read(reader: impl Read) -> Result<Store, DecodeError> {
+    let store = Store::new();
+    for item in Reader::new(reader)? {
+        item?
+            .insert_into(&store)
+            .map_err(DecodeError::Declaration)?;
+    }
+    Ok(store)
+}
+
+/// Streaming encoder, writes [Item]s one by one.
+/// [`Self::finish`] has to be called at the end
+pub struct Writer<W: Write> {
+    writer: W,
+    strings: HashMap<String, usize>,
+    symbols: HashMap<orco::Symbol, usize>,
+}
+
+impl<W: Write> Writer<W> {
+    /// Start encoding, writing the header
+    pub fn new(mut writer: W) -> std::io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        Ok(Self {
+            writer,
+            strings: HashMap::new(),
+            symbols: HashMap::new(),
+        })
+    }
+
+    /// Write an item. Bodies have to come after the declaration of their function
+    pub fn write(&mut self, item: &Item) -> std::io::Result<()> {
+        match item {
+            Item::Type { name, generics, ty } => {
+                self.byte(1)?;
+                self.symbol(*name)?;
+                self.types(generics)?;
+                self.ty(ty)
+            }
+            Item::Global { name, ty } => {
+                self.byte(2)?;
+                self.symbol(*name)?;
+                self.ty(ty)
+            }
+            Item::Function {
+                name,
+                generic_params,
+                signature,
+            } => {
+                self.byte(3)?;
+                self.symbol(*name)?;
+                self.types(generic_params)?;
+                self.signature(signature)
+            }
+            Item::Body {
+                function,
+                generics,
+                body,
+            } => {
+                self.byte(4)?;
+                self.symbol(*function)?;
+                self.types(generics)?;
+                self.body(body)
+            }
+        }
+    }
+
+    /// Write the end marker and give the writer back
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.byte(0)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn byte(&mut self, byte: u8) -> std::io::Result<()> {
+        self.writer.write_all(&[byte])
+    }
+
+    fn bool(&mut self, value: bool) -> std::io::Result<()> {
+        self.byte(value as u8)
+    }
+
+    fn uint(&mut self, mut value: u128) -> std::io::Result<()> {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                return self.byte(byte);
+            }
+            self.byte(byte | 0x80)?;
+        }
+    }
+
+    fn int(&mut self, value: i128) -> std::io::Result<()> {
+        self.uint(((value << 1) ^ (value >> 127)) as u128)
+    }
+
+    fn len(&mut self, len: usize) -> std::io::Result<()> {
+        self.uint(len as u128)
+    }
+
+    fn str(&mut self, string: &str) -> std::io::Result<()> {
+        if let Some(idx) = self.strings.get(string) {
+            return self.len(idx + 1);
+        }
+        self.len(0)?;
+        self.len(string.len())?;
+        self.writer.write_all(string.as_bytes())?;
+        self.strings.insert(string.to_owned(), self.strings.len());
+        Ok(())
+    }
+
+    fn opt_str(&mut self, string: Option<&str>) -> std::io::Result<()> {
+        self.bool(string.is_some())?;
+        match string {
+            Some(string) => self.str(string),
+            None => Ok(()),
+        }
+    }
+
+    fn symbol(&mut self, symbol: orco::Symbol) -> std::io::Result<()> {
+        if let Some(idx) = self.symbols.get(&symbol) {
+            return self.len(idx + 1);
+        }
+        self.len(0)?;
+        self.opt_str(symbol.krate.as_deref())?;
+        self.len(symbol.segments.len())?;
+        for segment in &symbol.segments {
+            self.str(segment)?;
+        }
+        self.str(&symbol.name)?;
+        self.types(&symbol.generics)?;
+        self.uint(symbol.disambiguator.into())?;
+        // Generics could contain symbols, so the index is assigned last
+        self.symbols.insert(symbol, self.symbols.len());
+        Ok(())
+    }
+
+    fn integer_size(&mut self, size: orco::types::IntegerSize) -> std::io::Result<()> {
+        match size {
+            orco::types::IntegerSize::Size => self.uint(0),
+            orco::types::IntegerSize::Bits(bits) => self.uint(u128::from(bits) + 1),
+        }
+    }
+
+    fn types(&mut self, types: &[Type]) -> std::io::Result<()> {
+        self.len(types.len())?;
+        for ty in types {
+            self.ty(ty)?;
+        }
+        Ok(())
+    }
+
+    fn ty(&mut self, ty: &Type) -> std::io::Result<()> {
+        match ty {
+            Type::Integer(size) => {
+                self.byte(0)?;
+                self.integer_size(*size)
+            }
+            Type::Unsigned(size) => {
+                self.byte(1)?;
+                self.integer_size(*size)
+            }
+            Type::Float(bits) => {
+                self.byte(2)?;
+                self.uint((*bits).into())
+            }
+            Type::Bool => self.byte(3),
+            Type::Char(wide) => {
+                self.byte(4)?;
+                self.bool(*wide)
+            }
+            Type::Symbol(name, generics) => {
+                self.byte(5)?;
+                self.symbol(*name)?;
+                self.types(generics)
+            }
+            Type::Array(ty, len) => {
+                self.byte(6)?;
+                self.ty(ty)?;
+                self.len(*len)
+            }
+            Type::Struct { fields } => {
+                self.byte(7)?;
+                self.len(fields.len())?;
+                for (name, ty) in fields {
+                    self.opt_str(name.as_deref())?;
+                    self.ty(ty)?;
+                }
+                Ok(())
+            }
+            Type::Ptr(ty, mutable) => {
+                self.byte(8)?;
+                self.ty(ty)?;
+                self.bool(*mutable)
+            }
+            Type::FnPtr {
+                params,
+                return_type,
+            } => {
+                self.byte(9)?;
+                self.types(params)?;
+                self.opt_ty(return_type.as_deref())
+            }
+            Type::Param(name) => {
+                self.byte(10)?;
+                self.str(name)
+            }
+            Type::Error => self.byte(11),
+        }
+    }
+
+    fn opt_ty(&mut self, ty: Option<&Type>) -> std::io::Result<()> {
+        self.bool(ty.is_some())?;
+        match ty {
+            Some(ty) => self.ty(ty),
+            None => Ok(()),
+        }
+    }
+
+    fn location(&mut self, location: Option<&orco::attrs::SourceLocation>) -> std::io::Result<()> {
+        self.bool(location.is_some())?;
+        let Some(location) = location else {
+            return Ok(());
+        };
+        self.str(&location.file)?;
+        self.uint(location.line.into())?;
+        self.uint(location.column.into())
+    }
+
+    fn signature(&mut self, signature: &orco::types::FunctionSignature) -> std::io::Result<()> {
+        self.len(signature.params.len())?;
+        for (name, ty) in &signature.params {
+            self.opt_str(name.as_deref())?;
+            self.ty(ty)?;
+        }
+        self.opt_ty(signature.return_type.as_ref())?;
+
+        use orco::attrs::Inlining;
+        self.byte(match signature.attrs.inlining {
+            Inlining::Never => 0,
+            Inlining::Auto => 1,
+            Inlining::Hint => 2,
+            Inlining::Always => 3,
+        })?;
+        self.bool(signature.attrs.destructor)?;
+        self.bool(signature.attrs.internal)?;
+        self.location(signature.attrs.location.as_ref())
+    }
+
+    fn place(&mut self, place: &ir::Place) -> std::io::Result<()> {
+        match place {
+            ir::Place::Variable(variable) => {
+                self.byte(0)?;
+                self.len(variable.0)
+            }
+            ir::Place::Global(name, generics) => {
+                self.byte(1)?;
+                self.symbol(*name)?;
+                self.types(generics)
+            }
+            ir::Place::Deref(expr) => {
+                self.byte(2)?;
+                self.expr(expr)
+            }
+            ir::Place::Field(place, idx) => {
+                self.byte(3)?;
+                self.place(place)?;
+                self.len(*idx)
+            }
+            ir::Place::Index(place, index) => {
+                self.byte(4)?;
+                self.place(place)?;
+                self.expr(index)
+            }
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[ir::Expression]) -> std::io::Result<()> {
+        self.len(exprs.len())?;
+        for expr in exprs {
+            self.expr(expr)?;
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &ir::Expression) -> std::io::Result<()> {
+        use ir::Intrinsic as I;
+        match expr {
+            ir::Expression::IConst(value, size) => {
+                self.byte(0)?;
+                self.int(*value)?;
+                self.integer_size(*size)
+            }
+            ir::Expression::UConst(value, size) => {
+                self.byte(1)?;
+                self.uint(*value)?;
+                self.integer_size(*size)
+            }
+            ir::Expression::FConst(value, size) => {
+                self.byte(2)?;
+                self.writer.write_all(&value.to_bits().to_le_bytes())?;
+                self.uint((*size).into())
+            }
+            ir::Expression::BConst(value) => {
+                self.byte(3)?;
+                self.bool(*value)
+            }
+            ir::Expression::Read(place) => {
+                self.byte(4)?;
+                self.place(place)
+            }
+            ir::Expression::Reference(place, mutable) => {
+                self.byte(5)?;
+                self.place(place)?;
+                self.bool(*mutable)
+            }
+            ir::Expression::Call(func, args) => {
+                self.byte(6)?;
+                self.expr(func)?;
+                self.exprs(args)
+            }
+            ir::Expression::Intrinsic(intrinsic) => {
+                self.byte(match intrinsic {
+                    I::Add(..) => 7,
+                    I::Mul(..) => 8,
+                    I::Eq(..) => 9,
+                    I::Not(..) => 10,
+                })?;
+                for operand in intrinsic.operands() {
+                    self.expr(operand)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn statement(&mut self, statement: &ir::Statement) -> std::io::Result<()> {
+        use ir::{AcfStatement as A, BcfStatement as B, Statement as S};
+        match statement {
+            S::Comment(comment) => {
+                self.byte(0)?;
+                self.str(comment)
+            }
+            S::Location(location) => {
+                self.byte(1)?;
+                self.location(location.as_ref())
+            }
+            S::StorageLive(variable) => {
+                self.byte(2)?;
+                self.len(variable.0)
+            }
+            S::StorageDead(variable) => {
+                self.byte(3)?;
+                self.len(variable.0)
+            }
+            S::Assign(place, value) => {
+                self.byte(4)?;
+                self.place(place)?;
+                self.expr(value)
+            }
+            S::Call(func, args) => {
+                self.byte(5)?;
+                self.expr(func)?;
+                self.exprs(args)
+            }
+            S::Return(value) => {
+                self.byte(6)?;
+                self.bool(value.is_some())?;
+                match value {
+                    Some(value) => self.expr(value),
+                    None => Ok(()),
+                }
+            }
+            S::Acf(A::Jump(label)) => {
+                self.byte(7)?;
+                self.len(label.0)
+            }
+            S::Acf(A::Cjump(condition, label)) => {
+                self.byte(8)?;
+                self.expr(condition)?;
+                self.len(label.0)
+            }
+            S::Bcf(B::If(condition)) => {
+                self.byte(9)?;
+                self.expr(condition)
+            }
+            S::Bcf(B::Else) => self.byte(10),
+            S::Bcf(B::End) => self.byte(11),
+            S::Bcf(B::Loop) => self.byte(12),
+            S::Bcf(B::Break) => self.byte(13),
+            S::Bcf(B::Continue) => self.byte(14),
+            S::Bcf(B::Cbreak(condition)) => {
+                self.byte(15)?;
+                self.expr(condition)
+            }
+            S::Bcf(B::Ccontinue(condition)) => {
+                self.byte(16)?;
+                self.expr(condition)
+            }
+            S::LoopAttributes(attrs) => {
+                use orco::attrs::Unrolling;
+                self.byte(17)?;
+                self.byte(match attrs.unrolling {
+                    Unrolling::Never => 0,
+                    Unrolling::Auto => 1,
+                })
+            }
+        }
+    }
+
+    fn body(&mut self, body: &ir::Body) -> std::io::Result<()> {
+        self.len(body.variables.len())?;
+        for variable in &body.variables {
+            self.ty(&variable.ty)?;
+            self.bool(variable.arg)?;
+            self.opt_str(variable.name.as_deref())?;
+        }
+        self.len(body.labels.len())?;
+        for label in &body.labels {
+            self.len(*label)?;
+        }
+        self.len(body.statements.len())?;
+        for statement in &body.statements {
+            self.statement(statement)?;
+        }
+        Ok(())
+    }
+}
+
+/// Streaming decoder, yields [Item]s one by one.
+/// Wrap unbuffered readers (f.e. files) in a [`std::io::BufReader`]
+pub struct Reader<R: Read> {
+    reader: R,
+    strings: Vec<String>,
+    symbols: Vec<orco::Symbol>,
+    done: bool,
+}
+
+impl<R: Read> Reader<R> {
+    /// Start decoding, checking the header
+    pub fn new(mut reader: R) -> Result<Self, DecodeError> {
+        let mut magic = [0; MAGIC.len()];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|err| match err.kind() {
+                std::io::ErrorKind::UnexpectedEof => DecodeError::BadMagic,
+                _ => DecodeError::Io(err),
+            })?;
+        if &magic != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        let mut version = [0; 2];
+        reader.read_exact(&mut version)?;
+        let version = u16::from_le_bytes(version);
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        Ok(Self {
+            reader,
+            strings: Vec::new(),
+            symbols: Vec::new(),
+            done: false,
+        })
+    }
+
+    /// Read the next item, [None] at the end
+    pub fn read(&mut self) -> Result<Option<Item>, DecodeError> {
+        if self.done {
+            return Ok(None);
+        }
+        let item = match self.byte()? {
+            0 => {
+                self.done = true;
+                return Ok(None);
+            }
+            1 => Item::Type {
+                name: self.symbol()?,
+                generics: self.types()?,
+                ty: self.ty()?,
+            },
+            2 => Item::Global {
+                name: self.symbol()?,
+                ty: self.ty()?,
+            },
+            3 => Item::Function {
+                name: self.symbol()?,
+                generic_params: self.types()?,
+                signature: self.signature()?,
+            },
+            4 => Item::Body {
+                function: self.symbol()?,
+                generics: self.types()?,
+                body: self.body()?,
+            },
+            tag => return invalid(format!("unknown item tag {tag}")),
+        };
+        Ok(Some(item))
+    }
+
+    fn byte(&mut self) -> DResult<u8> {
+        let mut byte = [0];
+        self.reader.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    fn bool(&mut self) -> DResult<bool> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => invalid(format!("invalid boolean {byte}")),
+        }
+    }
+
+    fn uint(&mut self) -> DResult<u128> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= 128 || (shift == 126 && byte & 0x7f > 0b11) {
+                return invalid("integer overflow");
+            }
+            value |= u128::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    fn int(&mut self) -> DResult<i128> {
+        let value = self.uint()?;
+        Ok((value >> 1) as i128 ^ -((value & 1) as i128))
+    }
+
+    /// Unsigned integer of a narrower type
+    fn narrow<T: TryFrom<u128>>(&mut self) -> DResult<T> {
+        let value = self.uint()?;
+        T::try_from(value).or_else(|_| invalid(format!("integer {value} out of range")))
+    }
+
+    fn str(&mut self) -> DResult<String> {
+        let idx: usize = self.narrow()?;
+        if idx > 0 {
+            return match self.strings.get(idx - 1) {
+                Some(string) => Ok(string.clone()),
+                None => invalid(format!("invalid string reference {idx}")),
+            };
+        }
+        let len: usize = self.narrow()?;
+        let mut bytes = Vec::new();
+        (&mut self.reader)
+            .take(len as u64)
+            .read_to_end(&mut bytes)?;
+        if bytes.len() != len {
+            return invalid("unexpected end of input");
+        }
+        let string = String::from_utf8(bytes).or_else(|_| invalid("invalid UTF-8"))?;
+        self.strings.push(string.clone());
+        Ok(string)
+    }
+
+    fn opt_str(&mut self) -> DResult<Option<String>> {
+        match self.bool()? {
+            true => self.str().map(Some),
+            false => Ok(None),
+        }
+    }
+
+    fn symbol(&mut self) -> DResult<orco::Symbol> {
+        let idx: usize = self.narrow()?;
+        if idx > 0 {
+            return match self.symbols.get(idx - 1) {
+                Some(symbol) => Ok(*symbol),
+                None => invalid(format!("invalid symbol reference {idx}")),
+            };
+        }
+        let krate = self.opt_str()?;
+        let segments = (0..self.narrow::<usize>()?)
+            .map(|_| self.str().map(Into::into))
+            .collect::<DResult<_>>()?;
+        let symbol = orco::Symbol::new(orco::symbol::Path {
+            krate: krate.map(Into::into),
+            segments,
+            name: self.str()?.into(),
+            generics: self.types()?,
+            disambiguator: self.narrow()?,
+        });
+        self.symbols.push(symbol);
+        Ok(symbol)
+    }
+
+    fn integer_size(&mut self) -> DResult<orco::types::IntegerSize> {
+        Ok(match self.narrow::<u32>()? {
+            0 => orco::types::IntegerSize::Size,
+            bits => orco::types::IntegerSize::Bits(
+                u16::try_from(bits - 1).or_else(|_| invalid("integer size out of range"))?,
+            ),
+        })
+    }
+
+    fn types(&mut self) -> DResult<Vec<Type>> {
+        (0..self.narrow::<usize>()?).map(|_| self.ty()).collect()
+    }
+
+    fn ty(&mut self) -> DResult<Type> {
+        Ok(match self.byte()? {
+            0 => Type::Integer(self.integer_size()?),
+            1 => Type::Unsigned(self.integer_size()?),
+            2 => Type::Float(self.narrow()?),
+            3 => Type::Bool,
+            4 => Type::Char(self.bool()?),
+            5 => Type::Symbol(self.symbol()?, self.types()?),
+            6 => Type::Array(Box::new(self.ty()?), self.narrow()?),
+            7 => Type::Struct {
+                fields: (0..self.narrow::<usize>()?)
+                    .map(|_| Ok((self.opt_str()?, self.ty()?)))
+                    .collect::<DResult<_>>()?,
+            },
+            8 => Type::Ptr(Box::new(self.ty()?), self.bool()?),
+            9 => Type::FnPtr {
+                params: self.types()?,
+                return_type: self.opt_ty()?.map(Box::new),
+            },
+            10 => Type::Param(self.str()?.into()),
+            11 => Type::Error,
+            tag => return invalid(format!("unknown type tag {tag}")),
+        })
+    }
+
+    fn opt_ty(&mut self) -> DResult<Option<Type>> {
+        match self.bool()? {
+            true => self.ty().map(Some),
+            false => Ok(None),
+        }
+    }
+
+    fn location(&mut self) -> DResult<Option<orco::attrs::SourceLocation>> {
+        if !self.bool()? {
+            return Ok(None);
+        }
+        Ok(Some(orco::attrs::SourceLocation {
+            file: self.str()?.into(),
+            line: self.narrow()?,
+            column: self.narrow()?,
+        }))
+    }
+
+    fn signature(&mut self) -> DResult<orco::types::FunctionSignature> {
+        let params = (0..self.narrow::<usize>()?)
+            .map(|_| Ok((self.opt_str()?, self.ty()?)))
+            .collect::<DResult<_>>()?;
+        let return_type = self.opt_ty()?;
+
+        use orco::attrs::Inlining;
+        let inlining = match self.byte()? {
+            0 => Inlining::Never,
+            1 => Inlining::Auto,
+            2 => Inlining::Hint,
+            3 => Inlining::Always,
+            tag => return invalid(format!("unknown inlining mode {tag}")),
+        };
+        Ok(orco::types::FunctionSignature {
+            params,
+            return_type,
+            attrs: orco::attrs::FunctionAttributes {
+                inlining,
+                destructor: self.bool()?,
+                internal: self.bool()?,
+                location: self.location()?,
+            },
+        })
+    }
+
+    fn place(&mut self) -> DResult<ir::Place> {
+        Ok(match self.byte()? {
+            0 => ir::Place::Variable(oc::Variable(self.narrow()?)),
+            1 => ir::Place::Global(self.symbol()?, self.types()?),
+            2 => ir::Place::Deref(Box::new(self.expr()?)),
+            3 => ir::Place::Field(Box::new(self.place()?), self.narrow()?),
+            4 => ir::Place::Index(Box::new(self.place()?), Box::new(self.expr()?)),
+            tag => return invalid(format!("unknown place tag {tag}")),
+        })
+    }
+
+    fn exprs(&mut self) -> DResult<Vec<ir::Expression>> {
+        (0..self.narrow::<usize>()?).map(|_| self.expr()).collect()
+    }
+
+    fn expr(&mut self) -> DResult<ir::Expression> {
+        use ir::Intrinsic as I;
+        Ok(match self.byte()? {
+            0 => ir::Expression::IConst(self.int()?, self.integer_size()?),
+            1 => ir::Expression::UConst(self.uint()?, self.integer_size()?),
+            2 => {
+                let mut bits = [0; 8];
+                self.reader.read_exact(&mut bits)?;
+                ir::Expression::FConst(f64::from_bits(u64::from_le_bytes(bits)), self.narrow()?)
+            }
+            3 => ir::Expression::BConst(self.bool()?),
+            4 => ir::Expression::Read(self.place()?),
+            5 => ir::Expression::Reference(self.place()?, self.bool()?),
+            6 => ir::Expression::Call(Box::new(self.expr()?), self.exprs()?),
+            7 => ir::Expression::Intrinsic(I::Add(self.operand()?, self.operand()?)),
+            8 => ir::Expression::Intrinsic(I::Mul(self.operand()?, self.operand()?)),
+            9 => ir::Expression::Intrinsic(I::Eq(self.operand()?, self.operand()?)),
+            10 => ir::Expression::Intrinsic(I::Not(self.operand()?)),
+            tag => return invalid(format!("unknown expression tag {tag}")),
+        })
+    }
+
+    fn operand(&mut self) -> DResult<Box<ir::Expression>> {
+        self.expr().map(Box::new)
+    }
+
+    fn statement(&mut self) -> DResult<ir::Statement> {
+        use ir::{AcfStatement as A, BcfStatement as B, Statement as S};
+        Ok(match self.byte()? {
+            0 => S::Comment(self.str()?),
+            1 => S::Location(self.location()?),
+            2 => S::StorageLive(oc::Variable(self.narrow()?)),
+            3 => S::StorageDead(oc::Variable(self.narrow()?)),
+            4 => S::Assign(self.place()?, self.expr()?),
+            5 => S::Call(self.expr()?, self.exprs()?),
+            6 => S::Return(match self.bool()? {
+                true => Some(self.expr()?),
+                false => None,
+            }),
+            7 => S::Acf(A::Jump(oc::Label(self.narrow()?))),
+            8 => S::Acf(A::Cjump(self.expr()?, oc::Label(self.narrow()?))),
+            9 => S::Bcf(B::If(self.expr()?)),
+            10 => S::Bcf(B::Else),
+            11 => S::Bcf(B::End),
+            12 => S::Bcf(B::Loop),
+            13 => S::Bcf(B::Break),
+            14 => S::Bcf(B::Continue),
+            15 => S::Bcf(B::Cbreak(self.expr()?)),
+            16 => S::Bcf(B::Ccontinue(self.expr()?)),
+            17 => {
+                use orco::attrs::Unrolling;
+                S::LoopAttributes(orco::attrs::LoopAttributes {
+                    unrolling: match self.byte()? {
+                        0 => Unrolling::Never,
+                        1 => Unrolling::Auto,
+                        tag => return invalid(format!("unknown unrolling mode {tag}")),
+                    },
+                })
+            }
+            tag => return invalid(format!("unknown statement tag {tag}")),
+        })
+    }
+
+    fn body(&mut self) -> DResult<ir::Body> {
+        let variables = (0..self.narrow::<usize>()?)
+            .map(|_| {
+                Ok(ir::Variable {
+                    ty: self.ty()?,
+                    arg: self.bool()?,
+                    name: self.opt_str()?,
+                })
+            })
+            .collect::<DResult<_>>()?;
+        let labels = (0..self.narrow::<usize>()?)
+            .map(|_| self.narrow())
+            .collect::<DResult<_>>()?;
+        let statements = (0..self.narrow::<usize>()?)
+            .map(|_| self.statement())
+            .collect::<DResult<_>>()?;
+        Ok(ir::Body {
+            variables,
+            labels,
+            statements,
+        })
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<Item, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.read().transpose();
+        if let Some(Err(_)) = item {
+            // Don't keep reading garbage
+            self.done = true;
+        }
+        item
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "type std::pair<#T> = { first: #T, second: #T };
+static counters: u64[4];
+
+[inline(never)] [location(src/lib.rs:3:5)] fn std::mem::swap<#T>(a: *mut #T, b: *mut #T) -> void;
+for <#T> {
+  let _0: *mut #T = <argument>; // a
+  let _1: *mut #T = <argument>; // b
+  let _2: #T;
+  location src/lib.rs:4:9;
+  _2 = *_0;
+  *_0 = *_1;
+  *_1 = _2;
+  return;
+}
+for <i32> {
+  let _0: *mut i32 = <argument>; // a
+  let _1: *mut i32 = <argument>; // b
+  let _2: i32;
+  _2 = *_0;
+  *_0 = *_1;
+  *_1 = _2;
+  return;
+}
+
+fn f(n: u32, x: f64) -> f64;
+for  {
+  let _0: u32 = <argument>;
+  let _1: f64 = <argument>;
+  let _2: std::pair<i32>;
+  // a comment
+  loop {
+    loop_attributes [unroll(never)];
+    break if _0 == 0 as u32;
+    if !(_0 == 1 as u32) {
+      _1 = _1 * -0.5 as f64;
+    } else {
+      std::mem::swap<i32>(&mut _2._0, &mut _2._1);
+    }
+    counters[0 as usize] = counters[0 as usize] + 1 as u64;
+    _0 = _0 + 4294967295 as u32;
+  }
+label0:
+  jump label1 if _1 == 0 as f64;
+  _1 = 0 as f64;
+  jump label0;
+label1:
+  return _1;
+}
+
+[destructor] fn teardown() -> void;
+
+[inline(always)] [internal] fn helper() -> void;
+for  {
+  return;
+}
+";
+
+    fn encode(store: &Store) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write(store, &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let store = crate::parser::parse(SAMPLE).unwrap();
+        let bytes = encode(&store);
+        let decoded = read(bytes.as_slice()).unwrap();
+        assert_eq!(decoded.to_string(), store.to_string());
+        assert_eq!(encode(&decoded), bytes, "encoding isn't deterministic");
+    }
+
+    #[test]
+    fn header() {
+        assert!(matches!(read(&b"ORCO"[..]), Err(DecodeError::BadMagic)));
+        assert!(matches!(
+            read(&b"NOTOIR\x01\x00\x00"[..]),
+            Err(DecodeError::BadMagic)
+        ));
+        assert!(matches!(
+            read(&b"ORCOIR\x01"[..]),
+            Err(DecodeError::Invalid(message)) if message == "unexpected end of input"
+        ));
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend((VERSION + 1).to_le_bytes());
+        bytes.push(0);
+        assert!(matches!(
+            read(bytes.as_slice()),
+            Err(DecodeError::UnsupportedVersion(version)) if version == VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = encode(&crate::parser::parse(SAMPLE).unwrap());
+        for len in MAGIC.len() + 2..bytes.len() {
+            match read(&bytes[..len]) {
+                Err(DecodeError::Invalid(message)) if message == "unexpected end of input" => (),
+                Err(err) => panic!("truncated to {len} bytes: {err}"),
+                Ok(_) => panic!("truncated to {len} bytes: decoded successfully"),
+            }
+        }
+    }
+
+    #[test]
+    fn streaming() {
+        let global = Item::Global {
+            name: "counter".into(),
+            ty: Type::Unsigned(orco::types::IntegerSize::Bits(64)),
+        };
+        let function = Item::Function {
+            name: "f".into(),
+            generic_params: Vec::new(),
+            signature: orco::types::FunctionSignature {
+                params: vec![(Some("x".to_owned()), Type::Bool)],
+                return_type: None,
+                attrs: Default::default(),
+            },
+        };
+
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        writer.write(&global).unwrap();
+        let first_end = writer.writer.len();
+        writer.write(&function).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let items = Reader::new(bytes.as_slice())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(items, [global.clone(), function]);
+
+        // Items read before the input ends are still returned, then the error, then nothing
+        let mut reader = Reader::new(&bytes[..first_end + 2]).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap(), global);
+        assert!(matches!(reader.next(), Some(Err(DecodeError::Invalid(_)))));
+        assert!(reader.next().is_none());
+
+        // Missing terminator
+        let results = Reader::new(&bytes[..bytes.len() - 1])
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(results.len(), 3);
+        assert!(results[..2].iter().all(Result::is_ok));
+        assert!(matches!(results[2], Err(DecodeError::Invalid(_))));
+    }
+}
@@ -1,16 +1,21 @@
 use super::{Codegen, ir, oc};
 
-impl oc::AcfCodegen for &mut Codegen<'_> {
+// Pending values are flushed before any control flow,
+// so they are not evaluated in another block or loop iteration
+
+impl oc::AcfCodegen for Codegen<'_> {
     fn alloc_label(&mut self) -> oc::Label {
         self.body.labels.push(0);
         oc::Label(self.body.labels.len() - 1)
     }
 
     fn label(&mut self, label: oc::Label) {
+        oc::BodyCodegen::flush(self);
         self.body.labels[label.0] = self.body.statements.len();
     }
 
     fn jump(&mut self, label: oc::Label) {
+        oc::BodyCodegen::flush(self);
         self.body
             .statements
             .push(ir::Statement::Acf(ir::AcfStatement::Jump(label)));
@@ -18,6 +23,7 @@ impl oc::AcfCodegen for &mut Codegen<'_> {
 
     fn cjump(&mut self, condition: oc::Value, label: oc::Label) {
         let condition = self.use_value(condition);
+        oc::BodyCodegen::flush(self);
         self.body
             .statements
             .push(ir::Statement::Acf(ir::AcfStatement::Cjump(
@@ -26,39 +32,45 @@ impl oc::AcfCodegen for &mut Codegen<'_> {
     }
 }
 
-impl oc::BcfCodegen for &mut Codegen<'_> {
+impl oc::BcfCodegen for Codegen<'_> {
     fn if_(&mut self, condition: oc::Value) {
         let condition = self.use_value(condition);
+        oc::BodyCodegen::flush(self);
         self.body
             .statements
             .push(ir::Statement::Bcf(ir::BcfStatement::If(condition)));
     }
 
     fn else_(&mut self) {
+        oc::BodyCodegen::flush(self);
         self.body
             .statements
             .push(ir::Statement::Bcf(ir::BcfStatement::Else));
     }
 
     fn end(&mut self) {
+        oc::BodyCodegen::flush(self);
         self.body
             .statements
             .push(ir::Statement::Bcf(ir::BcfStatement::End));
     }
 
     fn loop_(&mut self) {
+        oc::BodyCodegen::flush(self);
         self.body
             .statements
             .push(ir::Statement::Bcf(ir::BcfStatement::Loop));
     }
 
     fn break_(&mut self) {
+        oc::BodyCodegen::flush(self);
         self.body
             .statements
             .push(ir::Statement::Bcf(ir::BcfStatement::Break));
     }
 
     fn continue_(&mut self) {
+        oc::BodyCodegen::flush(self);
         self.body
             .statements
             .push(ir::Statement::Bcf(ir::BcfStatement::Continue));
@@ -66,6 +78,7 @@ impl oc::BcfCodegen for &mut Codegen<'_> {
 
     fn cbreak(&mut self, condition: oc::Value) {
         let condition = self.use_value(condition);
+        oc::BodyCodegen::flush(self);
         self.body
             .statements
             .push(ir::Statement::Bcf(ir::BcfStatement::Cbreak(condition)));
@@ -73,6 +86,7 @@ impl oc::BcfCodegen for &mut Codegen<'_> {
 
     fn ccontinue(&mut self, condition: oc::Value) {
         let condition = self.use_value(condition);
+        oc::BodyCodegen::flush(self);
         self.body
             .statements
             .push(ir::Statement::Bcf(ir::BcfStatement::Ccontinue(condition)));
@@ -1,6 +1,6 @@
 use super::{ir, oc};
 
-impl oc::Intrinsics for &mut super::Codegen<'_> {
+impl oc::Intrinsics for super::Codegen<'_> {
     fn add(&mut self, a: oc::Value, b: oc::Value) -> oc::Value {
         let a = Box::new(self.use_value(a));
         let b = Box::new(self.use_value(b));
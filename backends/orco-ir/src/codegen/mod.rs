@@ -1,6 +1,6 @@
 use crate::ir;
 use orco::codegen as oc;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 mod control_flow;
 mod intrinsics;
@@ -19,38 +19,68 @@ pub struct Codegen<'a> {
     values: HashMap<usize, ir::Expression>,
     /// Next value ID
     next_value_id: usize,
+    /// Variables, which had their address taken.
+    /// Writes through pointers can change them
+    escaped: HashSet<usize>,
+    /// Temporaries made by the codegen itself, they are never written again
+    temporaries: HashSet<usize>,
+    /// See [`oc::BodyCodegen::abort`]
+    aborted: bool,
 }
 
 impl<'a> Codegen<'a> {
-    #[allow(missing_docs)]
+    /// Start defining a function. Fails if the function is not declared
+    /// or this specialization is already defined
     pub fn new(
         store: &'a crate::Store,
         name: orco::Symbol,
         generic_params: Vec<orco::Type>,
-    ) -> Self {
+    ) -> Result<Self, orco::Error> {
         let mut body = ir::Body::default();
         let decls = store.functions.pin();
-        let function = decls
-            .get(&name)
-            .unwrap_or_else(|| panic!("trying to codegen undeclared function {name}"));
+        let function = decls.get(&name).ok_or(orco::Error::Undeclared(
+            orco::error::SymbolKind::Function,
+            name,
+        ))?;
+        let args = crate::generics::intern(&generic_params);
+        if function.bodies.pin().contains_key(&args) {
+            return Err(orco::Error::AlreadyDefined(name, generic_params));
+        }
+        let Some(map) = crate::generics::match_type_params(
+            &crate::generics::intern(&function.generic_params),
+            &args,
+            store,
+        ) else {
+            return Err(orco::Error::NoSpecialization(
+                orco::error::SymbolKind::Function,
+                name,
+                generic_params,
+            ));
+        };
 
-        body.variables.reserve(function.signature.params.len());
-        for (name, ty) in &function.signature.params {
+        // Parameters of a specialization have the types it was specialized with
+        let mut signature = function.signature.clone();
+        signature.instantiate(&map);
+        body.variables.reserve(signature.params.len());
+        for (name, ty) in signature.params {
             body.variables.push(ir::Variable {
-                ty: ty.clone(),
+                ty,
                 arg: true,
-                name: name.clone(),
+                name,
             });
         }
 
-        Self {
+        Ok(Self {
             store,
             name,
             generic_params,
             body,
             values: HashMap::new(),
             next_value_id: 0,
-        }
+            escaped: HashSet::new(),
+            temporaries: HashSet::new(),
+            aborted: false,
+        })
     }
 
     /// Insert an expression and return [`oc::Value`] for it
@@ -69,6 +99,80 @@ impl<'a> Codegen<'a> {
             .unwrap_or_else(|| panic!("invalid or previously used value #{}", value.0))
     }
 
+    /// Whether writing to `target` ([None] for any memory) could change the result of `expr`.
+    /// Pending calls conflict with anything, so that they happen in order
+    fn conflicts(&self, expr: &ir::Expression, target: Option<oc::Variable>) -> bool {
+        if expr.has_calls() {
+            return true;
+        }
+
+        let mut conflicts = false;
+        expr.visit_reads(&mut |place| {
+            // Functions can't change, global variables can
+            if let ir::Place::Global(name, _) = place
+                && !self.store.globals.pin().contains_key(name)
+            {
+                return;
+            }
+            conflicts |= match (place.root_variable(), target) {
+                (Some(read), Some(written)) => read == written,
+                (Some(variable), None) | (None, Some(variable)) => {
+                    self.escaped.contains(&variable.0)
+                }
+                (None, None) => true,
+            };
+        });
+        conflicts
+    }
+
+    /// Whether no statement can change the result of `expr`
+    fn is_stable(&self, expr: &ir::Expression) -> bool {
+        let mut stable = !expr.has_calls();
+        expr.visit_reads(&mut |place| {
+            stable &= match place {
+                ir::Place::Variable(variable) => self.temporaries.contains(&variable.0),
+                ir::Place::Global(name, _) => !self.store.globals.pin().contains_key(name),
+                _ => false,
+            };
+        });
+        stable
+    }
+
+    /// Store an expression in a new temporary variable
+    fn temporary(&mut self, expr: ir::Expression) -> oc::Variable {
+        let ty = expr.get_type(self.store, &self.body);
+        let tmp = oc::BodyCodegen::declare_var(self, ty, None);
+        self.temporaries.insert(tmp.0);
+        self.body
+            .statements
+            .push(ir::Statement::Assign(ir::Place::Variable(tmp), expr));
+        tmp
+    }
+
+    /// Materialize pending values, for which `conflicts` returns true.
+    /// Values are materialized in the order they were created
+    fn materialize_if(&mut self, conflicts: impl Fn(&Self, &ir::Expression) -> bool) {
+        let mut ids = self
+            .values
+            .iter()
+            .filter(|(_, expr)| conflicts(self, expr))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+
+        for id in ids {
+            let expr = self.values.remove(&id).unwrap();
+            let tmp = self.temporary(expr);
+            self.values
+                .insert(id, ir::Expression::Read(ir::Place::Variable(tmp)));
+        }
+    }
+
+    /// Materialize pending values before writing to `target` ([None] for any memory)
+    fn before_write(&mut self, target: Option<oc::Variable>) {
+        self.materialize_if(|this, expr| this.conflicts(expr, target));
+    }
+
     /// Convert [`oc::Place`] to [`ir::Place`]
     pub fn cvt_place(&mut self, place: oc::Place) -> ir::Place {
         match place {
@@ -76,6 +180,10 @@ impl<'a> Codegen<'a> {
             oc::Place::Global(name, generics) => ir::Place::Global(name, generics),
             oc::Place::Deref(value) => ir::Place::Deref(Box::new(self.use_value(value))),
             oc::Place::Field(place, idx) => ir::Place::Field(Box::new(self.cvt_place(*place)), idx),
+            oc::Place::Index(place, index) => {
+                let place = self.cvt_place(*place);
+                ir::Place::Index(Box::new(place), Box::new(self.use_value(index)))
+            }
         }
     }
 }
@@ -87,6 +195,21 @@ impl oc::BodyCodegen for Codegen<'_> {
             .push(ir::Statement::Comment(comment.to_owned()));
     }
 
+    fn set_location(&mut self, location: Option<orco::attrs::SourceLocation>) {
+        // Only the last location before a statement matters
+        if let Some(ir::Statement::Location(last)) = self.body.statements.last_mut() {
+            *last = location;
+        } else {
+            self.body.statements.push(ir::Statement::Location(location));
+        }
+    }
+
+    fn loop_attributes(&mut self, attrs: orco::attrs::LoopAttributes) {
+        self.body
+            .statements
+            .push(ir::Statement::LoopAttributes(attrs));
+    }
+
     fn type_of(&self, id: usize) -> orco::Type {
         self.values
             .get(&id)
@@ -94,6 +217,34 @@ impl oc::BodyCodegen for Codegen<'_> {
             .get_type(self.store, &self.body)
     }
 
+    fn variable_type(&self, variable: oc::Variable) -> orco::Type {
+        self.body.get_variable(variable).ty.clone()
+    }
+
+    fn global_type(&self, name: orco::Symbol, generics: &[orco::Type]) -> orco::Type {
+        ir::Place::Global(name, generics.to_vec())
+            .get_type(self.store, &self.body)
+            .0
+    }
+
+    fn symbol_kind(
+        &self,
+        name: orco::Symbol,
+        _generics: &[orco::Type],
+    ) -> Option<orco::error::SymbolKind> {
+        if self.store.functions.pin().contains_key(&name) {
+            Some(orco::error::SymbolKind::Function)
+        } else if self.store.globals.pin().contains_key(&name) {
+            Some(orco::error::SymbolKind::Global)
+        } else {
+            None
+        }
+    }
+
+    fn inline_type(&self, ty: orco::Type) -> orco::Type {
+        self.store.inline_type_aliases(ty)
+    }
+
     fn declare_var(&mut self, ty: orco::Type, name: Option<&str>) -> oc::Variable {
         self.body.variables.push(ir::Variable {
             ty,
@@ -103,14 +254,51 @@ impl oc::BodyCodegen for Codegen<'_> {
         oc::Variable(self.body.variables.len() - 1)
     }
 
+    fn storage_live(&mut self, variable: oc::Variable) {
+        self.body
+            .statements
+            .push(ir::Statement::StorageLive(variable));
+    }
+
+    fn storage_dead(&mut self, variable: oc::Variable) {
+        // Pending values can't read the variable after it's dead
+        self.before_write(Some(variable));
+        self.body
+            .statements
+            .push(ir::Statement::StorageDead(variable));
+    }
+
     fn assign(&mut self, target: oc::Place, value: oc::Value) {
         let target = self.cvt_place(target);
         let value = self.use_value(value);
+        self.before_write(target.root_variable());
         self.body
             .statements
             .push(ir::Statement::Assign(target, value));
     }
 
+    fn flush(&mut self) {
+        self.materialize_if(|this, expr| !this.is_stable(expr));
+    }
+
+    fn abort(&mut self) {
+        self.aborted = true;
+    }
+
+    fn dup(&mut self, value: oc::Value) -> (oc::Value, oc::Value) {
+        let expr = self.use_value(value);
+        if expr.is_const() {
+            return (self.expr(expr.clone()), self.expr(expr));
+        }
+
+        self.materialize_if(|_, expr| expr.has_calls());
+        let tmp = self.temporary(expr);
+        (
+            self.expr(ir::Expression::Read(ir::Place::Variable(tmp))),
+            self.expr(ir::Expression::Read(ir::Place::Variable(tmp))),
+        )
+    }
+
     fn iconst(&mut self, value: i128, size: orco::types::IntegerSize) -> oc::Value {
         self.expr(ir::Expression::IConst(value, size))
     }
@@ -129,7 +317,12 @@ impl oc::BodyCodegen for Codegen<'_> {
 
     fn read(&mut self, place: oc::Place) -> oc::Value {
         let place = self.cvt_place(place);
-        self.expr(ir::Expression::Read(place))
+        let expr = ir::Expression::Read(place);
+        // Pending calls could change memory this value reads
+        if self.conflicts(&expr, None) {
+            self.materialize_if(|_, expr| expr.has_calls());
+        }
+        self.expr(expr)
     }
 
     fn reference(&mut self, place: oc::Place, mutable: bool) -> oc::Value {
@@ -139,6 +332,9 @@ impl oc::BodyCodegen for Codegen<'_> {
             !mutable || can_be_mutable,
             "can't create mutable reference to an immutable {place}"
         );
+        if let Some(variable) = place.root_variable() {
+            self.escaped.insert(variable.0);
+        }
 
         self.expr(ir::Expression::Reference(place, mutable))
     }
@@ -151,6 +347,8 @@ impl oc::BodyCodegen for Codegen<'_> {
         };
 
         let args = args.into_iter().map(|arg| self.use_value(arg)).collect();
+        // A call can write to any memory
+        self.before_write(None);
         if has_retval {
             Some(self.expr(ir::Expression::Call(Box::new(func), args)))
         } else {
@@ -161,43 +359,39 @@ impl oc::BodyCodegen for Codegen<'_> {
 
     fn return_(&mut self, value: Option<oc::Value>) {
         let value = value.map(|value| self.use_value(value));
+        self.materialize_if(|_, expr| expr.has_calls());
         self.body.statements.push(ir::Statement::Return(value));
     }
-
-    fn intrinsics(&mut self) -> impl oc::Intrinsics + '_ {
-        self
-    }
-
-    fn acf(&mut self) -> impl oc::AcfCodegen + '_ {
-        self
-    }
-
-    fn bcf(&mut self) -> impl oc::BcfCodegen + '_ {
-        self
-    }
 }
 
 impl core::ops::Drop for Codegen<'_> {
     fn drop(&mut self) {
+        if self.aborted {
+            return;
+        }
+        // Unused calls still have to happen
+        self.materialize_if(|_, expr| expr.has_calls());
         self.store
-            .function_bodies
+            .functions
             .pin()
-            .get_or_insert_with(self.name, Default::default)
+            .get(&self.name)
+            .expect("[bug?] function got undeclared during codegen")
+            .bodies
             .pin()
             .try_insert(
-                core::mem::take(&mut self.generic_params),
+                crate::generics::intern(&self.generic_params),
                 core::mem::take(&mut self.body),
             )
             .unwrap_or_else(|_| panic!("function {} is already defined", self.name));
     }
 }
 
-impl orco::CodegenBackend for Store {
+impl orco::CodegenBackend for crate::Store {
     fn cg_function(
         &self,
         name: orco::Symbol,
         generic_params: Vec<orco::Type>,
-    ) -> impl orco::codegen::BodyCodegen {
-        codegen::Codegen::new(self, name, generic_params)
+    ) -> Result<Box<dyn orco::codegen::BodyCodegen + '_>, orco::Error> {
+        Ok(Box::new(Codegen::new(self, name, generic_params)?))
     }
 }
@@ -21,6 +21,10 @@ impl<CG: oc::BodyCodegen> super::FwdCtx<'_, CG> {
             ),
             ir::Place::Deref(expr) => oc::Place::Deref(self.expr(expr)),
             ir::Place::Field(place, idx) => self.place(place).field(*idx),
+            ir::Place::Index(place, index) => {
+                let place = self.place(place);
+                place.index(self.expr(index))
+            }
         }
     }
 
@@ -49,26 +53,25 @@ impl<CG: oc::BodyCodegen> super::FwdCtx<'_, CG> {
 
             ir::Expression::Intrinsic(intrinsic) => {
                 use crate::ir::Intrinsic as I;
-                use oc::Intrinsics as IT;
                 match intrinsic {
                     I::Add(a, b) => {
                         let a = self.expr(a);
                         let b = self.expr(b);
-                        self.cg.intrinsics().add(a, b)
+                        self.cg.add(a, b)
                     }
                     I::Mul(a, b) => {
                         let a = self.expr(a);
                         let b = self.expr(b);
-                        self.cg.intrinsics().mul(a, b)
+                        self.cg.mul(a, b)
                     }
                     I::Eq(a, b) => {
                         let a = self.expr(a);
                         let b = self.expr(b);
-                        self.cg.intrinsics().eq(a, b)
+                        self.cg.eq(a, b)
                     }
                     I::Not(a) => {
                         let a = self.expr(a);
-                        self.cg.intrinsics().not(a)
+                        self.cg.not(a)
                     }
                 }
             }
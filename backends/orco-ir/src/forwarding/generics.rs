@@ -1,224 +1,294 @@
 impl crate::Store {
     /// Generate monomorphization, see [`crate::Store::type_instances`]
-    /// and [`crate::Store::function_instances`]
-    pub fn monomorphize(&self) {
+    /// and [`crate::Store::function_instances`].
+    /// Instances that can't be resolved are skipped and reported, the rest are still recorded
+    pub fn monomorphize(&self) -> Result<(), Vec<orco::Error>> {
+        let mut errors = Vec::new();
         let type_instances = self.type_instances.pin();
-        let function_instances = self.type_instances.pin();
+        let function_instances = self.function_instances.pin();
         type_instances.clear();
         function_instances.clear();
 
         for (name, specs) in self.types.pin().iter() {
             for (generics, ty) in specs.pin().iter() {
-                if generics.iter().any(orco::Type::has_params) {
+                if generics.iter().any(|ty| ty.has_params()) {
                     continue;
                 }
 
-                if self.type_instances.pin().insert((*name, generics.clone())) {
-                    self.register_type(&ty);
+                let generics = generics.iter().map(|ty| ty.to_type()).collect();
+                if self.type_instances.pin().insert((*name, generics))
+                    && let Err(err) = self.register_type(&ty.to_type())
+                {
+                    errors.push(err);
                 }
             }
         }
 
-        let bodies = self.function_bodies.pin();
         for (name, decl) in self.functions.pin().iter() {
             if !decl.generic_params.iter().any(orco::Type::has_params) {
-                self.register_funcion(*name, &decl.generic_params);
+                errors.extend(self.register_funcion(*name, &decl.generic_params).err());
                 continue;
             }
 
-            let Some(specs) = bodies.get(name) else {
-                continue;
-            };
-
-            for (generics, _) in specs.pin().iter() {
-                if !decl.generic_params.iter().any(orco::Type::has_params) {
-                    self.register_funcion(*name, generics);
+            // Fully concrete specializations are instances on their own
+            for (generics, _) in decl.bodies.pin().iter() {
+                if !generics.iter().any(|ty| ty.has_params()) {
+                    let generics = generics.iter().map(|ty| ty.to_type()).collect::<Vec<_>>();
+                    errors.extend(self.register_funcion(*name, &generics).err());
                 }
             }
         }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
     }
 
     /// Declare all symbols from this IR in another [`orco::DeclarationBackend`],
-    /// monomorphizing generics
-    pub fn declare_mono(&self, backend: &impl orco::DeclarationBackend) {
+    /// monomorphizing generics. Symbols the backend rejects are reported,
+    /// the rest are still declared
+    pub fn declare_mono(
+        &self,
+        backend: &(impl orco::DeclarationBackend + ?Sized),
+    ) -> Result<(), Vec<orco::Error>> {
+        let mut errors = Vec::new();
         for (name, generics) in self.type_instances.pin().iter() {
-            self.get_type(*name, generics, |ty, map| {
-                backend.type_(*name, generics.clone(), ty.copy_instantiate(&map));
+            let mut result = Ok(());
+            let found = self.get_type(*name, generics, |ty, map| {
+                result = backend.type_(*name, generics.clone(), ty.instantiate(&map).to_type());
             });
+            errors.extend(found.and(result).err());
         }
 
         let functions = self.functions.pin();
         for (name, generics) in self.function_instances.pin().iter() {
+            // Instances are only recorded for declared functions with matching generics
             let decl = functions
                 .get(name)
-                .unwrap_or_else(|| panic!("function {name} not found"));
+                .unwrap_or_else(|| panic!("[bug?] function {name} not found"));
             let sig = decl.instantiate(self, generics);
-            backend.function(
+            let result = backend.function(
                 *name,
                 generics.clone(),
                 sig.params.clone(),
                 sig.return_type.clone(),
                 sig.attrs.clone(),
             );
+            errors.extend(result.err());
+        }
+
+        for (name, ty) in self.globals.pin().iter() {
+            errors.extend(backend.global(*name, ty.clone()).err());
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
         }
     }
 
-    /// Register a type instance for monomorphization, see [`Self::type_instances`]
-    pub fn register_type(&self, ty: &orco::Type) {
+    /// Register a type instance for monomorphization, see [`Self::type_instances`].
+    /// Fails if the type, or any type it depends on, can't be resolved
+    pub fn register_type(&self, ty: &orco::Type) -> Result<(), orco::Error> {
         use orco::Type;
         match ty {
             Type::Integer(..)
             | Type::Unsigned(..)
             | Type::Float(..)
             | Type::Bool
-            | Type::Char(..) => (),
+            | Type::Char(..)
+            | Type::Error => Ok(()),
             Type::Symbol(name, generics) => {
-                if self.type_instances.pin().insert((*name, generics.clone())) {
-                    self.get_type(*name, generics, |ty, map| {
-                        let ty = ty.copy_instantiate(&map);
-                        self.register_type(&ty);
-                    });
+                if !self.type_instances.pin().insert((*name, generics.clone())) {
+                    return Ok(());
                 }
-            }
-            Type::Array(ty, _) => self.register_type(ty),
-            Type::Struct { fields } => {
-                for (_, ty) in fields {
-                    self.register_type(ty);
+                let mut result = Ok(());
+                let found = self.get_type(*name, generics, |ty, map| {
+                    result = self.register_type(&ty.instantiate(&map).to_type());
+                });
+                // Unresolved types are not instances, so they are reported once
+                if found.is_err() {
+                    self.type_instances.pin().remove(&(*name, generics.clone()));
                 }
+                found.and(result)
             }
-            Type::Ptr(ty, _) => self.register_type(ty),
+            Type::Array(ty, _) | Type::Ptr(ty, _) => self.register_type(ty),
+            Type::Struct { fields } => fields.iter().try_for_each(|(_, ty)| self.register_type(ty)),
             Type::FnPtr {
                 params,
                 return_type,
-            } => {
-                for ty in params {
-                    self.register_type(ty);
-                }
-                if let Some(ty) = return_type {
-                    self.register_type(ty);
-                }
-            }
+            } => params
+                .iter()
+                .chain(return_type.as_deref())
+                .try_for_each(|ty| self.register_type(ty)),
             Type::Param(name) => {
                 panic!("encountered a type param #{name} while recording type instances")
             }
-            Type::Error => (),
         }
     }
 
-    /// Register a type instance for monomorphization, see [`Self::type_instances`]
-    pub fn register_funcion(&self, name: orco::Symbol, generics: &[orco::Type]) {
-        self.function_instances
-            .pin()
-            .insert((name, generics.to_vec()));
+    /// Register a function instance for monomorphization, see [`Self::function_instances`],
+    /// along with the types and functions it uses.
+    /// Fails if the function isn't declared or the generics don't match
+    pub fn register_funcion(
+        &self,
+        name: orco::Symbol,
+        generics: &[orco::Type],
+    ) -> Result<(), orco::Error> {
         let functions = self.functions.pin();
-        let decl = functions
-            .get(&name)
-            .unwrap_or_else(|| panic!("function {name} not found"));
+        let decl = functions.get(&name).ok_or(orco::Error::Undeclared(
+            orco::error::SymbolKind::Function,
+            name,
+        ))?;
+        let args = crate::generics::intern(generics);
+        if crate::generics::match_type_params(
+            &crate::generics::intern(&decl.generic_params),
+            &args,
+            self,
+        )
+        .is_none()
+        {
+            return Err(orco::Error::NoSpecialization(
+                orco::error::SymbolKind::Function,
+                name,
+                generics.to_vec(),
+            ));
+        }
+        if !self
+            .function_instances
+            .pin()
+            .insert((name, generics.to_vec()))
+        {
+            return Ok(());
+        }
 
         let signature = decl.instantiate(self, generics);
-        for (_, ty) in &signature.params {
-            self.register_type(ty);
-        }
-        if let Some(ty) = &signature.return_type {
-            self.register_type(&ty);
+        for ty in signature
+            .params
+            .iter()
+            .map(|(_, ty)| ty)
+            .chain(&signature.return_type)
+        {
+            self.register_type(ty)?;
         }
 
-        let bodies = self.function_bodies.pin();
-        let Some(specs) = bodies.get(&name) else {
-            return;
-        };
-        crate::generics::match_specialization(&specs, generics, self, |body, map| {
-            for variable in &body.variables {
-                self.register_type(&variable.ty.copy_instantiate(&map));
-            }
+        let registered =
+            crate::generics::match_specialization(&decl.bodies, &args, self, |body, map| {
+                for variable in &body.variables {
+                    self.register_type(&variable.ty.copy_instantiate(&map))?;
+                }
 
-            use crate::ir::Expression;
-            use crate::ir::Place;
-            use crate::ir::Statement;
-            fn register_place(store: &crate::Store, place: &Place) {
-                match place {
-                    Place::Variable(..) => todo!(),
-                    Place::Global(name, generics) => {
-                        store.register_funcion(*name, generics);
+                use crate::ir::Expression;
+                use crate::ir::Place;
+                use crate::ir::Statement;
+                type Map = crate::generics::TypeMap;
+                type Result = std::result::Result<(), orco::Error>;
+                fn register_place(store: &crate::Store, map: &Map, place: &Place) -> Result {
+                    match place {
+                        Place::Variable(..) => Ok(()),
+                        Place::Global(name, generics) => {
+                            if store.globals.pin().contains_key(name) {
+                                return Ok(());
+                            }
+                            let generics = generics
+                                .iter()
+                                .map(|ty| ty.copy_instantiate(map))
+                                .collect::<Vec<_>>();
+                            store.register_funcion(*name, &generics)
+                        }
+                        Place::Deref(expression) => register_expression(store, map, expression),
+                        Place::Field(place, _) => register_place(store, map, place),
+                        Place::Index(place, index) => {
+                            register_place(store, map, place)?;
+                            register_expression(store, map, index)
+                        }
                     }
-                    Place::Deref(expression) => register_expression(store, expression),
-                    Place::Field(place, _) => register_place(store, place),
                 }
-            }
 
-            fn register_expression(store: &crate::Store, expression: &Expression) {
-                match expression {
-                    Expression::IConst(..)
-                    | Expression::UConst(..)
-                    | Expression::FConst(..)
-                    | Expression::BConst(..) => (),
-                    Expression::Read(place) => register_place(store, place),
-                    Expression::Reference(place, _) => register_place(store, place),
-                    Expression::Call(function, args) => {
-                        register_expression(store, function);
-                        for arg in args {
-                            register_expression(store, arg);
+                fn register_expression(
+                    store: &crate::Store,
+                    map: &Map,
+                    expression: &Expression,
+                ) -> Result {
+                    match expression {
+                        Expression::IConst(..)
+                        | Expression::UConst(..)
+                        | Expression::FConst(..)
+                        | Expression::BConst(..) => Ok(()),
+                        Expression::Read(place) => register_place(store, map, place),
+                        Expression::Reference(place, _) => register_place(store, map, place),
+                        Expression::Call(function, args) => {
+                            register_expression(store, map, function)?;
+                            args.iter()
+                                .try_for_each(|arg| register_expression(store, map, arg))
                         }
-                    }
-                    Expression::Intrinsic(intrinsic) => {
-                        use crate::ir::Intrinsic;
-                        match intrinsic {
-                            Intrinsic::Add(a, b) | Intrinsic::Mul(a, b) | Intrinsic::Eq(a, b) => {
-                                register_expression(store, a);
-                                register_expression(store, b);
-                            }
-                            Intrinsic::Not(value) => {
-                                register_expression(store, value);
+                        Expression::Intrinsic(intrinsic) => {
+                            use crate::ir::Intrinsic;
+                            match intrinsic {
+                                Intrinsic::Add(a, b)
+                                | Intrinsic::Mul(a, b)
+                                | Intrinsic::Eq(a, b) => {
+                                    register_expression(store, map, a)?;
+                                    register_expression(store, map, b)
+                                }
+                                Intrinsic::Not(value) => register_expression(store, map, value),
                             }
                         }
                     }
                 }
-            }
 
-            for stmt in &body.statements {
-                match stmt {
-                    Statement::Comment(..) => (),
-                    Statement::Assign(place, expression) => {
-                        register_place(self, place);
-                        register_expression(self, expression);
-                    }
-                    Statement::Call(function, args) => {
-                        register_expression(self, function);
-                        for arg in args {
-                            register_expression(self, arg);
+                for stmt in &body.statements {
+                    match stmt {
+                        Statement::Comment(..)
+                        | Statement::Location(..)
+                        | Statement::LoopAttributes(..)
+                        | Statement::StorageLive(..)
+                        | Statement::StorageDead(..) => (),
+                        Statement::Assign(place, expression) => {
+                            register_place(self, &map, place)?;
+                            register_expression(self, &map, expression)?;
                         }
-                    }
-                    Statement::Return(retval) => {
-                        if let Some(expr) = retval {
-                            register_expression(self, expr);
+                        Statement::Call(function, args) => {
+                            register_expression(self, &map, function)?;
+                            for arg in args {
+                                register_expression(self, &map, arg)?;
+                            }
                         }
-                    }
-                    Statement::Acf(statement) => {
-                        use crate::ir::AcfStatement;
-                        match statement {
-                            AcfStatement::Jump(..) => (),
-                            AcfStatement::Cjump(expression, _) => {
-                                register_expression(self, expression)
+                        Statement::Return(retval) => {
+                            if let Some(expr) = retval {
+                                register_expression(self, &map, expr)?;
                             }
                         }
-                    }
-                    Statement::Bcf(statement) => {
-                        use crate::ir::BcfStatement;
-                        match statement {
-                            BcfStatement::Else
-                            | BcfStatement::End
-                            | BcfStatement::Loop
-                            | BcfStatement::Break
-                            | BcfStatement::Continue => (),
-                            BcfStatement::If(expression)
-                            | BcfStatement::Cbreak(expression)
-                            | BcfStatement::Ccontinue(expression) => {
-                                register_expression(self, expression)
+                        Statement::Acf(statement) => {
+                            use crate::ir::AcfStatement;
+                            match statement {
+                                AcfStatement::Jump(..) => (),
+                                AcfStatement::Cjump(expression, _) => {
+                                    register_expression(self, &map, expression)?
+                                }
+                            }
+                        }
+                        Statement::Bcf(statement) => {
+                            use crate::ir::BcfStatement;
+                            match statement {
+                                BcfStatement::Else
+                                | BcfStatement::End
+                                | BcfStatement::Loop
+                                | BcfStatement::Break
+                                | BcfStatement::Continue => (),
+                                BcfStatement::If(expression)
+                                | BcfStatement::Cbreak(expression)
+                                | BcfStatement::Ccontinue(expression) => {
+                                    register_expression(self, &map, expression)?
+                                }
                             }
                         }
                     }
                 }
-            }
-        });
+                Ok(())
+            });
+        // Functions without a body (f.e. foreign functions) don't use anything
+        registered.unwrap_or(Ok(()))
     }
 }
@@ -7,10 +7,14 @@ mod statements;
 
 impl super::Store {
     /// Declare all symbols from this IR in another [`orco::DeclarationBackend`]
-    pub fn declare(&self, backend: &impl orco::DeclarationBackend) {
+    pub fn declare(
+        &self,
+        backend: &(impl orco::DeclarationBackend + ?Sized),
+    ) -> Result<(), orco::Error> {
         for (name, specs) in self.types.pin().iter() {
             for (generics, ty) in specs.pin().iter() {
-                backend.type_(*name, generics.clone(), ty.clone());
+                let generics = generics.iter().map(|ty| ty.to_type()).collect();
+                backend.type_(*name, generics, ty.to_type())?;
             }
         }
 
@@ -21,29 +25,62 @@ impl super::Store {
                 decl.signature.params.clone(),
                 decl.signature.return_type.clone(),
                 decl.signature.attrs.clone(),
-            );
+            )?;
+        }
+
+        for (name, ty) in self.globals.pin().iter() {
+            backend.global(*name, ty.clone())?;
         }
+        Ok(())
     }
 
-    /// Codegen all functions in another [`orco::CodegenBackend`]
-    pub fn codegen(&self, backend: &impl orco::CodegenBackend) {
-        let decls = self.functions.pin();
-        for (name, specs) in self.function_bodies.pin().iter() {
-            let decl = decls
-                .get(name)
-                .unwrap_or_else(|| panic!("BUG: unable to find declaration while defining {name}"));
+    /// Codegen all functions (every specialization, as is) in another [`orco::CodegenBackend`]
+    pub fn codegen(
+        &self,
+        backend: &(impl orco::CodegenBackend + ?Sized),
+    ) -> Result<(), orco::Error> {
+        for (name, decl) in self.functions.pin().iter() {
             let args = (0..decl.signature.params.len())
                 .map(oc::Variable)
                 .collect::<Vec<_>>();
-            for (generics, body) in specs.pin().iter() {
+            for (generics, body) in decl.bodies.pin().iter() {
+                let generics = generics.iter().map(|ty| ty.to_type()).collect();
                 body.codegen(
-                    &mut backend.cg_function(*name, generics.clone()),
+                    &mut backend.cg_function(*name, generics)?,
                     &args,
                     crate::generics::TypeMap::new(),
                     oc::BodyCodegen::return_,
                 );
             }
         }
+        Ok(())
+    }
+
+    /// Codegen all function instances in another [`orco::CodegenBackend`],
+    /// see [`Self::monomorphize`] and [`Self::declare_mono`].
+    /// Instances without a body (f.e. foreign functions) are skipped
+    pub fn codegen_mono(
+        &self,
+        backend: &(impl orco::CodegenBackend + ?Sized),
+    ) -> Result<(), orco::Error> {
+        for (name, generics) in self.function_instances.pin().iter() {
+            let mut result = Ok(());
+            let found = self.get_function_body(*name, generics, |body, map| {
+                let args = (0..body.variables.iter().filter(|var| var.arg).count())
+                    .map(oc::Variable)
+                    .collect::<Vec<_>>();
+                result = backend
+                    .cg_function(*name, generics.clone())
+                    .map(|mut codegen| {
+                        body.codegen(&mut codegen, &args, map, oc::BodyCodegen::return_);
+                    });
+            });
+            match found {
+                Ok(()) | Err(orco::Error::NoSpecialization(..)) => result?,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
     }
 
     /// Inline-codegen one function into [`oc::BodyCodegen`]
@@ -53,12 +90,13 @@ impl super::Store {
         name: orco::Symbol,
         generics: &[orco::Type],
         args: Vec<oc::Value>,
-    ) -> Option<oc::Value> {
+    ) -> Result<Option<oc::Value>, orco::Error> {
         // TODO: IMPORTANT! Inline inner function calls and other dependencies on this backend
         let decls = self.functions.pin();
-        let decl = decls
-            .get(&name)
-            .unwrap_or_else(|| panic!("trying to inline an undeclared function {name}"));
+        let decl = decls.get(&name).ok_or(orco::Error::Undeclared(
+            orco::error::SymbolKind::Function,
+            name,
+        ))?;
         let signature = decl.instantiate(self, generics);
 
         let args = args
@@ -67,31 +105,34 @@ impl super::Store {
             .collect::<Vec<_>>();
         let retvar = signature.return_type.clone().map(|mut rt| {
             rt.instantiate(
-                &crate::generics::match_type_params(&decl.generic_params, generics, self)
-                    .unwrap_or_else(|| {
-                        panic!(
-                            "generics do not match for {name}{}",
-                            orco::types::fmt_generics(generics)
-                        )
-                    }),
+                &crate::generics::match_type_params(
+                    &crate::generics::intern(&decl.generic_params),
+                    &crate::generics::intern(generics),
+                    self,
+                )
+                .unwrap_or_else(|| {
+                    panic!(
+                        "generics do not match for {name}{}",
+                        orco::types::fmt_generics(generics)
+                    )
+                }),
             );
             codegen.declare_var(rt, Some("_retval"))
         });
 
-        use orco::codegen::AcfCodegen;
-        let return_label = codegen.acf().alloc_label();
+        let return_label = codegen.alloc_label();
 
         self.get_function_body(name, generics, |body, map| {
             body.codegen(codegen, &args, map, |cg, value| {
                 if let (Some(retval), Some(value)) = (retvar, value) {
                     cg.assign(retval.into(), value);
                 }
-                cg.acf().jump(return_label);
+                cg.jump(return_label);
             });
-        });
+        })?;
 
-        codegen.acf().label(return_label);
-        retvar.map(|rv| codegen.read(rv.into()))
+        codegen.label(return_label);
+        Ok(retvar.map(|rv| codegen.read(rv.into())))
     }
 }
 
@@ -135,16 +176,19 @@ impl ir::Body {
             }
         }
 
-        use oc::AcfCodegen as _;
-        let mut statement_idx_to_label = std::collections::HashMap::new();
+        // Several labels can point to the same statement
+        let mut statement_idx_to_labels = std::collections::HashMap::<_, Vec<_>>::new();
         for label in &self.labels {
-            let backend_label = *ctx.label_map.push_mut(ctx.cg.acf().alloc_label());
-            statement_idx_to_label.insert(label, backend_label);
+            let backend_label = *ctx.label_map.push_mut(ctx.cg.alloc_label());
+            statement_idx_to_labels
+                .entry(*label)
+                .or_default()
+                .push(backend_label);
         }
 
         for (idx, statement) in self.statements.iter().enumerate() {
-            if let Some(label) = statement_idx_to_label.get(&idx) {
-                ctx.cg.acf().label(*label);
+            for label in statement_idx_to_labels.remove(&idx).unwrap_or_default() {
+                ctx.cg.label(label);
             }
 
             if let ir::Statement::Return(expr) = statement {
@@ -155,5 +199,13 @@ impl ir::Body {
 
             ctx.stmt(statement);
         }
+
+        // Labels past the last statement
+        for label in statement_idx_to_labels
+            .remove(&self.statements.len())
+            .unwrap_or_default()
+        {
+            ctx.cg.label(label);
+        }
     }
 }
@@ -10,6 +10,10 @@ impl<CG: oc::BodyCodegen> super::FwdCtx<'_, CG> {
     pub fn stmt(&mut self, stmt: &ir::Statement) {
         match stmt {
             ir::Statement::Comment(comment) => self.cg.comment(comment),
+            ir::Statement::Location(location) => self.cg.set_location(location.clone()),
+            ir::Statement::LoopAttributes(attrs) => self.cg.loop_attributes(attrs.clone()),
+            ir::Statement::StorageLive(variable) => self.cg.storage_live(self.var(*variable)),
+            ir::Statement::StorageDead(variable) => self.cg.storage_dead(self.var(*variable)),
             ir::Statement::Assign(place, expr) => {
                 let place = self.place(place);
                 let expr = self.expr(expr);
@@ -34,16 +38,15 @@ impl<CG: oc::BodyCodegen> super::FwdCtx<'_, CG> {
 
     /// Codegen [`ir::AcfStatement`] into another [`oc::BodyCodegen`]
     fn acf(&mut self, stmt: &ir::AcfStatement) {
-        use oc::AcfCodegen as _;
         match stmt {
             ir::AcfStatement::Jump(label) => {
                 let label = self.label(*label);
-                self.cg.acf().jump(label)
+                self.cg.jump(label)
             }
             ir::AcfStatement::Cjump(expr, label) => {
                 let expr = self.expr(expr);
                 let label = self.label(*label);
-                self.cg.acf().cjump(expr, label)
+                self.cg.cjump(expr, label)
             }
         }
     }
@@ -51,24 +54,23 @@ impl<CG: oc::BodyCodegen> super::FwdCtx<'_, CG> {
     /// Codegen this statement into another [`oc::BodyCodegen`],
     /// mapping all variables and labels (ACF)
     fn bcf(&mut self, stmt: &ir::BcfStatement) {
-        use oc::BcfCodegen as _;
         match stmt {
             ir::BcfStatement::If(expr) => {
                 let expr = self.expr(expr);
-                self.cg.bcf().if_(expr)
+                self.cg.if_(expr)
             }
-            ir::BcfStatement::Else => self.cg.bcf().else_(),
-            ir::BcfStatement::End => self.cg.bcf().end(),
-            ir::BcfStatement::Loop => self.cg.bcf().loop_(),
-            ir::BcfStatement::Break => self.cg.bcf().break_(),
-            ir::BcfStatement::Continue => self.cg.bcf().continue_(),
+            ir::BcfStatement::Else => self.cg.else_(),
+            ir::BcfStatement::End => self.cg.end(),
+            ir::BcfStatement::Loop => self.cg.loop_(),
+            ir::BcfStatement::Break => self.cg.break_(),
+            ir::BcfStatement::Continue => self.cg.continue_(),
             ir::BcfStatement::Cbreak(expr) => {
                 let expr = self.expr(expr);
-                self.cg.bcf().cbreak(expr)
+                self.cg.cbreak(expr)
             }
             ir::BcfStatement::Ccontinue(expr) => {
                 let expr = self.expr(expr);
-                self.cg.bcf().ccontinue(expr)
+                self.cg.ccontinue(expr)
             }
         }
     }
@@ -1,40 +1,44 @@
 // FIXME: Horrible
 use crate::Store;
 use orco::Type;
+use orco::types::{IType, TypeKind};
 
-/// A map from a specialization (`Vec<Type>`, each type can hold named params) to the symbol
-pub type Specialized<T> = papaya::HashMap<Vec<Type>, T>;
+/// A map from a specialization (`Vec<IType>`, each type can hold named params) to the symbol
+pub type Specialized<T> = papaya::HashMap<Vec<IType>, T>;
 
 /// A type alias for a map from type parameter names to their types.
 /// See [`match_ty`]
-pub type TypeMap = std::collections::HashMap<orco::Symbol, Type>;
+pub type TypeMap = std::collections::HashMap<orco::sinter::IStr, IType>;
 
-/// Match generic argument type to parameter type, inferring [`Type::Param`] and
+/// Intern a list of types, f.e. generic args
+pub fn intern(types: &[Type]) -> Vec<IType> {
+    types.iter().map(IType::from).collect()
+}
+
+/// Match generic argument type to parameter type, inferring [`TypeKind::Param`] and
 /// writing it into `map`
-pub fn match_ty(param: &Type, arg: &Type, map: &mut TypeMap, store: &Store) -> Option<()> {
-    use Type::*;
-    let param = store.inline_type_aliases(param.clone());
-    let original_arg = arg.clone();
-    let arg = store.inline_type_aliases(arg.clone());
-    match (param, arg) {
-        (param @ (Integer(_) | Unsigned(_) | Float(_) | Bool | Char(_)), arg) if arg == param => {
-            Some(())
-        }
+pub fn match_ty(param: IType, arg: IType, map: &mut TypeMap, store: &Store) -> Option<()> {
+    use TypeKind::*;
+    let original_arg = arg;
+    let param = store.inline_itype(param);
+    let arg = store.inline_itype(arg);
+    match (param.kind(), arg.kind()) {
+        (Integer(_) | Unsigned(_) | Float(_) | Bool | Char(_), _) if arg == param => Some(()),
         (Symbol(..), _) => unreachable!(),
         (Array(ty, size), Array(arg_ty, arg_size)) if arg_size == size => {
-            match_ty(&ty, &arg_ty, map, store)
+            match_ty(*ty, *arg_ty, map, store)
         }
         (Struct { fields }, Struct { fields: arg_fields }) if arg_fields.len() == fields.len() => {
             for ((name, ty), (arg_name, arg_ty)) in fields.iter().zip(arg_fields.iter()) {
                 if name != arg_name {
                     return None;
                 }
-                match_ty(ty, arg_ty, map, store)?;
+                match_ty(*ty, *arg_ty, map, store)?;
             }
             Some(())
         }
         (Ptr(ty, mutability), Ptr(arg_ty, arg_mutability)) if arg_mutability == mutability => {
-            match_ty(&ty, &arg_ty, map, store)
+            match_ty(*ty, *arg_ty, map, store)
         }
         (
             FnPtr {
@@ -45,9 +49,19 @@ pub fn match_ty(param: &Type, arg: &Type, map: &mut TypeMap, store: &Store) -> O
                 params: arg_params,
                 return_type: arg_return_type,
             },
-        ) => todo!(),
-        (Param(name), arg) if !matches!(original_arg, Error) => {
-            map.insert(name, original_arg.clone());
+        ) if arg_params.len() == params.len()
+            && arg_return_type.is_some() == return_type.is_some() =>
+        {
+            for (ty, arg_ty) in params.iter().zip(arg_params.iter()) {
+                match_ty(*ty, *arg_ty, map, store)?;
+            }
+            match (return_type, arg_return_type) {
+                (Some(ty), Some(arg_ty)) => match_ty(*ty, *arg_ty, map, store),
+                _ => Some(()),
+            }
+        }
+        (Param(name), _) if !matches!(original_arg.kind(), Error) => {
+            map.insert(*name, original_arg);
             Some(())
         }
         _ => None,
@@ -56,14 +70,14 @@ pub fn match_ty(param: &Type, arg: &Type, map: &mut TypeMap, store: &Store) -> O
 
 /// Matches a generic to argumens and returns the match map.
 /// See [`match_ty`]
-pub fn match_type_params(params: &[Type], args: &[Type], store: &Store) -> Option<TypeMap> {
+pub fn match_type_params(params: &[IType], args: &[IType], store: &Store) -> Option<TypeMap> {
     if params.len() != args.len() {
         return None;
     }
 
     let mut map = TypeMap::new();
     for (param, arg) in params.iter().zip(args.iter()) {
-        match_ty(param, arg, &mut map, store)?
+        match_ty(*param, *arg, &mut map, store)?
     }
 
     Some(map)
@@ -74,7 +88,7 @@ pub fn match_type_params(params: &[Type], args: &[Type], store: &Store) -> Optio
 /// See also: [`match_ty`]
 pub fn match_specialization<T, R>(
     specs: &Specialized<T>,
-    args: &[Type],
+    args: &[IType],
     store: &Store,
     callback: impl FnOnce(&T, TypeMap) -> R,
 ) -> Option<R> {
@@ -96,17 +110,54 @@ pub fn match_specialization<T, R>(
     best.map(|(spec, map)| callback(spec, map))
 }
 
-impl crate::FunctionDecl {
+impl crate::Function {
     /// See [Type::instantiate]
     pub fn instantiate(
         &self,
         store: &crate::Store,
         generic_args: &[Type],
     ) -> orco::types::FunctionSignature {
-        let map = match_type_params(&self.generic_params, generic_args, store)
+        let map = match_type_params(&intern(&self.generic_params), &intern(generic_args), store)
             .expect("failed to instantiate function decl: generics did not match");
         let mut sig = self.signature.clone();
         sig.instantiate(&map);
         sig
     }
 }
+
+impl crate::ir::Body {
+    /// Substitute type parameters in variable types and generic arguments
+    /// of global symbols, see [Type::instantiate]
+    pub fn instantiate(&mut self, map: &TypeMap) {
+        use crate::ir::{Expression, Place, Statement};
+
+        fn place(target: &mut Place, map: &TypeMap) {
+            match target {
+                Place::Variable(..) | Place::Deref(..) => (),
+                Place::Global(_, generics) => {
+                    for ty in generics {
+                        ty.instantiate(map);
+                    }
+                }
+                Place::Field(inner, _) | Place::Index(inner, _) => place(inner, map),
+            }
+        }
+
+        if map.is_empty() {
+            return;
+        }
+        for variable in &mut self.variables {
+            variable.ty.instantiate(map);
+        }
+        for statement in &mut self.statements {
+            if let Statement::Assign(target, _) = statement {
+                place(target, map);
+            }
+            statement.visit_exprs_mut(&mut |expr| {
+                if let Expression::Read(target) | Expression::Reference(target, _) = expr {
+                    place(target, map);
+                }
+            });
+        }
+    }
+}
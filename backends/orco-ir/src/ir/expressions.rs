@@ -14,9 +14,117 @@ pub enum Place {
     Deref(Box<Expression>),
     /// Field access, using 0-based field index
     Field(Box<Place>, usize),
+    /// Array element access
+    Index(Box<Place>, Box<Expression>),
 }
 
 impl Place {
+    /// Variable this place is stored in. [None] if the place is behind a pointer
+    /// or is a global symbol
+    #[must_use]
+    pub fn root_variable(&self) -> Option<oc::Variable> {
+        match self {
+            Self::Variable(variable) => Some(*variable),
+            Self::Global(..) | Self::Deref(..) => None,
+            Self::Field(place, _) | Self::Index(place, _) => place.root_variable(),
+        }
+    }
+
+    /// Global symbol this place is stored in, see [`Self::root_variable`]
+    #[must_use]
+    pub fn root_global(&self) -> Option<orco::Symbol> {
+        match self {
+            Self::Global(name, _) => Some(*name),
+            Self::Variable(..) | Self::Deref(..) => None,
+            Self::Field(place, _) | Self::Index(place, _) => place.root_global(),
+        }
+    }
+
+    /// Mutable version of [`Self::root_variable`]
+    pub fn root_variable_mut(&mut self) -> Option<&mut oc::Variable> {
+        match self {
+            Self::Variable(variable) => Some(variable),
+            Self::Global(..) | Self::Deref(..) => None,
+            Self::Field(place, _) | Self::Index(place, _) => place.root_variable_mut(),
+        }
+    }
+
+    /// Calls `f` with every expression inside this place
+    /// (see [`Expression::visit`]), innermost first
+    pub fn visit_exprs(&self, f: &mut impl FnMut(&Expression)) {
+        match self {
+            Self::Variable(..) | Self::Global(..) => (),
+            Self::Deref(expr) => expr.visit(f),
+            Self::Field(place, _) => place.visit_exprs(f),
+            Self::Index(place, index) => {
+                place.visit_exprs(f);
+                index.visit(f);
+            }
+        }
+    }
+
+    /// Mutable version of [`Self::visit_exprs`]
+    pub fn visit_exprs_mut(&mut self, f: &mut impl FnMut(&mut Expression)) {
+        match self {
+            Self::Variable(..) | Self::Global(..) => (),
+            Self::Deref(expr) => expr.visit_mut(f),
+            Self::Field(place, _) => place.visit_exprs_mut(f),
+            Self::Index(place, index) => {
+                place.visit_exprs_mut(f);
+                index.visit_mut(f);
+            }
+        }
+    }
+
+    /// Pointer this place is behind, if any
+    #[must_use]
+    pub fn pointer(&self) -> Option<&Expression> {
+        match self {
+            Self::Variable(..) | Self::Global(..) => None,
+            Self::Deref(expr) => Some(expr),
+            Self::Field(place, _) | Self::Index(place, _) => place.pointer(),
+        }
+    }
+
+    /// Mutable version of [`Self::pointer`]
+    pub fn pointer_mut(&mut self) -> Option<&mut Expression> {
+        match self {
+            Self::Variable(..) | Self::Global(..) => None,
+            Self::Deref(expr) => Some(expr),
+            Self::Field(place, _) | Self::Index(place, _) => place.pointer_mut(),
+        }
+    }
+
+    /// Expressions evaluated to locate this place (the pointer it's behind
+    /// and array indices), in evaluation order
+    #[must_use]
+    pub fn operands(&self) -> Vec<&Expression> {
+        match self {
+            Self::Variable(..) | Self::Global(..) => Vec::new(),
+            Self::Deref(expr) => vec![expr],
+            Self::Field(place, _) => place.operands(),
+            Self::Index(place, index) => {
+                let mut operands = place.operands();
+                operands.push(index);
+                operands
+            }
+        }
+    }
+
+    /// Mutable version of [`Self::operands`]
+    pub fn operands_mut(&mut self) -> Vec<&mut Expression> {
+        match self {
+            Self::Variable(..) | Self::Global(..) => Vec::new(),
+            Self::Deref(expr) => vec![expr],
+            Self::Field(place, _) => place.operands_mut(),
+            Self::Index(place, index) => {
+                let mut operands = place.operands_mut();
+                operands.push(index);
+                operands
+            }
+        }
+    }
+
     /// Returns type and mutability
     pub fn get_type(&self, store: &crate::Store, body: &super::Body) -> (Type, bool) {
         match self {
@@ -24,16 +132,21 @@ impl Place {
                 let variable = body.get_variable(*variable);
                 (variable.ty.clone(), true)
             }
-            Self::Global(name, generics) => (
-                store
-                    .functions
-                    .pin()
-                    .get(name)
-                    .unwrap_or_else(|| panic!("undeclared symbol {name}"))
-                    .instantiate(store, generics)
-                    .ptr_type(),
-                false,
-            ),
+            Self::Global(name, generics) => {
+                if let Some(ty) = store.globals.pin().get(name) {
+                    return (ty.clone(), true);
+                }
+                (
+                    store
+                        .functions
+                        .pin()
+                        .get(name)
+                        .unwrap_or_else(|| panic!("undeclared symbol {name}"))
+                        .instantiate(store, generics)
+                        .ptr_type(),
+                    false,
+                )
+            }
             Self::Deref(expr) => match store.inline_type_aliases(expr.get_type(store, body)) {
                 Type::Ptr(ty, mutable) => (*ty, mutable),
                 ty => panic!("trying to dereference non-pointer type {ty}"),
@@ -48,6 +161,13 @@ impl Place {
                     mutable,
                 )
             }
+            Self::Index(place, _) => {
+                let (ty, mutable) = place.get_type(store, body);
+                match store.inline_type_aliases(ty) {
+                    Type::Array(ty, _) => (*ty, mutable),
+                    ty => panic!("trying to index non-array type {ty}"),
+                }
+            }
         }
     }
 }
@@ -59,13 +179,22 @@ impl std::fmt::Display for Place {
             Place::Global(name, generics) => {
                 write!(f, "{name}{}", orco::types::fmt_generics(generics))
             }
+            Place::Deref(expr) if expr.is_binary() => write!(f, "*({expr})"),
             Place::Deref(expr) => write!(f, "*{expr}"),
+            Place::Field(place, idx) if matches!(**place, Place::Deref(..)) => {
+                write!(f, "({place})._{idx}")
+            }
             Place::Field(place, idx) => write!(f, "{place}._{idx}"),
+            Place::Index(place, index) if matches!(**place, Place::Deref(..)) => {
+                write!(f, "({place})[{index}]")
+            }
+            Place::Index(place, index) => write!(f, "{place}[{index}]"),
         }
     }
 }
 
-/// Basic expressions
+/// Basic expressions. Expressions are evaluated when the statement
+/// they are in is executed, operands are evaluated left to right
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub enum Expression {
     /// See [`oc::BodyCodegen::iconst`]
@@ -88,6 +217,107 @@ pub enum Expression {
 }
 
 impl Expression {
+    /// Whether this expression is a constant, which can be
+    /// copied freely instead of being stored in a temporary
+    #[must_use]
+    pub fn is_const(&self) -> bool {
+        matches!(
+            self,
+            Self::IConst(..) | Self::UConst(..) | Self::FConst(..) | Self::BConst(..)
+        )
+    }
+
+    /// Whether this expression is a binary intrinsic (f.e. `a + b`),
+    /// such expressions are parenthesized when nested
+    #[must_use]
+    pub fn is_binary(&self) -> bool {
+        matches!(self, Self::Intrinsic(intrinsic) if intrinsic.operands().count() == 2)
+    }
+
+    /// Whether evaluating this expression calls a function
+    #[must_use]
+    pub fn has_calls(&self) -> bool {
+        match self {
+            Self::IConst(..) | Self::UConst(..) | Self::FConst(..) | Self::BConst(..) => false,
+            Self::Read(place) | Self::Reference(place, _) => {
+                place.operands().into_iter().any(Self::has_calls)
+            }
+            Self::Call(..) => true,
+            Self::Intrinsic(intrinsic) => intrinsic.operands().any(Self::has_calls),
+        }
+    }
+
+    /// Calls `f` with every subexpression (including pointers places are behind)
+    /// and then with this expression
+    pub fn visit(&self, f: &mut impl FnMut(&Expression)) {
+        match self {
+            Self::IConst(..) | Self::UConst(..) | Self::FConst(..) | Self::BConst(..) => (),
+            Self::Read(place) | Self::Reference(place, _) => place.visit_exprs(f),
+            Self::Call(func, args) => {
+                func.visit(f);
+                for arg in args {
+                    arg.visit(f);
+                }
+            }
+            Self::Intrinsic(intrinsic) => {
+                for operand in intrinsic.operands() {
+                    operand.visit(f);
+                }
+            }
+        }
+        f(self);
+    }
+
+    /// Mutable version of [`Self::visit`], `f` can replace expressions bottom-up
+    pub fn visit_mut(&mut self, f: &mut impl FnMut(&mut Expression)) {
+        match self {
+            Self::IConst(..) | Self::UConst(..) | Self::FConst(..) | Self::BConst(..) => (),
+            Self::Read(place) | Self::Reference(place, _) => place.visit_exprs_mut(f),
+            Self::Call(func, args) => {
+                func.visit_mut(f);
+                for arg in args {
+                    arg.visit_mut(f);
+                }
+            }
+            Self::Intrinsic(intrinsic) => {
+                for operand in intrinsic.operands_mut() {
+                    operand.visit_mut(f);
+                }
+            }
+        }
+        f(self);
+    }
+
+    /// Visit all places this expression reads from.
+    /// Places that are only referenced are not included
+    pub fn visit_reads(&self, f: &mut impl FnMut(&Place)) {
+        match self {
+            Self::IConst(..) | Self::UConst(..) | Self::FConst(..) | Self::BConst(..) => (),
+            Self::Read(place) => {
+                f(place);
+                for operand in place.operands() {
+                    operand.visit_reads(f);
+                }
+            }
+            Self::Reference(place, _) => {
+                for operand in place.operands() {
+                    operand.visit_reads(f);
+                }
+            }
+            Self::Call(func, args) => {
+                func.visit_reads(f);
+                for arg in args {
+                    arg.visit_reads(f);
+                }
+            }
+            Self::Intrinsic(intrinsic) => {
+                for operand in intrinsic.operands() {
+                    operand.visit_reads(f);
+                }
+            }
+        }
+    }
+
     /// Get type of the value this statement produces
     pub fn get_type(&self, store: &crate::Store, body: &super::Body) -> Type {
         match self {
@@ -122,19 +352,32 @@ impl std::fmt::Display for Expression {
             Self::Reference(place, mutable) => {
                 write!(f, "&{} {place}", if *mutable { "mut" } else { "const" })?
             }
-            Self::Call(func, args) => {
-                write!(f, "{func}(")?;
-                for (idx, arg) in args.iter().enumerate() {
-                    if idx > 0 {
-                        write!(f, ", ")?;
-                    }
-                    write!(f, "{arg}")?;
-                }
-                write!(f, ")")?;
-            }
+            Self::Call(func, args) => fmt_call(f, func, args)?,
 
             Self::Intrinsic(intrinsic) => write!(f, "{intrinsic}")?,
         }
         Ok(())
     }
 }
+
+/// Display a call, see [`Expression::Call`] and [`super::Statement::Call`]
+pub(super) fn fmt_call(
+    f: &mut std::fmt::Formatter<'_>,
+    func: &Expression,
+    args: &[Expression],
+) -> std::fmt::Result {
+    // Calls bind tighter than prefix operators
+    match func {
+        Expression::Read(Place::Deref(..))
+        | Expression::Reference(..)
+        | Expression::Intrinsic(..) => write!(f, "({func})(")?,
+        _ => write!(f, "{func}(")?,
+    }
+    for (idx, arg) in args.iter().enumerate() {
+        if idx > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{arg}")?;
+    }
+    write!(f, ")")
+}
@@ -0,0 +1,78 @@
+use super::{AcfStatement, BcfStatement, Body, Statement};
+
+impl Body {
+    /// Indices of statements control can go to after each statement.
+    /// `statements.len()` stands for leaving the function
+    #[must_use]
+    pub fn successors(&self) -> Vec<Vec<usize>> {
+        let exit = self.statements.len();
+
+        // Match BCF blocks: `ends[i]` is the `else` or `end` closing statement `i`,
+        // `loops[i]` is the innermost loop statement `i` is in
+        let mut ends = vec![None; exit];
+        let mut loops = vec![None; exit];
+        let mut stack = Vec::new();
+        let mut loop_stack = Vec::new();
+        for (idx, statement) in self.statements.iter().enumerate() {
+            loops[idx] = loop_stack.last().copied();
+            match statement {
+                Statement::Bcf(BcfStatement::If(..)) => stack.push(idx),
+                Statement::Bcf(BcfStatement::Loop) => {
+                    stack.push(idx);
+                    loop_stack.push(idx);
+                }
+                Statement::Bcf(BcfStatement::Else) => {
+                    let block = stack.pop().expect("else without an if");
+                    ends[block] = Some(idx);
+                    stack.push(idx);
+                }
+                Statement::Bcf(BcfStatement::End) => {
+                    let block = stack.pop().expect("end without a block");
+                    ends[block] = Some(idx);
+                    if let Statement::Bcf(BcfStatement::Loop) = self.statements[block] {
+                        loop_stack.pop();
+                    }
+                }
+                _ => (),
+            }
+        }
+        assert!(stack.is_empty(), "{} unclosed block(s)", stack.len());
+
+        let end_of = |block: usize| ends[block].expect("unclosed block");
+        let loop_of = |idx: usize| loops[idx].expect("break or continue outside of a loop");
+        let label = |label: orco::codegen::Label| self.labels[label.0];
+
+        // Ends of loops get their successors from the `loop` statement
+        let mut successors = vec![Vec::new(); exit];
+        for (idx, statement) in self.statements.iter().enumerate() {
+            successors[idx] = match statement {
+                Statement::Return(..) => vec![exit],
+                Statement::Acf(AcfStatement::Jump(target)) => vec![label(*target)],
+                Statement::Acf(AcfStatement::Cjump(_, target)) => vec![idx + 1, label(*target)],
+                Statement::Bcf(BcfStatement::If(..)) => {
+                    let end = end_of(idx);
+                    match self.statements[end] {
+                        Statement::Bcf(BcfStatement::Else) => vec![idx + 1, end + 1],
+                        _ => vec![idx + 1, end],
+                    }
+                }
+                Statement::Bcf(BcfStatement::Else) => vec![end_of(idx)],
+                Statement::Bcf(BcfStatement::Loop) => {
+                    successors[end_of(idx)] = vec![idx + 1];
+                    vec![idx + 1]
+                }
+                Statement::Bcf(BcfStatement::End) if !successors[idx].is_empty() => {
+                    std::mem::take(&mut successors[idx])
+                }
+                Statement::Bcf(BcfStatement::Break) => vec![end_of(loop_of(idx)) + 1],
+                Statement::Bcf(BcfStatement::Continue) => vec![loop_of(idx) + 1],
+                Statement::Bcf(BcfStatement::Cbreak(..)) => {
+                    vec![idx + 1, end_of(loop_of(idx)) + 1]
+                }
+                Statement::Bcf(BcfStatement::Ccontinue(..)) => vec![idx + 1, loop_of(idx) + 1],
+                _ => vec![idx + 1],
+            };
+        }
+        successors
+    }
+}
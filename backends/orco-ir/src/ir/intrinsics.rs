@@ -21,13 +21,31 @@ impl Intrinsic {
         true
     }
 
+    /// Operands of this intrinsic, in evaluation order
+    pub fn operands(&self) -> impl Iterator<Item = &Expression> {
+        let (a, b) = match self {
+            Self::Add(a, b) | Self::Mul(a, b) | Self::Eq(a, b) => (a, Some(b)),
+            Self::Not(a) => (a, None),
+        };
+        std::iter::once(a.as_ref()).chain(b.map(AsRef::as_ref))
+    }
+
+    /// Mutable version of [`Self::operands`]
+    pub fn operands_mut(&mut self) -> impl Iterator<Item = &mut Expression> {
+        let (a, b) = match self {
+            Self::Add(a, b) | Self::Mul(a, b) | Self::Eq(a, b) => (a, Some(b)),
+            Self::Not(a) => (a, None),
+        };
+        std::iter::once(a.as_mut()).chain(b.map(AsMut::as_mut))
+    }
+
     /// Get type of the value this intrinsic produces.
     /// Similar to [`super::Statement::get_type`]
     pub fn get_type(&self, store: &crate::Store, body: &super::Body) -> orco::Type {
         match self {
             Self::Add(a, _) => a.get_type(store, body),
             Self::Mul(a, _) => a.get_type(store, body),
-            Self::Eq(a, _) => a.get_type(store, body),
+            Self::Eq(..) => orco::Type::Bool,
             Self::Not(a) => a.get_type(store, body),
         }
     }
@@ -35,11 +53,16 @@ impl Intrinsic {
 
 impl std::fmt::Display for Intrinsic {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Nested binary operations are always parenthesized
+        let operand = |expr: &Expression| match expr.is_binary() {
+            true => format!("({expr})"),
+            false => expr.to_string(),
+        };
         match self {
-            Intrinsic::Add(a, b) => write!(f, "{a} + {b}"),
-            Intrinsic::Mul(a, b) => write!(f, "{a} * {b}"),
-            Intrinsic::Eq(a, b) => write!(f, "{a} == {b}"),
-            Intrinsic::Not(a) => write!(f, "!{a}"),
+            Intrinsic::Add(a, b) => write!(f, "{} + {}", operand(a), operand(b)),
+            Intrinsic::Mul(a, b) => write!(f, "{} * {}", operand(a), operand(b)),
+            Intrinsic::Eq(a, b) => write!(f, "{} == {}", operand(a), operand(b)),
+            Intrinsic::Not(a) => write!(f, "!{}", operand(a)),
         }
     }
 }
@@ -7,6 +7,9 @@ pub use statements::{AcfStatement, BcfStatement, Statement};
 mod intrinsics;
 pub use intrinsics::Intrinsic;
 
+mod flow;
+mod storage;
+
 /// Info about one variable in a body
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Variable {
@@ -57,14 +60,14 @@ impl std::fmt::Display for Body {
             writeln!(f)?;
         }
 
-        let mut statement_idx_to_label = std::collections::HashMap::new();
+        let mut statement_idx_to_labels = std::collections::HashMap::<_, Vec<_>>::new();
         for (idx, label) in self.labels.iter().enumerate() {
-            statement_idx_to_label.insert(label, idx);
+            statement_idx_to_labels.entry(*label).or_default().push(idx);
         }
 
         let mut indent = 1;
         for (idx, statement) in self.statements.iter().enumerate() {
-            if let Some(label) = statement_idx_to_label.get(&idx) {
+            for label in statement_idx_to_labels.remove(&idx).unwrap_or_default() {
                 writeln!(f, "label{label}:")?;
             }
 
@@ -89,6 +92,16 @@ impl std::fmt::Display for Body {
                 indent += 1
             }
         }
+
+        // Labels at the end of the body and ones that point nowhere
+        let mut rest = statement_idx_to_labels
+            .into_values()
+            .flatten()
+            .collect::<Vec<_>>();
+        rest.sort_unstable();
+        for label in rest {
+            writeln!(f, "label{label}:")?;
+        }
         write!(f, "}}")
     }
 }
@@ -6,6 +6,15 @@ use orco::codegen as oc;
 pub enum Statement {
     /// See [`oc::BodyCodegen::comment`]
     Comment(String),
+    /// See [`oc::BodyCodegen::set_location`]
+    Location(Option<orco::attrs::SourceLocation>),
+    /// See [`oc::BodyCodegen::loop_attributes`]. Applies to the loop
+    /// whose header block this statement is in
+    LoopAttributes(orco::attrs::LoopAttributes),
+    /// See [`oc::BodyCodegen::storage_live`]
+    StorageLive(oc::Variable),
+    /// See [`oc::BodyCodegen::storage_dead`]
+    StorageDead(oc::Variable),
     /// See [`oc::BodyCodegen::assign`]
     Assign(Place, Expression),
     /// See [`oc::BodyCodegen::call`].
@@ -20,6 +29,123 @@ pub enum Statement {
     Bcf(BcfStatement),
 }
 
+impl Statement {
+    /// Visit all places this statement reads from, see [`Expression::visit_reads`].
+    /// The assignment target is not included, but its operands (see [`Place::operands`]) are
+    pub fn visit_reads(&self, f: &mut impl FnMut(&Place)) {
+        match self {
+            Self::Comment(..)
+            | Self::Location(..)
+            | Self::LoopAttributes(..)
+            | Self::StorageLive(..)
+            | Self::StorageDead(..)
+            | Self::Return(None)
+            | Self::Acf(AcfStatement::Jump(..))
+            | Self::Bcf(
+                BcfStatement::Else
+                | BcfStatement::End
+                | BcfStatement::Loop
+                | BcfStatement::Break
+                | BcfStatement::Continue,
+            ) => (),
+            Self::Assign(target, value) => {
+                for operand in target.operands() {
+                    operand.visit_reads(f);
+                }
+                value.visit_reads(f);
+            }
+            Self::Call(func, args) => {
+                func.visit_reads(f);
+                for arg in args {
+                    arg.visit_reads(f);
+                }
+            }
+            Self::Return(Some(value))
+            | Self::Acf(AcfStatement::Cjump(value, _))
+            | Self::Bcf(
+                BcfStatement::If(value)
+                | BcfStatement::Cbreak(value)
+                | BcfStatement::Ccontinue(value),
+            ) => value.visit_reads(f),
+        }
+    }
+
+    /// Calls [`Expression::visit`] on every expression in this statement,
+    /// including operands of the assignment target
+    pub fn visit_exprs(&self, f: &mut impl FnMut(&Expression)) {
+        match self {
+            Self::Comment(..)
+            | Self::Location(..)
+            | Self::LoopAttributes(..)
+            | Self::StorageLive(..)
+            | Self::StorageDead(..)
+            | Self::Return(None)
+            | Self::Acf(AcfStatement::Jump(..))
+            | Self::Bcf(
+                BcfStatement::Else
+                | BcfStatement::End
+                | BcfStatement::Loop
+                | BcfStatement::Break
+                | BcfStatement::Continue,
+            ) => (),
+            Self::Assign(target, value) => {
+                target.visit_exprs(f);
+                value.visit(f);
+            }
+            Self::Call(func, args) => {
+                func.visit(f);
+                for arg in args {
+                    arg.visit(f);
+                }
+            }
+            Self::Return(Some(value))
+            | Self::Acf(AcfStatement::Cjump(value, _))
+            | Self::Bcf(
+                BcfStatement::If(value)
+                | BcfStatement::Cbreak(value)
+                | BcfStatement::Ccontinue(value),
+            ) => value.visit(f),
+        }
+    }
+
+    /// Mutable version of [`Self::visit_exprs`]
+    pub fn visit_exprs_mut(&mut self, f: &mut impl FnMut(&mut Expression)) {
+        match self {
+            Self::Comment(..)
+            | Self::Location(..)
+            | Self::LoopAttributes(..)
+            | Self::StorageLive(..)
+            | Self::StorageDead(..)
+            | Self::Return(None)
+            | Self::Acf(AcfStatement::Jump(..))
+            | Self::Bcf(
+                BcfStatement::Else
+                | BcfStatement::End
+                | BcfStatement::Loop
+                | BcfStatement::Break
+                | BcfStatement::Continue,
+            ) => (),
+            Self::Assign(target, value) => {
+                target.visit_exprs_mut(f);
+                value.visit_mut(f);
+            }
+            Self::Call(func, args) => {
+                func.visit_mut(f);
+                for arg in args {
+                    arg.visit_mut(f);
+                }
+            }
+            Self::Return(Some(value))
+            | Self::Acf(AcfStatement::Cjump(value, _))
+            | Self::Bcf(
+                BcfStatement::If(value)
+                | BcfStatement::Cbreak(value)
+                | BcfStatement::Ccontinue(value),
+            ) => value.visit_mut(f),
+        }
+    }
+}
+
 impl std::fmt::Display for Statement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -31,16 +157,17 @@ impl std::fmt::Display for Statement {
                     write!(f, "// {line}")?;
                 }
             }
+            Self::Location(Some(location)) => write!(f, "location {location};")?,
+            Self::Location(None) => write!(f, "location unknown;")?,
+            Self::LoopAttributes(attrs) => {
+                write!(f, "loop_attributes {};", attrs.to_string().trim_end())?;
+            }
+            Self::StorageLive(variable) => write!(f, "storage_live _{};", variable.0)?,
+            Self::StorageDead(variable) => write!(f, "storage_dead _{};", variable.0)?,
             Self::Assign(target, value) => write!(f, "{target} = {value};")?,
             Self::Call(func, args) => {
-                write!(f, "{func}(")?;
-                for (idx, arg) in args.iter().enumerate() {
-                    if idx > 0 {
-                        write!(f, ", ")?;
-                    }
-                    write!(f, "{arg}")?;
-                }
-                write!(f, ")")?;
+                super::expressions::fmt_call(f, func, args)?;
+                write!(f, ";")?;
             }
             Self::Return(value) => {
                 write!(f, "return")?;
@@ -0,0 +1,131 @@
+use super::{AcfStatement, BcfStatement, Body, Expression, Place, Statement};
+use orco::codegen as oc;
+use std::collections::HashSet;
+
+/// Calls `f` with every variable a place uses (a variable it's stored in,
+/// a pointer it's behind or an index)
+fn place_variables(place: &Place, f: &mut impl FnMut(oc::Variable)) {
+    match place {
+        Place::Variable(variable) => f(*variable),
+        Place::Global(..) => (),
+        Place::Deref(expr) => expr_variables(expr, f),
+        Place::Field(place, _) => place_variables(place, f),
+        Place::Index(place, index) => {
+            place_variables(place, f);
+            expr_variables(index, f);
+        }
+    }
+}
+
+/// Calls `f` with every variable an expression reads or references
+fn expr_variables(expr: &Expression, f: &mut impl FnMut(oc::Variable)) {
+    match expr {
+        Expression::Read(place) | Expression::Reference(place, _) => place_variables(place, f),
+        Expression::Call(func, args) => {
+            expr_variables(func, f);
+            for arg in args {
+                expr_variables(arg, f);
+            }
+        }
+        Expression::Intrinsic(intrinsic) => {
+            for operand in intrinsic.operands() {
+                expr_variables(operand, f);
+            }
+        }
+        _ => (),
+    }
+}
+
+impl Statement {
+    /// Calls `f` with every variable this statement reads, writes or references
+    pub fn visit_variables(&self, mut f: impl FnMut(oc::Variable)) {
+        match self {
+            Self::Comment(..)
+            | Self::Location(..)
+            | Self::LoopAttributes(..)
+            | Self::StorageLive(..)
+            | Self::StorageDead(..) => (),
+            Self::Assign(target, value) => {
+                place_variables(target, &mut f);
+                expr_variables(value, &mut f);
+            }
+            Self::Call(func, args) => {
+                expr_variables(func, &mut f);
+                for arg in args {
+                    expr_variables(arg, &mut f);
+                }
+            }
+            Self::Return(value) => {
+                if let Some(value) = value {
+                    expr_variables(value, &mut f);
+                }
+            }
+            Self::Acf(AcfStatement::Cjump(condition, _))
+            | Self::Bcf(
+                BcfStatement::If(condition)
+                | BcfStatement::Cbreak(condition)
+                | BcfStatement::Ccontinue(condition),
+            ) => expr_variables(condition, &mut f),
+            Self::Acf(..) | Self::Bcf(..) => (),
+        }
+    }
+}
+
+impl Body {
+    /// Finds uses of variables after [`Statement::StorageDead`] (on every path to the use,
+    /// without [`Statement::StorageLive`] in between).
+    /// Returns statement indices and variables
+    #[must_use]
+    pub fn dead_uses(&self) -> Vec<(usize, oc::Variable)> {
+        let successors = self.successors();
+
+        // Variables that are dead on every path to the statement,
+        // [None] if the statement wasn't reached yet
+        let mut dead: Vec<Option<HashSet<usize>>> = vec![None; self.statements.len()];
+        let mut worklist = Vec::new();
+        if !self.statements.is_empty() {
+            dead[0] = Some(HashSet::new());
+            worklist.push(0);
+        }
+
+        while let Some(idx) = worklist.pop() {
+            let mut state = dead[idx].clone().unwrap();
+            match &self.statements[idx] {
+                Statement::StorageLive(variable) => {
+                    state.remove(&variable.0);
+                }
+                Statement::StorageDead(variable) => {
+                    state.insert(variable.0);
+                }
+                _ => (),
+            }
+
+            for &succ in &successors[idx] {
+                let Some(target) = dead.get_mut(succ) else {
+                    continue;
+                };
+                let new = match target {
+                    Some(target) => target.intersection(&state).copied().collect(),
+                    None => state.clone(),
+                };
+                if target.as_ref() != Some(&new) {
+                    *target = Some(new);
+                    worklist.push(succ);
+                }
+            }
+        }
+
+        let mut uses = Vec::new();
+        for (idx, statement) in self.statements.iter().enumerate() {
+            let Some(dead) = &dead[idx] else {
+                continue;
+            };
+            statement.visit_variables(|variable| {
+                if dead.contains(&variable.0) {
+                    uses.push((idx, variable));
+                }
+            });
+        }
+        uses
+    }
+}
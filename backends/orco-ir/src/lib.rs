@@ -6,21 +6,45 @@
 /// Intermediate representation for code
 pub mod ir;
 
-// /// Code generation impl
-// pub mod codegen;
+/// Code generation impl
+pub mod codegen;
 
 /// Utilities to work with generics and specializations
 pub mod generics;
 
-// /// IR forwarding - invoking another backend
-// /// to generate code from the IR
-// mod forwarding;
+/// Compact, versioned binary encoding of a [Store]
+pub mod binary;
+
+/// Parser for the textual IR, which is what [Store]'s
+/// [Display](std::fmt::Display) impl produces
+pub mod parser;
+
+/// Well-formedness checks for a whole [Store], see [`Store::verify`]
+pub mod verify;
+
+/// Control flow graph, dominators, natural loops and liveness of function bodies,
+/// see [`analysis::Cfg`]
+pub mod analysis;
+
+/// Scalar optimizations on function bodies, see [`ir::Body::simplify`]
+pub mod opt;
+
+/// Named passes and pipelines, see [`passes::PassManager`]
+pub mod passes;
+
+/// Polymorphic lowering with type descriptor passing
+pub mod poly;
+
+/// IR forwarding - invoking another backend
+/// to generate code from the IR
+mod forwarding;
 
 use generics::Specialized;
+use orco::error::SymbolKind;
 use papaya::{HashMap, HashSet};
 
 /// Function declaration, see [`Store::functions`]
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Clone, Debug)]
 pub struct Function {
     #[allow(missing_docs)]
     pub generic_params: Vec<orco::Type>,
@@ -34,9 +58,11 @@ pub struct Function {
 #[derive(Clone, Debug, Default)]
 pub struct Store {
     /// Type aliases
-    pub types: HashMap<orco::Symbol, Specialized<orco::Type>>,
+    pub types: HashMap<orco::Symbol, Specialized<orco::types::IType>>,
     /// Function declarations
     pub functions: HashMap<orco::Symbol, Function>,
+    /// Global variables
+    pub globals: HashMap<orco::Symbol, orco::Type>,
 
     /// List of generic params to monomorphize types
     type_instances: HashSet<(orco::Symbol, Vec<orco::Type>)>,
@@ -53,23 +79,21 @@ impl Store {
 
     /// If `ty` is a type alias, will be replaced by what is aliased.
     /// Inner aliases (f.e. struct field types) are not replaced!
-    pub fn inline_type_aliases(&self, mut ty: orco::Type) -> orco::Type {
+    pub fn inline_type_aliases(&self, ty: orco::Type) -> orco::Type {
+        self.inline_itype((&ty).into()).to_type()
+    }
+
+    /// Same as [`Self::inline_type_aliases`], but works on interned types
+    pub fn inline_itype(&self, mut ty: orco::types::IType) -> orco::types::IType {
         let types = self.types.pin();
-        while let orco::Type::Symbol(name, generics) = ty {
+        while let orco::types::TypeKind::Symbol(name, generics) = ty.kind() {
             let specs = types
-                .get(&name)
+                .get(name)
                 .unwrap_or_else(|| panic!("undeclared type {name}"));
-            ty = generics::match_specialization(specs, &generics, self, |ty, map| {
-                let mut ty = ty.clone();
-                ty.instantiate(&map);
-                ty
+            ty = generics::match_specialization(specs, generics, self, |ty, map| {
+                ty.instantiate(&map)
             })
-            .unwrap_or_else(move || {
-                panic!(
-                    "no matching specialization for type {}",
-                    orco::Type::Symbol(name, generics)
-                )
-            });
+            .unwrap_or_else(|| panic!("no matching specialization for type {ty}"));
         }
         ty
     }
@@ -79,18 +103,15 @@ impl Store {
         &self,
         name: orco::Symbol,
         generics: &[orco::Type],
-        callback: impl FnOnce(&orco::Type, generics::TypeMap),
-    ) {
+        callback: impl FnOnce(orco::types::IType, generics::TypeMap),
+    ) -> Result<(), orco::Error> {
         let types = self.types.pin();
         let specs = types
             .get(&name)
-            .unwrap_or_else(|| panic!("undeclared type {name}"));
-        generics::match_specialization(&specs, generics, self, callback).unwrap_or_else(|| {
-            panic!(
-                "no matching specialization for {name}{}",
-                orco::types::fmt_generics(generics)
-            )
-        })
+            .ok_or(orco::Error::Undeclared(SymbolKind::Type, name))?;
+        let args = generics::intern(generics);
+        generics::match_specialization(specs, &args, self, |ty, map| callback(*ty, map))
+            .ok_or_else(|| orco::Error::NoSpecialization(SymbolKind::Type, name, generics.to_vec()))
     }
 
     /// Find a best-matching function body for a set of generics
@@ -99,16 +120,14 @@ impl Store {
         name: orco::Symbol,
         generics: &[orco::Type],
         callback: impl FnOnce(&ir::Body, generics::TypeMap),
-    ) {
-        let bodies = self.function_bodies.pin();
-        let specs = bodies
+    ) -> Result<(), orco::Error> {
+        let functions = self.functions.pin();
+        let function = functions
             .get(&name)
-            .unwrap_or_else(|| panic!("undeclared function {name}"));
-        generics::match_specialization(&specs, generics, self, callback).unwrap_or_else(|| {
-            panic!(
-                "no matching specialization for {name}{}",
-                orco::types::fmt_generics(generics)
-            )
+            .ok_or(orco::Error::Undeclared(SymbolKind::Function, name))?;
+        let args = generics::intern(generics);
+        generics::match_specialization(&function.bodies, &args, self, callback).ok_or_else(|| {
+            orco::Error::NoSpecialization(SymbolKind::Function, name, generics.to_vec())
         })
     }
 }
@@ -121,45 +140,88 @@ impl orco::DeclarationBackend for Store {
         params: Vec<(Option<String>, orco::Type)>,
         return_type: Option<orco::Type>,
         attrs: orco::attrs::FunctionAttributes,
-    ) {
+    ) -> Result<(), orco::Error> {
+        let already_declared =
+            || orco::Error::AlreadyDeclared(SymbolKind::Function, name, Vec::new());
+        if self.globals.pin().contains_key(&name) {
+            return Err(already_declared());
+        }
         self.functions
             .pin()
             .try_insert(
                 name,
-                FunctionDecl {
+                Function {
                     generic_params,
                     signature: orco::types::FunctionSignature {
                         params,
                         return_type,
                         attrs,
                     },
+                    bodies: Specialized::new(),
                 },
             )
-            .unwrap_or_else(|_| panic!("function {name} is already declared"));
+            .map_err(|_| already_declared())?;
+        Ok(())
     }
 
-    fn type_(&self, name: orco::Symbol, generic_params: Vec<orco::Type>, ty: orco::Type) {
+    fn type_(
+        &self,
+        name: orco::Symbol,
+        generic_params: Vec<orco::Type>,
+        ty: orco::Type,
+    ) -> Result<(), orco::Error> {
         self.types
             .pin()
             .get_or_insert_with(name, Default::default)
             .pin()
-            .try_insert(generic_params, ty)
-            .unwrap_or_else(|_| panic!("type {name} is already declared"));
+            .try_insert(generics::intern(&generic_params), (&ty).into())
+            .map_err(|_| orco::Error::AlreadyDeclared(SymbolKind::Type, name, generic_params))?;
+        Ok(())
+    }
+    fn global(&self, name: orco::Symbol, ty: orco::Type) -> Result<(), orco::Error> {
+        let already_declared =
+            || orco::Error::AlreadyDeclared(SymbolKind::Global, name, Vec::new());
+        if self.functions.pin().contains_key(&name) {
+            return Err(already_declared());
+        }
+        self.globals
+            .pin()
+            .try_insert(name, ty)
+            .map_err(|_| already_declared())?;
+        Ok(())
     }
 }
 
+/// Items are sorted, so the output is stable
 impl std::fmt::Display for Store {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for (name, specs) in self.types.pin().iter() {
-            for (spec, ty) in specs.pin().iter() {
+        let types = self.types.pin();
+        let mut types = types.iter().collect::<Vec<_>>();
+        types.sort_unstable_by_key(|(name, _)| **name);
+        for (name, specs) in types {
+            let specs = specs.pin();
+            let mut specs = specs.iter().collect::<Vec<_>>();
+            specs.sort_unstable();
+            for (spec, ty) in specs {
                 writeln!(f, "type {name}{} = {ty};", orco::types::fmt_generics(spec))?;
             }
         }
 
         writeln!(f)?;
 
-        let bodies = self.function_bodies.pin();
-        for (name, decl) in self.functions.pin().iter() {
+        let globals = self.globals.pin();
+        let mut globals = globals.iter().collect::<Vec<_>>();
+        globals.sort_unstable();
+        for (name, ty) in globals {
+            writeln!(f, "static {name}: {ty};")?;
+        }
+
+        writeln!(f)?;
+
+        let functions = self.functions.pin();
+        let mut functions = functions.iter().collect::<Vec<_>>();
+        functions.sort_unstable_by_key(|(name, _)| **name);
+        for (name, decl) in functions {
             writeln!(
                 f,
                 "{}fn {name}{}{};",
@@ -168,12 +230,11 @@ impl std::fmt::Display for Store {
                 decl.signature,
             )?;
 
-            let Some(defs) = bodies.get(name) else {
-                continue;
-            };
-
-            for (spec, body) in defs.pin().iter() {
-                writeln!(f, "for {} {body}", orco::types::fmt_generics(spec)).unwrap();
+            let bodies = decl.bodies.pin();
+            let mut bodies = bodies.iter().collect::<Vec<_>>();
+            bodies.sort_unstable_by_key(|(spec, _)| *spec);
+            for (spec, body) in bodies {
+                writeln!(f, "for {} {body}", orco::types::fmt_generics(spec))?;
             }
 
             writeln!(f)?;
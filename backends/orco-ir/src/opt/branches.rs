@@ -0,0 +1,127 @@
+use crate::ir::{self, AcfStatement, BcfStatement, Expression, Statement};
+
+impl ir::Body {
+    /// Resolve branches on constant conditions: conditional jumps, breaks and continues
+    /// become unconditional or disappear, `if`s with a constant condition are replaced
+    /// by the branch that is taken. `if`s are left alone if a jump targets their insides.
+    /// Returns whether anything changed
+    pub fn eliminate_constant_branches(&mut self) -> bool {
+        let mut changed = false;
+        let mut remove = vec![false; self.statements.len()];
+        for (idx, statement) in self.statements.iter_mut().enumerate() {
+            let (condition, unconditional) = match statement {
+                Statement::Acf(AcfStatement::Cjump(Expression::BConst(condition), label)) => {
+                    (*condition, Statement::Acf(AcfStatement::Jump(*label)))
+                }
+                Statement::Bcf(BcfStatement::Cbreak(Expression::BConst(condition))) => {
+                    (*condition, Statement::Bcf(BcfStatement::Break))
+                }
+                Statement::Bcf(BcfStatement::Ccontinue(Expression::BConst(condition))) => {
+                    (*condition, Statement::Bcf(BcfStatement::Continue))
+                }
+                _ => continue,
+            };
+            if condition {
+                *statement = unconditional;
+            } else {
+                remove[idx] = true;
+            }
+            changed = true;
+        }
+
+        let used = self.used_labels();
+        let targets = self
+            .labels
+            .iter()
+            .enumerate()
+            .filter(|(label, _)| used.contains(label))
+            .map(|(_, idx)| *idx)
+            .collect::<Vec<_>>();
+        let ends = self.block_ends();
+        for (idx, statement) in self.statements.iter().enumerate() {
+            let Statement::Bcf(BcfStatement::If(Expression::BConst(condition))) = statement else {
+                continue;
+            };
+            let middle = ends[idx].expect("unclosed if");
+            let end = match self.statements[middle] {
+                Statement::Bcf(BcfStatement::Else) => ends[middle].expect("unclosed else"),
+                _ => middle,
+            };
+            if targets.iter().any(|&target| target > idx && target <= end) {
+                continue;
+            }
+
+            // Remove the markers and the branch that is not taken
+            remove[idx] = true;
+            remove[end] = true;
+            if *condition {
+                remove[middle..end].fill(true);
+            } else {
+                remove[idx..=middle].fill(true);
+            }
+            changed = true;
+        }
+
+        self.retain_statements(|idx, _| !remove[idx]);
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::opt::check;
+
+    /// An `if` with a jump into it is kept
+    #[test]
+    fn constant_branches() {
+        check(
+            &["const-branches"],
+            "fn f(a: i32) -> i32;
+for  {
+  let _0: i32 = <argument>;
+  if true {
+    _0 = _0 + 1 as i32;
+  } else {
+    _0 = 0 as i32;
+  }
+  if false {
+    _0 = 2 as i32;
+  }
+  loop {
+    break if false;
+    continue if false;
+    break if true;
+  }
+  jump label0 if _0 == 0 as i32;
+  if false {
+label0:
+    _0 = 3 as i32;
+  }
+  jump label1 if false;
+  jump label1 if true;
+  _0 = 5 as i32;
+label1:
+  return _0;
+}
+",
+            "fn f(a: i32) -> i32;
+for  {
+  let _0: i32 = <argument>;
+  _0 = _0 + 1 as i32;
+  loop {
+    break;
+  }
+  jump label0 if _0 == 0 as i32;
+  if false {
+label0:
+    _0 = 3 as i32;
+  }
+  jump label1;
+  _0 = 5 as i32;
+label1:
+  return _0;
+}
+",
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use crate::ir::{self, BcfStatement, Expression, Place, Statement};
+use std::collections::{HashMap, HashSet};
+
+/// Replace reads of variables with known values. Returns whether anything changed
+fn substitute(expr: &mut Expression, known: &HashMap<usize, Expression>) -> bool {
+    let mut changed = false;
+    expr.visit_mut(&mut |expr| {
+        let Expression::Read(place) = expr else {
+            return;
+        };
+        let Some(variable) = place.root_variable() else {
+            return;
+        };
+        match known.get(&variable.0) {
+            // Copies can replace the variable in any place rooted at it
+            Some(Expression::Read(Place::Variable(source))) => {
+                *place.root_variable_mut().unwrap() = *source;
+                changed = true;
+            }
+            Some(value) if matches!(place, Place::Variable(..)) => {
+                *expr = value.clone();
+                changed = true;
+            }
+            _ => (),
+        }
+    });
+    changed
+}
+
+impl ir::Body {
+    /// Forward copies (`_1 = _0;`) and constants (`_1 = 3 as i32;`) into reads
+    /// of the assigned variable in the following straight-line code,
+    /// up to the next label or block boundary. Variables which had their address taken
+    /// are left alone. Returns whether anything changed
+    pub fn propagate_copies(&mut self) -> bool {
+        let escaped = self.escaped_variables();
+        let labels = self.labels.iter().copied().collect::<HashSet<_>>();
+        let mut known = HashMap::<usize, Expression>::new();
+        let mut changed = false;
+        for (idx, statement) in self.statements.iter_mut().enumerate() {
+            // Control can come from elsewhere
+            if labels.contains(&idx)
+                || matches!(
+                    statement,
+                    Statement::Bcf(BcfStatement::Else | BcfStatement::End | BcfStatement::Loop)
+                )
+            {
+                known.clear();
+            }
+
+            if !known.is_empty() {
+                changed |= match statement {
+                    Statement::Assign(target, value) => {
+                        let mut changed = false;
+                        if let Some(pointer) = target.pointer_mut() {
+                            changed |= substitute(pointer, &known);
+                        }
+                        changed | substitute(value, &known)
+                    }
+                    Statement::Call(func, args) => {
+                        let mut changed = substitute(func, &known);
+                        for arg in args {
+                            changed |= substitute(arg, &known);
+                        }
+                        changed
+                    }
+                    Statement::Return(Some(value))
+                    | Statement::Acf(ir::AcfStatement::Cjump(value, _))
+                    | Statement::Bcf(
+                        BcfStatement::If(value)
+                        | BcfStatement::Cbreak(value)
+                        | BcfStatement::Ccontinue(value),
+                    ) => substitute(value, &known),
+                    _ => false,
+                };
+            }
+
+            let written = match statement {
+                Statement::Assign(target, _) if target.pointer().is_none() => {
+                    target.root_variable()
+                }
+                Statement::StorageLive(variable) | Statement::StorageDead(variable) => {
+                    Some(*variable)
+                }
+                _ => None,
+            };
+            if let Some(written) = written {
+                known.retain(|variable, value| {
+                    *variable != written.0
+                        && !matches!(value, Expression::Read(Place::Variable(source)) if *source == written)
+                });
+            }
+
+            if let Statement::Assign(Place::Variable(target), value) = statement
+                && !escaped.contains(&target.0)
+            {
+                match value {
+                    Expression::Read(Place::Variable(source))
+                        if source != target && !escaped.contains(&source.0) =>
+                    {
+                        known.insert(target.0, value.clone());
+                    }
+                    value if value.is_const() => {
+                        known.insert(target.0, value.clone());
+                    }
+                    _ => (),
+                }
+            }
+
+            if let Statement::Bcf(BcfStatement::If(..)) = statement {
+                known.clear();
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::opt::check;
+
+    /// Writes to the source, escaped variables and labels stop propagation
+    #[test]
+    fn propagate_copies() {
+        check(
+            &["copy-prop"],
+            "fn f(a: i32, b: i32) -> i32;
+for  {
+  let _0: i32 = <argument>;
+  let _1: i32 = <argument>;
+  let _2: i32;
+  let _3: i32;
+  let _4: i32;
+  let _5: *mut i32;
+  _2 = _0;
+  _3 = 4 as i32;
+  _4 = 5 as i32;
+  _5 = &mut _4;
+  _1 = _2 + _3 + _4;
+  _0 = 1 as i32;
+  _1 = _1 + _2;
+label0:
+  return _1 + _3;
+}
+",
+            "fn f(a: i32, b: i32) -> i32;
+for  {
+  let _0: i32 = <argument>;
+  let _1: i32 = <argument>;
+  let _2: i32;
+  let _3: i32;
+  let _4: i32;
+  let _5: *mut i32;
+  _2 = _0;
+  _3 = 4 as i32;
+  _4 = 5 as i32;
+  _5 = &mut _4;
+  _1 = (_0 + 4 as i32) + _4;
+  _0 = 1 as i32;
+  _1 = _1 + _2;
+label0:
+  return _1 + _3;
+}
+",
+        );
+    }
+}
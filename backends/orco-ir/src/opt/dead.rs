@@ -0,0 +1,118 @@
+use crate::analysis::Liveness;
+use crate::ir::{self, Expression, Statement};
+use orco::codegen as oc;
+use std::collections::HashSet;
+
+impl ir::Body {
+    /// Remove assignments of a place to itself and assignments to variables
+    /// whose value is never read afterwards (unless the value calls something),
+    /// then variables that are never used (apart from storage markers), renumbering the rest.
+    /// Arguments and variables which had their address taken are kept.
+    /// Returns whether anything changed
+    pub fn eliminate_dead_code(&mut self) -> bool {
+        let escaped = self.escaped_variables();
+        let cfg = self.cfg();
+        let live_out = Liveness::new(self, &cfg).statement_live_out(self, &cfg);
+        let changed = self.retain_statements(|idx, statement| match statement {
+            // Assignment to itself
+            Statement::Assign(target, Expression::Read(source))
+                if target == source && !source.pointer().is_some_and(Expression::has_calls) =>
+            {
+                false
+            }
+            Statement::Assign(target, value) => match target.root_variable() {
+                Some(variable) if target.pointer().is_none() => {
+                    escaped.contains(&variable.0)
+                        || live_out[idx].contains(&variable.0)
+                        || value.has_calls()
+                }
+                _ => true,
+            },
+            _ => true,
+        });
+
+        // Variables which are used for anything, but storage markers
+        let mut used = HashSet::new();
+        for statement in &self.statements {
+            statement.visit_variables(|variable| {
+                used.insert(variable.0);
+            });
+        }
+        used.extend(&escaped);
+        used.extend(
+            self.variables
+                .iter()
+                .enumerate()
+                .filter(|(_, variable)| variable.arg)
+                .map(|(idx, _)| idx),
+        );
+        if used.len() == self.variables.len() {
+            return changed;
+        }
+
+        self.retain_statements(|_, statement| match statement {
+            Statement::StorageLive(variable) | Statement::StorageDead(variable) => {
+                used.contains(&variable.0)
+            }
+            _ => true,
+        });
+        let mut new_idx = vec![None; self.variables.len()];
+        let variables = std::mem::take(&mut self.variables);
+        for (idx, variable) in variables.into_iter().enumerate() {
+            if used.contains(&idx) {
+                new_idx[idx] = Some(oc::Variable(self.variables.len()));
+                self.variables.push(variable);
+            }
+        }
+        self.rename_variables(|variable| {
+            new_idx[variable.0].expect("[bug?] used variable was removed")
+        });
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::opt::check;
+
+    /// Stores with calls and escaped variables are kept
+    #[test]
+    fn eliminate_dead_code() {
+        check(
+            &["dce"],
+            "fn g() -> i32;
+
+fn f(a: i32) -> i32;
+for  {
+  let _0: i32 = <argument>;
+  let _1: i32;
+  let _2: i32;
+  let _3: i32;
+  let _4: i32;
+  let _5: *mut i32;
+  storage_live _3;
+  _1 = _0 + 1 as i32;
+  _2 = g();
+  _0 = _0;
+  _5 = &mut _4;
+  _1 = _0 * 2 as i32;
+  storage_dead _3;
+  return _1;
+}
+",
+            "fn f(a: i32) -> i32;
+for  {
+  let _0: i32 = <argument>;
+  let _1: i32;
+  let _2: i32;
+  let _3: i32;
+  _2 = g();
+  _1 = _0 * 2 as i32;
+  return _1;
+}
+
+fn g() -> i32;
+",
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use crate::ir::{self, Expression, Intrinsic};
+use orco::types::IntegerSize;
+
+/// Wrap a signed integer to `size`. [None] if the size is not known
+/// and the value doesn't fit into 32 bits, which every target has
+fn wrap_signed(value: i128, size: IntegerSize) -> Option<i128> {
+    match size {
+        IntegerSize::Bits(bits @ 1..128) => {
+            let shift = 128 - u32::from(bits);
+            Some((value << shift) >> shift)
+        }
+        IntegerSize::Bits(_) => Some(value),
+        IntegerSize::Size => i32::try_from(value).ok().map(i128::from),
+    }
+}
+
+/// Same as [`wrap_signed`], but for unsigned integers
+fn wrap_unsigned(value: u128, size: IntegerSize) -> Option<u128> {
+    match size {
+        IntegerSize::Bits(bits @ 1..128) => Some(value & ((1 << bits) - 1)),
+        IntegerSize::Bits(_) => Some(value),
+        IntegerSize::Size => u32::try_from(value).ok().map(u128::from),
+    }
+}
+
+/// Evaluate a float operation with the precision of the type. [None] for unsupported sizes
+fn float_op(a: f64, b: f64, size: u16, op: fn(f64, f64) -> f64) -> Option<f64> {
+    match size {
+        64 => Some(op(a, b)),
+        // f64 is precise enough for rounding the result to f32 to give the correct f32 result
+        32 => Some(op(a, b) as f32 as f64),
+        _ => None,
+    }
+}
+
+fn is_zero(expr: &Expression) -> bool {
+    matches!(expr, Expression::IConst(0, _) | Expression::UConst(0, _))
+}
+
+fn is_one(expr: &Expression) -> bool {
+    matches!(expr, Expression::IConst(1, _) | Expression::UConst(1, _))
+}
+
+/// Fold one expression, assuming its operands are already folded
+fn fold(expr: &Expression) -> Option<Expression> {
+    use Expression::{BConst, FConst, IConst, UConst};
+    let Expression::Intrinsic(intrinsic) = expr else {
+        return None;
+    };
+    Some(match intrinsic {
+        Intrinsic::Add(a, b) => match (&**a, &**b) {
+            (IConst(a, size), IConst(b, b_size)) if size == b_size => {
+                IConst(wrap_signed(a.checked_add(*b)?, *size)?, *size)
+            }
+            (UConst(a, size), UConst(b, b_size)) if size == b_size => {
+                UConst(wrap_unsigned(a.wrapping_add(*b), *size)?, *size)
+            }
+            (FConst(a, size), FConst(b, b_size)) if size == b_size => {
+                FConst(float_op(*a, *b, *size, |a, b| a + b)?, *size)
+            }
+            (a, b) if is_zero(b) => a.clone(),
+            (a, b) if is_zero(a) => b.clone(),
+            _ => return None,
+        },
+        Intrinsic::Mul(a, b) => match (&**a, &**b) {
+            (IConst(a, size), IConst(b, b_size)) if size == b_size => {
+                IConst(wrap_signed(a.checked_mul(*b)?, *size)?, *size)
+            }
+            (UConst(a, size), UConst(b, b_size)) if size == b_size => {
+                UConst(wrap_unsigned(a.checked_mul(*b)?, *size)?, *size)
+            }
+            (FConst(a, size), FConst(b, b_size)) if size == b_size => {
+                FConst(float_op(*a, *b, *size, |a, b| a * b)?, *size)
+            }
+            (a, b) if is_one(b) => a.clone(),
+            (a, b) if is_one(a) => b.clone(),
+            // The other operand still has to be evaluated if it calls something
+            (a, b) if is_zero(b) && !a.has_calls() => b.clone(),
+            (a, b) if is_zero(a) && !b.has_calls() => a.clone(),
+            _ => return None,
+        },
+        Intrinsic::Eq(a, b) => match (&**a, &**b) {
+            (IConst(a, size), IConst(b, b_size)) if size == b_size => BConst(a == b),
+            (UConst(a, size), UConst(b, b_size)) if size == b_size => BConst(a == b),
+            (FConst(a, size), FConst(b, b_size)) if size == b_size => BConst(a == b),
+            (BConst(a), BConst(b)) => BConst(a == b),
+            _ => return None,
+        },
+        Intrinsic::Not(a) => match &**a {
+            BConst(a) => BConst(!a),
+            _ => return None,
+        },
+    })
+}
+
+/// Fold an expression and all of its subexpressions, see [`ir::Body::fold_constants`].
+/// Returns whether anything changed
+pub(super) fn fold_expression(expr: &mut Expression) -> bool {
+    let mut changed = false;
+    expr.visit_mut(&mut |expr| {
+        if let Some(folded) = fold(expr) {
+            *expr = folded;
+            changed = true;
+        }
+    });
+    changed
+}
+
+impl ir::Body {
+    /// Evaluate intrinsics with constant operands and simplify trivial arithmetic
+    /// (`x + 0`, `x * 1`, `x * 0`). Integers wrap around, but operations on integers
+    /// of platform-dependent size are only folded if the result fits into 32 bits.
+    /// Returns whether anything changed
+    pub fn fold_constants(&mut self) -> bool {
+        let mut changed = false;
+        for statement in &mut self.statements {
+            statement.visit_exprs_mut(&mut |expr| {
+                if let Some(folded) = fold(expr) {
+                    *expr = folded;
+                    changed = true;
+                }
+            });
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::opt::check;
+
+    /// Wrapping arithmetic, trivial operands and operands that call something
+    #[test]
+    fn fold_constants() {
+        check(
+            &["fold"],
+            "fn g() -> i32;
+
+fn f(a: i32, b: u8) -> bool;
+for  {
+  let _0: i32 = <argument>;
+  let _1: u8 = <argument>;
+  let _2: i32;
+  _2 = 2 as i32 * 3 as i32 + _0 * 1 as i32;
+  _1 = 250 as u8 + 10 as u8;
+  _2 = _0 * 0 as i32 + _2;
+  _2 = g() * 0 as i32;
+  _2 = 2147483647 as i32 + 1 as i32;
+  return !(_2 == 6 as i32 + 0 as i32);
+}
+",
+            "fn f(a: i32, b: u8) -> bool;
+for  {
+  let _0: i32 = <argument>;
+  let _1: u8 = <argument>;
+  let _2: i32;
+  _2 = 6 as i32 + _0;
+  _1 = 4 as u8;
+  _2 = _2;
+  _2 = g() * 0 as i32;
+  _2 = -2147483648 as i32;
+  return !(_2 == 6 as i32);
+}
+
+fn g() -> i32;
+",
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use crate::analysis::{Cfg, Dominators, Loops};
+use crate::ir::{self, Expression, Place, Statement};
+use std::collections::HashMap;
+
+/// How a variable is written in a body
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Writes {
+    /// Never (only arguments can be read without being written)
+    None,
+    /// Once, as a whole, by the statement with this index
+    Once(usize),
+    /// More than once, partially or through storage markers
+    Many,
+}
+
+/// Whether statement `a` dominates statement `b`
+fn dominates(cfg: &Cfg, dominators: &Dominators, a: usize, b: usize) -> bool {
+    let (block_a, block_b) = (cfg.statement_blocks[a], cfg.statement_blocks[b]);
+    if block_a == block_b {
+        a <= b
+    } else {
+        dominators.dominates(block_a, block_b)
+    }
+}
+
+impl ir::Body {
+    /// How each variable is written
+    fn writes(&self) -> Vec<Writes> {
+        let mut writes = vec![Writes::None; self.variables.len()];
+        for (idx, statement) in self.statements.iter().enumerate() {
+            let (variable, whole) = match statement {
+                Statement::Assign(target, _) => match target.root_variable() {
+                    Some(variable) => (variable, matches!(target, Place::Variable(..))),
+                    None => continue,
+                },
+                Statement::StorageLive(variable) | Statement::StorageDead(variable) => {
+                    (*variable, false)
+                }
+                _ => continue,
+            };
+            writes[variable.0] = match writes[variable.0] {
+                Writes::None if whole => Writes::Once(idx),
+                _ => Writes::Many,
+            };
+        }
+        for (idx, variable) in self.variables.iter().enumerate() {
+            if variable.arg && writes[idx] != Writes::None {
+                writes[idx] = Writes::Many;
+            }
+        }
+        writes
+    }
+
+    /// Forward values of variables that are assigned once into the uses that assignment
+    /// dominates, as long as the value is the same there: it can only read constants,
+    /// arguments that are never written and other variables that are assigned once before.
+    /// Values other than constants and plain variable reads are only forwarded
+    /// into a single use in the same loop. Variables which had their address taken
+    /// are left alone. Returns whether anything changed
+    pub fn forward_single_assignments(&mut self) -> bool {
+        let escaped = self.escaped_variables();
+        let writes = self.writes();
+        let cfg = self.cfg();
+        let dominators = cfg.dominators();
+        let loops = Loops::new(&cfg, &dominators);
+
+        let mut uses = vec![0; self.variables.len()];
+        for statement in &self.statements {
+            statement.visit_exprs(&mut |expr| {
+                if let Expression::Read(place) = expr
+                    && let Some(variable) = place.root_variable()
+                {
+                    uses[variable.0] += 1;
+                }
+            });
+        }
+
+        // Values to forward and the statements assigning them
+        let mut forward = HashMap::new();
+        for variable in 0..self.variables.len() {
+            let Writes::Once(def) = writes[variable] else {
+                continue;
+            };
+            let Statement::Assign(_, value) = &self.statements[def] else {
+                unreachable!();
+            };
+            if escaped.contains(&variable) {
+                continue;
+            }
+
+            let mut stable = !value.has_calls();
+            value.visit(&mut |expr| {
+                let (Expression::Read(place) | Expression::Reference(place, _)) = expr else {
+                    return;
+                };
+                // Memory can change at any time
+                stable &= match place.root_variable() {
+                    Some(operand) => {
+                        operand.0 != variable
+                            && !escaped.contains(&operand.0)
+                            && match writes[operand.0] {
+                                Writes::None => self.variables[operand.0].arg,
+                                Writes::Once(operand_def) => {
+                                    operand_def != def
+                                        && dominates(&cfg, &dominators, operand_def, def)
+                                }
+                                Writes::Many => false,
+                            }
+                    }
+                    None => false,
+                };
+            });
+            let cheap = matches!(value, Expression::Read(Place::Variable(..))) || value.is_const();
+            if stable && (cheap || uses[variable] == 1) {
+                forward.insert(variable, (def, value.clone(), cheap));
+            }
+        }
+        if forward.is_empty() {
+            return false;
+        }
+
+        let mut changed = false;
+        for (idx, statement) in self.statements.iter_mut().enumerate() {
+            statement.visit_exprs_mut(&mut |expr| {
+                let Expression::Read(place) = expr else {
+                    return;
+                };
+                let Some(variable) = place.root_variable() else {
+                    return;
+                };
+                let Some((def, value, cheap)) = forward.get(&variable.0) else {
+                    return;
+                };
+                if *def == idx || !dominates(&cfg, &dominators, *def, idx) {
+                    return;
+                }
+                let same_loop = loops.innermost(cfg.statement_blocks[*def])
+                    == loops.innermost(cfg.statement_blocks[idx]);
+                match value {
+                    Expression::Read(Place::Variable(source)) => {
+                        *place.root_variable_mut().unwrap() = *source;
+                    }
+                    value if matches!(place, Place::Variable(..)) && (*cheap || same_loop) => {
+                        *expr = value.clone();
+                    }
+                    _ => return,
+                }
+                changed = true;
+            });
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::opt::check;
+
+    /// Copies are forwarded anywhere, other values only into a single use in the same loop
+    #[test]
+    fn forward_single_assignments() {
+        check(
+            &["forward"],
+            "fn g() -> i32;
+
+fn f(a: i32) -> i32;
+for  {
+  let _0: i32 = <argument>;
+  let _1: i32;
+  let _2: i32;
+  let _3: i32;
+  let _4: i32;
+  let _5: i32;
+  let _6: i32;
+  _1 = _0 + 1 as i32;
+  _2 = g();
+  _3 = _0 * 2 as i32;
+  _4 = _0 * 3 as i32;
+  _6 = _0;
+  _5 = 0 as i32;
+  loop {
+    _5 = _5 + _4 + _6;
+    break if _5 == 100 as i32;
+  }
+  return _1 + _2 + _3 + _3;
+}
+",
+            "fn f(a: i32) -> i32;
+for  {
+  let _0: i32 = <argument>;
+  let _1: i32;
+  let _2: i32;
+  let _3: i32;
+  let _4: i32;
+  let _5: i32;
+  let _6: i32;
+  _1 = _0 + 1 as i32;
+  _2 = g();
+  _3 = _0 * 2 as i32;
+  _4 = _0 * 3 as i32;
+  _6 = _0;
+  _5 = 0 as i32;
+  loop {
+    _5 = (_5 + _4) + _0;
+    break if _5 == 100 as i32;
+  }
+  return (((_0 + 1 as i32) + _2) + _3) + _3;
+}
+
+fn g() -> i32;
+",
+        );
+    }
+}
@@ -0,0 +1,480 @@
+use crate::ir::{self, AcfStatement, BcfStatement, Expression, Place, Statement};
+use orco::attrs::Inlining;
+use orco::codegen as oc;
+
+/// Size limits for [`crate::Store::inline_functions`].
+/// Sizes are counted in statements, not counting comments and locations
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InlineLimits {
+    /// Largest [`Inlining::Auto`] function to inline
+    pub auto: usize,
+    /// Largest [`Inlining::Hint`] function to inline
+    pub hint: usize,
+    /// How deep inlined calls can nest
+    pub depth: usize,
+}
+
+impl InlineLimits {
+    /// Only inline [`Inlining::Always`] functions
+    pub const ALWAYS: Self = Self {
+        auto: 0,
+        hint: 0,
+        depth: 16,
+    };
+
+    /// Only inline functions that are no larger than the call they replace
+    /// and [`Inlining::Always`] functions, for optimizing for size
+    pub const SIZE: Self = Self {
+        auto: 1,
+        hint: 1,
+        depth: 16,
+    };
+}
+
+impl Default for InlineLimits {
+    fn default() -> Self {
+        Self {
+            auto: 16,
+            hint: 64,
+            depth: 16,
+        }
+    }
+}
+
+/// Body ready to be inlined and its return type, see [`crate::Store::inlinable`]
+type Inlinable = (ir::Body, Option<orco::Type>);
+
+/// State of a [`crate::Store::inline_functions`] pass
+struct Inliner {
+    limits: InlineLimits,
+    /// Functions being inlined into, the one being optimized first.
+    /// These are never inlined, so recursion stops
+    stack: Vec<orco::Symbol>,
+    /// Inlinable bodies by callee, so each is prepared once per pass.
+    /// A body prepared deeper in the `stack` might have fewer calls inlined,
+    /// which is still correct wherever it is reused
+    cache: std::collections::HashMap<(orco::Symbol, Vec<orco::Type>), Option<Inlinable>>,
+}
+
+/// What happens to the value a call returns, see [`direct_call`]
+enum CallResult {
+    /// `f(...);`
+    Discard,
+    /// `place = f(...);`
+    Assign(Place),
+    /// `return f(...);`
+    Return,
+}
+
+/// Function a statement calls directly (through [`Place::Global`]) as a whole
+/// and its arguments
+fn direct_call(statement: &Statement) -> Option<(orco::Symbol, &[orco::Type], &[Expression])> {
+    let (func, args) = match statement {
+        Statement::Call(func, args) => (func, args),
+        Statement::Assign(target, Expression::Call(func, args))
+            if !target.operands().into_iter().any(Expression::has_calls) =>
+        {
+            (&**func, args)
+        }
+        Statement::Return(Some(Expression::Call(func, args))) => (&**func, args),
+        _ => return None,
+    };
+    match func {
+        Expression::Read(Place::Global(name, generics)) => Some((*name, generics, args)),
+        _ => None,
+    }
+}
+
+/// Operands of a statement in evaluation order. Operands of a direct call
+/// (see [`direct_call`]) are listed instead of the call itself
+fn statement_operands(statement: &mut Statement) -> Vec<&mut Expression> {
+    fn call_operands(value: &mut Expression) -> Vec<&mut Expression> {
+        match value {
+            Expression::Call(..) => expression_operands(value),
+            value => vec![value],
+        }
+    }
+    match statement {
+        Statement::Assign(target, value) => {
+            let mut operands = target.operands_mut();
+            operands.extend(call_operands(value));
+            operands
+        }
+        Statement::Call(func, args) => std::iter::once(func).chain(args).collect(),
+        Statement::Return(Some(value)) => call_operands(value),
+        Statement::Acf(AcfStatement::Cjump(condition, _))
+        | Statement::Bcf(
+            BcfStatement::If(condition)
+            | BcfStatement::Cbreak(condition)
+            | BcfStatement::Ccontinue(condition),
+        ) => vec![condition],
+        _ => Vec::new(),
+    }
+}
+
+/// Operands of an expression in evaluation order
+fn expression_operands(expr: &mut Expression) -> Vec<&mut Expression> {
+    match expr {
+        Expression::IConst(..)
+        | Expression::UConst(..)
+        | Expression::FConst(..)
+        | Expression::BConst(..) => Vec::new(),
+        Expression::Read(place) | Expression::Reference(place, _) => place.operands_mut(),
+        Expression::Call(func, args) => std::iter::once(&mut **func).chain(args).collect(),
+        Expression::Intrinsic(intrinsic) => intrinsic.operands_mut().collect(),
+    }
+}
+
+impl ir::Body {
+    /// Size of the body, as [`InlineLimits`] counts it
+    fn inline_size(&self) -> usize {
+        self.statements
+            .iter()
+            .filter(|statement| {
+                !matches!(
+                    statement,
+                    Statement::Comment(..)
+                        | Statement::Location(..)
+                        | Statement::LoopAttributes(..)
+                )
+            })
+            .count()
+    }
+
+    /// Replace the call at `idx` (see [`direct_call`]) with `callee`,
+    /// which returns a value of type `return_type`. Returns how many statements
+    /// the call was replaced with
+    fn splice_call(
+        &mut self,
+        idx: usize,
+        callee: ir::Body,
+        return_type: Option<orco::Type>,
+    ) -> usize {
+        let (args, result) =
+            match std::mem::replace(&mut self.statements[idx], Statement::Comment(String::new())) {
+                Statement::Call(_, args) => (args, CallResult::Discard),
+                Statement::Assign(target, Expression::Call(_, args)) => {
+                    (args, CallResult::Assign(target))
+                }
+                Statement::Return(Some(Expression::Call(_, args))) => (args, CallResult::Return),
+                _ => unreachable!("[bug?] not a direct call"),
+            };
+        // The value still has to be evaluated if it calls something
+        let result = match (result, return_type) {
+            (CallResult::Discard, Some(ty)) => {
+                self.variables.push(ir::Variable {
+                    ty,
+                    arg: false,
+                    name: Some("_retval".to_owned()),
+                });
+                CallResult::Assign(Place::Variable(oc::Variable(self.variables.len() - 1)))
+            }
+            (result, _) => result,
+        };
+
+        // Arguments become ordinary variables
+        let mut callee = callee;
+        let offset = self.variables.len();
+        callee.rename_variables(|variable| oc::Variable(variable.0 + offset));
+        let mut statements = Vec::with_capacity(callee.statements.len() + args.len());
+        for (arg, value) in args.into_iter().enumerate() {
+            statements.push(Statement::Assign(
+                Place::Variable(oc::Variable(offset + arg)),
+                value,
+            ));
+        }
+        self.variables
+            .extend(callee.variables.into_iter().map(|variable| ir::Variable {
+                arg: false,
+                ..variable
+            }));
+
+        let label_offset = self.labels.len();
+        let return_label = oc::Label(label_offset + callee.labels.len());
+        let mut return_jumps = false;
+        let mut new_idx = Vec::with_capacity(callee.statements.len() + 1);
+        let last = callee.statements.len().saturating_sub(1);
+        for (callee_idx, statement) in callee.statements.into_iter().enumerate() {
+            new_idx.push(statements.len());
+            match statement {
+                Statement::Return(value) if !matches!(result, CallResult::Return) => {
+                    if let (CallResult::Assign(target), Some(value)) = (&result, value) {
+                        statements.push(Statement::Assign(target.clone(), value));
+                    }
+                    if callee_idx != last {
+                        statements.push(Statement::Acf(AcfStatement::Jump(return_label)));
+                        return_jumps = true;
+                    }
+                }
+                Statement::Acf(AcfStatement::Jump(label)) => statements.push(Statement::Acf(
+                    AcfStatement::Jump(oc::Label(label.0 + label_offset)),
+                )),
+                Statement::Acf(AcfStatement::Cjump(condition, label)) => {
+                    statements.push(Statement::Acf(AcfStatement::Cjump(
+                        condition,
+                        oc::Label(label.0 + label_offset),
+                    )));
+                }
+                statement => statements.push(statement),
+            }
+        }
+        new_idx.push(statements.len());
+
+        let count = statements.len();
+        for label in &mut self.labels {
+            if *label > idx {
+                *label = *label + count - 1;
+            }
+        }
+        self.labels.extend(
+            callee
+                .labels
+                .iter()
+                .map(|label| idx + new_idx[(*label).min(new_idx.len() - 1)]),
+        );
+        if return_jumps {
+            self.labels.push(idx + count);
+        }
+        self.statements.splice(idx..=idx, statements);
+        count
+    }
+}
+
+impl crate::Store {
+    /// Size limit for inlining `name<generics>` into the last function
+    /// on the `stack` and its return type, without preparing the body.
+    /// [None] if it is not to be inlined
+    fn inline_limit(
+        &self,
+        name: orco::Symbol,
+        generics: &[orco::Type],
+        inliner: &Inliner,
+    ) -> Option<(usize, Option<orco::Type>)> {
+        // Recursive calls are never inlined
+        if inliner.stack.contains(&name) || inliner.stack.len() > inliner.limits.depth {
+            return None;
+        }
+        let functions = self.functions.pin();
+        let function = functions.get(&name)?;
+        // Monomorphization might pick another specialization later
+        if generics.iter().any(orco::Type::has_params) && function.bodies.len() > 1 {
+            return None;
+        }
+        let limit = match function.signature.attrs.inlining {
+            Inlining::Never => return None,
+            Inlining::Auto => inliner.limits.auto,
+            Inlining::Hint => inliner.limits.hint,
+            Inlining::Always => usize::MAX,
+        };
+        Some((limit, function.instantiate(self, generics).return_type))
+    }
+
+    /// Whether `name<generics>` could be inlined: [`Self::inline_limit`]
+    /// and the size of the body before calls in it are inlined
+    fn may_inline(&self, name: orco::Symbol, generics: &[orco::Type], inliner: &Inliner) -> bool {
+        let Some((limit, _)) = self.inline_limit(name, generics, inliner) else {
+            return false;
+        };
+        let mut fits = false;
+        let _ = self.get_function_body(name, generics, |callee, _| {
+            fits = callee.inline_size() <= limit;
+        });
+        fits
+    }
+
+    /// Body of `name<generics>` ready to be inlined into the last function
+    /// on the `stack` (types instantiated, calls inlined)
+    /// and its return type. [None] if it should not be inlined.
+    /// Bodies are prepared once and cached in the `inliner`
+    fn inlinable(
+        &self,
+        name: orco::Symbol,
+        generics: &[orco::Type],
+        inliner: &mut Inliner,
+    ) -> Option<Inlinable> {
+        let (limit, return_type) = self.inline_limit(name, generics, inliner)?;
+        let key = (name, generics.to_vec());
+        if let Some(cached) = inliner.cache.get(&key) {
+            return cached.clone();
+        }
+
+        let mut body = None;
+        let _ = self.get_function_body(name, generics, |callee, map| {
+            if callee.inline_size() <= limit {
+                let mut callee = callee.clone();
+                callee.instantiate(&map);
+                body = Some(callee);
+            }
+        });
+        let inlinable = body.and_then(|mut body| {
+            inliner.stack.push(name);
+            self.inline_calls(&mut body, inliner);
+            inliner.stack.pop();
+            (body.inline_size() <= limit).then_some((body, return_type))
+        });
+        inliner.cache.insert(key, inlinable.clone());
+        inlinable
+    }
+
+    /// Whether a call nested in the statement (not a direct call, see [`direct_call`])
+    /// could be inlined, see [`Self::outline_calls`]
+    fn has_nested_inlinable(&self, statement: &mut Statement, inliner: &Inliner) -> bool {
+        let mut callees = Vec::new();
+        for operand in statement_operands(statement) {
+            operand.visit(&mut |expr| {
+                if let Expression::Call(func, _) = expr
+                    && let Expression::Read(Place::Global(name, generics)) = &**func
+                {
+                    callees.push((*name, generics.clone()));
+                }
+            });
+        }
+        callees
+            .into_iter()
+            .any(|(name, generics)| self.may_inline(name, &generics, inliner))
+    }
+
+    /// Whether an expression evaluates to the same value anywhere in a statement,
+    /// so it doesn't have to be moved into a temporary before a call
+    fn is_stable(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::Reference(place, _) => place.operands().is_empty(),
+            // Functions can't be changed by a call
+            Expression::Read(Place::Global(name, _)) => !self.globals.pin().contains_key(name),
+            expr => expr.is_const(),
+        }
+    }
+
+    /// Make `operands` (in evaluation order) free of calls by moving calls into
+    /// temporaries assigned by `outlined`. Operands evaluated before the last call
+    /// are moved as well, since the call could change what they read
+    fn outline_operands(
+        &self,
+        body: &mut ir::Body,
+        operands: Vec<&mut Expression>,
+        outlined: &mut Vec<Statement>,
+    ) {
+        let Some(last) = operands.iter().rposition(|operand| operand.has_calls()) else {
+            return;
+        };
+        for (idx, operand) in operands.into_iter().enumerate().take(last + 1) {
+            self.outline_operands(body, expression_operands(operand), outlined);
+            if (idx < last || matches!(operand, Expression::Call(..))) && !self.is_stable(operand) {
+                let ty = operand.get_type(self, body);
+                body.variables.push(ir::Variable {
+                    ty,
+                    arg: false,
+                    name: None,
+                });
+                let tmp = Place::Variable(oc::Variable(body.variables.len() - 1));
+                let value = std::mem::replace(operand, Expression::Read(tmp.clone()));
+                outlined.push(Statement::Assign(tmp, value));
+            }
+        }
+    }
+
+    /// Move calls nested in the statement at `idx` into temporaries,
+    /// so that each of them is a direct call (see [`direct_call`]) and can be inlined.
+    /// Evaluation order is kept
+    fn outline_calls(&self, body: &mut ir::Body, idx: usize) {
+        let mut statement =
+            std::mem::replace(&mut body.statements[idx], Statement::Comment(String::new()));
+        let mut outlined = Vec::new();
+        self.outline_operands(body, statement_operands(&mut statement), &mut outlined);
+        outlined.push(statement);
+        body.splice_statements(idx..idx + 1, outlined);
+    }
+
+    /// Inline calls in `body` of the last function on the `stack`.
+    /// Returns whether anything changed
+    fn inline_calls(&self, body: &mut ir::Body, inliner: &mut Inliner) -> bool {
+        let mut changed = false;
+        let mut idx = 0;
+        while idx < body.statements.len() {
+            if self.has_nested_inlinable(&mut body.statements[idx], inliner) {
+                self.outline_calls(body, idx);
+                changed = true;
+            }
+            let callee = direct_call(&body.statements[idx])
+                .map(|(name, generics, _)| (name, generics.to_vec()))
+                .and_then(|(name, generics)| self.inlinable(name, &generics, inliner));
+            match callee {
+                // Calls in the inlined body are already inlined
+                Some((callee, return_type)) => {
+                    idx += body.splice_call(idx, callee, return_type);
+                    changed = true;
+                }
+                None => idx += 1,
+            }
+        }
+        changed
+    }
+
+    /// Inline direct calls in every function body: [`Inlining::Always`] functions
+    /// are inlined (unless the call is recursive), [`Inlining::Never`] functions are not,
+    /// others are inlined if they are small enough, see [`InlineLimits`].
+    /// Internal (see [`orco::attrs::FunctionAttributes::internal`]) [`Inlining::Always`]
+    /// functions that are not referenced anymore are removed.
+    /// Returns whether anything changed
+    pub fn inline_functions(&self, limits: InlineLimits) -> bool {
+        let mut inliner = Inliner {
+            limits,
+            stack: Vec::new(),
+            cache: std::collections::HashMap::new(),
+        };
+        let mut changed = false;
+        for (name, function) in self.functions.pin().iter() {
+            let bodies = function.bodies.pin();
+            let keys = bodies.keys().cloned().collect::<Vec<_>>();
+            for key in keys {
+                let Some(body) = bodies.get(&key) else {
+                    continue;
+                };
+                let mut body = body.clone();
+                inliner.stack = vec![*name];
+                if self.inline_calls(&mut body, &mut inliner) {
+                    bodies.insert(key, body);
+                    changed = true;
+                }
+            }
+        }
+        changed | self.remove_inlined_functions()
+    }
+
+    /// Remove internal [`Inlining::Always`] functions with bodies, which are not referenced
+    /// from any function body. Others could be called from outside of the module.
+    /// Returns whether anything was removed
+    fn remove_inlined_functions(&self) -> bool {
+        let mut referenced = std::collections::HashSet::new();
+        let functions = self.functions.pin();
+        for (name, function) in functions.iter() {
+            for (_, body) in function.bodies.pin().iter() {
+                for statement in &body.statements {
+                    statement.visit_exprs(&mut |expr| {
+                        if let Expression::Read(place) | Expression::Reference(place, _) = expr
+                            && let Some(global) = place.root_global()
+                            && global != *name
+                        {
+                            referenced.insert(global);
+                        }
+                    });
+                }
+            }
+        }
+
+        let unused = functions
+            .iter()
+            .filter(|(name, function)| {
+                function.signature.attrs.inlining == Inlining::Always
+                    && function.signature.attrs.internal
+                    && !function.bodies.is_empty()
+                    && !referenced.contains(*name)
+            })
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        for name in &unused {
+            functions.remove(name);
+        }
+        !unused.is_empty()
+    }
+}
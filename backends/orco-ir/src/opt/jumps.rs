@@ -0,0 +1,257 @@
+use crate::ir::{self, AcfStatement, BcfStatement, Statement};
+use orco::codegen as oc;
+
+impl ir::Body {
+    /// Index of the first statement at or after `idx`, which is not a comment
+    fn skip_comments(&self, mut idx: usize) -> usize {
+        while let Some(Statement::Comment(..)) = self.statements.get(idx) {
+            idx += 1;
+        }
+        idx
+    }
+
+    /// Label a jump to `label` ends up at, following chains of unconditional jumps
+    fn final_target(&self, mut label: oc::Label) -> oc::Label {
+        let mut visited = vec![false; self.labels.len()];
+        while !std::mem::replace(&mut visited[label.0], true) {
+            match self
+                .statements
+                .get(self.skip_comments(self.labels[label.0]))
+            {
+                Some(Statement::Acf(AcfStatement::Jump(next))) => label = *next,
+                _ => break,
+            }
+        }
+        label
+    }
+
+    /// Retarget jumps to unconditional jumps to their final destination,
+    /// replace jumps to returns without a value (or with a constant) with the return itself
+    /// and remove jumps to the next statement. Returns whether anything changed
+    pub fn thread_jumps(&mut self) -> bool {
+        let mut changed = false;
+        for idx in 0..self.statements.len() {
+            let (Statement::Acf(AcfStatement::Jump(label))
+            | Statement::Acf(AcfStatement::Cjump(_, label))) = self.statements[idx]
+            else {
+                continue;
+            };
+            let target = self.final_target(label);
+            if target != label {
+                match &mut self.statements[idx] {
+                    Statement::Acf(AcfStatement::Jump(label) | AcfStatement::Cjump(_, label)) => {
+                        *label = target;
+                    }
+                    _ => unreachable!(),
+                }
+                changed = true;
+            }
+
+            if let Statement::Acf(AcfStatement::Jump(_)) = self.statements[idx]
+                && let Some(Statement::Return(value)) = self
+                    .statements
+                    .get(self.skip_comments(self.labels[target.0]))
+                && value.as_ref().is_none_or(ir::Expression::is_const)
+            {
+                self.statements[idx] = Statement::Return(value.clone());
+                changed = true;
+            }
+        }
+
+        let to_next = |idx: usize, statement: &Statement, body: &Self| match statement {
+            Statement::Acf(AcfStatement::Jump(label)) => {
+                body.skip_comments(body.labels[label.0]) == body.skip_comments(idx + 1)
+            }
+            Statement::Acf(AcfStatement::Cjump(condition, label)) => {
+                !condition.has_calls()
+                    && body.skip_comments(body.labels[label.0]) == body.skip_comments(idx + 1)
+            }
+            _ => false,
+        };
+        let remove = self
+            .statements
+            .iter()
+            .enumerate()
+            .map(|(idx, statement)| to_next(idx, statement, self))
+            .collect::<Vec<_>>();
+        changed | self.retain_statements(|idx, _| !remove[idx])
+    }
+
+    /// Remove statements control never reaches. Blocks are removed
+    /// as a whole, when none of the statements in them are reachable.
+    /// Returns whether anything changed
+    pub fn remove_unreachable(&mut self) -> bool {
+        if self.statements.is_empty() {
+            return false;
+        }
+        let successors = self.successors();
+        let mut reachable = vec![false; self.statements.len()];
+        let mut worklist = vec![0];
+        while let Some(idx) = worklist.pop() {
+            if idx >= reachable.len() || std::mem::replace(&mut reachable[idx], true) {
+                continue;
+            }
+            worklist.extend(&successors[idx]);
+        }
+
+        let ends = self.block_ends();
+        let mut remove = vec![false; self.statements.len()];
+        for (idx, statement) in self.statements.iter().enumerate() {
+            if reachable[idx] {
+                continue;
+            }
+            match statement {
+                Statement::Bcf(BcfStatement::If(..) | BcfStatement::Loop) => {
+                    // `else` always has an `end`
+                    let mut end = ends[idx].expect("unclosed block");
+                    if let Statement::Bcf(BcfStatement::Else) = self.statements[end] {
+                        end = ends[end].expect("unclosed else");
+                    }
+                    if !reachable[idx..=end].contains(&true) {
+                        remove[idx..=end].fill(true);
+                    }
+                }
+                Statement::Bcf(BcfStatement::Else | BcfStatement::End) => (),
+                _ => remove[idx] = true,
+            }
+        }
+        self.retain_statements(|idx, _| !remove[idx])
+    }
+
+    /// Remove labels which no jump targets, renumbering the rest.
+    /// Returns whether anything changed
+    pub fn remove_unused_labels(&mut self) -> bool {
+        let used = self.used_labels();
+        if used.len() == self.labels.len() {
+            return false;
+        }
+
+        let mut new_label = vec![None; self.labels.len()];
+        let labels = std::mem::take(&mut self.labels);
+        for (label, idx) in labels.into_iter().enumerate() {
+            if used.contains(&label) {
+                new_label[label] = Some(oc::Label(self.labels.len()));
+                self.labels.push(idx);
+            }
+        }
+        for statement in &mut self.statements {
+            if let Statement::Acf(AcfStatement::Jump(label) | AcfStatement::Cjump(_, label)) =
+                statement
+            {
+                *label = new_label[label.0].expect("[bug?] used label was removed");
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::opt::check;
+
+    /// Chains of jumps, jumps to returns and jumps to the next statement
+    #[test]
+    fn thread_jumps() {
+        check(
+            &["thread-jumps"],
+            "fn f(a: bool) -> i32;
+for  {
+  let _0: bool = <argument>;
+  jump label0 if _0;
+  jump label1;
+label0:
+  jump label2;
+label1:
+  return 2 as i32;
+label2:
+  jump label3;
+label3:
+  _0 = false;
+  return 1 as i32;
+}
+",
+            "fn f(a: bool) -> i32;
+for  {
+  let _0: bool = <argument>;
+  jump label3 if _0;
+  return 2 as i32;
+label0:
+  jump label3;
+label1:
+  return 2 as i32;
+label2:
+label3:
+  _0 = false;
+  return 1 as i32;
+}
+",
+        );
+    }
+
+    /// Blocks are removed as a whole or not at all
+    #[test]
+    fn remove_unreachable() {
+        check(
+            &["unreachable"],
+            "fn f(a: i32) -> i32;
+for  {
+  let _0: i32 = <argument>;
+  jump label0;
+  _0 = 1 as i32;
+  loop {
+    _0 = 2 as i32;
+  }
+label0:
+  if _0 == 0 as i32 {
+    return 1 as i32;
+    _0 = 3 as i32;
+  }
+  return _0;
+  _0 = 4 as i32;
+}
+",
+            "fn f(a: i32) -> i32;
+for  {
+  let _0: i32 = <argument>;
+  jump label0;
+label0:
+  if _0 == 0 as i32 {
+    return 1 as i32;
+  }
+  return _0;
+}
+",
+        );
+    }
+
+    /// Used labels are renumbered
+    #[test]
+    fn remove_unused_labels() {
+        check(
+            &["unused-labels"],
+            "fn f(a: i32) -> i32;
+for  {
+  let _0: i32 = <argument>;
+label0:
+  _0 = _0 + 1 as i32;
+label1:
+  jump label2 if _0 == 3 as i32;
+  jump label1;
+label2:
+  return _0;
+}
+",
+            "fn f(a: i32) -> i32;
+for  {
+  let _0: i32 = <argument>;
+  _0 = _0 + 1 as i32;
+label0:
+  jump label1 if _0 == 3 as i32;
+  jump label0;
+label1:
+  return _0;
+}
+",
+        );
+    }
+}
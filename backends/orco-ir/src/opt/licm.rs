@@ -0,0 +1,420 @@
+use crate::analysis::{Cfg, Dominators, Loop, Loops};
+use crate::ir::{self, AcfStatement, BcfStatement, Expression, Place, Statement};
+use orco::Type;
+use orco::codegen as oc;
+use std::collections::HashSet;
+
+/// Whether an expression yields the same value everywhere in a loop:
+/// it doesn't call anything, doesn't go through pointers and only reads
+/// variables that are not `modified` in the loop
+fn is_invariant(expr: &Expression, modified: &HashSet<usize>) -> bool {
+    match expr {
+        Expression::IConst(..)
+        | Expression::UConst(..)
+        | Expression::FConst(..)
+        | Expression::BConst(..) => true,
+        Expression::Read(place) => {
+            place.pointer().is_none()
+                && place
+                    .root_variable()
+                    .is_some_and(|variable| !modified.contains(&variable.0))
+                && place
+                    .operands()
+                    .into_iter()
+                    .all(|index| is_invariant(index, modified))
+        }
+        Expression::Reference(..) | Expression::Call(..) => false,
+        Expression::Intrinsic(intrinsic) => intrinsic
+            .operands()
+            .all(|operand| is_invariant(operand, modified)),
+    }
+}
+
+/// Whether evaluating an expression can't have undefined behavior, so it can be
+/// evaluated even where the original code wouldn't: signed arithmetic can overflow,
+/// pointers can be dangling and indices can be out of bounds
+fn is_speculatable(expr: &Expression, store: &crate::Store, body: &ir::Body) -> bool {
+    fn place_is_speculatable(place: &Place) -> bool {
+        match place {
+            Place::Variable(..) | Place::Global(..) => true,
+            Place::Field(place, _) => place_is_speculatable(place),
+            Place::Deref(..) | Place::Index(..) => false,
+        }
+    }
+
+    match expr {
+        Expression::IConst(..)
+        | Expression::UConst(..)
+        | Expression::FConst(..)
+        | Expression::BConst(..) => true,
+        Expression::Read(place) => place_is_speculatable(place),
+        Expression::Reference(..) | Expression::Call(..) => false,
+        Expression::Intrinsic(intrinsic) => {
+            let overflows = match intrinsic {
+                ir::Intrinsic::Add(a, _) | ir::Intrinsic::Mul(a, _) => !matches!(
+                    a.get_type(store, body),
+                    Type::Unsigned(..) | Type::Float(..)
+                ),
+                ir::Intrinsic::Eq(..) | ir::Intrinsic::Not(..) => false,
+            };
+            !overflows
+                && intrinsic
+                    .operands()
+                    .all(|operand| is_speculatable(operand, store, body))
+        }
+    }
+}
+
+/// Calls `f` with outermost subexpressions first, only going into
+/// the operands of an expression if `f` returns false for it
+fn visit_outermost(expr: &mut Expression, f: &mut impl FnMut(&mut Expression) -> bool) {
+    if f(expr) {
+        return;
+    }
+    match expr {
+        Expression::IConst(..)
+        | Expression::UConst(..)
+        | Expression::FConst(..)
+        | Expression::BConst(..) => (),
+        Expression::Read(place) | Expression::Reference(place, _) => {
+            for operand in place.operands_mut() {
+                visit_outermost(operand, f);
+            }
+        }
+        Expression::Call(func, args) => {
+            visit_outermost(func, f);
+            for arg in args {
+                visit_outermost(arg, f);
+            }
+        }
+        Expression::Intrinsic(intrinsic) => {
+            for operand in intrinsic.operands_mut() {
+                visit_outermost(operand, f);
+            }
+        }
+    }
+}
+
+impl ir::Body {
+    /// Where code executed once before entering a loop can be inserted
+    /// and jumps that have to be retargeted past it. For BCF loops that is
+    /// right before the [`BcfStatement::Loop`], for ACF loops it's the header, which
+    /// has to be entered only through jumps from the loop once inside it.
+    /// [None] if there is no such place
+    fn preheader(&self, cfg: &Cfg, data: &Loop) -> Option<(usize, Vec<usize>)> {
+        let header = cfg.blocks[data.header].statements.start;
+        let successors = self.successors();
+        let in_loop = |idx: usize| data.contains(cfg.statement_blocks[idx]);
+        let used = self.used_labels();
+        let header_labels = (0..self.labels.len())
+            .filter(|label| self.labels[*label] == header && used.contains(label))
+            .collect::<HashSet<_>>();
+        let jumps_to_header = |idx: usize| match &self.statements[idx] {
+            Statement::Acf(AcfStatement::Jump(label) | AcfStatement::Cjump(_, label)) => {
+                header_labels.contains(&label.0)
+            }
+            _ => false,
+        };
+
+        if header > 0
+            && matches!(
+                self.statements[header - 1],
+                Statement::Bcf(BcfStatement::Loop)
+            )
+        {
+            // Jumps from outside the loop would skip the preheader
+            let outside =
+                (0..self.statements.len()).any(|idx| !in_loop(idx) && jumps_to_header(idx));
+            return (!outside && !in_loop(header - 1)).then(|| (header - 1, Vec::new()));
+        }
+
+        // Control coming back into the header from the loop has to go through a label,
+        // which can be moved past the preheader
+        let mut retarget = Vec::new();
+        for idx in (0..self.statements.len()).filter(|idx| in_loop(*idx)) {
+            if !successors[idx].contains(&header) {
+                continue;
+            }
+            let through_label = match &self.statements[idx] {
+                Statement::Acf(AcfStatement::Jump(..)) => true,
+                Statement::Acf(AcfStatement::Cjump(..)) => idx + 1 != header,
+                _ => false,
+            };
+            if !through_label || !jumps_to_header(idx) {
+                return None;
+            }
+            retarget.push(idx);
+        }
+        Some((header, retarget))
+    }
+
+    /// Hoist loop-invariant expressions out of one loop, see [`Self::hoist_loop_invariants`].
+    /// Returns whether anything changed
+    fn hoist_loop(
+        &mut self,
+        store: &crate::Store,
+        cfg: &Cfg,
+        dominators: &Dominators,
+        data: &Loop,
+    ) -> bool {
+        let Some((preheader, retarget)) = self.preheader(cfg, data) else {
+            return false;
+        };
+        let statements = data
+            .nodes
+            .iter()
+            .flat_map(|block| cfg.blocks[*block].statements.clone())
+            .collect::<Vec<_>>();
+
+        // Blocks executed on every iteration, before the loop is left or repeated
+        let exits = data
+            .exit_edges(cfg)
+            .into_iter()
+            .map(|(block, _)| block)
+            .chain(data.latches.iter().copied())
+            .collect::<Vec<_>>();
+        let every_iteration =
+            |block: usize| exits.iter().all(|exit| dominators.dominates(block, *exit));
+
+        // Variables written in the loop. Variables which had their address taken
+        // can be written through pointers at any time
+        let mut modified = self.escaped_variables();
+        for idx in &statements {
+            match &self.statements[*idx] {
+                Statement::Assign(target, _) => {
+                    modified.extend(target.root_variable().map(|v| v.0))
+                }
+                Statement::StorageLive(variable) | Statement::StorageDead(variable) => {
+                    modified.insert(variable.0);
+                }
+                _ => (),
+            }
+        }
+
+        // Hoisted expressions and variables holding them
+        let mut hoisted = Vec::<(Expression, oc::Variable)>::new();
+        let mut variables = Vec::new();
+        for idx in statements {
+            let every_iteration = every_iteration(cfg.statement_blocks[idx]);
+            let mut statement =
+                std::mem::replace(&mut self.statements[idx], Statement::Comment(String::new()));
+            let mut hoist = |expr: &mut Expression| {
+                // Nothing to gain from hoisting constants and plain variable reads
+                if expr.is_const()
+                    || matches!(expr, Expression::Read(Place::Variable(..)))
+                    || !is_invariant(expr, &modified)
+                    || !(every_iteration || is_speculatable(expr, store, self))
+                {
+                    return false;
+                }
+                let variable = match hoisted.iter().find(|(other, _)| other == expr) {
+                    Some((_, variable)) => *variable,
+                    None => {
+                        let variable = oc::Variable(self.variables.len() + variables.len());
+                        variables.push(ir::Variable {
+                            ty: expr.get_type(store, self),
+                            arg: false,
+                            name: None,
+                        });
+                        hoisted.push((expr.clone(), variable));
+                        variable
+                    }
+                };
+                *expr = Expression::Read(Place::Variable(variable));
+                true
+            };
+            match &mut statement {
+                Statement::Assign(target, value) => {
+                    if let Some(pointer) = target.pointer_mut() {
+                        visit_outermost(pointer, &mut hoist);
+                    }
+                    visit_outermost(value, &mut hoist);
+                }
+                Statement::Call(func, args) => {
+                    visit_outermost(func, &mut hoist);
+                    for arg in args {
+                        visit_outermost(arg, &mut hoist);
+                    }
+                }
+                Statement::Return(Some(value))
+                | Statement::Acf(AcfStatement::Cjump(value, _))
+                | Statement::Bcf(
+                    BcfStatement::If(value)
+                    | BcfStatement::Cbreak(value)
+                    | BcfStatement::Ccontinue(value),
+                ) => visit_outermost(value, &mut hoist),
+                _ => (),
+            }
+            self.statements[idx] = statement;
+        }
+        if hoisted.is_empty() {
+            return false;
+        }
+        self.variables.extend(variables);
+
+        let len = hoisted.len();
+        self.splice_statements(
+            preheader..preheader,
+            hoisted
+                .into_iter()
+                .map(|(expr, variable)| Statement::Assign(Place::Variable(variable), expr))
+                .collect(),
+        );
+
+        // Jumps back into the loop skip the hoisted code
+        if !retarget.is_empty() {
+            let label = oc::Label(self.labels.len());
+            self.labels
+                .push(cfg.blocks[data.header].statements.start + len);
+            for idx in retarget {
+                let idx = if idx < preheader { idx } else { idx + len };
+                if let Statement::Acf(AcfStatement::Jump(target) | AcfStatement::Cjump(_, target)) =
+                    &mut self.statements[idx]
+                {
+                    *target = label;
+                }
+            }
+        }
+        true
+    }
+
+    /// Loop-invariant code motion: hoist expressions, which don't call anything,
+    /// don't go through pointers and only read variables the loop doesn't write,
+    /// into temporaries computed once before the loop. Works with BCF loops and loops
+    /// made of backward jumps. Expressions that can have undefined behavior
+    /// (f.e. signed overflow) are only hoisted from blocks executed on every iteration,
+    /// the rest are evaluated before the loop even if the loop would never reach them.
+    /// Returns whether anything changed
+    pub fn hoist_loop_invariants(&mut self, store: &crate::Store) -> bool {
+        let mut changed = false;
+        // Positions change after hoisting, so the analysis is redone every time
+        'outer: loop {
+            let cfg = self.cfg();
+            let dominators = cfg.dominators();
+            let loops = Loops::new(&cfg, &dominators);
+            // Innermost loops first, code hoisted from them can move further out later
+            for data in loops.loops.iter().rev() {
+                if self.hoist_loop(store, &cfg, &dominators, data) {
+                    changed = true;
+                    continue 'outer;
+                }
+            }
+            return changed;
+        }
+    }
+}
+
+impl crate::Store {
+    /// Run [`ir::Body::hoist_loop_invariants`] on every function body
+    pub fn hoist_loop_invariants(&self) -> bool {
+        self.transform_bodies(|body| body.hoist_loop_invariants(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::opt::check;
+
+    /// Only expressions that can't overflow are hoisted out of an `if`
+    #[test]
+    fn conditional_code() {
+        check(
+            &["licm"],
+            "fn f(a: i32, b: u32, n: u32, c: bool) -> u32;
+for  {
+  let _0: i32 = <argument>;
+  let _1: u32 = <argument>;
+  let _2: u32 = <argument>;
+  let _3: bool = <argument>;
+  let _4: i32;
+  let _5: u32;
+  let _6: bool;
+  loop {
+    break if _2 == 0 as u32;
+    if _3 {
+      _4 = _0 + 1 as i32;
+      _5 = _1 + 1 as u32;
+      _6 = _0 == 1 as i32;
+    }
+    _2 = _2 + 4294967295 as u32;
+  }
+  return _5;
+}
+",
+            "fn f(a: i32, b: u32, n: u32, c: bool) -> u32;
+for  {
+  let _0: i32 = <argument>;
+  let _1: u32 = <argument>;
+  let _2: u32 = <argument>;
+  let _3: bool = <argument>;
+  let _4: i32;
+  let _5: u32;
+  let _6: bool;
+  let _7: u32;
+  let _8: bool;
+  _7 = _1 + 1 as u32;
+  _8 = _0 == 1 as i32;
+  loop {
+    break if _2 == 0 as u32;
+    if _3 {
+      _4 = _0 + 1 as i32;
+      _5 = _7;
+      _6 = _8;
+    }
+    _2 = _2 + 4294967295 as u32;
+  }
+  return _5;
+}
+",
+        );
+    }
+
+    /// Signed arithmetic after a conditional `break` is not executed
+    /// on the last iteration, so it stays, while the one before it is hoisted
+    #[test]
+    fn code_after_exit() {
+        check(
+            &["licm"],
+            "fn f(a: i32, b: u32, n: u32) -> u32;
+for  {
+  let _0: i32 = <argument>;
+  let _1: u32 = <argument>;
+  let _2: u32 = <argument>;
+  let _3: i32;
+  let _4: i32;
+  let _5: u32;
+  loop {
+    _3 = _0 * 2 as i32;
+    break if _2 == 0 as u32;
+    _4 = _0 + 1 as i32;
+    _5 = _1 * 3 as u32;
+    _2 = _2 + 4294967295 as u32;
+  }
+  return _5;
+}
+",
+            "fn f(a: i32, b: u32, n: u32) -> u32;
+for  {
+  let _0: i32 = <argument>;
+  let _1: u32 = <argument>;
+  let _2: u32 = <argument>;
+  let _3: i32;
+  let _4: i32;
+  let _5: u32;
+  let _6: i32;
+  let _7: u32;
+  _6 = _0 * 2 as i32;
+  _7 = _1 * 3 as u32;
+  loop {
+    _3 = _6;
+    break if _2 == 0 as u32;
+    _4 = _0 + 1 as i32;
+    _5 = _7;
+    _2 = _2 + 4294967295 as u32;
+  }
+  return _5;
+}
+",
+        );
+    }
+}
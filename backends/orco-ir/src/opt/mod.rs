@@ -0,0 +1,239 @@
+use crate::ir::{self, BcfStatement, Statement};
+use orco::codegen as oc;
+use std::collections::HashSet;
+use std::ops::Range;
+
+mod branches;
+mod copies;
+mod dead;
+mod fold;
+mod forward;
+mod inline;
+mod jumps;
+mod licm;
+mod sroa;
+mod unroll;
+
+pub use inline::InlineLimits;
+pub use unroll::UnrollLimits;
+
+impl ir::Body {
+    /// Run all scalar optimizations until none of them changes anything:
+    /// [`Self::fold_constants`], [`Self::eliminate_constant_branches`],
+    /// [`Self::propagate_copies`], [`Self::forward_single_assignments`],
+    /// [`Self::eliminate_dead_code`], [`Self::thread_jumps`],
+    /// [`Self::remove_unreachable`] and [`Self::remove_unused_labels`].
+    /// Returns whether anything changed
+    pub fn simplify(&mut self) -> bool {
+        let mut changed = false;
+        loop {
+            let round = self.fold_constants()
+                | self.eliminate_constant_branches()
+                | self.propagate_copies()
+                | self.forward_single_assignments()
+                | self.eliminate_dead_code()
+                | self.thread_jumps()
+                | self.remove_unreachable()
+                | self.remove_unused_labels();
+            if !round {
+                return changed;
+            }
+            changed = true;
+        }
+    }
+
+    /// Remove statements for which `keep` returns false. Labels placed on removed statements
+    /// move to the next statement that is kept. Returns whether anything was removed
+    pub fn retain_statements(&mut self, mut keep: impl FnMut(usize, &Statement) -> bool) -> bool {
+        let len = self.statements.len();
+        self.flat_map_statements(|idx, statement| keep(idx, &statement).then_some(statement));
+        self.statements.len() != len
+    }
+
+    /// Replace every statement with the statements `f` returns for it.
+    /// Labels move to the first replacement (or the next statement, if there are none)
+    pub fn flat_map_statements<I: IntoIterator<Item = Statement>>(
+        &mut self,
+        mut f: impl FnMut(usize, Statement) -> I,
+    ) {
+        // New index of each statement (and of the end of the body)
+        let mut new_idx = Vec::with_capacity(self.statements.len() + 1);
+        let statements = std::mem::take(&mut self.statements);
+        for (idx, statement) in statements.into_iter().enumerate() {
+            new_idx.push(self.statements.len());
+            self.statements.extend(f(idx, statement));
+        }
+        new_idx.push(self.statements.len());
+
+        for label in &mut self.labels {
+            *label = new_idx[(*label).min(new_idx.len() - 1)];
+        }
+    }
+
+    /// Replace a range of statements with `replacement`. Labels placed inside the range
+    /// move to the first replacement (labels placed at the start of an empty range
+    /// end up on the first inserted statement)
+    pub fn splice_statements(&mut self, range: Range<usize>, replacement: Vec<Statement>) {
+        let (start, end, len) = (range.start, range.end, replacement.len());
+        self.statements.splice(range, replacement);
+        for label in &mut self.labels {
+            if *label <= start {
+                continue;
+            }
+            *label = if *label < end {
+                start
+            } else {
+                *label + len - (end - start)
+            };
+        }
+    }
+
+    /// Labels any jump targets
+    #[must_use]
+    pub fn used_labels(&self) -> HashSet<usize> {
+        self.statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Acf(
+                    ir::AcfStatement::Jump(label) | ir::AcfStatement::Cjump(_, label),
+                ) => Some(label.0),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Variables that have their address taken, see [`ir::Expression::Reference`].
+    /// Writes through pointers and calls can change them at any time
+    #[must_use]
+    pub fn escaped_variables(&self) -> HashSet<usize> {
+        let mut escaped = HashSet::new();
+        for statement in &self.statements {
+            statement.visit_exprs(&mut |expr| {
+                if let ir::Expression::Reference(place, _) = expr
+                    && let Some(variable) = place.root_variable()
+                {
+                    escaped.insert(variable.0);
+                }
+            });
+        }
+        escaped
+    }
+
+    /// Index of the `else` or `end` closing each block-opening statement ([`BcfStatement::If`],
+    /// [`BcfStatement::Else`] and [`BcfStatement::Loop`]), [None] for other statements
+    #[must_use]
+    pub fn block_ends(&self) -> Vec<Option<usize>> {
+        let mut ends = vec![None; self.statements.len()];
+        let mut stack = Vec::new();
+        for (idx, statement) in self.statements.iter().enumerate() {
+            match statement {
+                Statement::Bcf(BcfStatement::If(..) | BcfStatement::Loop) => stack.push(idx),
+                Statement::Bcf(BcfStatement::Else) => {
+                    let block = stack.pop().expect("else without an if");
+                    ends[block] = Some(idx);
+                    stack.push(idx);
+                }
+                Statement::Bcf(BcfStatement::End) => {
+                    let block = stack.pop().expect("end without a block");
+                    ends[block] = Some(idx);
+                }
+                _ => (),
+            }
+        }
+        ends
+    }
+
+    /// Rename variables in every statement
+    pub fn rename_variables(&mut self, rename: impl Fn(oc::Variable) -> oc::Variable) {
+        for statement in &mut self.statements {
+            match statement {
+                Statement::StorageLive(variable) | Statement::StorageDead(variable) => {
+                    *variable = rename(*variable);
+                }
+                Statement::Assign(target, _) => {
+                    if let Some(variable) = target.root_variable_mut() {
+                        *variable = rename(*variable);
+                    }
+                }
+                _ => (),
+            }
+            statement.visit_exprs_mut(&mut |expr| {
+                if let ir::Expression::Read(place) | ir::Expression::Reference(place, _) = expr
+                    && let Some(variable) = place.root_variable_mut()
+                {
+                    *variable = rename(*variable);
+                }
+            });
+        }
+    }
+}
+
+impl crate::Store {
+    /// Apply a transformation to every function body (every specialization).
+    /// Returns whether it changed any of them
+    pub fn transform_bodies(&self, mut transform: impl FnMut(&mut ir::Body) -> bool) -> bool {
+        let mut changed = false;
+        for (_, function) in self.functions.pin().iter() {
+            let bodies = function.bodies.pin();
+            let keys = bodies.keys().cloned().collect::<Vec<_>>();
+            for key in keys {
+                let Some(body) = bodies.get(&key) else {
+                    continue;
+                };
+                let mut body = body.clone();
+                if transform(&mut body) {
+                    bodies.insert(key, body);
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Run [`ir::Body::simplify`] on every function body
+    pub fn simplify(&self) -> bool {
+        self.transform_bodies(ir::Body::simplify)
+    }
+}
+
+/// Parse `before`, run `passes` on it (verifying the result) and compare with `after`
+#[cfg(test)]
+fn check(passes: &[&str], before: &str, after: &str) {
+    let mut store = crate::parser::parse(before).unwrap();
+    let mut manager = crate::passes::PassManager::new();
+    manager.verify = true;
+    manager.run(&mut store, passes).unwrap();
+    assert_eq!(store.to_string().trim(), after.trim());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check;
+
+    /// Passes enable each other
+    #[test]
+    fn simplify() {
+        check(
+            &["simplify"],
+            "fn f(a: i32) -> i32;
+for  {
+  let _0: i32 = <argument>;
+  let _1: i32;
+  let _2: bool;
+  _1 = 2 as i32 * 3 as i32;
+  _2 = _1 == 6 as i32;
+  jump label0 if _2;
+  _0 = _0 + 100 as i32;
+label0:
+  return _0 + _1;
+}
+",
+            "fn f(a: i32) -> i32;
+for  {
+  let _0: i32 = <argument>;
+  return _0 + 6 as i32;
+}
+",
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use crate::ir::{self, Expression, Place, Statement};
+use orco::codegen as oc;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/// Replace fields of split variables (see [`ir::Body::split_aggregates`])
+/// with their variables. `split` maps each split variable to the variables of its fields
+fn rewrite_place(place: &mut Place, split: &HashMap<usize, Range<usize>>) {
+    if let Place::Index(inner, _) = place {
+        return rewrite_place(inner, split);
+    }
+    let Place::Field(inner, field) = place else {
+        return;
+    };
+    match **inner {
+        Place::Variable(variable) if let Some(fields) = split.get(&variable.0) => {
+            *place = Place::Variable(oc::Variable(fields.start + *field));
+        }
+        _ => rewrite_place(inner, split),
+    }
+}
+
+/// Whether evaluating a place more than once is the same as evaluating it once
+fn is_pure(place: &Place) -> bool {
+    !place.operands().into_iter().any(Expression::has_calls)
+}
+
+impl ir::Body {
+    /// Variables of struct types that can be split into variables for each field,
+    /// with the types of the fields. They must not have their address taken
+    /// and must only be used as a whole when copied from or to another place
+    fn splittable_aggregates(&self, store: &crate::Store) -> HashMap<usize, Vec<ir::Variable>> {
+        let escaped = self.escaped_variables();
+        let mut candidates = self
+            .variables
+            .iter()
+            .enumerate()
+            .filter(|(idx, variable)| !variable.arg && !escaped.contains(idx))
+            .filter_map(
+                |(idx, variable)| match store.inline_type_aliases(variable.ty.clone()) {
+                    orco::Type::Struct { fields } => {
+                        let fields = fields
+                            .into_iter()
+                            .enumerate()
+                            .map(|(field, (name, ty))| ir::Variable {
+                                ty,
+                                arg: false,
+                                name: variable.name.as_ref().map(|variable| match name {
+                                    Some(name) => format!("{variable}.{name}"),
+                                    None => format!("{variable}._{field}"),
+                                }),
+                            })
+                            .collect();
+                        Some((idx, fields))
+                    }
+                    _ => None,
+                },
+            )
+            .collect::<HashMap<_, _>>();
+
+        // Uses of whole variables, other than copies
+        let mut whole = HashSet::new();
+        for statement in &self.statements {
+            if let Statement::Assign(Place::Variable(variable), value) = statement
+                && !matches!(value, Expression::Read(source) if is_pure(source))
+            {
+                whole.insert(variable.0);
+            }
+            let mut visit = |expr: &Expression| {
+                if let Expression::Read(Place::Variable(variable)) = expr {
+                    whole.insert(variable.0);
+                }
+            };
+            match statement {
+                Statement::Assign(target, value) => {
+                    target.visit_exprs(&mut visit);
+                    match value {
+                        Expression::Read(Place::Variable(..)) if is_pure(target) => (),
+                        value => value.visit(&mut visit),
+                    }
+                }
+                statement => statement.visit_exprs(&mut visit),
+            }
+        }
+        candidates.retain(|variable, _| !whole.contains(variable));
+        candidates
+    }
+
+    /// Scalar replacement of aggregates: split variables of struct types into a variable
+    /// per field, if they don't have their address taken and are only used as a whole
+    /// when copied (`_1 = *_0;`, `*_0 = _1;`). The original variables are left unused,
+    /// see [`Self::eliminate_dead_code`]. Returns whether anything changed
+    pub fn split_aggregates(&mut self, store: &crate::Store) -> bool {
+        let candidates = self.splittable_aggregates(store);
+        if candidates.is_empty() {
+            return false;
+        }
+
+        // Variables of the fields of each split variable
+        let mut split = HashMap::new();
+        let mut candidates = candidates.into_iter().collect::<Vec<_>>();
+        candidates.sort_by_key(|(variable, _)| *variable);
+        for (variable, fields) in candidates {
+            let first = self.variables.len();
+            self.variables.extend(fields);
+            split.insert(variable, first..self.variables.len());
+        }
+
+        self.flat_map_statements(|_, statement| {
+            // Expand whole-variable copies and storage markers into fields
+            let statements = match statement {
+                Statement::StorageLive(variable) | Statement::StorageDead(variable)
+                    if split.contains_key(&variable.0) =>
+                {
+                    let live = matches!(statement, Statement::StorageLive(..));
+                    split[&variable.0]
+                        .clone()
+                        .map(|field| {
+                            let field = oc::Variable(field);
+                            if live {
+                                Statement::StorageLive(field)
+                            } else {
+                                Statement::StorageDead(field)
+                            }
+                        })
+                        .collect()
+                }
+                Statement::Assign(Place::Variable(variable), Expression::Read(source))
+                    if split.contains_key(&variable.0) =>
+                {
+                    split[&variable.0]
+                        .clone()
+                        .map(|field| {
+                            Statement::Assign(
+                                Place::Variable(oc::Variable(field)),
+                                Expression::Read(Place::Field(
+                                    Box::new(source.clone()),
+                                    field - split[&variable.0].start,
+                                )),
+                            )
+                        })
+                        .collect()
+                }
+                Statement::Assign(target, Expression::Read(Place::Variable(variable)))
+                    if split.contains_key(&variable.0) =>
+                {
+                    split[&variable.0]
+                        .clone()
+                        .map(|field| {
+                            Statement::Assign(
+                                Place::Field(
+                                    Box::new(target.clone()),
+                                    field - split[&variable.0].start,
+                                ),
+                                Expression::Read(Place::Variable(oc::Variable(field))),
+                            )
+                        })
+                        .collect()
+                }
+                statement => vec![statement],
+            };
+
+            statements.into_iter().map(|mut statement| {
+                if let Statement::Assign(target, _) = &mut statement {
+                    rewrite_place(target, &split);
+                }
+                statement.visit_exprs_mut(&mut |expr| {
+                    if let Expression::Read(place) | Expression::Reference(place, _) = expr {
+                        rewrite_place(place, &split);
+                    }
+                });
+                statement
+            })
+        });
+        true
+    }
+}
+
+impl crate::Store {
+    /// Run [`ir::Body::split_aggregates`] on every function body
+    pub fn split_aggregates(&self) -> bool {
+        self.transform_bodies(|body| body.split_aggregates(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::opt::check;
+
+    /// Copies from and to memory and storage markers are expanded into fields,
+    /// fields are written one at a time
+    #[test]
+    fn split_copies_and_partial_writes() {
+        check(
+            &["sroa"],
+            "type point = { x: i32, y: i32 };
+
+fn f(p: *mut point) -> i32;
+for  {
+  let _0: *mut point = <argument>;
+  let _1: point; // a
+  let _2: point; // b
+  storage_live _1;
+  _1 = *_0;
+  _1._0 = _1._0 + 1 as i32;
+  *_0 = _1;
+  storage_dead _1;
+  _2._1 = 2 as i32;
+  _2._0 = _2._1 * 3 as i32;
+  return _2._0;
+}
+",
+            "type point = { x: i32, y: i32 };
+
+
+fn f(p: *mut point) -> i32;
+for  {
+  let _0: *mut point = <argument>;
+  let _1: point; // a
+  let _2: point; // b
+  let _3: i32; // a.x
+  let _4: i32; // a.y
+  let _5: i32; // b.x
+  let _6: i32; // b.y
+  storage_live _3;
+  storage_live _4;
+  _3 = (*_0)._0;
+  _4 = (*_0)._1;
+  _3 = _3 + 1 as i32;
+  (*_0)._0 = _3;
+  (*_0)._1 = _4;
+  storage_dead _3;
+  storage_dead _4;
+  _6 = 2 as i32;
+  _5 = _6 * 3 as i32;
+  return _5;
+}
+",
+        );
+    }
+
+    /// A variable with a field that had its address taken and one passed as a whole are not split
+    #[test]
+    fn keep_escaped_and_whole() {
+        check(
+            &["sroa"],
+            "type point = { x: i32, y: i32 };
+
+fn g(p: point) -> void;
+
+fn f() -> i32;
+for  {
+  let _0: point;
+  let _1: *mut i32;
+  let _2: point;
+  _0._0 = 4 as i32;
+  _1 = &mut _0._1;
+  *_1 = 5 as i32;
+  _2._0 = 6 as i32;
+  g(_2);
+  return _0._1 + _2._0;
+}
+",
+            "type point = { x: i32, y: i32 };
+
+
+fn f() -> i32;
+for  {
+  let _0: point;
+  let _1: *mut i32;
+  let _2: point;
+  _0._0 = 4 as i32;
+  _1 = &mut _0._1;
+  *_1 = 5 as i32;
+  _2._0 = 6 as i32;
+  g(_2);
+  return _0._1 + _2._0;
+}
+
+fn g(p: point) -> void;
+",
+        );
+    }
+}
@@ -0,0 +1,413 @@
+use crate::analysis::{Cfg, Loop, Loops};
+use crate::ir::{self, AcfStatement, BcfStatement, Expression, Intrinsic, Place, Statement};
+use orco::attrs::Unrolling;
+use orco::codegen as oc;
+
+/// Size limits for [`ir::Body::unroll_loops`].
+/// Sizes are counted in statements, not counting comments and locations
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnrollLimits {
+    /// Most iterations a loop can have to be unrolled
+    pub trips: usize,
+    /// Largest size of the unrolled loop
+    pub size: usize,
+}
+
+impl Default for UnrollLimits {
+    fn default() -> Self {
+        Self {
+            trips: 16,
+            size: 128,
+        }
+    }
+}
+
+/// A loop, that can be unrolled: its statements are `body`, apart from
+/// the exit, `exit` is the conditional exit and `step` is the only assignment
+/// of the induction variable (`_n = _n + step;`). Both are executed once per iteration
+struct Candidate {
+    body: std::ops::Range<usize>,
+    /// Statements to replace with the unrolled loop
+    range: std::ops::Range<usize>,
+    exit: usize,
+    /// Where control goes after the exit, [None] for right after [`Self::range`]
+    exit_label: Option<oc::Label>,
+    variable: oc::Variable,
+    step: usize,
+    /// Value of the variable when entering the loop
+    init: Expression,
+    /// Whether each statement of the body is in its straight-line code
+    top_level: Vec<bool>,
+}
+
+/// Replace reads of `variable` with `value` and fold the expression
+fn evaluate(expr: &Expression, variable: oc::Variable, value: &Expression) -> Expression {
+    let mut expr = expr.clone();
+    expr.visit_mut(&mut |expr| {
+        if *expr == Expression::Read(Place::Variable(variable)) {
+            *expr = value.clone();
+        }
+    });
+    super::fold::fold_expression(&mut expr);
+    expr
+}
+
+impl ir::Body {
+    /// Whether control can come to statement `idx` from somewhere else than the previous
+    /// statement, through a jump
+    fn is_jump_target(&self, used: &std::collections::HashSet<usize>, idx: usize) -> bool {
+        used.iter().any(|label| self.labels[*label] == idx)
+    }
+
+    /// Constant value `variable` has when control reaches statement `start`, looking only
+    /// at straight-line code before it (and a jump to `start` right before it)
+    fn value_before(&self, variable: oc::Variable, start: usize) -> Option<Expression> {
+        let used = self.used_labels();
+        let mut idx = start;
+        loop {
+            idx = idx.checked_sub(1)?;
+            match &self.statements[idx] {
+                Statement::Assign(Place::Variable(target), value) if *target == variable => {
+                    return matches!(value, Expression::IConst(..) | Expression::UConst(..))
+                        .then(|| value.clone());
+                }
+                Statement::Assign(target, _) if target.root_variable() == Some(variable) => {
+                    return None;
+                }
+                Statement::StorageLive(target) | Statement::StorageDead(target)
+                    if *target == variable =>
+                {
+                    return None;
+                }
+                // Calls can't change variables that don't have their address taken
+                Statement::Comment(..)
+                | Statement::Location(..)
+                | Statement::LoopAttributes(..)
+                | Statement::StorageLive(..)
+                | Statement::StorageDead(..)
+                | Statement::Assign(..)
+                | Statement::Call(..) => (),
+                // A jump right into the loop
+                Statement::Acf(AcfStatement::Jump(label)) if idx + 1 == start => {
+                    if self.labels[label.0] != start {
+                        return None;
+                    }
+                }
+                _ => return None,
+            }
+            if self.is_jump_target(&used, idx) {
+                return None;
+            }
+        }
+    }
+
+    /// Check that `range` is only entered at the start and left through the only
+    /// conditional exit in `body` (a `break if` of the loop or a jump out for ACF loops),
+    /// and find the induction variable. Both have to be in straight-line code
+    /// of the body, not nested in anything
+    fn unroll_candidate(
+        &self,
+        body: std::ops::Range<usize>,
+        range: std::ops::Range<usize>,
+        acf: bool,
+    ) -> Option<Candidate> {
+        let used = self.used_labels();
+        if (range.start + 1..range.end).any(|idx| self.is_jump_target(&used, idx)) {
+            return None;
+        }
+
+        // Open BCF blocks (whether each one is a loop) and whether each statement
+        // is in the straight-line code of the body
+        let mut blocks = Vec::new();
+        let mut top_level = Vec::new();
+        let mut exit = None;
+        for idx in body.clone() {
+            let statement = &self.statements[idx];
+            top_level.push(blocks.is_empty());
+            let in_loop = blocks.contains(&true);
+            match statement {
+                Statement::Bcf(BcfStatement::If(..)) => blocks.push(false),
+                Statement::Bcf(BcfStatement::Loop) => blocks.push(true),
+                Statement::Bcf(BcfStatement::Else) => (),
+                Statement::Bcf(BcfStatement::End) => {
+                    blocks.pop()?;
+                }
+                Statement::Bcf(BcfStatement::Cbreak(..))
+                    if !acf && !in_loop && blocks.is_empty() && exit.is_none() =>
+                {
+                    exit = Some((idx, None));
+                }
+                Statement::Bcf(..) if !in_loop => return None,
+                Statement::Acf(AcfStatement::Cjump(_, label))
+                    if acf
+                        && blocks.is_empty()
+                        && exit.is_none()
+                        && !range.contains(&self.labels[label.0]) =>
+                {
+                    exit = Some((idx, Some(*label)));
+                }
+                Statement::Acf(..) | Statement::Return(..) => return None,
+                _ => (),
+            }
+        }
+        if !blocks.is_empty() {
+            return None;
+        }
+        let (exit, exit_label) = exit?;
+
+        // Induction variable: read by the exit and written once per iteration,
+        // by adding a constant
+        let mut read = Vec::new();
+        self.statements[exit].visit_reads(&mut |place| {
+            if let Place::Variable(variable) = place {
+                read.push(*variable);
+            }
+        });
+        let escaped = self.escaped_variables();
+        let mut step = None;
+        for idx in body.clone() {
+            let Statement::Assign(
+                Place::Variable(variable),
+                Expression::Intrinsic(Intrinsic::Add(a, b)),
+            ) = &self.statements[idx]
+            else {
+                continue;
+            };
+            if top_level[idx - body.start]
+                && read.contains(variable)
+                && !escaped.contains(&variable.0)
+                && **a == Expression::Read(Place::Variable(*variable))
+                && matches!(**b, Expression::IConst(..) | Expression::UConst(..))
+            {
+                if step.is_some() {
+                    return None;
+                }
+                step = Some((idx, *variable));
+            }
+        }
+        let (step, variable) = step?;
+        let writes = body.clone().filter(|idx| match &self.statements[*idx] {
+            Statement::Assign(target, _) => target.root_variable() == Some(variable),
+            Statement::StorageLive(target) | Statement::StorageDead(target) => *target == variable,
+            _ => false,
+        });
+        if writes.count() != 1 {
+            return None;
+        }
+
+        Some(Candidate {
+            init: self.value_before(variable, range.start)?,
+            body,
+            range,
+            exit,
+            exit_label,
+            variable,
+            step,
+            top_level,
+        })
+    }
+
+    /// Find out whether a loop can be unrolled, see [`Self::unroll_loops`]
+    fn unroll_loop_candidate(&self, cfg: &Cfg, data: &Loop) -> Option<Candidate> {
+        if self.loop_attributes(cfg, data).unrolling == Unrolling::Never {
+            return None;
+        }
+        let header = cfg.blocks[data.header].statements.start;
+        if header > 0
+            && matches!(
+                self.statements[header - 1],
+                Statement::Bcf(BcfStatement::Loop)
+            )
+        {
+            // Jumps to the loop statement would skip the initial value
+            if self.is_jump_target(&self.used_labels(), header - 1) {
+                return None;
+            }
+            let end = self.block_ends()[header - 1]?;
+            return self.unroll_candidate(header..end, header - 1..end + 1, false);
+        }
+
+        // The only latch has to end with a jump back to the header
+        // and the loop has to be right before it
+        let [latch] = data.latches[..] else {
+            return None;
+        };
+        let last = cfg.blocks[latch].statements.end - 1;
+        match &self.statements[last] {
+            Statement::Acf(AcfStatement::Jump(label)) if self.labels[label.0] == header => (),
+            _ => return None,
+        }
+        if (header..=last).any(|idx| !data.contains(cfg.statement_blocks[idx]))
+            || data
+                .nodes
+                .iter()
+                .any(|block| !(header..=last).contains(&cfg.blocks[*block].statements.start))
+        {
+            return None;
+        }
+        // The loop is entered only from the statement before it
+        let entry = (0..self.statements.len()).any(|idx| match &self.statements[idx] {
+            Statement::Acf(AcfStatement::Jump(label) | AcfStatement::Cjump(_, label)) => {
+                self.labels[label.0] == header && idx != last && idx + 1 != header
+            }
+            _ => false,
+        });
+        if entry {
+            return None;
+        }
+        self.unroll_candidate(header..last, header..last + 1, true)
+    }
+
+    /// Unroll one loop. [None] if it has too many iterations or would get too big
+    fn unroll(&self, candidate: &Candidate, limits: UnrollLimits) -> Option<Vec<Statement>> {
+        let Statement::Assign(_, Expression::Intrinsic(Intrinsic::Add(_, step))) =
+            &self.statements[candidate.step]
+        else {
+            unreachable!();
+        };
+        let condition = match &self.statements[candidate.exit] {
+            Statement::Bcf(BcfStatement::Cbreak(condition))
+            | Statement::Acf(AcfStatement::Cjump(condition, _)) => condition,
+            _ => unreachable!(),
+        };
+
+        // Number of full iterations
+        let mut value = candidate.init.clone();
+        let mut trips = 0;
+        loop {
+            let mut stepped =
+                Expression::Intrinsic(Intrinsic::Add(Box::new(value.clone()), step.clone()));
+            super::fold::fold_expression(&mut stepped);
+            if !stepped.is_const() {
+                return None;
+            }
+            let seen = match candidate.step < candidate.exit {
+                true => &stepped,
+                false => &value,
+            };
+            match evaluate(condition, candidate.variable, seen) {
+                Expression::BConst(true) => break,
+                Expression::BConst(false) => (),
+                _ => return None,
+            }
+            value = stepped;
+            trips += 1;
+            if trips > limits.trips {
+                return None;
+            }
+        }
+
+        let code = |idx: &usize| {
+            !matches!(
+                self.statements[*idx],
+                Statement::Comment(..) | Statement::Location(..) | Statement::LoopAttributes(..)
+            )
+        };
+        let iteration = candidate.body.clone().filter(|idx| *idx != candidate.exit);
+        let size = trips * iteration.clone().filter(code).count()
+            + (candidate.body.start..candidate.exit).filter(code).count();
+        if size > limits.size {
+            return None;
+        }
+
+        // Attributes of the unrolled loop would apply to whatever loop the copies end up in
+        let copy = |idx: usize| match &self.statements[idx] {
+            Statement::LoopAttributes(..) if candidate.top_level[idx - candidate.body.start] => {
+                None
+            }
+            statement => Some(statement.clone()),
+        };
+        let mut statements = Vec::new();
+        for _ in 0..trips {
+            statements.extend(iteration.clone().filter_map(copy));
+        }
+        statements.extend((candidate.body.start..candidate.exit).filter_map(copy));
+        if let Some(label) = candidate.exit_label {
+            statements.push(Statement::Acf(AcfStatement::Jump(label)));
+        }
+        Some(statements)
+    }
+
+    /// Fully unroll loops with a constant number of iterations (both BCF loops
+    /// and loops made of backward jumps). A loop is unrolled if it's only left through
+    /// a single conditional exit, that depends on a variable, which starts with a constant
+    /// and is increased by a constant once per iteration, and isn't marked with
+    /// [`Unrolling::Never`] (see [`ir::Statement::LoopAttributes`]).
+    /// Run [`Self::simplify`] afterwards to fold the copies.
+    /// Returns whether anything changed
+    pub fn unroll_loops(&mut self, limits: UnrollLimits) -> bool {
+        let mut changed = false;
+        // Positions change after unrolling, so the analysis is redone every time
+        'outer: loop {
+            let cfg = self.cfg();
+            let dominators = cfg.dominators();
+            let loops = Loops::new(&cfg, &dominators);
+            for data in loops.loops.iter().rev() {
+                let Some(candidate) = self.unroll_loop_candidate(&cfg, data) else {
+                    continue;
+                };
+                let Some(statements) = self.unroll(&candidate, limits) else {
+                    continue;
+                };
+                self.splice_statements(candidate.range, statements);
+                changed = true;
+                continue 'outer;
+            }
+            return changed;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::opt::check;
+
+    /// The same loop is unrolled unless it is marked with `unroll(never)`
+    #[test]
+    fn never_unroll() {
+        check(
+            &["unroll"],
+            "fn f(a: i32) -> i32;
+for  {
+  let _0: i32 = <argument>;
+  let _1: u32;
+  _1 = 0 as u32;
+  loop {
+    loop_attributes [unroll(never)];
+    break if _1 == 3 as u32;
+    _0 = _0 * 2 as i32;
+    _1 = _1 + 1 as u32;
+  }
+  _1 = 0 as u32;
+  loop {
+    break if _1 == 3 as u32;
+    _0 = _0 * 2 as i32;
+    _1 = _1 + 1 as u32;
+  }
+  return _0;
+}
+",
+            "fn f(a: i32) -> i32;
+for  {
+  let _0: i32 = <argument>;
+  let _1: u32;
+  _1 = 0 as u32;
+  loop {
+    loop_attributes [unroll(never)];
+    break if _1 == 3 as u32;
+    _0 = _0 * 2 as i32;
+    _1 = _1 + 1 as u32;
+  }
+  _1 = 0 as u32;
+  _0 = _0 * 2 as i32;
+  _1 = _1 + 1 as u32;
+  _0 = _0 * 2 as i32;
+  _1 = _1 + 1 as u32;
+  _0 = _0 * 2 as i32;
+  _1 = _1 + 1 as u32;
+  return _0;
+}
+",
+        );
+    }
+}
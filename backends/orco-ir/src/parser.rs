@@ -0,0 +1,1073 @@
+use crate::{Store, ir};
+use orco::Type;
+use orco::codegen as oc;
+use orco::symbol::is_plain_ident;
+
+/// Error produced when parsing textual IR
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column number
+    pub column: usize,
+    #[allow(missing_docs)]
+    pub message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+type PResult<T> = Result<T, ParseError>;
+
+impl std::str::FromStr for Store {
+    type Err = ParseError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        parse(source)
+    }
+}
+
+/// Parse a [Store] from the format its [Display](std::fmt::Display) impl produces.
+/// `//` comments between items are ignored
+pub fn parse(source: &str) -> Result<Store, ParseError> {
+    let store = Store::new();
+    Parser::new(source).store(&store)?;
+    Ok(store)
+}
+
+/// Parse a single [Type], see [Type]'s [Display](std::fmt::Display) impl
+pub fn parse_type(source: &str) -> Result<Type, ParseError> {
+    let mut parser = Parser::new(source);
+    let ty = parser.type_()?;
+    parser.end()?;
+    Ok(ty)
+}
+
+/// Constructor of a binary [`ir::Intrinsic`]
+type BinaryOp = fn(Box<ir::Expression>, Box<ir::Expression>) -> ir::Intrinsic;
+
+/// Binary operators by precedence, loosest first
+const BINARY: [(&str, BinaryOp); 3] = [
+    ("==", ir::Intrinsic::Eq),
+    ("+", ir::Intrinsic::Add),
+    ("*", ir::Intrinsic::Mul),
+];
+
+/// Recursive descent parser over the source text
+struct Parser<'a> {
+    source: &'a str,
+    pos: usize,
+    /// Number of variables in the body being parsed
+    variables: usize,
+    /// Statement index of each label of the body being parsed
+    labels: Vec<Option<usize>>,
+    /// Label uses with their positions, to report undefined labels
+    label_uses: Vec<(usize, usize)>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            pos: 0,
+            variables: 0,
+            labels: Vec::new(),
+            label_uses: Vec::new(),
+        }
+    }
+
+    fn error_at(&self, pos: usize, message: impl Into<String>) -> ParseError {
+        let before = &self.source[..pos];
+        ParseError {
+            line: before.matches('\n').count() + 1,
+            column: before.rsplit('\n').next().unwrap_or("").chars().count() + 1,
+            message: message.into(),
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        self.error_at(self.pos, message)
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+
+    fn skip_ws(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Whether the rest of the input starts with `token` (after whitespace)
+    fn at(&mut self, token: &str) -> bool {
+        self.skip_ws();
+        self.rest().starts_with(token)
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let at = self.at(token);
+        if at {
+            self.pos += token.len();
+        }
+        at
+    }
+
+    fn expect(&mut self, token: &str) -> PResult<()> {
+        match self.eat(token) {
+            true => Ok(()),
+            false => Err(self.error(format!("expected `{token}`"))),
+        }
+    }
+
+    /// Next word (`[A-Za-z0-9_$]*`), not consumed
+    fn word(&mut self) -> &'a str {
+        self.skip_ws();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$'))
+            .unwrap_or(rest.len());
+        &rest[..len]
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let at = self.word() == keyword;
+        if at {
+            self.pos += keyword.len();
+        }
+        at
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> PResult<()> {
+        match self.eat_keyword(keyword) {
+            true => Ok(()),
+            false => Err(self.error(format!("expected `{keyword}`"))),
+        }
+    }
+
+    fn end(&mut self) -> PResult<()> {
+        self.skip_ws();
+        match self.rest().is_empty() {
+            true => Ok(()),
+            false => Err(self.error("expected end of input")),
+        }
+    }
+
+    /// Consume the rest of the line, without the line break
+    fn line(&mut self) -> &'a str {
+        let rest = self.rest();
+        let line = rest.split('\n').next().unwrap_or("");
+        self.pos += line.len();
+        line
+    }
+
+    /// A `// comment` till the end of the line
+    fn comment(&mut self) -> String {
+        self.pos += "//".len();
+        let line = self.line();
+        line.strip_prefix(' ').unwrap_or(line).to_owned()
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> PResult<T> {
+        let word = self.word();
+        let number = word
+            .parse()
+            .map_err(|_| self.error(format!("expected a number, got `{word}`")))?;
+        self.pos += word.len();
+        Ok(number)
+    }
+
+    /// Comma-separated list, up to (and including) `close`
+    fn list<T>(
+        &mut self,
+        close: &str,
+        mut item: impl FnMut(&mut Self) -> PResult<T>,
+    ) -> PResult<Vec<T>> {
+        let mut items = Vec::new();
+        while !self.eat(close) {
+            if !items.is_empty() {
+                self.expect(",")?;
+                if self.eat(close) {
+                    break;
+                }
+            }
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+
+    /// Quoted string with Rust escapes
+    fn string(&mut self) -> PResult<String> {
+        let start = self.pos;
+        self.expect("\"")?;
+        let rest = self.rest();
+        let invalid_escape = |idx: usize| self.error_at(start + 1 + idx, "invalid escape sequence");
+        let mut chars = rest.char_indices();
+        let mut string = String::new();
+        let len = loop {
+            let Some((idx, c)) = chars.next() else {
+                return Err(self.error_at(start, "unterminated string"));
+            };
+            let c = match c {
+                '"' => break idx + 1,
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => '\n',
+                    Some((_, 'r')) => '\r',
+                    Some((_, 't')) => '\t',
+                    Some((_, '0')) => '\0',
+                    Some((_, c @ ('\\' | '"' | '\''))) => c,
+                    Some((_, 'u')) => {
+                        let code = rest[idx + 2..]
+                            .strip_prefix('{')
+                            .and_then(|code| code.split_once('}'))
+                            .map(|(code, _)| code)
+                            .ok_or_else(|| invalid_escape(idx))?;
+                        for _ in 0..code.len() + 2 {
+                            chars.next();
+                        }
+                        u32::from_str_radix(code, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| invalid_escape(idx))?
+                    }
+                    _ => return Err(invalid_escape(idx)),
+                },
+                c => c,
+            };
+            string.push(c);
+        };
+        self.pos += len;
+        Ok(string)
+    }
+
+    /// Identifier, plain or quoted (see [`orco::symbol::Ident`])
+    fn ident(&mut self) -> PResult<String> {
+        if self.at("\"") {
+            return self.string();
+        }
+        let word = self.word();
+        if !is_plain_ident(word) {
+            return Err(self.error(format!("expected an identifier, got `{word}`")));
+        }
+        self.pos += word.len();
+        Ok(word.to_owned())
+    }
+
+    /// Symbol path, see [`orco::symbol::Path`]'s [Display](std::fmt::Display) impl
+    fn path(&mut self) -> PResult<orco::Symbol> {
+        let rooted = self.eat("::");
+        let mut components = vec![self.ident()?];
+        let mut generics = Vec::new();
+        while self.eat("::") {
+            if self.at("<") {
+                generics = self.generics()?;
+                break;
+            }
+            components.push(self.ident()?);
+        }
+
+        let mut disambiguator = 0;
+        if let Some(rest) = self.rest().strip_prefix('#')
+            && rest.starts_with(|c: char| c.is_ascii_digit())
+        {
+            self.pos += 1;
+            disambiguator = self.number()?;
+        }
+
+        let name = components.pop().unwrap();
+        let krate = match rooted || components.is_empty() {
+            true => None,
+            false => Some(components.remove(0)),
+        };
+        Ok(orco::Symbol::new(orco::symbol::Path {
+            krate: krate.map(Into::into),
+            segments: components.into_iter().map(Into::into).collect(),
+            name: name.into(),
+            generics,
+            disambiguator,
+        }))
+    }
+
+    /// Optional generics in `<>`
+    fn generics(&mut self) -> PResult<Vec<Type>> {
+        match self.eat("<") {
+            true => self.list(">", Self::type_),
+            false => Ok(Vec::new()),
+        }
+    }
+
+    fn type_(&mut self) -> PResult<Type> {
+        let mut ty = self.type_atom()?;
+        while self.eat("[") {
+            let len = self.number()?;
+            self.expect("]")?;
+            ty = Type::Array(Box::new(ty), len);
+        }
+        Ok(ty)
+    }
+
+    fn type_atom(&mut self) -> PResult<Type> {
+        if self.eat("*") {
+            let mutable = self.eat_keyword("mut");
+            if !mutable {
+                self.expect_keyword("const")?;
+            }
+            return Ok(Type::Ptr(Box::new(self.type_()?), mutable));
+        }
+
+        if self.at("(") {
+            let start = self.pos;
+            self.pos += 1;
+            let params = self.list(")", Self::type_)?;
+            if self.eat("->") {
+                return Ok(Type::FnPtr {
+                    params,
+                    return_type: self.return_type()?.map(Box::new),
+                });
+            }
+            // Parenthesized type
+            return match <[Type; 1]>::try_from(params) {
+                Ok([ty]) => Ok(ty),
+                Err(_) => Err(self.error_at(start, "expected `->` after function pointer params")),
+            };
+        }
+
+        if self.eat("{") {
+            let fields = self.list("}", |parser| Ok((parser.name()?, parser.type_()?)))?;
+            return Ok(Type::Struct { fields });
+        }
+
+        if self.eat("#") {
+            return Ok(Type::Param(self.ident()?.into()));
+        }
+
+        if self.eat("<error>") {
+            return Ok(Type::Error);
+        }
+
+        let word = self.word();
+        if let Some(ty) = primitive(word) {
+            self.pos += word.len();
+            return Ok(ty);
+        }
+
+        let name = self.path()?;
+        Ok(Type::Symbol(name, self.generics()?))
+    }
+
+    /// Return type after `->`
+    fn return_type(&mut self) -> PResult<Option<Type>> {
+        match self.eat_keyword("void") {
+            true => Ok(None),
+            false => self.type_().map(Some),
+        }
+    }
+
+    /// Optional `name:` before a type (of a field or a param). Unquoted `_N` stands for no name
+    fn name(&mut self) -> PResult<Option<String>> {
+        let start = self.pos;
+        let name = if self.at("\"") {
+            Some(self.string()?)
+        } else {
+            let word = self.word();
+            if !is_plain_ident(word) && index(word, "_").is_none() {
+                return Ok(None);
+            }
+            self.pos += word.len();
+            is_plain_ident(word).then(|| word.to_owned())
+        };
+
+        if self.at(":") && !self.at("::") {
+            self.pos += 1;
+            return Ok(name);
+        }
+        self.pos = start;
+        Ok(None)
+    }
+
+    fn variable(&mut self) -> PResult<oc::Variable> {
+        let word = self.word();
+        let Some(idx) = index(word, "_") else {
+            return Err(self.error(format!("expected a variable, got `{word}`")));
+        };
+        if idx >= self.variables {
+            return Err(self.error(format!("undeclared variable _{idx}")));
+        }
+        self.pos += word.len();
+        Ok(oc::Variable(idx))
+    }
+
+    fn label(&mut self) -> PResult<oc::Label> {
+        let word = self.word();
+        let Some(idx) = index(word, "label") else {
+            return Err(self.error(format!("expected a label, got `{word}`")));
+        };
+        self.label_uses.push((idx, self.pos));
+        self.pos += word.len();
+        Ok(oc::Label(idx))
+    }
+
+    /// Source location (`file:line:column`), taking the whole `text`
+    fn location(&self, text: &str, pos: usize) -> PResult<orco::attrs::SourceLocation> {
+        let invalid = || self.error_at(pos, format!("invalid source location `{text}`"));
+        let mut parts = text.trim().rsplitn(3, ':');
+        let column = parts.next().and_then(|column| column.parse().ok());
+        let line = parts.next().and_then(|line| line.parse().ok());
+        match (parts.next(), line, column) {
+            (Some(file), Some(line), Some(column)) => Ok(orco::attrs::SourceLocation {
+                file: file.into(),
+                line,
+                column,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+
+    fn expr(&mut self) -> PResult<ir::Expression> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> PResult<ir::Expression> {
+        let Some((op, intrinsic)) = BINARY.get(level) else {
+            return self.unary();
+        };
+        let mut lhs = self.binary(level + 1)?;
+        while self.eat(op) {
+            let rhs = self.binary(level + 1)?;
+            lhs = ir::Expression::Intrinsic(intrinsic(Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> PResult<ir::Expression> {
+        if self.eat("!") {
+            let expr = self.unary()?;
+            return Ok(ir::Expression::Intrinsic(ir::Intrinsic::Not(Box::new(
+                expr,
+            ))));
+        }
+        if self.eat("&") {
+            let mutable = self.eat_keyword("mut");
+            if !mutable {
+                self.expect_keyword("const")?;
+            }
+            let start = self.pos;
+            let place = self.place(start)?;
+            return Ok(ir::Expression::Reference(place, mutable));
+        }
+        if self.eat("*") {
+            let expr = self.unary()?;
+            return Ok(ir::Expression::Read(ir::Place::Deref(Box::new(expr))));
+        }
+        self.postfix()
+    }
+
+    /// Parse a unary expression, that has to be a place
+    fn place(&mut self, start: usize) -> PResult<ir::Place> {
+        match self.unary()? {
+            ir::Expression::Read(place) => Ok(place),
+            _ => Err(self.error_at(start, "expected a place")),
+        }
+    }
+
+    fn postfix(&mut self) -> PResult<ir::Expression> {
+        self.skip_ws();
+        let start = self.pos;
+        let mut expr = self.primary()?;
+        loop {
+            if self.eat("(") {
+                let args = self.list(")", Self::expr)?;
+                expr = ir::Expression::Call(Box::new(expr), args);
+            } else if self.eat(".") {
+                let word = self.word();
+                let Some(idx) = index(word, "_") else {
+                    return Err(self.error(format!("expected a field index, got `{word}`")));
+                };
+                self.pos += word.len();
+                let ir::Expression::Read(place) = expr else {
+                    return Err(self.error_at(start, "expected a place"));
+                };
+                expr = ir::Expression::Read(ir::Place::Field(Box::new(place), idx));
+            } else if self.eat("[") {
+                let index = self.expr()?;
+                self.expect("]")?;
+                let ir::Expression::Read(place) = expr else {
+                    return Err(self.error_at(start, "expected a place"));
+                };
+                expr = ir::Expression::Read(ir::Place::Index(Box::new(place), Box::new(index)));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> PResult<ir::Expression> {
+        if self.eat("(") {
+            let expr = self.expr()?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+
+        let word = self.word();
+        match word {
+            "true" | "false" => {
+                self.pos += word.len();
+                return Ok(ir::Expression::BConst(word == "true"));
+            }
+            _ if index(word, "_").is_some() => {
+                return Ok(ir::Expression::Read(ir::Place::Variable(self.variable()?)));
+            }
+            _ => (),
+        }
+
+        let is_literal = self
+            .rest()
+            .starts_with(|c: char| c == '-' || c.is_ascii_digit())
+            || {
+                // `inf` and `NaN` are literals only when followed by `as`
+                let start = self.pos;
+                self.pos += word.len();
+                let is_literal = self.word() == "as";
+                self.pos = start;
+                is_literal && !word.is_empty()
+            };
+        if is_literal {
+            return self.literal();
+        }
+
+        let name = self.path()?;
+        Ok(ir::Expression::Read(ir::Place::Global(
+            name,
+            self.generics()?,
+        )))
+    }
+
+    /// `value as type`
+    fn literal(&mut self) -> PResult<ir::Expression> {
+        let start = self.pos;
+        let negative = self.rest().starts_with('-');
+        if negative {
+            self.pos += 1;
+        }
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.'))
+            .unwrap_or(rest.len());
+        let value = format!("{}{}", if negative { "-" } else { "" }, &rest[..len]);
+        self.pos += len;
+        self.expect_keyword("as")?;
+
+        let ty = self.word();
+        let invalid = || self.error_at(start, format!("invalid literal `{value}`"));
+        let expr = match primitive(ty) {
+            Some(Type::Integer(size)) => {
+                ir::Expression::IConst(value.parse().map_err(|_| invalid())?, size)
+            }
+            Some(Type::Unsigned(size)) => {
+                ir::Expression::UConst(value.parse().map_err(|_| invalid())?, size)
+            }
+            Some(Type::Float(size)) => {
+                ir::Expression::FConst(value.parse().map_err(|_| invalid())?, size)
+            }
+            _ => return Err(self.error(format!("expected a number type, got `{ty}`"))),
+        };
+        self.pos += ty.len();
+        Ok(expr)
+    }
+
+    fn body(&mut self) -> PResult<ir::Body> {
+        self.expect("{")?;
+        let mut body = ir::Body::default();
+
+        while self.eat_keyword("let") {
+            self.skip_ws();
+            if index(self.word(), "_") != Some(body.variables.len()) {
+                return Err(self.error(format!(
+                    "expected variable _{} (variables are declared in order)",
+                    body.variables.len(),
+                )));
+            }
+            self.pos += self.word().len();
+            self.expect(":")?;
+            let ty = self.type_()?;
+            let arg = self.eat("=");
+            if arg {
+                self.expect("<argument>")?;
+            }
+            self.expect(";")?;
+
+            // Debug name is a comment on the same line
+            let rest = self.rest();
+            let name = match rest.trim_start_matches([' ', '\t']).starts_with("//") {
+                true => {
+                    self.pos += rest.len() - rest.trim_start_matches([' ', '\t']).len();
+                    Some(self.comment())
+                }
+                false => None,
+            };
+            body.variables.push(ir::Variable { ty, arg, name });
+        }
+
+        self.variables = body.variables.len();
+        self.labels.clear();
+        self.label_uses.clear();
+        let mut depth = 0_usize;
+        loop {
+            self.skip_ws();
+            let start = self.pos;
+            if self.rest().is_empty() {
+                return Err(self.error("unclosed body, expected `}`"));
+            }
+
+            let word = self.word();
+            if let Some(label) = index(word, "label") {
+                self.pos += word.len();
+                self.expect(":")?;
+                if self.labels.len() <= label {
+                    self.labels.resize(label + 1, None);
+                }
+                if self.labels[label].replace(body.statements.len()).is_some() {
+                    return Err(self.error_at(start, format!("label{label} is already placed")));
+                }
+                continue;
+            }
+
+            let statement = if self.rest().starts_with("//") {
+                ir::Statement::Comment(self.comment())
+            } else if self.eat("}") {
+                if depth == 0 {
+                    break;
+                }
+                if self.eat_keyword("else") {
+                    self.expect("{")?;
+                    ir::Statement::Bcf(ir::BcfStatement::Else)
+                } else {
+                    depth -= 1;
+                    ir::Statement::Bcf(ir::BcfStatement::End)
+                }
+            } else if self.eat_keyword("location") {
+                let location = match self.eat_keyword("unknown") {
+                    true => None,
+                    false => {
+                        self.skip_ws();
+                        let pos = self.pos;
+                        let line = self.line();
+                        let Some(text) = line.trim_end().strip_suffix(';') else {
+                            return Err(self.error("expected `;`"));
+                        };
+                        self.pos = pos + text.len();
+                        Some(self.location(text, pos)?)
+                    }
+                };
+                self.expect(";")?;
+                ir::Statement::Location(location)
+            } else if self.eat_keyword("loop_attributes") {
+                let attrs = self.loop_attributes()?;
+                self.expect(";")?;
+                ir::Statement::LoopAttributes(attrs)
+            } else if self.eat_keyword("storage_live") {
+                let variable = self.variable()?;
+                self.expect(";")?;
+                ir::Statement::StorageLive(variable)
+            } else if self.eat_keyword("storage_dead") {
+                let variable = self.variable()?;
+                self.expect(";")?;
+                ir::Statement::StorageDead(variable)
+            } else if self.eat_keyword("return") {
+                let value = match self.at(";") {
+                    true => None,
+                    false => Some(self.expr()?),
+                };
+                self.expect(";")?;
+                ir::Statement::Return(value)
+            } else if self.eat_keyword("jump") {
+                let label = self.label()?;
+                let statement = match self.eat_keyword("if") {
+                    true => ir::AcfStatement::Cjump(self.expr()?, label),
+                    false => ir::AcfStatement::Jump(label),
+                };
+                self.expect(";")?;
+                ir::Statement::Acf(statement)
+            } else if self.eat_keyword("if") {
+                let condition = self.expr()?;
+                self.expect("{")?;
+                depth += 1;
+                ir::Statement::Bcf(ir::BcfStatement::If(condition))
+            } else if self.eat_keyword("loop") {
+                self.expect("{")?;
+                depth += 1;
+                ir::Statement::Bcf(ir::BcfStatement::Loop)
+            } else if self.eat_keyword("break") {
+                let statement = match self.eat_keyword("if") {
+                    true => ir::BcfStatement::Cbreak(self.expr()?),
+                    false => ir::BcfStatement::Break,
+                };
+                self.expect(";")?;
+                ir::Statement::Bcf(statement)
+            } else if self.eat_keyword("continue") {
+                let statement = match self.eat_keyword("if") {
+                    true => ir::BcfStatement::Ccontinue(self.expr()?),
+                    false => ir::BcfStatement::Continue,
+                };
+                self.expect(";")?;
+                ir::Statement::Bcf(statement)
+            } else {
+                let expr = self.expr()?;
+                let statement = if self.eat("=") {
+                    let ir::Expression::Read(place) = expr else {
+                        return Err(self.error_at(start, "expected a place"));
+                    };
+                    ir::Statement::Assign(place, self.expr()?)
+                } else if let ir::Expression::Call(func, args) = expr {
+                    ir::Statement::Call(*func, args)
+                } else {
+                    return Err(self.error_at(start, "expected a statement"));
+                };
+                self.expect(";")?;
+                statement
+            };
+            body.statements.push(statement);
+        }
+
+        if let Some(&(label, pos)) = self
+            .label_uses
+            .iter()
+            .find(|(label, _)| self.labels.get(*label).is_none_or(Option::is_none))
+        {
+            return Err(self.error_at(pos, format!("label{label} is never placed")));
+        }
+        body.labels = self.labels.iter().map(|idx| idx.unwrap_or(0)).collect();
+        Ok(body)
+    }
+
+    /// `[inline(mode)]`, `[destructor]`, `[internal]` and `[location(file:line:column)]`
+    fn attributes(&mut self) -> PResult<orco::attrs::FunctionAttributes> {
+        use orco::attrs::Inlining;
+        let mut attrs = orco::attrs::FunctionAttributes::default();
+        loop {
+            if self.eat("[inline(") {
+                attrs.inlining = match self.word() {
+                    "never" => Inlining::Never,
+                    "auto" => Inlining::Auto,
+                    "hint" => Inlining::Hint,
+                    "always" => Inlining::Always,
+                    mode => return Err(self.error(format!("unknown inlining mode `{mode}`"))),
+                };
+                self.pos += self.word().len();
+                self.expect(")]")?;
+            } else if self.eat("[destructor]") {
+                attrs.destructor = true;
+            } else if self.eat("[internal]") {
+                attrs.internal = true;
+            } else if self.eat("[location(") {
+                let pos = self.pos;
+                let Some((text, _)) = self.rest().split_once(")]") else {
+                    return Err(self.error("expected `)]`"));
+                };
+                attrs.location = Some(self.location(text, pos)?);
+                self.pos += text.len() + ")]".len();
+            } else {
+                return Ok(attrs);
+            }
+        }
+    }
+
+    /// `[unroll(mode)]`
+    fn loop_attributes(&mut self) -> PResult<orco::attrs::LoopAttributes> {
+        use orco::attrs::Unrolling;
+        let mut attrs = orco::attrs::LoopAttributes::default();
+        while self.eat("[unroll(") {
+            attrs.unrolling = match self.word() {
+                "never" => Unrolling::Never,
+                "auto" => Unrolling::Auto,
+                mode => return Err(self.error(format!("unknown unrolling mode `{mode}`"))),
+            };
+            self.pos += self.word().len();
+            self.expect(")]")?;
+        }
+        Ok(attrs)
+    }
+
+    fn store(&mut self, store: &Store) -> PResult<()> {
+        use orco::DeclarationBackend as _;
+        let mut function = None;
+        loop {
+            self.skip_ws();
+            let start = self.pos;
+            if self.rest().is_empty() {
+                return Ok(());
+            }
+            if self.rest().starts_with("//") {
+                self.comment();
+                continue;
+            }
+
+            let result = if self.eat_keyword("type") {
+                let name = self.path()?;
+                let generics = self.generics()?;
+                self.expect("=")?;
+                let ty = self.type_()?;
+                self.expect(";")?;
+                store.type_(name, generics, ty)
+            } else if self.eat_keyword("static") {
+                let name = self.path()?;
+                self.expect(":")?;
+                let ty = self.type_()?;
+                self.expect(";")?;
+                store.global(name, ty)
+            } else if self.eat_keyword("for") {
+                let Some(name) = function else {
+                    return Err(self.error_at(start, "specialization outside of a function"));
+                };
+                let generics = self.generics()?;
+                let body = self.body()?;
+                let functions = store.functions.pin();
+                let decl = functions
+                    .get(&name)
+                    .expect("[bug?] function got undeclared while parsing");
+                match decl
+                    .bodies
+                    .pin()
+                    .try_insert(crate::generics::intern(&generics), body)
+                {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(orco::Error::AlreadyDefined(name, generics)),
+                }
+            } else {
+                let attrs = self.attributes()?;
+                self.expect_keyword("fn")?;
+                let name = self.path()?;
+                let generic_params = self.generics()?;
+                self.expect("(")?;
+                let params = self.list(")", |parser| Ok((parser.name()?, parser.type_()?)))?;
+                self.expect("->")?;
+                let return_type = self.return_type()?;
+                self.expect(";")?;
+                function = Some(name);
+                store.function(name, generic_params, params, return_type, attrs)
+            };
+            result.map_err(|err| self.error_at(start, err.to_string()))?;
+        }
+    }
+}
+
+/// Number after `prefix`, f.e. `_0` or `label1`
+fn index(word: &str, prefix: &str) -> Option<usize> {
+    let number = word.strip_prefix(prefix)?;
+    if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    number.parse().ok()
+}
+
+/// Primitive type by name, f.e. `i32`
+fn primitive(word: &str) -> Option<Type> {
+    use orco::types::IntegerSize;
+    let size = |bits: &str| match bits {
+        "size" => Some(IntegerSize::Size),
+        bits => bits.parse().ok().map(IntegerSize::Bits),
+    };
+    match word {
+        "bool" => Some(Type::Bool),
+        "achar" => Some(Type::Char(false)),
+        "uchar" => Some(Type::Char(true)),
+        _ => match word.split_at_checked(1)? {
+            ("i", bits) => size(bits).map(Type::Integer),
+            ("u", bits) => size(bits).map(Type::Unsigned),
+            ("f", bits) => bits.parse().ok().map(Type::Float),
+            _ => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: &[&str] = &[
+        "type pair<#T> = { first: #T, second: #T };
+type point = { x: i32, y: i32 };
+
+static counters: u64[4];
+static origin: point;
+
+[inline(always)] [location(src/lib.rs:3:5)] fn add(a: i32, b: i32) -> i32;
+for  {
+  let _0: i32 = <argument>; // a
+  let _1: i32 = <argument>; // b
+  location src/lib.rs:4:9;
+  return _0 + _1;
+}
+
+[internal] fn helper() -> void;
+
+fn id<#T>(x: #T) -> #T;
+for <#T> {
+  let _0: #T = <argument>;
+  return _0;
+}
+
+[destructor] fn teardown() -> void;
+",
+        "fn sum(p: *mut point, f: (i32, i32) -> i32) -> i32;
+for  {
+  let _0: *mut point = <argument>;
+  let _1: (i32, i32) -> i32 = <argument>;
+  let _2: i32;
+  let _3: *const i32;
+  storage_live _2;
+  _2 = _1((*_0)._0, (*_0)._1);
+  _3 = &const (*_0)._1;
+  counters[1 as usize] = counters[1 as usize] + 1 as u64;
+  storage_dead _2;
+  return *_3 * -2 as i32;
+}
+",
+        "fn control(n: u32, flag: bool) -> f64;
+for  {
+  let _0: u32 = <argument>;
+  let _1: bool = <argument>;
+  let _2: f64;
+  _2 = 0.5 as f64;
+  // a comment
+  loop {
+    loop_attributes [unroll(never)];
+    break if _0 == 0 as u32;
+    continue if !_1;
+    if _1 == false {
+      _2 = _2 * 2 as f64;
+    } else {
+      _2 = id<f64>(_2);
+    }
+    _0 = _0 + 4294967295 as u32;
+  }
+label0:
+  jump label1 if _1;
+  _1 = true;
+  jump label0;
+label1:
+  location unknown;
+  return _2;
+}
+",
+    ];
+
+    #[test]
+    fn round_trip() {
+        for sample in SAMPLES {
+            let printed = parse(sample).unwrap().to_string();
+            assert_eq!(parse(&printed).unwrap().to_string(), printed);
+            assert_eq!(printed.trim(), sample.trim(), "sample isn't canonical");
+        }
+    }
+
+    #[test]
+    fn round_trip_types() {
+        for ty in [
+            "i32",
+            "*const *mut u8",
+            "{ a: i32, b: f64[3] }",
+            "(usize, *const achar) -> void",
+            "pair<#T>[2][4]",
+        ] {
+            assert_eq!(parse_type(ty).unwrap().to_string(), ty);
+        }
+    }
+
+    /// Line, column and message of the error
+    fn error(source: &str) -> (usize, usize, String) {
+        let err = parse(source).unwrap_err();
+        (err.line, err.column, err.message)
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            (
+                "fn f() -> i32;\nfor {\n  return 1;\n}\n",
+                3,
+                11,
+                "expected `as`",
+            ),
+            (
+                "fn f() -> i32;\nfor {\n  let _1: i32;\n}\n",
+                3,
+                7,
+                "expected variable _0 (variables are declared in order)",
+            ),
+            (
+                "fn f() -> i32;\nfor {\n  return _4;\n}\n",
+                3,
+                10,
+                "undeclared variable _4",
+            ),
+            (
+                "fn f() -> void;\nfor {\n  jump label3;\n}\n",
+                3,
+                8,
+                "label3 is never placed",
+            ),
+            (
+                "fn f() -> void;\nfor {\nlabel0:\nlabel0:\n  return;\n}\n",
+                4,
+                1,
+                "label0 is already placed",
+            ),
+            (
+                "fn f() -> void;\nfor {\n  return;\n",
+                4,
+                1,
+                "unclosed body, expected `}`",
+            ),
+            (
+                "for {\n  return;\n}\n",
+                1,
+                1,
+                "specialization outside of a function",
+            ),
+            (
+                "static x: i32;\nstatic x: u8;\n",
+                2,
+                1,
+                "global x is already declared",
+            ),
+            (
+                "fn f() -> void;\nfor {\n  1 as i32;\n}\n",
+                3,
+                3,
+                "expected a statement",
+            ),
+            (
+                "fn f() -> i32;\nfor {\n  let _0: i32;\n  &mut _0 = 1 as i32;\n}\n",
+                4,
+                3,
+                "expected a place",
+            ),
+            (
+                "fn f() -> void;\nfor {\n  loop_attributes [unroll(sometimes)];\n}\n",
+                3,
+                27,
+                "unknown unrolling mode `sometimes`",
+            ),
+            (
+                "fn f() -> void;\nfor {\n  return 1.5 as i32;\n}\n",
+                3,
+                10,
+                "invalid literal `1.5`",
+            ),
+            ("fn f(x: i32 -> void;\n", 1, 13, "expected `,`"),
+            ("type t = *i32;\n", 1, 11, "expected `const`"),
+        ];
+        for (source, line, column, message) in cases {
+            assert_eq!(
+                error(source),
+                (line, column, message.to_owned()),
+                "{source}"
+            );
+        }
+    }
+}
@@ -0,0 +1,411 @@
+use crate::{Store, ir, verify::VerifyError};
+use std::time::{Duration, Instant};
+
+/// Transformation of a single function body, see [`PassKind::Function`]
+pub type FunctionPass = dyn Fn(&mut ir::Body) -> bool + Send + Sync;
+
+/// Transformation of a whole store, see [`PassKind::Store`]
+pub type StorePass = dyn Fn(&mut Store) -> Result<bool, String> + Send + Sync;
+
+/// What a pass transforms
+pub enum PassKind {
+    /// Transforms every function body on its own, see [`Store::transform_bodies`].
+    /// Returns whether the body changed
+    Function(Box<FunctionPass>),
+    /// Transforms the whole store. Returns whether anything changed
+    Store(Box<StorePass>),
+}
+
+/// A named transformation, see [`PassManager::register`]
+pub struct Pass {
+    #[allow(missing_docs)]
+    pub name: &'static str,
+    /// One line description for listings
+    pub description: &'static str,
+    /// Passes that have to run before this one. They are scheduled
+    /// automatically, unless they already ran earlier in the pipeline
+    pub requires: &'static [&'static str],
+    #[allow(missing_docs)]
+    pub kind: PassKind,
+}
+
+impl Pass {
+    /// A pass that transforms every function body on its own
+    pub fn function(
+        name: &'static str,
+        description: &'static str,
+        pass: impl Fn(&mut ir::Body) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name,
+            description,
+            requires: &[],
+            kind: PassKind::Function(Box::new(pass)),
+        }
+    }
+
+    /// A pass that transforms the whole store
+    pub fn store(
+        name: &'static str,
+        description: &'static str,
+        pass: impl Fn(&mut Store) -> Result<bool, String> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name,
+            description,
+            requires: &[],
+            kind: PassKind::Store(Box::new(pass)),
+        }
+    }
+
+    /// Set [`Self::requires`]
+    #[must_use]
+    pub fn requires(mut self, requires: &'static [&'static str]) -> Self {
+        self.requires = requires;
+        self
+    }
+
+    /// Run the pass on a store. Returns whether anything changed
+    pub fn run(&self, store: &mut Store) -> Result<bool, String> {
+        match &self.kind {
+            PassKind::Function(pass) => Ok(store.transform_bodies(|body| pass(body))),
+            PassKind::Store(pass) => pass(store),
+        }
+    }
+}
+
+/// Statistics of one pass run, see [`PassManager::run`]
+#[derive(Clone, Debug)]
+pub struct PassStats {
+    #[allow(missing_docs)]
+    pub name: &'static str,
+    /// Time the pass took, not counting verification
+    pub duration: Duration,
+    /// Whether the pass changed anything
+    pub changed: bool,
+}
+
+impl std::fmt::Display for PassStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:16} {:>10.3}ms{}",
+            self.name,
+            self.duration.as_secs_f64() * 1000.0,
+            if self.changed { "" } else { " (no changes)" }
+        )
+    }
+}
+
+/// Error, that occured while scheduling or running passes
+#[derive(Clone, Debug)]
+pub enum PassError {
+    /// There is no pass or pipeline with this name
+    Unknown(String),
+    /// Passes require each other (the chain is listed)
+    Cycle(Vec<&'static str>),
+    /// A pass failed
+    Failed {
+        #[allow(missing_docs)]
+        pass: &'static str,
+        #[allow(missing_docs)]
+        message: String,
+    },
+    /// Verification found problems after a pass ([None] means before the first one)
+    Verify {
+        #[allow(missing_docs)]
+        after: Option<&'static str>,
+        #[allow(missing_docs)]
+        errors: Vec<VerifyError>,
+    },
+}
+
+impl std::fmt::Display for PassError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unknown(name) => write!(f, "unknown pass or pipeline {name:?}"),
+            Self::Cycle(chain) => write!(f, "passes require each other: {}", chain.join(" -> ")),
+            Self::Failed { pass, message } => write!(f, "pass {pass}: {message}"),
+            Self::Verify { after, errors } => {
+                match after {
+                    Some(pass) => write!(f, "verification failed after pass {pass}")?,
+                    None => write!(f, "verification failed before running passes")?,
+                }
+                write!(f, " with {} errors", errors.len())?;
+                for err in errors {
+                    write!(f, "\n  {err}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for PassError {}
+
+/// Registry of passes and pipelines (named lists of passes and other pipelines).
+/// Pipelines `O0`, `O1`, `O2`, `O3`, `Os` and `Oz` correspond to optimization levels,
+/// see [`PassManager::opt_level`]
+pub struct PassManager {
+    passes: Vec<Pass>,
+    pipelines: Vec<(&'static str, Vec<&'static str>)>,
+    /// Verify the store before the first pass and after each pass, see [`Store::verify`]
+    pub verify: bool,
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PassManager {
+    /// A pass manager with all passes and pipelines this crate provides
+    #[must_use]
+    pub fn new() -> Self {
+        let mut manager = Self::empty();
+        manager.register(Pass::store(
+            "poly",
+            "polymorphic lowering, adds a `name$poly` twin of each generic function",
+            |store| {
+                let lowered = store.lower_polymorphic().map_err(|err| err.to_string())?;
+                let changed = lowered.functions.len() != store.functions.len();
+                *store = lowered;
+                Ok(changed)
+            },
+        ));
+        manager.register(Pass::store(
+            "always-inline",
+            "inline functions marked as always inline",
+            |store| Ok(store.inline_functions(crate::opt::InlineLimits::ALWAYS)),
+        ));
+        manager.register(Pass::store(
+            "inline",
+            "inline small functions and functions marked as always inline",
+            |store| Ok(store.inline_functions(crate::opt::InlineLimits::default())),
+        ));
+        manager.register(Pass::store(
+            "size-inline",
+            "inline functions no larger than a call and functions marked as always inline",
+            |store| Ok(store.inline_functions(crate::opt::InlineLimits::SIZE)),
+        ));
+        manager.register(Pass::store(
+            "sroa",
+            "scalar replacement of aggregates, splits struct variables into fields",
+            |store| Ok(store.split_aggregates()),
+        ));
+        manager.register(Pass::store("licm", "loop-invariant code motion", |store| {
+            Ok(store.hoist_loop_invariants())
+        }));
+        manager.register(
+            Pass::function(
+                "unroll",
+                "full unrolling of loops with a small constant number of iterations",
+                |body| body.unroll_loops(crate::opt::UnrollLimits::default()),
+            )
+            // Loop bounds have to be folded into constants first
+            .requires(&["simplify"]),
+        );
+        manager.register(Pass::function(
+            "simplify",
+            "all scalar optimizations below, until nothing changes",
+            ir::Body::simplify,
+        ));
+        manager.register(Pass::function(
+            "fold",
+            "constant folding",
+            ir::Body::fold_constants,
+        ));
+        manager.register(Pass::function(
+            "const-branches",
+            "constant branch elimination",
+            ir::Body::eliminate_constant_branches,
+        ));
+        manager.register(Pass::function(
+            "copy-prop",
+            "copy and constant propagation",
+            ir::Body::propagate_copies,
+        ));
+        manager.register(Pass::function(
+            "forward",
+            "forwarding of variables assigned once into their uses",
+            ir::Body::forward_single_assignments,
+        ));
+        manager.register(Pass::function(
+            "dce",
+            "dead store and dead variable elimination",
+            ir::Body::eliminate_dead_code,
+        ));
+        manager.register(Pass::function(
+            "thread-jumps",
+            "jump threading",
+            ir::Body::thread_jumps,
+        ));
+        manager.register(Pass::function(
+            "unreachable",
+            "unreachable code removal",
+            ir::Body::remove_unreachable,
+        ));
+        manager.register(Pass::function(
+            "unused-labels",
+            "unused label removal",
+            ir::Body::remove_unused_labels,
+        ));
+
+        manager.register_pipeline("O0", &["always-inline"]);
+        manager.register_pipeline("O1", &["always-inline", "simplify"]);
+        manager.register_pipeline(
+            "O2",
+            &["inline", "sroa", "forward", "simplify", "licm", "simplify"],
+        );
+        manager.register_pipeline("O3", &["O2", "unroll", "simplify"]);
+        // No loop-invariant code motion or unrolling, both trade size for speed
+        manager.register_pipeline("Os", &["size-inline", "sroa", "forward", "simplify"]);
+        manager.register_pipeline("Oz", &["always-inline", "sroa", "forward", "simplify"]);
+        manager
+    }
+
+    /// A pass manager without any passes or pipelines
+    #[must_use]
+    pub fn empty() -> Self {
+        Self {
+            passes: Vec::new(),
+            pipelines: Vec::new(),
+            verify: false,
+        }
+    }
+
+    /// Add a pass, replacing the one with the same name
+    pub fn register(&mut self, pass: Pass) {
+        match self.passes.iter_mut().find(|other| other.name == pass.name) {
+            Some(other) => *other = pass,
+            None => self.passes.push(pass),
+        }
+    }
+
+    /// Add a pipeline, replacing the one with the same name.
+    /// Elements can be passes or other pipelines
+    pub fn register_pipeline(&mut self, name: &'static str, passes: &[&'static str]) {
+        let passes = passes.to_vec();
+        match self.pipelines.iter_mut().find(|other| other.0 == name) {
+            Some(other) => other.1 = passes,
+            None => self.pipelines.push((name, passes)),
+        }
+    }
+
+    /// All registered passes, in registration order
+    pub fn passes(&self) -> impl Iterator<Item = &Pass> {
+        self.passes.iter()
+    }
+
+    /// All registered pipelines with their elements, in registration order
+    pub fn pipelines(&self) -> impl Iterator<Item = (&'static str, &[&'static str])> {
+        self.pipelines
+            .iter()
+            .map(|(name, passes)| (*name, passes.as_slice()))
+    }
+
+    /// Pipeline name for an optimization level as `-C opt-level` spells it
+    /// (`0`, `1`, `2`, `3`, `s` or `z`)
+    #[must_use]
+    pub fn opt_level(level: &str) -> Option<&'static str> {
+        Some(match level {
+            "0" => "O0",
+            "1" => "O1",
+            "2" => "O2",
+            "3" => "O3",
+            "s" => "Os",
+            "z" => "Oz",
+            _ => return None,
+        })
+    }
+
+    /// Resolve pass and pipeline names into the list of passes to run, in order.
+    /// Pipelines are expanded and passes are preceded by the passes they require
+    pub fn schedule(&self, names: &[&str]) -> Result<Vec<&Pass>, PassError> {
+        let mut scheduled = Vec::new();
+        let mut stack = Vec::new();
+        for name in names {
+            self.schedule_name(name, &mut scheduled, &mut stack)?;
+        }
+        Ok(scheduled)
+    }
+
+    fn schedule_name<'a>(
+        &'a self,
+        name: &str,
+        scheduled: &mut Vec<&'a Pass>,
+        stack: &mut Vec<&'static str>,
+    ) -> Result<(), PassError> {
+        let cycle = |stack: &[&'static str], name: &'static str| {
+            let start = stack.iter().position(|other| *other == name).unwrap();
+            let mut chain = stack[start..].to_vec();
+            chain.push(name);
+            PassError::Cycle(chain)
+        };
+
+        if let Some((name, passes)) = self.pipelines.iter().find(|(other, _)| *other == name) {
+            if stack.contains(name) {
+                return Err(cycle(stack, name));
+            }
+            stack.push(name);
+            for pass in passes {
+                self.schedule_name(pass, scheduled, stack)?;
+            }
+            stack.pop();
+            return Ok(());
+        }
+
+        let pass = self
+            .passes
+            .iter()
+            .find(|pass| pass.name == name)
+            .ok_or_else(|| PassError::Unknown(name.to_owned()))?;
+        if stack.contains(&pass.name) {
+            return Err(cycle(stack, pass.name));
+        }
+        stack.push(pass.name);
+        for required in pass.requires {
+            if !scheduled.iter().any(|pass| pass.name == *required) {
+                self.schedule_name(required, scheduled, stack)?;
+            }
+        }
+        stack.pop();
+        scheduled.push(pass);
+        Ok(())
+    }
+
+    /// Schedule (see [`Self::schedule`]) and run passes, verifying
+    /// the store in between if [`Self::verify`] is set. Returns statistics of each pass run
+    pub fn run(&self, store: &mut Store, names: &[&str]) -> Result<Vec<PassStats>, PassError> {
+        let passes = self.schedule(names)?;
+        let verify = |store: &Store, after: Option<&'static str>| {
+            if !self.verify {
+                return Ok(());
+            }
+            let errors = store.verify();
+            if errors.is_empty() {
+                Ok(())
+            } else {
+                Err(PassError::Verify { after, errors })
+            }
+        };
+
+        verify(store, None)?;
+        let mut stats = Vec::with_capacity(passes.len());
+        for pass in passes {
+            let start = Instant::now();
+            let changed = pass.run(store).map_err(|message| PassError::Failed {
+                pass: pass.name,
+                message,
+            })?;
+            stats.push(PassStats {
+                name: pass.name,
+                duration: start.elapsed(),
+                changed,
+            });
+            verify(store, Some(pass.name))?;
+        }
+        Ok(stats)
+    }
+}
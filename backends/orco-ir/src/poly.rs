@@ -0,0 +1,765 @@
+//! Polymorphic lowering: compile generic functions once, instead of monomorphizing them.
+//!
+//! A lowered function takes a pointer to a type descriptor (see [`descriptor_type`])
+//! for each of its type params as hidden leading parameters. Values of a type param
+//! are opaque and live behind pointers: variables hold a pointer to their storage,
+//! which is allocated on function entry (with [`alloc`]) and freed on return (with [`free`]).
+//! Generic arguments are passed as pointers to copies, which are owned by the callee,
+//! generic return values are written through a hidden `__ret` pointer, passed after the descriptors.
+//! Assigning to a generic place drops the value it held, so does `storage_dead`
+//! and returning (before the storage is freed). Variables track whether they hold a value
+//! with a hidden flag, places behind pointers are assumed to always hold one.
+//!
+//! Descriptors, [`alloc`] and [`free`] are provided by the host (f.e. C code using
+//! the library), so only type params themselves can be passed to other generic functions.
+//! Functions using aggregates containing type params (`Vec<T>`, `(T, u8)`, ...),
+//! calling generic functions with concrete generic args, using generic calls
+//! inside of expressions or calling generic functions that can't be lowered themselves
+//! are skipped: they are still monomorphized as usual
+use crate::generics::{intern, match_type_params};
+use crate::{Store, ir};
+use orco::Type;
+use orco::codegen as oc;
+use orco::sinter::IStr;
+use orco::symbol::Path;
+use orco::types::TypeKind;
+use std::collections::{HashMap, HashSet};
+
+/// Type descriptor symbol, see [`descriptor_type`]
+#[must_use]
+pub fn descriptor() -> orco::Symbol {
+    "__orco_type_descriptor".into()
+}
+
+/// Fields of a type descriptor: `size`, `align`, `copy(dst, src)` and `drop(ptr)`
+#[must_use]
+pub fn descriptor_type() -> Type {
+    let usize = Type::Unsigned(orco::types::IntegerSize::Size);
+    Type::Struct {
+        fields: vec![
+            (Some("size".to_owned()), usize.clone()),
+            (Some("align".to_owned()), usize),
+            (
+                Some("copy".to_owned()),
+                Type::FnPtr {
+                    params: vec![opaque(true), opaque(false)],
+                    return_type: None,
+                },
+            ),
+            (
+                Some("drop".to_owned()),
+                Type::FnPtr {
+                    params: vec![opaque(true)],
+                    return_type: None,
+                },
+            ),
+        ],
+    }
+}
+
+/// `alloc(size, align) -> *mut ?`, provided by the host
+#[must_use]
+pub fn alloc() -> orco::Symbol {
+    "__orco_poly_alloc".into()
+}
+
+/// `free(ptr: *mut ?)`, provided by the host
+#[must_use]
+pub fn free() -> orco::Symbol {
+    "__orco_poly_free".into()
+}
+
+/// Generic function calls to this symbol (`__drop_in_place<T>(ptr: *mut T)`)
+/// are lowered to a call to `drop` of the type descriptor
+#[must_use]
+pub fn drop_in_place() -> orco::Symbol {
+    "__drop_in_place".into()
+}
+
+/// Name of the polymorphic version of a generic function
+#[must_use]
+pub fn poly_name(name: orco::Symbol) -> orco::Symbol {
+    orco::Symbol::new(Path {
+        name: format!("{}$poly", name.name).into(),
+        ..name.path().clone()
+    })
+}
+
+fn opaque(mutable: bool) -> Type {
+    Type::Ptr(Box::new(Type::Error), mutable)
+}
+
+fn descriptor_ptr() -> Type {
+    Type::Ptr(Box::new(Type::Symbol(descriptor(), Vec::new())), false)
+}
+
+/// Type params of a function, if its generic params are all plain type params
+fn type_params(generic_params: &[Type]) -> Option<Vec<IStr>> {
+    generic_params
+        .iter()
+        .map(|ty| match ty {
+            Type::Param(name) => Some(*name),
+            _ => None,
+        })
+        .collect()
+}
+
+fn param_of(ty: &Type) -> Option<IStr> {
+    match ty {
+        Type::Param(name) => Some(*name),
+        _ => None,
+    }
+}
+
+/// A function can't be lowered, so it is skipped, see module docs
+struct Unsupported;
+
+type Lower<T> = Result<T, Unsupported>;
+
+/// Representation of a type in polymorphic code
+fn lower_ty(ty: &Type) -> Lower<Type> {
+    if !ty.has_params() {
+        return Ok(ty.clone());
+    }
+    match ty {
+        Type::Param(..) => Ok(opaque(true)),
+        Type::Ptr(ty, mutable) if matches!(**ty, Type::Param(..)) => Ok(opaque(*mutable)),
+        Type::Ptr(ty, mutable) => Ok(Type::Ptr(Box::new(lower_ty(ty)?), *mutable)),
+        _ => Err(Unsupported),
+    }
+}
+
+/// Declare the symbols provided by the host, unless they are declared already
+/// (f.e. by an earlier run). Fails if they are declared differently
+fn declare_runtime(store: &Store) -> Result<(), orco::Error> {
+    use orco::DeclarationBackend as _;
+
+    match store.types.pin().get(&descriptor()) {
+        Some(specs) if specs.pin().get(&Vec::new()) == Some(&(&descriptor_type()).into()) => (),
+        Some(_) => {
+            return Err(orco::Error::AlreadyDeclared(
+                orco::error::SymbolKind::Type,
+                descriptor(),
+                Vec::new(),
+            ));
+        }
+        None => store.type_(descriptor(), Vec::new(), descriptor_type())?,
+    }
+
+    let usize = Type::Unsigned(orco::types::IntegerSize::Size);
+    let runtime = [
+        (
+            alloc(),
+            vec![
+                (Some("size".to_owned()), usize.clone()),
+                (Some("align".to_owned()), usize),
+            ],
+            Some(opaque(true)),
+        ),
+        (free(), vec![(Some("ptr".to_owned()), opaque(true))], None),
+    ];
+    for (name, params, return_type) in runtime {
+        match store.functions.pin().get(&name) {
+            Some(function)
+                if function.generic_params.is_empty()
+                    && function.signature.params == params
+                    && function.signature.return_type == return_type => {}
+            Some(_) => {
+                return Err(orco::Error::AlreadyDeclared(
+                    orco::error::SymbolKind::Function,
+                    name,
+                    Vec::new(),
+                ));
+            }
+            None => store.function(name, Vec::new(), params, return_type, Default::default())?,
+        }
+    }
+    Ok(())
+}
+
+impl Store {
+    /// Add a polymorphic version (see [`poly_name`] and module docs) of every generic function
+    /// with a fully generic body, that can be lowered. Monomorphic code is left as is.
+    /// Functions that have a polymorphic version already are skipped,
+    /// so lowering again doesn't change anything.
+    /// Fails if runtime functions are already declared with a different signature
+    pub fn lower_polymorphic(&self) -> Result<Store, orco::Error> {
+        let lowered = self.clone();
+        declare_runtime(&lowered)?;
+
+        let functions = self.functions.pin();
+        let mut candidates = HashMap::new();
+        for (name, function) in functions.iter() {
+            if functions.contains_key(&poly_name(*name)) {
+                continue;
+            }
+            let Some(params) = type_params(&function.generic_params) else {
+                continue;
+            };
+            if params.is_empty() {
+                continue;
+            }
+            let bodies = function.bodies.pin();
+            let Some(body) = bodies.get(&intern(&function.generic_params)) else {
+                continue;
+            };
+            if let Ok(function) = lower_function(self, function, body, &params) {
+                candidates.insert(*name, function);
+            }
+        }
+
+        // Callers of generic functions that weren't lowered can't be lowered either
+        loop {
+            let missing = candidates
+                .iter()
+                .filter(|(_, (_, callees))| {
+                    callees.iter().any(|callee| {
+                        !candidates.contains_key(callee)
+                            && !functions.contains_key(&poly_name(*callee))
+                    })
+                })
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>();
+            if missing.is_empty() {
+                break;
+            }
+            for name in missing {
+                candidates.remove(&name);
+            }
+        }
+
+        let functions = lowered.functions.pin();
+        for (name, (function, _)) in candidates {
+            functions.insert(poly_name(name), function);
+        }
+        drop(functions);
+        Ok(lowered)
+    }
+}
+
+/// Lower a function, returning it along with the generic functions it calls
+fn lower_function(
+    store: &Store,
+    function: &crate::Function,
+    body: &ir::Body,
+    params: &[IStr],
+) -> Lower<(crate::Function, HashSet<orco::Symbol>)> {
+    let returns_generic = function
+        .signature
+        .return_type
+        .as_ref()
+        .and_then(param_of)
+        .is_some();
+    let mut signature = function.signature.clone();
+    let mut hidden = params
+        .iter()
+        .map(|param| (Some(format!("__type_{param}")), descriptor_ptr()))
+        .collect::<Vec<_>>();
+    if returns_generic {
+        hidden.push((Some("__ret".to_owned()), opaque(true)));
+        signature.return_type = None;
+    }
+    for (_, ty) in &mut signature.params {
+        *ty = lower_ty(ty)?;
+    }
+    signature.return_type = signature.return_type.as_ref().map(lower_ty).transpose()?;
+    hidden.append(&mut signature.params);
+    signature.params = hidden;
+
+    let (body, callees) = Lowering::new(store, body, params, returns_generic)?.lower()?;
+    let function = crate::Function {
+        generic_params: Vec::new(),
+        signature,
+        bodies: [(Vec::new(), body)].into_iter().collect(),
+    };
+    Ok((function, callees))
+}
+
+/// A generic variable, owning its storage
+#[derive(Clone, Copy)]
+struct Owned {
+    storage: oc::Variable,
+    param: IStr,
+    /// Flag telling if the storage holds a value, which has to be dropped
+    init: oc::Variable,
+}
+
+/// Lowering of one function body
+struct Lowering<'a> {
+    store: &'a Store,
+    old: &'a ir::Body,
+    body: ir::Body,
+    /// Old variable index to new variable
+    variables: Vec<oc::Variable>,
+    /// Old variable index to its storage, for generic variables
+    owned: Vec<Option<Owned>>,
+    /// Type param to the variable holding its descriptor
+    descriptors: HashMap<IStr, oc::Variable>,
+    /// Hidden return pointer
+    ret: Option<oc::Variable>,
+    /// The body uses BCF, so conditional drops are `if`s rather than jumps
+    bcf: bool,
+    /// Statements the labels added by the lowering are placed at
+    labels: Vec<usize>,
+    /// Generic functions called, their polymorphic versions are called instead
+    callees: HashSet<orco::Symbol>,
+}
+
+fn read(variable: oc::Variable) -> ir::Expression {
+    ir::Expression::Read(ir::Place::Variable(variable))
+}
+
+impl<'a> Lowering<'a> {
+    fn new(
+        store: &'a Store,
+        old: &'a ir::Body,
+        params: &[IStr],
+        returns_generic: bool,
+    ) -> Lower<Self> {
+        let mut this = Self {
+            store,
+            old,
+            body: ir::Body::default(),
+            variables: Vec::with_capacity(old.variables.len()),
+            owned: vec![None; old.variables.len()],
+            descriptors: HashMap::new(),
+            ret: None,
+            bcf: old
+                .statements
+                .iter()
+                .any(|statement| matches!(statement, ir::Statement::Bcf(..))),
+            labels: Vec::new(),
+            callees: HashSet::new(),
+        };
+
+        for param in params {
+            let variable = this.var(descriptor_ptr(), true, Some(format!("__type_{param}")));
+            this.descriptors.insert(*param, variable);
+        }
+        if returns_generic {
+            this.ret = Some(this.var(opaque(true), true, Some("__ret".to_owned())));
+        }
+        for variable in &old.variables {
+            let new = this.var(lower_ty(&variable.ty)?, variable.arg, variable.name.clone());
+            this.variables.push(new);
+        }
+        Ok(this)
+    }
+
+    fn var(&mut self, ty: Type, arg: bool, name: Option<String>) -> oc::Variable {
+        self.body.variables.push(ir::Variable { ty, arg, name });
+        oc::Variable(self.body.variables.len() - 1)
+    }
+
+    fn emit(&mut self, statement: ir::Statement) {
+        self.body.statements.push(statement);
+    }
+
+    fn lower(mut self) -> Lower<(ir::Body, HashSet<orco::Symbol>)> {
+        // Prologue: allocate storage of generic variables, arguments are owned already
+        for (idx, variable) in self.old.variables.iter().enumerate() {
+            let Some(param) = param_of(&variable.ty) else {
+                continue;
+            };
+            let storage = self.variables[idx];
+            if !variable.arg {
+                let allocated = self.alloc(param)?;
+                self.emit(ir::Statement::Assign(
+                    ir::Place::Variable(storage),
+                    allocated,
+                ));
+            }
+            let init = self.var(Type::Bool, false, None);
+            self.emit(ir::Statement::Assign(
+                ir::Place::Variable(init),
+                ir::Expression::BConst(variable.arg),
+            ));
+            self.owned[idx] = Some(Owned {
+                storage,
+                param,
+                init,
+            });
+        }
+
+        let mut statement_map = Vec::with_capacity(self.old.statements.len() + 1);
+        for statement in &self.old.statements {
+            statement_map.push(self.body.statements.len());
+            self.statement(statement)?;
+        }
+        statement_map.push(self.body.statements.len());
+
+        self.body.labels = self
+            .old
+            .labels
+            .iter()
+            .map(|statement| statement_map[*statement])
+            .chain(self.labels)
+            .collect();
+        Ok((self.body, self.callees))
+    }
+
+    fn descriptor_field(&self, param: IStr, idx: usize) -> Lower<ir::Expression> {
+        let descriptor = self.descriptors.get(&param).ok_or(Unsupported)?;
+        Ok(ir::Expression::Read(ir::Place::Field(
+            Box::new(ir::Place::Deref(Box::new(read(*descriptor)))),
+            idx,
+        )))
+    }
+
+    fn global(name: orco::Symbol) -> Box<ir::Expression> {
+        Box::new(ir::Expression::Read(ir::Place::Global(name, Vec::new())))
+    }
+
+    fn alloc(&self, param: IStr) -> Lower<ir::Expression> {
+        Ok(ir::Expression::Call(
+            Self::global(alloc()),
+            vec![
+                self.descriptor_field(param, 0)?,
+                self.descriptor_field(param, 1)?,
+            ],
+        ))
+    }
+
+    fn copy(&mut self, param: IStr, dst: ir::Expression, src: ir::Expression) -> Lower<()> {
+        let copy = self.descriptor_field(param, 2)?;
+        self.emit(ir::Statement::Call(copy, vec![dst, src]));
+        Ok(())
+    }
+
+    fn drop_value(&mut self, param: IStr, ptr: ir::Expression) -> Lower<()> {
+        let drop = self.descriptor_field(param, 3)?;
+        self.emit(ir::Statement::Call(drop, vec![ptr]));
+        Ok(())
+    }
+
+    fn free(&mut self, ptr: ir::Expression) {
+        self.emit(ir::Statement::Call(*Self::global(free()), vec![ptr]));
+    }
+
+    /// Drop the value of a generic variable, if it holds one
+    fn drop_owned(&mut self, owned: Owned) -> Lower<()> {
+        if self.bcf {
+            self.emit(ir::Statement::Bcf(ir::BcfStatement::If(read(owned.init))));
+            self.drop_value(owned.param, read(owned.storage))?;
+            self.emit(ir::Statement::Bcf(ir::BcfStatement::End));
+        } else {
+            let skip = oc::Label(self.old.labels.len() + self.labels.len());
+            self.labels.push(0);
+            let empty = ir::Intrinsic::Not(Box::new(read(owned.init)));
+            self.emit(ir::Statement::Acf(ir::AcfStatement::Cjump(
+                ir::Expression::Intrinsic(empty),
+                skip,
+            )));
+            self.drop_value(owned.param, read(owned.storage))?;
+            *self.labels.last_mut().unwrap() = self.body.statements.len();
+        }
+        self.set_init(owned, false);
+        Ok(())
+    }
+
+    fn set_init(&mut self, owned: Owned, init: bool) {
+        self.emit(ir::Statement::Assign(
+            ir::Place::Variable(owned.init),
+            ir::Expression::BConst(init),
+        ));
+    }
+
+    /// Copy a generic value into new storage, f.e. to pass it to a function
+    fn copy_to_new(&mut self, param: IStr, src: ir::Expression) -> Lower<ir::Expression> {
+        let tmp = self.var(opaque(true), false, None);
+        let storage = self.alloc(param)?;
+        self.emit(ir::Statement::Assign(ir::Place::Variable(tmp), storage));
+        self.copy(param, read(tmp), src)?;
+        Ok(read(tmp))
+    }
+
+    fn type_of(&self, expr: &ir::Expression) -> Type {
+        expr.get_type(self.store, self.old)
+    }
+
+    fn place_type(&self, place: &ir::Place) -> Type {
+        place.get_type(self.store, self.old).0
+    }
+
+    fn statement(&mut self, statement: &ir::Statement) -> Lower<()> {
+        match statement {
+            ir::Statement::Comment(..)
+            | ir::Statement::Location(..)
+            | ir::Statement::LoopAttributes(..) => {
+                self.emit(statement.clone());
+            }
+            ir::Statement::StorageLive(variable) | ir::Statement::StorageDead(variable) => {
+                // Generic storage lives for the whole function, only the value dies
+                if let Some(owned) = self.owned[variable.0] {
+                    if let ir::Statement::StorageDead(..) = statement {
+                        self.drop_owned(owned)?;
+                    }
+                    return Ok(());
+                }
+                let new = self.variables[variable.0];
+                self.emit(match statement {
+                    ir::Statement::StorageLive(..) => ir::Statement::StorageLive(new),
+                    _ => ir::Statement::StorageDead(new),
+                });
+            }
+            ir::Statement::Assign(place, expr) => match param_of(&self.place_type(place)) {
+                Some(_) if matches!(expr, ir::Expression::Read(src) if src == place) => (),
+                Some(param) => self.assign_generic(param, place, expr)?,
+                None => {
+                    let place = self.place(place)?;
+                    let expr = self.expr(expr)?;
+                    self.emit(ir::Statement::Assign(place, expr));
+                }
+            },
+            ir::Statement::Call(func, args) => {
+                let ret = match param_of(
+                    &self.type_of(&ir::Expression::Call(func.clone().into(), args.clone())),
+                ) {
+                    Some(param) => {
+                        let tmp = self.var(opaque(true), false, None);
+                        let storage = self.alloc(param)?;
+                        self.emit(ir::Statement::Assign(ir::Place::Variable(tmp), storage));
+                        Some((tmp, param))
+                    }
+                    None => None,
+                };
+                let (func, args) = self.call_operands(func, args, ret.map(|(tmp, _)| read(tmp)))?;
+                self.emit(ir::Statement::Call(func, args));
+                if let Some((tmp, param)) = ret {
+                    self.drop_value(param, read(tmp))?;
+                    self.free(read(tmp));
+                }
+            }
+            ir::Statement::Return(value) => {
+                let mut lowered = None;
+                match (value, self.ret) {
+                    (Some(ir::Expression::Call(func, args)), Some(ret)) => {
+                        let (func, args) = self.call_operands(func, args, Some(read(ret)))?;
+                        self.emit(ir::Statement::Call(func, args));
+                    }
+                    (Some(value), Some(ret)) => {
+                        let param = param_of(&self.type_of(value))
+                            .expect("[bug?] returning a non-generic value through __ret");
+                        let src = self.expr(value)?;
+                        self.copy(param, read(ret), src)?;
+                    }
+                    (Some(value), None) => lowered = Some(self.expr(value)?),
+                    (None, _) => (),
+                }
+
+                let owned = self.owned.iter().flatten().copied().collect::<Vec<_>>();
+                // The value might use generic storage, which is freed below
+                if let (Some(value), Some(expr)) = (value, lowered.as_mut())
+                    && !owned.is_empty()
+                {
+                    let tmp = self.var(lower_ty(&self.type_of(value))?, false, None);
+                    let expr = std::mem::replace(expr, read(tmp));
+                    self.emit(ir::Statement::Assign(ir::Place::Variable(tmp), expr));
+                }
+                for owned in owned {
+                    self.drop_owned(owned)?;
+                    self.free(read(owned.storage));
+                }
+                self.emit(ir::Statement::Return(lowered));
+            }
+            ir::Statement::Acf(ir::AcfStatement::Jump(label)) => {
+                self.emit(ir::Statement::Acf(ir::AcfStatement::Jump(*label)));
+            }
+            ir::Statement::Acf(ir::AcfStatement::Cjump(condition, label)) => {
+                let condition = self.expr(condition)?;
+                self.emit(ir::Statement::Acf(ir::AcfStatement::Cjump(
+                    condition, *label,
+                )));
+            }
+            ir::Statement::Bcf(statement) => {
+                use ir::BcfStatement as BS;
+                let statement = match statement {
+                    BS::If(condition) => BS::If(self.expr(condition)?),
+                    BS::Cbreak(condition) => BS::Cbreak(self.expr(condition)?),
+                    BS::Ccontinue(condition) => BS::Ccontinue(self.expr(condition)?),
+                    statement => statement.clone(),
+                };
+                self.emit(ir::Statement::Bcf(statement));
+            }
+        }
+        Ok(())
+    }
+
+    /// Assign to a place of a generic type, dropping the value it held.
+    /// The new value is evaluated first, so it can still use the old one
+    fn assign_generic(
+        &mut self,
+        param: IStr,
+        place: &ir::Place,
+        expr: &ir::Expression,
+    ) -> Lower<()> {
+        let owned = match place {
+            ir::Place::Variable(variable) => self.owned[variable.0],
+            _ => None,
+        };
+        // The destination is written after the drop, so it's evaluated once
+        let dst = match owned {
+            Some(owned) => owned.storage,
+            None => {
+                let ptr = self.ptr(place)?;
+                let tmp = self.var(opaque(true), false, None);
+                self.emit(ir::Statement::Assign(ir::Place::Variable(tmp), ptr));
+                tmp
+            }
+        };
+
+        let write = match expr {
+            ir::Expression::Call(func, args) => {
+                let (func, args) = self.call_operands(func, args, Some(read(dst)))?;
+                ir::Statement::Call(func, args)
+            }
+            expr => {
+                let src = self.expr(expr)?;
+                let copy = self.descriptor_field(param, 2)?;
+                ir::Statement::Call(copy, vec![read(dst), src])
+            }
+        };
+        match owned {
+            Some(owned) => self.drop_owned(owned)?,
+            None => self.drop_value(param, read(dst))?,
+        }
+        self.emit(write);
+        if let Some(owned) = owned {
+            self.set_init(owned, true);
+        }
+        Ok(())
+    }
+
+    /// Lower a place of a non-generic type
+    fn place(&mut self, place: &ir::Place) -> Lower<ir::Place> {
+        Ok(match place {
+            ir::Place::Variable(variable) => ir::Place::Variable(self.variables[variable.0]),
+            // Pointers to generic functions would need their descriptors bound
+            ir::Place::Global(_, generics) if generics.iter().any(Type::has_params) => {
+                return Err(Unsupported);
+            }
+            ir::Place::Global(name, generics) => ir::Place::Global(*name, generics.clone()),
+            ir::Place::Deref(expr) => ir::Place::Deref(Box::new(self.expr(expr)?)),
+            ir::Place::Field(place, idx) => ir::Place::Field(Box::new(self.place(place)?), *idx),
+            ir::Place::Index(place, index) => {
+                ir::Place::Index(Box::new(self.place(place)?), Box::new(self.expr(index)?))
+            }
+        })
+    }
+
+    /// Pointer to a place of a generic type
+    fn ptr(&mut self, place: &ir::Place) -> Lower<ir::Expression> {
+        match place {
+            ir::Place::Variable(variable) => Ok(read(self.variables[variable.0])),
+            ir::Place::Deref(expr) => self.expr(expr),
+            _ => Err(Unsupported),
+        }
+    }
+
+    /// Lower an expression. Values of generic types are pointers to their storage
+    fn expr(&mut self, expr: &ir::Expression) -> Lower<ir::Expression> {
+        Ok(match expr {
+            ir::Expression::IConst(..)
+            | ir::Expression::UConst(..)
+            | ir::Expression::FConst(..)
+            | ir::Expression::BConst(..) => expr.clone(),
+            ir::Expression::Read(place) if param_of(&self.place_type(place)).is_some() => {
+                self.ptr(place)?
+            }
+            ir::Expression::Read(place) => ir::Expression::Read(self.place(place)?),
+            ir::Expression::Reference(place, _) if param_of(&self.place_type(place)).is_some() => {
+                self.ptr(place)?
+            }
+            ir::Expression::Reference(place, mutable) => {
+                ir::Expression::Reference(self.place(place)?, *mutable)
+            }
+            // Generic return values need storage, which only assignments and returns provide
+            ir::Expression::Call(..) if param_of(&self.type_of(expr)).is_some() => {
+                return Err(Unsupported);
+            }
+            ir::Expression::Call(func, args) => {
+                let (func, args) = self.call_operands(func, args, None)?;
+                ir::Expression::Call(Box::new(func), args)
+            }
+            ir::Expression::Intrinsic(intrinsic) => {
+                use ir::Intrinsic as I;
+                let mut op = |expr: &ir::Expression| {
+                    if param_of(&self.type_of(expr)).is_some() {
+                        return Err(Unsupported);
+                    }
+                    Ok(Box::new(self.expr(expr)?))
+                };
+                ir::Expression::Intrinsic(match intrinsic {
+                    I::Add(a, b) => I::Add(op(a)?, op(b)?),
+                    I::Mul(a, b) => I::Mul(op(a)?, op(b)?),
+                    I::Eq(a, b) => I::Eq(op(a)?, op(b)?),
+                    I::Not(a) => I::Not(op(a)?),
+                })
+            }
+        })
+    }
+
+    /// Operands of a call, `ret` is where a generic return value is written to
+    fn call_operands(
+        &mut self,
+        func: &ir::Expression,
+        args: &[ir::Expression],
+        ret: Option<ir::Expression>,
+    ) -> Lower<(ir::Expression, Vec<ir::Expression>)> {
+        let ir::Expression::Read(ir::Place::Global(name, generics)) = func else {
+            let func = self.expr(func)?;
+            let args = args
+                .iter()
+                .map(|arg| self.expr(arg))
+                .collect::<Lower<_>>()?;
+            return Ok((func, args));
+        };
+        if !generics.iter().any(Type::has_params) {
+            let args = args
+                .iter()
+                .map(|arg| self.expr(arg))
+                .collect::<Lower<_>>()?;
+            return Ok((func.clone(), args));
+        }
+
+        if *name == drop_in_place() {
+            let [Type::Param(param)] = generics.as_slice() else {
+                return Err(Unsupported);
+            };
+            let args = args
+                .iter()
+                .map(|arg| self.expr(arg))
+                .collect::<Lower<_>>()?;
+            return Ok((self.descriptor_field(*param, 3)?, args));
+        }
+
+        let functions = self.store.functions.pin();
+        let callee = functions.get(name).ok_or(Unsupported)?;
+        let callee_params = type_params(&callee.generic_params).ok_or(Unsupported)?;
+        let map = match_type_params(
+            &intern(&callee.generic_params),
+            &intern(generics),
+            self.store,
+        )
+        .ok_or(Unsupported)?;
+
+        let mut lowered = Vec::with_capacity(callee_params.len() + args.len() + 1);
+        for param in &callee_params {
+            // Descriptors of concrete types are not available here
+            let Some(TypeKind::Param(own)) = map.get(param).map(|ty| ty.kind()) else {
+                return Err(Unsupported);
+            };
+            lowered.push(read(*self.descriptors.get(own).ok_or(Unsupported)?));
+        }
+        lowered.extend(ret);
+        for arg in args {
+            let value = self.expr(arg)?;
+            lowered.push(match param_of(&self.type_of(arg)) {
+                Some(param) => self.copy_to_new(param, value)?,
+                None => value,
+            });
+        }
+        self.callees.insert(*name);
+        Ok((*Self::global(poly_name(*name)), lowered))
+    }
+}
@@ -0,0 +1,872 @@
+use crate::{Store, ir};
+use orco::Type;
+use orco::attrs::SourceLocation;
+use orco::codegen as oc;
+use orco::codegen::impls::Violation;
+use orco::error::SymbolKind;
+use std::collections::HashSet;
+
+/// What is wrong, see [VerifyError]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// Same problems [`oc::impls::Validator`] catches during codegen
+    Violation(Violation),
+    /// Undeclared symbol, missing specialization or a type alias cycle
+    Symbol(orco::Error),
+    /// A variable is used after its storage is dead, see [`ir::Body::dead_uses`]
+    DeadUse(oc::Variable),
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::Violation(violation) => write!(f, "{violation}"),
+            Problem::Symbol(err) => write!(f, "{err}"),
+            Problem::DeadUse(variable) => {
+                write!(
+                    f,
+                    "variable _{} is used after its storage is dead",
+                    variable.0
+                )
+            }
+        }
+    }
+}
+
+/// A problem found by [`Store::verify`], with the place it was found at
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifyError {
+    /// Kind of the item the problem is in
+    pub kind: SymbolKind,
+    /// Name of the item the problem is in
+    pub name: orco::Symbol,
+    /// Specialization of the type or the function body, generic params for function declarations
+    pub generics: Vec<Type>,
+    /// Index of the statement in the function body, [None] for declarations
+    /// and problems with the body as a whole
+    pub statement: Option<usize>,
+    /// Last [`ir::Statement::Location`] before the statement
+    /// or the location of the function declaration
+    pub location: Option<SourceLocation>,
+    #[allow(missing_docs)]
+    pub problem: Problem,
+}
+
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}{}",
+            self.kind,
+            self.name,
+            orco::types::fmt_generics(&self.generics)
+        )?;
+        if let Some(statement) = self.statement {
+            write!(f, " statement #{statement}")?;
+        }
+        if let Some(location) = &self.location {
+            write!(f, " at {location}")?;
+        }
+        write!(f, ": {}", self.problem)
+    }
+}
+
+impl Store {
+    /// Check that every declaration and every function body (including all specializations)
+    /// is well-formed: all symbols are declared, types of assignments, calls and returns match,
+    /// places are valid, jumps target placed labels, BCF blocks are balanced
+    /// and variables are not used after their storage is dead.
+    ///
+    /// Returns every problem found, an empty list means the store is fine.
+    /// Unlike [`ir::Expression::get_type`] and friends, never panics on malformed code
+    #[must_use]
+    pub fn verify(&self) -> Vec<VerifyError> {
+        let mut verifier = Verifier::new(self);
+        verifier.types();
+        verifier.globals();
+        verifier.functions();
+        verifier.errors
+    }
+}
+
+/// Calls `f` with every symbol a type mentions
+fn visit_symbols(ty: &Type, f: &mut impl FnMut(orco::Symbol)) {
+    match ty {
+        Type::Symbol(name, generics) => {
+            f(*name);
+            for ty in generics {
+                visit_symbols(ty, f);
+            }
+        }
+        Type::Array(ty, _) | Type::Ptr(ty, _) => visit_symbols(ty, f),
+        Type::Struct { fields } => {
+            for (_, ty) in fields {
+                visit_symbols(ty, f);
+            }
+        }
+        Type::FnPtr {
+            params,
+            return_type,
+        } => {
+            for ty in params.iter().chain(return_type.as_deref()) {
+                visit_symbols(ty, f);
+            }
+        }
+        _ => (),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BlockType {
+    If,
+    Else,
+    Loop,
+}
+
+struct Verifier<'a> {
+    store: &'a Store,
+    /// Types that mention undeclared symbols or cyclic types.
+    /// Resolving them would panic or hang, problems are reported at their declaration
+    broken: HashSet<orco::Symbol>,
+    /// Types that are part of an alias cycle
+    cyclic: HashSet<orco::Symbol>,
+    /// Upper bound on the length of an alias chain, longer ones are cycles
+    max_aliases: usize,
+    errors: Vec<VerifyError>,
+
+    /// Kind, name and generics of the item being checked
+    item: Option<(SymbolKind, orco::Symbol, Vec<Type>)>,
+    statement: Option<usize>,
+    location: Option<SourceLocation>,
+}
+
+impl<'a> Verifier<'a> {
+    fn new(store: &'a Store) -> Self {
+        let mut verifier = Self {
+            store,
+            broken: HashSet::new(),
+            cyclic: HashSet::new(),
+            max_aliases: 0,
+            errors: Vec::new(),
+
+            item: None,
+            statement: None,
+            location: None,
+        };
+
+        let types = store.types.pin();
+        verifier.max_aliases = types.iter().map(|(_, specs)| specs.len()).sum::<usize>() + 1;
+        verifier.spread_broken(|name| !types.contains_key(&name));
+        for (name, specs) in types.iter() {
+            for (generics, _) in specs.pin().iter() {
+                let ty = Type::Symbol(*name, generics.iter().map(|ty| ty.to_type()).collect());
+                if !verifier.broken.contains(name)
+                    && let Err(Some(_)) = verifier.resolve(ty)
+                {
+                    verifier.cyclic.insert(*name);
+                }
+            }
+        }
+        verifier.broken.extend(verifier.cyclic.iter().copied());
+        verifier.spread_broken(|_| false);
+        verifier
+    }
+
+    /// Mark types mentioning symbols for which `broken` returns true or already broken types
+    fn spread_broken(&mut self, broken: impl Fn(orco::Symbol) -> bool) {
+        let types = self.store.types.pin();
+        loop {
+            let mut changed = false;
+            for (name, specs) in types.iter() {
+                if self.broken.contains(name) {
+                    continue;
+                }
+                let mut is_broken = false;
+                for (generics, ty) in specs.pin().iter() {
+                    for ty in generics.iter().chain([ty]) {
+                        visit_symbols(&ty.to_type(), &mut |symbol| {
+                            is_broken |= broken(symbol) || self.broken.contains(&symbol);
+                        });
+                    }
+                }
+                if is_broken {
+                    self.broken.insert(*name);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    fn enter(
+        &mut self,
+        kind: SymbolKind,
+        name: orco::Symbol,
+        generics: Vec<Type>,
+        location: Option<SourceLocation>,
+    ) {
+        self.item = Some((kind, name, generics));
+        self.statement = None;
+        self.location = location;
+    }
+
+    fn report(&mut self, problem: impl Into<Problem>) {
+        let (kind, name, generics) = self
+            .item
+            .clone()
+            .expect("[bug?] problem reported outside of an item");
+        self.errors.push(VerifyError {
+            kind,
+            name,
+            generics,
+            statement: self.statement,
+            location: self.location.clone(),
+            problem: problem.into(),
+        });
+    }
+
+    /// Whether all symbols in a type are declared and have a matching specialization
+    fn well_formed(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Symbol(name, generics) => {
+                self.store.types.pin().contains_key(name)
+                    && !self.broken.contains(name)
+                    && generics.iter().all(|ty| self.well_formed(ty))
+                    && self.store.get_type(*name, generics, |_, _| ()).is_ok()
+            }
+            Type::Array(ty, _) | Type::Ptr(ty, _) => self.well_formed(ty),
+            Type::Struct { fields } => fields.iter().all(|(_, ty)| self.well_formed(ty)),
+            Type::FnPtr {
+                params,
+                return_type,
+            } => params
+                .iter()
+                .chain(return_type.as_deref())
+                .all(|ty| self.well_formed(ty)),
+            _ => true,
+        }
+    }
+
+    /// Report undeclared symbols and missing specializations in a type.
+    /// Returns whether the type is well-formed
+    fn check_type(&mut self, ty: &Type) -> bool {
+        match ty {
+            Type::Symbol(name, generics) => {
+                let mut ok = true;
+                for ty in generics {
+                    ok &= self.check_type(ty);
+                }
+                if !self.store.types.pin().contains_key(name) {
+                    self.report(orco::Error::Undeclared(SymbolKind::Type, *name));
+                    return false;
+                }
+                // Problems of broken types are reported at their declaration
+                if !ok || self.broken.contains(name) {
+                    return false;
+                }
+                match self.store.get_type(*name, generics, |_, _| ()) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        self.report(err);
+                        false
+                    }
+                }
+            }
+            Type::Array(ty, _) | Type::Ptr(ty, _) => self.check_type(ty),
+            Type::Struct { fields } => {
+                let mut ok = true;
+                for (_, ty) in fields {
+                    ok &= self.check_type(ty);
+                }
+                ok
+            }
+            Type::FnPtr {
+                params,
+                return_type,
+            } => {
+                let mut ok = true;
+                for ty in params.iter().chain(return_type.as_deref()) {
+                    ok &= self.check_type(ty);
+                }
+                ok
+            }
+            _ => true,
+        }
+    }
+
+    /// Inline type aliases at the top level of a type.
+    /// Fails on malformed types, [Some] if an alias cycle is detected
+    fn resolve(&self, mut ty: Type) -> Result<Type, Option<orco::Error>> {
+        for _ in 0..self.max_aliases {
+            let Type::Symbol(name, generics) = &ty else {
+                return Ok(ty);
+            };
+            if !self.well_formed(&ty) {
+                return Err(None);
+            }
+            let mut resolved = Type::Error;
+            self.store
+                .get_type(*name, generics, |ty, map| {
+                    resolved = ty.instantiate(&map).to_type();
+                })
+                .map_err(|_| None)?;
+            ty = resolved;
+        }
+        match ty {
+            Type::Symbol(name, _) => Err(Some(orco::Error::DependencyCycle(name))),
+            ty => Ok(ty),
+        }
+    }
+
+    /// Same as [`Store::inline_type_aliases`], but yields [`Type::Error`] for malformed types
+    fn inline(&self, ty: Type) -> Type {
+        self.resolve(ty).unwrap_or(Type::Error)
+    }
+
+    /// See [`oc::impls::Validator`]
+    fn compatible(&self, expected: &Type, found: &Type) -> bool {
+        if expected == found {
+            return true;
+        }
+        match (self.inline(expected.clone()), self.inline(found.clone())) {
+            (Type::Error, _) | (_, Type::Error) => true,
+            // Mutable pointers can be used as const ones
+            (Type::Ptr(expected, expected_mut), Type::Ptr(found, found_mut)) => {
+                (found_mut || !expected_mut) && self.compatible(&expected, &found)
+            }
+            (expected, found) => expected == found,
+        }
+    }
+
+    fn expect_type(&mut self, expected: &Type, found: Type) {
+        if !self.compatible(expected, &found) {
+            self.report(Violation::TypeMismatch {
+                expected: expected.clone(),
+                found,
+            });
+        }
+    }
+
+    fn types(&mut self) {
+        let types = self.store.types.pin();
+        let mut types = types.iter().collect::<Vec<_>>();
+        types.sort_unstable_by_key(|(name, _)| **name);
+        for (name, specs) in types {
+            let specs = specs.pin();
+            let mut specs = specs.iter().collect::<Vec<_>>();
+            specs.sort_unstable();
+            for (generics, ty) in specs {
+                let generics = generics.iter().map(|ty| ty.to_type()).collect::<Vec<_>>();
+                self.enter(SymbolKind::Type, *name, generics.clone(), None);
+                for ty in &generics {
+                    self.check_type(ty);
+                }
+                self.check_type(&ty.to_type());
+                if self.cyclic.contains(name) {
+                    self.report(orco::Error::DependencyCycle(*name));
+                }
+            }
+        }
+    }
+
+    fn globals(&mut self) {
+        let globals = self.store.globals.pin();
+        let mut globals = globals.iter().collect::<Vec<_>>();
+        globals.sort_unstable();
+        for (name, ty) in globals {
+            self.enter(SymbolKind::Global, *name, Vec::new(), None);
+            self.check_type(ty);
+        }
+    }
+
+    fn functions(&mut self) {
+        let functions = self.store.functions.pin();
+        let mut functions = functions.iter().collect::<Vec<_>>();
+        functions.sort_unstable_by_key(|(name, _)| **name);
+        for (name, decl) in functions {
+            let location = decl.signature.attrs.location.clone();
+            self.enter(
+                SymbolKind::Function,
+                *name,
+                decl.generic_params.clone(),
+                location.clone(),
+            );
+            let mut ok = true;
+            for ty in &decl.generic_params {
+                ok &= self.check_type(ty);
+            }
+            for (_, ty) in &decl.signature.params {
+                self.check_type(ty);
+            }
+            if let Some(ty) = &decl.signature.return_type {
+                self.check_type(ty);
+            }
+
+            let bodies = decl.bodies.pin();
+            let mut bodies = bodies.iter().collect::<Vec<_>>();
+            bodies.sort_unstable_by_key(|(generics, _)| *generics);
+            for (generics, body) in bodies {
+                let generics = generics.iter().map(|ty| ty.to_type()).collect::<Vec<_>>();
+                self.enter(
+                    SymbolKind::Function,
+                    *name,
+                    generics.clone(),
+                    location.clone(),
+                );
+                let mut ok = ok;
+                for ty in &generics {
+                    ok &= self.check_type(ty);
+                }
+
+                // Signature of this specialization
+                let mut signature = decl.signature.clone();
+                if ok {
+                    match crate::generics::match_type_params(
+                        &crate::generics::intern(&decl.generic_params),
+                        &crate::generics::intern(&generics),
+                        self.store,
+                    ) {
+                        Some(map) => signature.instantiate(&map),
+                        None => self.report(orco::Error::NoSpecialization(
+                            SymbolKind::Function,
+                            *name,
+                            generics,
+                        )),
+                    }
+                }
+                BodyVerifier {
+                    verifier: self,
+                    body,
+                }
+                .verify(&signature);
+            }
+        }
+    }
+}
+
+struct BodyVerifier<'a, 'b> {
+    verifier: &'b mut Verifier<'a>,
+    body: &'b ir::Body,
+}
+
+impl BodyVerifier<'_, '_> {
+    fn report(&mut self, problem: impl Into<Problem>) {
+        self.verifier.report(problem);
+    }
+
+    fn verify(mut self, signature: &orco::types::FunctionSignature) {
+        for variable in &self.body.variables {
+            self.verifier.check_type(&variable.ty);
+        }
+
+        let args = self.body.variables.iter().filter(|var| var.arg).count();
+        if args != signature.params.len() {
+            self.report(Violation::ArityMismatch {
+                expected: signature.params.len(),
+                found: args,
+            });
+        }
+        for ((_, ty), variable) in signature.params.iter().zip(&self.body.variables) {
+            if variable.arg {
+                self.verifier.expect_type(ty, variable.ty.clone());
+            }
+        }
+
+        let (errors, location) = (self.verifier.errors.len(), self.verifier.location.clone());
+        let mut blocks = Vec::new();
+        for (idx, statement) in self.body.statements.iter().enumerate() {
+            self.verifier.statement = Some(idx);
+            self.statement(statement, &signature.return_type, &mut blocks);
+        }
+        self.verifier.statement = None;
+        if !blocks.is_empty() {
+            self.report(Violation::UnclosedBlocks(blocks.len()));
+        }
+
+        // Control flow analysis would panic on malformed bodies
+        if self.verifier.errors.len() == errors {
+            for (idx, variable) in self.body.dead_uses() {
+                self.verifier.statement = Some(idx);
+                self.verifier.location = self.body.statements[..idx]
+                    .iter()
+                    .rev()
+                    .find_map(|statement| match statement {
+                        ir::Statement::Location(location) => Some(location.clone()),
+                        _ => None,
+                    })
+                    .unwrap_or_else(|| location.clone());
+                self.report(Problem::DeadUse(variable));
+            }
+            self.verifier.statement = None;
+        }
+    }
+
+    fn statement(
+        &mut self,
+        statement: &ir::Statement,
+        return_type: &Option<Type>,
+        blocks: &mut Vec<BlockType>,
+    ) {
+        use ir::{AcfStatement as A, BcfStatement as B, Statement as S};
+        match statement {
+            S::Comment(..) | S::LoopAttributes(..) => (),
+            S::Location(location) => self.verifier.location.clone_from(location),
+            S::StorageLive(variable) | S::StorageDead(variable) => {
+                self.variable(*variable);
+            }
+            S::Assign(target, value) => {
+                let (target_ty, mutable) = self.place(target);
+                let ty = self.expr(value);
+                if !mutable {
+                    self.report(Violation::ImmutablePlace);
+                }
+                self.verifier.expect_type(&target_ty, ty);
+            }
+            S::Call(func, args) => {
+                self.call(func, args);
+            }
+            S::Return(value) => {
+                let found = value.as_ref().map(|value| self.expr(value));
+                match (return_type, found) {
+                    (Some(expected), Some(found)) => self.verifier.expect_type(expected, found),
+                    (None, None) => (),
+                    (expected, found) => self.report(Violation::ReturnMismatch {
+                        expected: expected.clone(),
+                        found,
+                    }),
+                }
+            }
+            S::Acf(A::Jump(label)) => self.label(*label),
+            S::Acf(A::Cjump(condition, label)) => {
+                self.condition(condition);
+                self.label(*label);
+            }
+            S::Bcf(B::If(condition)) => {
+                self.condition(condition);
+                blocks.push(BlockType::If);
+            }
+            S::Bcf(B::Else) => match blocks.last_mut() {
+                Some(block @ BlockType::If) => *block = BlockType::Else,
+                _ => self.report(Violation::ElseWithoutIf),
+            },
+            S::Bcf(B::End) => {
+                if blocks.pop().is_none() {
+                    self.report(Violation::EndWithoutBlock);
+                }
+            }
+            S::Bcf(B::Loop) => blocks.push(BlockType::Loop),
+            S::Bcf(B::Break | B::Continue) => self.in_loop(blocks),
+            S::Bcf(B::Cbreak(condition) | B::Ccontinue(condition)) => {
+                self.condition(condition);
+                self.in_loop(blocks);
+            }
+        }
+    }
+
+    fn in_loop(&mut self, blocks: &[BlockType]) {
+        if !blocks.contains(&BlockType::Loop) {
+            self.report(Violation::OutsideLoop);
+        }
+    }
+
+    fn label(&mut self, label: oc::Label) {
+        match self.body.labels.get(label.0) {
+            // Labels can be placed after the last statement
+            Some(&idx) if idx <= self.body.statements.len() => (),
+            Some(_) => self.report(Violation::LabelNotPlaced(label)),
+            None => self.report(Violation::UnknownLabel(label)),
+        }
+    }
+
+    fn condition(&mut self, condition: &ir::Expression) {
+        let ty = self.expr(condition);
+        self.verifier.expect_type(&Type::Bool, ty);
+    }
+
+    fn variable(&mut self, variable: oc::Variable) -> Type {
+        match self.body.variables.get(variable.0) {
+            Some(variable) => variable.ty.clone(),
+            None => {
+                self.report(Violation::UnknownVariable(variable));
+                Type::Error
+            }
+        }
+    }
+
+    /// Returns type of the place and whether it's mutable
+    fn place(&mut self, place: &ir::Place) -> (Type, bool) {
+        match place {
+            ir::Place::Variable(variable) => (self.variable(*variable), true),
+            ir::Place::Global(name, generics) => {
+                let mut ok = true;
+                for ty in generics {
+                    ok &= self.verifier.check_type(ty);
+                }
+                if let Some(ty) = self.verifier.store.globals.pin().get(name) {
+                    return (ty.clone(), true);
+                }
+                let functions = self.verifier.store.functions.pin();
+                let Some(decl) = functions.get(name) else {
+                    self.report(orco::Error::Undeclared(SymbolKind::Global, *name));
+                    return (Type::Error, false);
+                };
+                if !ok
+                    || !decl
+                        .generic_params
+                        .iter()
+                        .all(|ty| self.verifier.well_formed(ty))
+                {
+                    return (Type::Error, false);
+                }
+                match crate::generics::match_type_params(
+                    &crate::generics::intern(&decl.generic_params),
+                    &crate::generics::intern(generics),
+                    self.verifier.store,
+                ) {
+                    Some(map) => {
+                        let mut signature = decl.signature.clone();
+                        signature.instantiate(&map);
+                        (signature.ptr_type(), false)
+                    }
+                    None => {
+                        self.report(orco::Error::NoSpecialization(
+                            SymbolKind::Function,
+                            *name,
+                            generics.clone(),
+                        ));
+                        (Type::Error, false)
+                    }
+                }
+            }
+            ir::Place::Deref(expr) => {
+                let ty = self.expr(expr);
+                match self.verifier.inline(ty) {
+                    Type::Ptr(ty, mutable) => (*ty, mutable),
+                    // Malformed types are reported on their own
+                    Type::Error => (Type::Error, true),
+                    ty => {
+                        self.report(Violation::NotAPointer(ty));
+                        (Type::Error, true)
+                    }
+                }
+            }
+            ir::Place::Field(place, index) => {
+                let (ty, mutable) = self.place(place);
+                match self.verifier.inline(ty) {
+                    Type::Struct { mut fields } if *index < fields.len() => {
+                        (fields.swap_remove(*index).1, mutable)
+                    }
+                    Type::Struct { fields } => {
+                        self.report(Violation::FieldOutOfRange {
+                            index: *index,
+                            count: fields.len(),
+                        });
+                        (Type::Error, mutable)
+                    }
+                    Type::Error => (Type::Error, mutable),
+                    ty => {
+                        self.report(Violation::NotAStruct(ty));
+                        (Type::Error, mutable)
+                    }
+                }
+            }
+            ir::Place::Index(place, index) => {
+                let (ty, mutable) = self.place(place);
+                let index = self.expr(index);
+                match self.verifier.inline(index) {
+                    Type::Integer(..) | Type::Unsigned(..) | Type::Error => (),
+                    ty => self.report(Violation::NotAnIndex(ty)),
+                }
+                match self.verifier.inline(ty) {
+                    Type::Array(ty, _) => (*ty, mutable),
+                    Type::Error => (Type::Error, mutable),
+                    ty => {
+                        self.report(Violation::NotAnArray(ty));
+                        (Type::Error, mutable)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the return type, [`Type::Error`] if the function doesn't return anything
+    fn call(&mut self, func: &ir::Expression, args: &[ir::Expression]) -> Type {
+        let ty = self.expr(func);
+        let arg_types = args.iter().map(|arg| self.expr(arg)).collect::<Vec<_>>();
+        match self.verifier.inline(ty) {
+            Type::FnPtr {
+                params,
+                return_type,
+            } => {
+                if params.len() == arg_types.len() {
+                    for (param, arg) in params.iter().zip(arg_types) {
+                        self.verifier.expect_type(param, arg);
+                    }
+                } else {
+                    self.report(Violation::ArityMismatch {
+                        expected: params.len(),
+                        found: arg_types.len(),
+                    });
+                }
+                return_type.map_or(Type::Error, |ty| *ty)
+            }
+            Type::Error => Type::Error,
+            ty => {
+                self.report(Violation::NotAFunction(ty));
+                Type::Error
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &ir::Expression) -> Type {
+        match expr {
+            ir::Expression::IConst(_, size) => Type::Integer(*size),
+            ir::Expression::UConst(_, size) => Type::Unsigned(*size),
+            ir::Expression::FConst(_, size) => Type::Float(*size),
+            ir::Expression::BConst(_) => Type::Bool,
+            ir::Expression::Read(place) => self.place(place).0,
+            ir::Expression::Reference(place, mutable) => {
+                let (ty, place_mutable) = self.place(place);
+                if *mutable && !place_mutable {
+                    self.report(Violation::ImmutablePlace);
+                }
+                Type::Ptr(Box::new(ty), *mutable)
+            }
+            ir::Expression::Call(func, args) => self.call(func, args),
+            ir::Expression::Intrinsic(intrinsic) => {
+                use ir::Intrinsic as I;
+                match intrinsic {
+                    I::Add(a, b) | I::Mul(a, b) | I::Eq(a, b) => {
+                        let ty = self.expr(a);
+                        let b_ty = self.expr(b);
+                        self.verifier.expect_type(&ty, b_ty);
+                        match intrinsic {
+                            I::Eq(..) => Type::Bool,
+                            _ => ty,
+                        }
+                    }
+                    I::Not(a) => self.expr(a),
+                }
+            }
+        }
+    }
+}
+
+impl From<Violation> for Problem {
+    fn from(violation: Violation) -> Self {
+        Self::Violation(violation)
+    }
+}
+
+impl From<orco::Error> for Problem {
+    fn from(err: orco::Error) -> Self {
+        Self::Symbol(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    /// Parse a store and verify it, returning formatted errors
+    fn verify(source: &str) -> Vec<String> {
+        crate::parser::parse(source)
+            .unwrap()
+            .verify()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn well_formed() {
+        let source = "static a: i32[2];
+type pair = { a: i32, b: i32 };
+fn f(p: *mut pair, c: bool) -> i32;
+for {
+  let _0: *mut pair = <argument>;
+  let _1: bool = <argument>;
+  loop {
+    break if _1;
+    (*_0)._0 = a[1 as usize];
+    _1 = true;
+  }
+  return (*_0)._1;
+}
+";
+        assert_eq!(verify(source), Vec::<String>::new());
+    }
+
+    #[test]
+    fn failing() {
+        let cases: &[(&str, &[&str])] = &[
+            (
+                "fn f(x: i32) -> void;\nfor {\n  let _0: i32 = <argument>;\n  if _0 {\n  }\n  return;\n}\n",
+                &["function f statement #0: expected type bool, found i32"],
+            ),
+            (
+                "fn f() -> void;\nfor {\n  jump label0 if 1 as i32;\nlabel0:\n  return;\n}\n",
+                &["function f statement #0: expected type bool, found i32"],
+            ),
+            (
+                "fn f(x: i32) -> bool;\nfor {\n  let _0: i32 = <argument>;\n  return _0;\n}\n",
+                &["function f statement #0: expected type bool, found i32"],
+            ),
+            (
+                "fn f() -> i32;\nfor {\n  return;\n}\n",
+                &["function f statement #0: function returns i32, but return has no value"],
+            ),
+            (
+                "fn g(x: i32) -> void;\nfn f(x: i32) -> void;\nfor {\n  let _0: i32 = <argument>;\n  g(_0, _0);\n  return;\n}\n",
+                &["function f statement #0: function takes 1 arguments, but 2 were passed"],
+            ),
+            (
+                "fn f() -> void;\nfor {\n  break;\n}\n",
+                &["function f statement #0: break or continue outside of a loop"],
+            ),
+            (
+                "fn f(x: i32) -> i32;\nfor {\n  let _0: i32 = <argument>;\n  storage_dead _0;\n  return _0;\n}\n",
+                &["function f statement #1: variable _0 is used after its storage is dead"],
+            ),
+            (
+                "fn f(x: i32) -> void;\nfor {\n  let _0: i32 = <argument>;\n  *_0 = 1 as i32;\n  _0._1 = 1 as i32;\n  _0[0 as usize] = 1 as i32;\n  return;\n}\n",
+                &[
+                    "function f statement #0: trying to dereference non-pointer type i32",
+                    "function f statement #1: trying to access a field on non-struct type i32",
+                    "function f statement #2: trying to index non-array type i32",
+                ],
+            ),
+            (
+                "static a: i32[2];\nfn f() -> void;\nfor {\n  a[true] = 1 as i32;\n  return;\n}\n",
+                &["function f statement #0: array index has non-integer type bool"],
+            ),
+            (
+                "fn f(p: *const i32) -> void;\nfor {\n  let _0: *const i32 = <argument>;\n  location a.rs:2:3;\n  *_0 = 1 as i32;\n  return;\n}\n",
+                &["function f statement #1 at a.rs:2:3: place is not mutable"],
+            ),
+            (
+                "fn f() -> void;\nfor {\n  f = f;\n  return;\n}\n",
+                &["function f statement #0: place is not mutable"],
+            ),
+            (
+                "fn f() -> void;\nfor {\n  h();\n  return;\n}\n",
+                &["function f statement #0: undeclared global h"],
+            ),
+            (
+                "fn g<#T>(x: #T) -> void;\nfor <i32> {\n  let _0: u8 = <argument>;\n  return;\n}\n",
+                &["function g<i32>: expected type i32, found u8"],
+            ),
+            (
+                "type a = b;\ntype b = a;\nstatic g: c;\n",
+                &[
+                    "type a: type dependency cycle detected on a, possibly an infinitely-recursive type",
+                    "type b: type dependency cycle detected on b, possibly an infinitely-recursive type",
+                    "global g: undeclared type c",
+                ],
+            ),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(verify(source), *expected, "{source}");
+        }
+    }
+}
@@ -1,39 +1,42 @@
 use crate::TyCtxt;
 use orco::codegen as oc;
-use orco::codegen::AcfCodegen as _;
 use std::collections::HashMap;
 
 mod operand;
 
-struct CodegenCtx<'a, 'tcx: 'a, B, CG> {
+struct CodegenCtx<'a, 'tcx: 'a, CG> {
     tcx: TyCtxt<'tcx>,
-    backend: &'a B,
     codegen: CG,
     body: &'a rustc_middle::mir::Body<'tcx>,
-    map: crate::types::GenericMap<'a>,
     variables: HashMap<rustc_middle::mir::Local, Option<oc::Variable>>,
     labels: HashMap<rustc_middle::mir::BasicBlock, oc::Label>,
 }
 
-impl<'tcx, B: orco::DeclarationBackend<'tcx>, CG: oc::BodyCodegen> CodegenCtx<'_, 'tcx, B, CG> {
-    fn generic_name(
-        &self,
-        key: rustc_hir::def_id::DefId,
-        args: &rustc_middle::ty::GenericArgs,
-    ) -> orco::Symbol {
-        crate::names::generic_name(self.tcx, self.backend, key, self.map, args)
-    }
-
+impl<'tcx, CG: oc::BodyCodegen> CodegenCtx<'_, 'tcx, CG> {
     fn convert_ty(&self, ty: rustc_middle::ty::Ty) -> Option<orco::Type> {
-        crate::types::convert(self.tcx, self.backend, ty, self.map)
+        crate::types::convert(self.tcx, ty)
     }
 
     fn codegen_statement(&mut self, stmt: &rustc_middle::mir::Statement<'tcx>) {
         // self.codegen.comment(&format!("{stmt:#?}"));
+        self.codegen
+            .set_location(crate::spans::convert(self.tcx, stmt.source_info.span));
 
         use rustc_middle::mir::StatementKind;
         let (place, rvalue) = match &stmt.kind {
             StatementKind::Assign(assign) => assign.as_ref(),
+            StatementKind::StorageLive(local) => {
+                if let Some(variable) = self.variables[local] {
+                    self.codegen.storage_live(variable);
+                }
+                return;
+            }
+            StatementKind::StorageDead(local) => {
+                if let Some(variable) = self.variables[local] {
+                    self.codegen.storage_dead(variable);
+                }
+                return;
+            }
             StatementKind::SetDiscriminant { .. } => todo!(),
             StatementKind::Intrinsic(..) => todo!(),
             stmt => {
@@ -68,7 +71,7 @@ impl<'tcx, B: orco::DeclarationBackend<'tcx>, CG: oc::BodyCodegen> CodegenCtx<'_
                             }
                         }
                     }
-                    AK::Adt(key, variant, ..) => {
+                    AK::Adt(key, variant, args, ..) => {
                         let adt = self.tcx.adt_def(*key);
                         let variant = &adt.variants()[*variant];
                         for (idx, op) in fields.iter_enumerated() {
@@ -76,7 +79,7 @@ impl<'tcx, B: orco::DeclarationBackend<'tcx>, CG: oc::BodyCodegen> CodegenCtx<'_
                             let place = place.project_deeper(
                                 &[rustc_middle::mir::PlaceElem::Field(
                                     idx,
-                                    self.tcx.type_of(field.did).skip_binder(), // TODO: Generics?!!!
+                                    field.ty(self.tcx, args).skip_norm_wip(),
                                 )],
                                 self.tcx,
                             );
@@ -92,28 +95,91 @@ impl<'tcx, B: orco::DeclarationBackend<'tcx>, CG: oc::BodyCodegen> CodegenCtx<'_
                 }
             }
             Rvalue::BinaryOp(op, operands) => {
-                let params: Vec<_> = self
-                    .op(&operands.0)
-                    .into_iter()
-                    .chain(self.op(&operands.1))
-                    .collect();
-
-                let ty = operands.0.ty(self.body, self.tcx).to_string();
-                let value = crate::intrinsics().inline_call(
-                    &mut self.codegen,
-                    format!("__{op:?}#{ty}").into(),
-                    params,
-                );
+                let ty = operands.0.ty(self.body, self.tcx);
+                let (Some(lhs), Some(rhs)) = (self.op(&operands.0), self.op(&operands.1)) else {
+                    panic!("[bug?] binary operation on a zero-sized type");
+                };
+                let value = self.binary_op(*op, ty, lhs, rhs);
                 if let (Some(place), Some(value)) = (self.place(*place), value) {
                     self.codegen.assign(place, value);
                 }
             }
+            Rvalue::UnaryOp(op, operand) => {
+                use rustc_middle::mir::UnOp;
+                let Some(value) = self.op(operand) else {
+                    panic!("[bug?] unary operation on a zero-sized type");
+                };
+                let value = match op {
+                    UnOp::Not => self.codegen.not(value),
+                    UnOp::Neg => {
+                        let minus_one = match self.codegen.type_of(value.0) {
+                            orco::Type::Integer(size) => self.codegen.iconst(-1, size),
+                            orco::Type::Float(size) => self.codegen.fconst(-1.0, size),
+                            ty => panic!("[bug?] negation of {ty}"),
+                        };
+                        self.codegen.mul(value, minus_one)
+                    }
+                    UnOp::PtrMetadata => todo!(),
+                };
+                if let Some(place) = self.place(*place) {
+                    self.codegen.assign(place, value);
+                }
+            }
+            Rvalue::Ref(_, kind, target) => {
+                let mutable = kind.to_mutbl_lossy().is_mut();
+                if let (Some(place), Some(target)) = (self.place(*place), self.place(*target)) {
+                    let value = self.codegen.reference(target, mutable);
+                    self.codegen.assign(place, value);
+                }
+            }
+            Rvalue::RawPtr(kind, target) => {
+                let mutable = kind.to_mutbl_lossy().is_mut();
+                if let (Some(place), Some(target)) = (self.place(*place), self.place(*target)) {
+                    let value = self.codegen.reference(target, mutable);
+                    self.codegen.assign(place, value);
+                }
+            }
             _ => self.codegen.comment(&format!("TODO: {stmt:?}")), // TODO
         }
     }
 
+    /// Lower a binary operation to an orco intrinsic if there is one,
+    /// or to a call to one of [`crate::intrinsics`]
+    fn binary_op(
+        &mut self,
+        op: rustc_middle::mir::BinOp,
+        ty: rustc_middle::ty::Ty<'tcx>,
+        lhs: oc::Value,
+        rhs: oc::Value,
+    ) -> Option<oc::Value> {
+        use rustc_middle::mir::BinOp;
+        let name = match op {
+            BinOp::Add | BinOp::AddUnchecked => return Some(self.codegen.add(lhs, rhs)),
+            BinOp::Mul | BinOp::MulUnchecked => return Some(self.codegen.mul(lhs, rhs)),
+            BinOp::Eq => return Some(self.codegen.eq(lhs, rhs)),
+            BinOp::Ne => {
+                let eq = self.codegen.eq(lhs, rhs);
+                return Some(self.codegen.not(eq));
+            }
+            BinOp::SubUnchecked => "Sub".to_owned(),
+            BinOp::Shl | BinOp::ShlUnchecked | BinOp::Shr | BinOp::ShrUnchecked => {
+                todo!("shifts")
+            }
+            BinOp::Cmp | BinOp::Offset => todo!("{op:?}"),
+            op => format!("{op:?}"),
+        };
+        let ty = self
+            .convert_ty(ty)
+            .expect("[bug?] binary operation on a zero-sized type");
+        let func = self.codegen.read(oc::Place::Global(
+            crate::intrinsics::name(&name, ty),
+            Vec::new(),
+        ));
+        self.codegen.call(func, vec![lhs, rhs])
+    }
+
     fn codegen_block(&mut self, block: rustc_middle::mir::BasicBlock) {
-        self.codegen.acf().label(self.labels[&block]);
+        self.codegen.label(self.labels[&block]);
         let block = &self.body[block];
 
         for stmt in &block.statements {
@@ -121,13 +187,25 @@ impl<'tcx, B: orco::DeclarationBackend<'tcx>, CG: oc::BodyCodegen> CodegenCtx<'_
         }
 
         // self.codegen.comment(&format!("{:#?}", block.terminator()));
+        self.codegen.set_location(crate::spans::convert(
+            self.tcx,
+            block.terminator().source_info.span,
+        ));
         use rustc_middle::mir::TerminatorKind;
         match &block.terminator().kind {
-            TerminatorKind::Goto { target } => self.codegen.acf().jump(self.labels[target]),
+            TerminatorKind::Goto { target } => self.codegen.jump(self.labels[target]),
             TerminatorKind::SwitchInt { discr, targets } => {
-                use oc::Intrinsics as _;
-                for (value, target) in targets.iter() {
-                    let discr = self.op(discr).expect("SwitchInt on unit discriminant");
+                let mut rest = Some(self.op(discr).expect("SwitchInt on unit discriminant"));
+                let arms = targets.iter().collect::<Vec<_>>();
+                for (idx, (value, target)) in arms.iter().copied().enumerate() {
+                    let discr = rest.take().unwrap();
+                    let discr = if idx + 1 < arms.len() {
+                        let (discr, copy) = self.codegen.dup(discr);
+                        rest = Some(copy);
+                        discr
+                    } else {
+                        discr
+                    };
                     let value = match self.codegen.type_of(discr.0) {
                         orco::Type::Integer(is) => self.codegen.iconst(value as _, is),
                         orco::Type::Unsigned(is) => self.codegen.uconst(value as _, is),
@@ -138,15 +216,15 @@ impl<'tcx, B: orco::DeclarationBackend<'tcx>, CG: oc::BodyCodegen> CodegenCtx<'_
                             );
                             self.codegen.bconst(value != 0)
                         }
-                        orco::Type::Symbol(name) => {
+                        orco::Type::Symbol(name, _) => {
                             todo!("symbol discriminant type in SwitchInt ({name})")
                         }
                         ty => panic!("invalid discriminant type in SwitchInt: {ty}"),
                     };
-                    let condition = self.codegen.intrinsics().eq(discr, value);
-                    self.codegen.acf().cjump(condition, self.labels[&target]);
+                    let condition = self.codegen.eq(discr, value);
+                    self.codegen.cjump(condition, self.labels[&target]);
                 }
-                self.codegen.acf().jump(self.labels[&targets.otherwise()]);
+                self.codegen.jump(self.labels[&targets.otherwise()]);
             }
             TerminatorKind::UnwindResume => (),
             TerminatorKind::UnwindTerminate(..) => todo!(),
@@ -157,7 +235,7 @@ impl<'tcx, B: orco::DeclarationBackend<'tcx>, CG: oc::BodyCodegen> CodegenCtx<'_
             }
             TerminatorKind::Unreachable => todo!(),
             TerminatorKind::Drop { target, .. } => {
-                self.codegen.acf().jump(self.labels[target]);
+                self.codegen.jump(self.labels[target]);
                 // TODO
             }
             TerminatorKind::Call {
@@ -177,7 +255,7 @@ impl<'tcx, B: orco::DeclarationBackend<'tcx>, CG: oc::BodyCodegen> CodegenCtx<'_
                     );
                 }
                 if let Some(target) = target {
-                    self.codegen.acf().jump(oc::Label(target.index()));
+                    self.codegen.jump(oc::Label(target.index()));
                 }
             }
             TerminatorKind::TailCall { func, args, .. } => {
@@ -187,7 +265,7 @@ impl<'tcx, B: orco::DeclarationBackend<'tcx>, CG: oc::BodyCodegen> CodegenCtx<'_
                 self.codegen.return_(retval);
             }
             TerminatorKind::Assert { target, .. } => {
-                self.codegen.acf().jump(self.labels[target]);
+                self.codegen.jump(self.labels[target]);
                 // TODO
             }
             TerminatorKind::Yield { .. } => todo!(),
@@ -203,17 +281,13 @@ impl<'tcx, B: orco::DeclarationBackend<'tcx>, CG: oc::BodyCodegen> CodegenCtx<'_
 /// Note: Generates dirty code, not meant to be human-readable
 pub fn body<'a, 'tcx: 'a>(
     tcx: TyCtxt<'tcx>,
-    backend: &impl orco::DeclarationBackend<'tcx>,
     codegen: impl oc::BodyCodegen,
     body: &'a rustc_middle::mir::Body<'tcx>,
-    map: crate::types::GenericMap,
 ) {
     let mut ctx = CodegenCtx {
         tcx,
-        backend,
         codegen,
         body,
-        map,
         variables: HashMap::with_capacity(body.local_decls.len()),
         labels: HashMap::with_capacity(body.basic_blocks.len()),
     };
@@ -248,7 +322,7 @@ pub fn body<'a, 'tcx: 'a>(
     }
 
     for idx in body.basic_blocks.indices() {
-        ctx.labels.insert(idx, ctx.codegen.acf().alloc_label());
+        ctx.labels.insert(idx, ctx.codegen.alloc_label());
     }
 
     for block in body.basic_blocks.reverse_postorder() {
@@ -256,94 +330,97 @@ pub fn body<'a, 'tcx: 'a>(
     }
 }
 
-pub fn cg_function<'tcx, B>(tcx: TyCtxt<'tcx>, backend: &B, key: DefId)
-where
-    B: orco::DeclarationBackend + orco::CodegenBackend,
+/// Define function (or specialization) `name` with the body of `key`.
+/// Generic params are kept as [`orco::Type::Param`], so the body is polymorphic
+pub fn cg_function<B>(
+    tcx: TyCtxt,
+    backend: &B,
+    name: orco::Symbol,
+    generic_params: Vec<orco::Type>,
+    key: rustc_hir::def_id::DefId,
+) where
+    B: oc::CodegenBackend + ?Sized,
 {
-    crate::types::wrap_generics(
-        tcx,
-        backend,
-        key.into(),
-        key.into(),
-        "cg_",
-        move |tcx, backend, name, map| {
-            if map.generic() {
-                backend.invoke_macro(crate::names::convert_path(tcx, key), map.args());
-            }
-            body(
-                tcx,
-                backend,
-                backend.cg_function(name),
-                tcx.optimized_mir(key),
-                map,
-            );
-        },
-    )
+    let result = backend
+        .cg_function(name, generic_params)
+        .map(|codegen| body(tcx, codegen, tcx.optimized_mir(key)));
+    crate::Context { tcx, backend }.report(key, result);
 }
 
 /// Codegen all the functions using the backend provided.
+/// Trait method implementations become specializations of the trait method.
 /// See [`crate::declare`]
-pub fn codegen<'a, B>(tcx: TyCtxt<'a>, backend: &B, items: &rustc_middle::hir::ModuleItems)
+pub fn codegen<B>(tcx: TyCtxt, backend: &B, items: &rustc_middle::hir::ModuleItems)
 where
-    B: oc::CodegenBackend + orco::DeclarationBackend,
+    B: oc::CodegenBackend + ?Sized,
 {
     let backend = rustc_data_structures::sync::IntoDynSyncSend(backend);
     items
         .par_items(|item| {
             let item = tcx.hir_item(item);
-            let key = item.owner_id.def_id;
+            let key = item.owner_id.to_def_id();
+            let ctx = crate::Context {
+                tcx,
+                backend: *backend,
+            };
+            let function = |key: rustc_hir::def_id::DefId| {
+                cg_function(
+                    tcx,
+                    *backend,
+                    ctx.convert_path(key),
+                    ctx.convert_generics(key),
+                    key,
+                );
+            };
 
             use rustc_hir::ItemKind as IK;
             match item.kind {
                 IK::Static(..) => (),
                 IK::Const(..) => (),
-                IK::Fn { .. } => cg_func(tcx, backend, key),
+                IK::Fn { .. } => function(key),
                 IK::GlobalAsm { .. } => todo!("global_asm!"),
-                IK::Impl(impl_) if let Some(trait_) = impl_.of_trait => {
-                    let Some(trait_key) = trait_.trait_ref.trait_def_id() else {
-                        panic!("[bug?] trait impl of a non-trait?!");
-                    };
-
-                    // TODO: Generics
-                    let map = tcx.impl_item_implementor_ids(key);
-                    for item in tcx.associated_items(trait_key).in_definition_order() {
-                        let (impl_key, is_default_impl) = map
-                            .get(&item.def_id)
-                            .map_or((item.def_id, true), |key| (*key, false));
-                        let mut name = crate::names::convert_path(tcx, item.def_id);
-                        let trait_name = name.as_str().into();
-
-                        let self_ty = crate::types::convert(
-                            tcx,
-                            backend,
-                            tcx.type_of(key).instantiate_identity().skip_norm_wip(),
-                            crate::types::GenericMap::default(),
-                        );
-                        if let Some(ty) = &self_ty {
-                            name.push('_');
-                            name.push_str(&ty.hashable_name());
+                IK::Trait { items, .. } => {
+                    // Default implementations, used when an impl doesn't override them
+                    for item in items {
+                        if let rustc_hir::TraitItemKind::Fn(_, rustc_hir::TraitFn::Provided(..)) =
+                            tcx.hir_trait_item(*item).kind
+                        {
+                            function(item.owner_id.to_def_id());
                         }
-
-                        let trait_generic_args = self_ty.into_iter().collect::<Vec<_>>();
-                        backend.invoke_macro(trait_name, &trait_generic_args);
-                        let map = if is_default_impl {
-                            crate::types::GenericMap(1, &trait_generic_args)
-                        } else {
-                            crate::types::GenericMap::default()
+                    }
+                }
+                IK::Impl(impl_) if impl_.of_trait.is_some() => {
+                    let trait_ref = tcx
+                        .impl_trait_ref(key)
+                        .instantiate_identity()
+                        .skip_norm_wip();
+                    let trait_args = crate::types::convert_generic_args(tcx, trait_ref.args);
+                    for item in tcx.associated_items(key).in_definition_order() {
+                        let Some(trait_item) = item.trait_item_def_id() else {
+                            continue;
                         };
-
-                        body(
+                        if !item.is_fn() {
+                            continue;
+                        }
+                        let mut generic_params = trait_args.clone();
+                        for param in &tcx.generics_of(item.def_id).own_params {
+                            generic_params.push(orco::Type::Param(param.name.as_str().into()));
+                        }
+                        cg_function(
                             tcx,
-                            backend,
-                            backend.cg_function(name.into()),
-                            tcx.optimized_mir(impl_key),
-                            map,
+                            *backend,
+                            ctx.convert_path(trait_item),
+                            generic_params,
+                            item.def_id,
                         );
                     }
                 }
                 IK::Impl(impl_) => {
                     for item in impl_.items {
-                        cg_func(tcx, backend, item.owner_id.to_def_id());
+                        let key = item.owner_id.to_def_id();
+                        if tcx.def_kind(key) == rustc_hir::def::DefKind::AssocFn {
+                            function(key);
+                        }
                     }
                 }
                 _ => (),
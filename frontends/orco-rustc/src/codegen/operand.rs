@@ -1,6 +1,6 @@
 use super::{CodegenCtx, oc};
 
-impl<'tcx, B: orco::DeclarationBackend<'tcx>, CG: oc::BodyCodegen> CodegenCtx<'_, 'tcx, B, CG> {
+impl<'tcx, CG: oc::BodyCodegen> CodegenCtx<'_, 'tcx, CG> {
     pub(super) fn place(&mut self, place: rustc_middle::mir::Place<'tcx>) -> Option<oc::Place> {
         let mut res = oc::Place::Variable(self.variables[&place.local]?);
         for (_, proj) in place.iter_projections() {
@@ -74,10 +74,12 @@ impl<'tcx, B: orco::DeclarationBackend<'tcx>, CG: oc::BodyCodegen> CodegenCtx<'_
                     },
                     ConstValue::ZeroSized => match ty.kind() {
                         // TODO: We might need to do more
-                        // TODO: Generics
-                        rustc_middle::ty::TyKind::FnDef(func, generics) => self
-                            .codegen
-                            .read(oc::Place::Global(self.generic_name(*func, generics))),
+                        rustc_middle::ty::TyKind::FnDef(func, args) => {
+                            self.codegen.read(oc::Place::Global(
+                                crate::names::convert_path(self.tcx, *func),
+                                crate::types::convert_generic_args(self.tcx, args.skip_binder()),
+                            ))
+                        }
                         rustc_middle::ty::TyKind::Adt(..) => {
                             let var = self.codegen.declare_var(self.convert_ty(ty)?, Some("zst"));
                             self.codegen.read(var.into())
@@ -1,5 +1,5 @@
 use orco::Type;
-use orco::codegen::{BodyCodegen as _, Intrinsics as _};
+use orco::codegen::{BodyCodegen, Builder, Intrinsics as _};
 
 fn integers(mut cb: impl FnMut(Type)) {
     use orco::types::IntegerSize as IS;
@@ -12,109 +12,131 @@ fn integers(mut cb: impl FnMut(Type)) {
     cb(Type::Unsigned(IS::Size));
 }
 
+fn floats(mut cb: impl FnMut(Type)) {
+    cb(Type::Float(32));
+    cb(Type::Float(64));
+}
+
+/// Symbol of an intrinsic implementing operation `op` on type `ty`
+#[must_use]
+pub fn name(op: &str, ty: Type) -> orco::Symbol {
+    orco::Symbol::new(orco::symbol::Path {
+        generics: vec![ty],
+        ..orco::symbol::Path::bare(format!("__{op}"))
+    })
+}
+
 fn tuple2(ty1: Type, ty2: Type) -> Type {
     Type::Struct {
         fields: vec![(None, ty1), (None, ty2)],
     }
 }
 
+const ARITHMETIC: &[&str] = &["Sub", "Div", "Rem"];
+const BITWISE: &[&str] = &["BitAnd", "BitOr", "BitXor"];
+const COMPARISON: &[&str] = &["Lt", "Le", "Gt", "Ge"];
+const CHECKED: &[&str] = &["AddWithOverflow", "SubWithOverflow", "MulWithOverflow"];
+
+/// Whether the intrinsic is defined by [`codegen`]
+fn is_defined(op: &str, ty: &Type) -> bool {
+    *ty == Type::Bool || matches!(op, "AddWithOverflow" | "MulWithOverflow")
+}
+
 /// Declares rust's intrinsics
-pub fn declare<'a>(backend: &impl orco::DeclarationBackend<'a>) {
-    let intrinsic = |name: String, params, rt| {
-        backend.function(
-            name.into(),
-            params,
-            Some(rt),
-            orco::attrs::FunctionAttributes {
-                inlining: orco::attrs::Inlining::Always,
-            },
-        )
+pub fn declare(backend: &(impl orco::DeclarationBackend + ?Sized)) {
+    let intrinsic = |op: &str, ty: &Type, rt| {
+        backend
+            .function(
+                name(op, ty.clone()),
+                Vec::new(),
+                vec![(None, ty.clone()), (None, ty.clone())],
+                Some(rt),
+                orco::attrs::FunctionAttributes {
+                    inlining: if is_defined(op, ty) {
+                        orco::attrs::Inlining::Always
+                    } else {
+                        orco::attrs::Inlining::Auto
+                    },
+                    destructor: false,
+                    // Defined in every crate that uses them
+                    internal: is_defined(op, ty),
+                    location: None,
+                },
+            )
+            .unwrap_or_else(|err| panic!("[bug?] {err}"));
     };
 
     integers(|ty| {
-        intrinsic(
-            format!("__MulWithOverflow#{}", ty.hashable_name()),
-            vec![(None, ty.clone()), (None, ty.clone())],
-            tuple2(ty.clone(), Type::Bool),
-        );
-        intrinsic(
-            format!("__AddWithOverflow#{}", ty.hashable_name()),
-            vec![(None, ty.clone()), (None, ty.clone())],
-            tuple2(ty.clone(), Type::Bool),
-        );
-        intrinsic(
-            format!("__Lt#{}", ty.hashable_name()),
-            vec![(None, ty.clone()), (None, ty.clone())],
-            Type::Bool,
-        );
-        intrinsic(
-            format!("__Gt#{}", ty.hashable_name()),
-            vec![(None, ty.clone()), (None, ty.clone())],
-            Type::Bool,
-        );
-        intrinsic(
-            format!("__Eq#{}", ty.hashable_name()),
-            vec![(None, ty.clone()), (None, ty.clone())],
-            Type::Bool,
-        );
-        intrinsic(
-            format!("__Div#{}", ty.hashable_name()),
-            vec![(None, ty.clone()), (None, ty.clone())],
-            ty.clone(),
-        );
+        for op in ARITHMETIC.iter().chain(BITWISE) {
+            intrinsic(op, &ty, ty.clone());
+        }
+        for op in COMPARISON {
+            intrinsic(op, &ty, Type::Bool);
+        }
+        for op in CHECKED {
+            intrinsic(op, &ty, tuple2(ty.clone(), Type::Bool));
+        }
+    });
+    floats(|ty| {
+        for op in ARITHMETIC {
+            intrinsic(op, &ty, ty.clone());
+        }
+        for op in COMPARISON {
+            intrinsic(op, &ty, Type::Bool);
+        }
     });
-    intrinsic(
-        "__BitAnd#bool".to_owned(),
-        vec![(None, Type::Bool), (None, Type::Bool)],
-        Type::Bool,
-    );
+    for op in BITWISE {
+        intrinsic(op, &Type::Bool, Type::Bool);
+    }
+}
+
+fn define(
+    backend: &(impl orco::CodegenBackend + ?Sized),
+    symbol: orco::Symbol,
+    body: impl FnOnce(&mut Builder<Box<dyn BodyCodegen + '_>>),
+) {
+    orco::codegen::builder::define(backend, symbol, Vec::new(), body)
+        .unwrap_or_else(|err| panic!("[bug?] {err}"));
 }
 
-/// Codegens rust's intrinsics
-pub fn codegen(backend: &impl orco::CodegenBackend) {
+/// Defines the intrinsics that can be expressed with orco intrinsics,
+/// see [`declare`]
+pub fn codegen(backend: &(impl orco::CodegenBackend + ?Sized)) {
     integers(|ty| {
-        let mut cg =
-            backend.cg_function(format!("__MulWithOverflow#{}", ty.hashable_name()).into());
-        let a = cg.read(orco::codegen::Variable(0).into());
-        let b = cg.read(orco::codegen::Variable(1).into());
-        let result = cg.declare_var(tuple2(ty.clone(), Type::Bool), None);
-        let sum = cg.intrinsics().mul(a, b);
-        cg.assign(result.place().field(0), sum);
-        let cfalse = cg.bconst(false);
-        cg.assign(result.place().field(1), cfalse);
-        let result = cg.read(result.into());
-        cg.return_(Some(result));
-        let mut cg =
-            backend.cg_function(format!("__AddWithOverflow#{}", ty.hashable_name()).into());
-        let a = cg.read(orco::codegen::Variable(0).into());
-        let b = cg.read(orco::codegen::Variable(1).into());
-        let result = cg.declare_var(tuple2(ty.clone(), Type::Bool), None);
-        let sum = cg.intrinsics().add(a, b);
-        cg.assign(result.place().field(0), sum);
-        let cfalse = cg.bconst(false);
-        cg.assign(result.place().field(1), cfalse);
-        let result = cg.read(result.into());
-        cg.return_(Some(result));
-        let mut cg = backend.cg_function(format!("__Lt#{}", ty.hashable_name()).into()); // FIXME: HARDCODED
-        cg.return_(None);
-        let mut cg = backend.cg_function(format!("__Gt#{}", ty.hashable_name()).into()); // FIXME: HARDCODED
-        cg.return_(None);
-        let mut cg = backend.cg_function(format!("__Eq#{}", ty.hashable_name()).into()); // FIXME: HARDCODED
-        cg.return_(None);
-        let mut cg = backend.cg_function(format!("__Div#{}", ty.hashable_name()).into()); // FIXME: HARDCODED
-        cg.return_(None);
+        // FIXME: Overflow is not detected yet
+        define(backend, name("MulWithOverflow", ty.clone()), |b| {
+            let (a, c) = (b.param(0), b.param(1));
+            let (a, c) = (b.get(&a), b.get(&c));
+            let product = b.mul(a, c);
+            let overflow = b.bconst(false);
+            let result = b.struct_(vec![product, overflow]);
+            b.return_(Some(result));
+        });
+        define(backend, name("AddWithOverflow", ty.clone()), |b| {
+            let (a, c) = (b.param(0), b.param(1));
+            let (a, c) = (b.get(&a), b.get(&c));
+            let sum = b.add(a, c);
+            let overflow = b.bconst(false);
+            let result = b.struct_(vec![sum, overflow]);
+            b.return_(Some(result));
+        });
     });
-    let mut cg = backend.cg_function("__BitAnd#bool".into()); // FIXME: HARDCODED
-    cg.return_(None);
-}
 
-static BACKEND: std::sync::OnceLock<orco_ir::Backend<'static>> = std::sync::OnceLock::new();
-/// WIP
-pub fn intrinsics() -> &'static orco_ir::Backend<'static> {
-    BACKEND.get_or_init(|| {
-        let backend = orco_ir::Backend::new();
-        declare(&backend);
-        codegen(&backend);
-        backend
-    })
+    define(backend, name("BitAnd", Type::Bool), |b| {
+        let (a, c) = (b.param(0), b.param(1));
+        let condition = b.get(&a);
+        b.if_else(condition, |b| b.ret(&c), |b| b.ret(&a));
+    });
+    define(backend, name("BitOr", Type::Bool), |b| {
+        let (a, c) = (b.param(0), b.param(1));
+        let condition = b.get(&a);
+        b.if_else(condition, |b| b.ret(&a), |b| b.ret(&c));
+    });
+    define(backend, name("BitXor", Type::Bool), |b| {
+        let (a, c) = (b.param(0), b.param(1));
+        let (a, c) = (b.get(&a), b.get(&c));
+        let eq = b.eq(a, c);
+        let ne = b.not(eq);
+        b.return_(Some(ne));
+    });
 }
@@ -23,6 +23,9 @@ pub mod names;
 /// Type conversion
 pub mod types;
 
+/// Span conversion, for debug info
+pub mod spans;
+
 /// rustc backend implementation
 pub mod rustc_backend;
 
@@ -33,34 +36,32 @@ pub mod symbols;
 pub mod codegen;
 pub use codegen::codegen;
 
-// /// Intrinsic implementations
-// pub mod intrinsics;
-// pub use intrinsics::intrinsics;
+/// Implementations of rust binary operations orco has no intrinsics for.
+/// Ones that can't be expressed with orco intrinsics are only declared,
+/// so for now they have to be provided at link time
+pub mod intrinsics;
 
 /// Base context for all declaration/codegen operations
 #[allow(missing_docs)]
-pub struct Context<'tcx, 'b, B> {
+pub struct Context<'tcx, 'b, B: ?Sized> {
     pub tcx: TyCtxt<'tcx>,
     pub backend: &'b B,
 }
 
-impl<B> Copy for Context<'_, '_, B> {}
-impl<B> Clone for Context<'_, '_, B> {
+impl<B: ?Sized> Copy for Context<'_, '_, B> {}
+impl<B: ?Sized> Clone for Context<'_, '_, B> {
     fn clone(&self) -> Self {
-        Self {
-            tcx: self.tcx,
-            backend: self.backend,
-        }
+        *self
     }
 }
 
-impl<B> Context<'_, '_, B> {
+impl<B: ?Sized> Context<'_, '_, B> {
     /// Shorthand for calling [`names::convert_path`]
     pub fn convert_path(
         self,
         key: impl rustc_middle::query::IntoQueryKey<rustc_hir::def_id::DefId>,
     ) -> orco::Symbol {
-        names::convert_path(self.tcx, key.into_query_key()).into()
+        names::convert_path(self.tcx, key.into_query_key())
     }
 
     /// Shorthand for calling [`types::convert`]
@@ -68,6 +69,24 @@ impl<B> Context<'_, '_, B> {
         types::convert(self.tcx, ty)
     }
 
+    /// Shorthand for calling [`spans::convert`]
+    pub fn convert_span(self, span: rustc_span::Span) -> Option<orco::attrs::SourceLocation> {
+        spans::convert(self.tcx, span)
+    }
+
+    /// Report a backend error at the definition of `key`.
+    /// Compilation goes on, so that other errors get reported too
+    pub fn report(
+        self,
+        key: impl rustc_middle::query::IntoQueryKey<rustc_hir::def_id::DefId>,
+        result: Result<(), orco::Error>,
+    ) {
+        if let Err(err) = result {
+            let span = self.tcx.def_span(key.into_query_key());
+            self.tcx.dcx().span_err(span, err.to_string());
+        }
+    }
+
     /// Shorthand for calling [`types::convert_generic_params`]
     pub fn convert_generics(
         self,
@@ -81,7 +100,7 @@ impl<B> Context<'_, '_, B> {
 /// See [`TyCtxt::hir_crate_items`]
 pub fn declare<B>(tcx: TyCtxt, backend: &B, items: &rustc_middle::hir::ModuleItems)
 where
-    B: orco::DeclarationBackend + Send + Sync,
+    B: orco::DeclarationBackend + Send + Sync + ?Sized,
 {
     let backend = rustc_data_structures::sync::IntoDynSyncSend(backend);
     items
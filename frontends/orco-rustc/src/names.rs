@@ -1,27 +1,36 @@
 use crate::TyCtxt;
 
-/// Convert path to a string
+/// Convert path to a structured symbol.
+/// Items in `extern` blocks are bare, so they can be linked against
 #[must_use]
-pub fn convert_path(tcx: TyCtxt, key: rustc_hir::def_id::DefId) -> String {
+pub fn convert_path(tcx: TyCtxt, key: rustc_hir::def_id::DefId) -> orco::Symbol {
     let path = tcx.def_path(key);
-    let mut s = tcx.crate_name(path.krate).to_string();
-    s.reserve(path.data.len() * 16 + 16);
+    let mut krate = Some(tcx.crate_name(path.krate).as_str().into());
+    let mut segments = Vec::with_capacity(path.data.len());
 
     for component in &path.data {
         if matches!(
             component.data,
             rustc_hir::definitions::DefPathData::ForeignMod
         ) {
-            s.clear()
+            krate = None;
+            segments.clear();
         } else {
-            if !s.is_empty() {
-                s.push_str("::")
-            }
-            s.push_str(component.as_sym(true).as_str());
+            segments.push(component.as_sym(true).as_str().into());
         }
     }
 
-    s
+    let Some(last) = path.data.last() else {
+        panic!("[bug?] trying to convert path of a crate root");
+    };
+    segments.pop();
+    orco::Symbol::new(orco::symbol::Path {
+        krate,
+        segments,
+        name: last.as_sym(false).as_str().into(),
+        generics: Vec::new(),
+        disambiguator: last.disambiguator,
+    })
 }
 
 /// Extract the pattern name, if there is one concrete name
@@ -10,6 +10,57 @@ use rustc_session::Session;
 /// `rustc_ssa_codegen` backend for orco
 pub struct OrcoCodegenBackend;
 
+/// A backend monomorphized code is generated in, printed at the end
+pub trait OutputBackend: orco::Backend + std::fmt::Display + Send + Sync {}
+impl<B: orco::Backend + std::fmt::Display + Send + Sync> OutputBackend for B {}
+
+/// Pick output backends at runtime, using `ORCO_BACKEND` environment variable:
+/// a comma-separated list of `c` (the default) for C and `ir` for orco IR
+pub fn output_backends(
+    tcx: TyCtxt<'_>,
+) -> orco::codegen::impls::Multiplexer<Box<dyn OutputBackend>> {
+    let names = match std::env::var("ORCO_BACKEND") {
+        Ok(names) => names,
+        Err(std::env::VarError::NotPresent) => "c".to_owned(),
+        Err(err) => tcx.dcx().fatal(format!("invalid ORCO_BACKEND: {err}")),
+    };
+    let backends = names
+        .split(',')
+        .map(|name| -> Box<dyn OutputBackend> {
+            match name.trim() {
+                "c" => Box::new(orco_cgen::Backend::new()),
+                "ir" => Box::new(orco_ir::Store::new()),
+                name => tcx.dcx().fatal(format!("unknown orco backend {name:?}")),
+            }
+        })
+        .collect();
+    orco::codegen::impls::Multiplexer::new(backends)
+}
+
+/// Run the pass pipeline matching `-C opt-level` on the IR.
+/// Pass timings are printed with `-Z time-passes`
+pub fn optimize(tcx: TyCtxt<'_>, ir: &mut orco_ir::Store) {
+    use rustc_session::config::OptLevel;
+    let pipeline = match tcx.sess.opts.optimize {
+        OptLevel::No => "O0",
+        OptLevel::Less => "O1",
+        OptLevel::More => "O2",
+        OptLevel::Aggressive => "O3",
+        OptLevel::Size => "Os",
+        OptLevel::SizeMin => "Oz",
+    };
+    let manager = orco_ir::passes::PassManager::new();
+    match manager.run(ir, &[pipeline]) {
+        Ok(stats) if tcx.sess.opts.unstable_opts.time_passes => {
+            for stats in stats {
+                eprintln!("orco {stats}");
+            }
+        }
+        Ok(_) => (),
+        Err(err) => tcx.dcx().fatal(err.to_string()),
+    }
+}
+
 impl rustc_codegen_ssa::traits::CodegenBackend for OrcoCodegenBackend {
     fn name(&self) -> &'static str {
         "orco codegen"
@@ -24,14 +75,27 @@ impl rustc_codegen_ssa::traits::CodegenBackend for OrcoCodegenBackend {
         // rustc_middle::mir::write_mir_pretty(tcx, &mut std::io::stdout()).unwrap();
         let items = tcx.hir_crate_items(());
 
-        let ir = orco_ir::Store::new();
+        let mut ir = orco_ir::Store::new();
+        crate::intrinsics::declare(&ir);
         crate::declare(tcx, &ir, items);
-        // crate::codegen(tcx, &ir, items);
+        crate::intrinsics::codegen(&ir);
+        crate::codegen(tcx, &ir, items);
+        tcx.dcx().abort_if_errors();
+        optimize(tcx, &mut ir);
 
-        let backend = orco_cgen::Backend::new();
-        ir.monomorphize();
-        ir.declare_mono(&backend);
-        print!("{backend}");
+        let output = output_backends(tcx);
+        if let Err(errors) = ir.monomorphize().and_then(|()| ir.declare_mono(&output)) {
+            for err in errors {
+                tcx.dcx().err(err.to_string());
+            }
+            tcx.dcx().abort_if_errors();
+        }
+        if let Err(err) = ir.codegen_mono(&output).and_then(|()| output.finish()) {
+            tcx.dcx().fatal(err.to_string());
+        }
+        for backend in &output.backends {
+            print!("{backend}");
+        }
 
         std::process::exit(0)
     }
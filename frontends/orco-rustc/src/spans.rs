@@ -0,0 +1,22 @@
+use rustc_middle::ty::TyCtxt;
+
+/// Convert a span to [`orco::attrs::SourceLocation`] (start of the span).
+/// Spans from macro expansions point to the macro invocation
+pub fn convert(tcx: TyCtxt, span: rustc_span::Span) -> Option<orco::attrs::SourceLocation> {
+    let span = span.source_callsite();
+    if span.is_dummy() {
+        return None;
+    }
+
+    let location = tcx.sess.source_map().lookup_char_pos(span.lo());
+    Some(orco::attrs::SourceLocation {
+        file: location
+            .file
+            .name
+            .prefer_remapped_unconditionally()
+            .to_string()
+            .into(),
+        line: location.line as _,
+        column: location.col.0 as u32 + 1,
+    })
+}
@@ -1,5 +1,6 @@
 fn convert_fn_attrs(
     attrs: &rustc_middle::middle::codegen_fn_attrs::CodegenFnAttrs,
+    location: Option<orco::attrs::SourceLocation>,
 ) -> orco::attrs::FunctionAttributes {
     use orco::attrs as oa;
     use rustc_hir::attrs as ra;
@@ -11,17 +12,23 @@ fn convert_fn_attrs(
             ra::InlineAttr::Never => oa::Inlining::Never,
             ra::InlineAttr::Force { .. } => oa::Inlining::Always,
         },
+        destructor: false,
+        internal: false,
+        location,
     }
 }
 
 impl<B> crate::Context<'_, '_, B>
 where
-    B: orco::DeclarationBackend,
+    B: orco::DeclarationBackend + ?Sized,
 {
     /// Declare a function from MIR by [`rustc_hir::def_id::LocalDefId`].
     /// The function MUST have a body. For bodyless functions, see [`Self::function_decl`]
     pub fn function(self, key: rustc_hir::def_id::LocalDefId) {
-        let attrs = convert_fn_attrs(self.tcx.codegen_fn_attrs(key));
+        let attrs = convert_fn_attrs(
+            self.tcx.codegen_fn_attrs(key),
+            self.convert_span(self.tcx.def_span(key)),
+        );
         let sig = self.tcx.fn_sig(key).instantiate_identity().skip_binder();
         let body = self.tcx.hir_body_owned_by(key);
 
@@ -34,13 +41,14 @@ where
             params.push((name, ty));
         }
 
-        self.backend.function(
+        let result = self.backend.function(
             self.convert_path(key),
             self.convert_generics(key),
             params,
             self.convert_ty(sig.output()),
             attrs.clone(),
         );
+        self.report(key, result);
     }
 
     /// Declare a foregin function.
@@ -51,7 +59,10 @@ where
         key: rustc_hir::def_id::DefId,
         idents: &[Option<rustc_span::Ident>],
     ) {
-        let attrs = convert_fn_attrs(self.tcx.codegen_fn_attrs(key));
+        let attrs = convert_fn_attrs(
+            self.tcx.codegen_fn_attrs(key),
+            self.convert_span(self.tcx.def_span(key)),
+        );
         let sig = self.tcx.fn_sig(key).instantiate_identity().skip_binder();
 
         let mut params = Vec::with_capacity(sig.inputs().len());
@@ -62,13 +73,14 @@ where
             params.push((idents[i].map(|ident| ident.as_str().to_owned()), ty));
         }
 
-        self.backend.function(
+        let result = self.backend.function(
             self.convert_path(key),
             self.convert_generics(key),
             params,
             self.convert_ty(sig.output()),
             attrs.clone(),
         );
+        self.report(key, result);
     }
 
     /// Declare a struct type from MIR by [`rustc_hir::def_id::DefId`].
@@ -96,10 +108,11 @@ where
                 ty,
             ));
         }
-        self.backend.type_(
+        let result = self.backend.type_(
             self.convert_path(key),
             self.convert_generics(key),
             orco::Type::Struct { fields },
         );
+        self.report(key, result);
     }
 }
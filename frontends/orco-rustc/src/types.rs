@@ -30,7 +30,7 @@ pub fn convert(tcx: TyCtxt, ty: rustc_middle::ty::Ty) -> Option<orco::Type> {
             FloatTy::F128 => 128,
         }),
         TyKind::Adt(def, generics) => orco::Type::Symbol(
-            crate::names::convert_path(tcx, def.did()).into(),
+            crate::names::convert_path(tcx, def.did()),
             convert_generic_args(tcx, generics),
         ),
         TyKind::Foreign(..) => todo!(),
@@ -98,6 +98,7 @@ pub fn convert_generic_args(tcx: TyCtxt, args: &rustc_middle::ty::GenericArgs) -
         .collect()
 }
 
+/// Generic params of an item (parent's first), as [`orco::Type::Param`]s
 pub fn convert_generic_params(tcx: TyCtxt, key: rustc_hir::def_id::DefId) -> Vec<orco::Type> {
     let generics = tcx.generics_of(key);
     let mut types = generics
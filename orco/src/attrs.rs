@@ -3,6 +3,14 @@
 pub struct FunctionAttributes {
     /// Inlining mode
     pub inlining: Inlining,
+    /// Run the function at process exit (after `main` returns or on `exit`).
+    /// Such a function takes no arguments and returns nothing
+    pub destructor: bool,
+    /// Not visible outside of the module (`static` in C), so it can be removed
+    /// once nothing references it, f.e. after it was inlined everywhere
+    pub internal: bool,
+    /// Where the function is defined in the source code, for debug info
+    pub location: Option<SourceLocation>,
 }
 
 impl std::fmt::Display for FunctionAttributes {
@@ -10,11 +18,37 @@ impl std::fmt::Display for FunctionAttributes {
         if self.inlining != Inlining::Auto {
             write!(f, "[inline({})] ", self.inlining)?;
         }
+        if self.destructor {
+            write!(f, "[destructor] ")?;
+        }
+        if self.internal {
+            write!(f, "[internal] ")?;
+        }
+        if let Some(location) = &self.location {
+            write!(f, "[location({location})] ")?;
+        }
 
         Ok(())
     }
 }
 
+/// A position in the source code, see [`crate::codegen::BodyCodegen::set_location`]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourceLocation {
+    /// Path to the source file
+    pub file: sinter::IStr,
+    /// 1-based line number
+    pub line: u32,
+    /// 1-based column number
+    pub column: u32,
+}
+
+impl std::fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
 /// Inlining mode, [`Inlining::Auto`] by default
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Inlining {
@@ -39,3 +73,39 @@ impl std::fmt::Display for Inlining {
         }
     }
 }
+
+/// Attributes for a loop, see [`crate::codegen::BodyCodegen::loop_attributes`]
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LoopAttributes {
+    /// Unrolling mode
+    pub unrolling: Unrolling,
+}
+
+impl std::fmt::Display for LoopAttributes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.unrolling != Unrolling::Auto {
+            write!(f, "[unroll({})] ", self.unrolling)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Loop unrolling mode, [`Unrolling::Auto`] by default
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Unrolling {
+    /// Never unroll the loop
+    Never,
+    /// Automatically decide, whether to unroll the loop for optimization purpuses
+    #[default]
+    Auto,
+}
+
+impl std::fmt::Display for Unrolling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Unrolling::Never => write!(f, "never"),
+            Unrolling::Auto => write!(f, "auto"),
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! A structured layer on top of [`BodyCodegen`]: typed variable handles,
+//! closure-based blocks and struct construction, with types inferred from values.
+//! ```ignore
+//! define(&backend, "max".into(), Vec::new(), |b| {
+//!     let (a, c) = (b.param(0), b.param(1));
+//!     let (lhs, rhs) = (b.get(&a), b.get(&c));
+//!     let cond = b.call_global("lt".into(), Vec::new(), vec![lhs, rhs]).unwrap();
+//!     b.if_else(cond, |b| b.ret(&c), |b| b.ret(&a));
+//! })?;
+//! ```
+use super::*;
+
+/// A variable along with its type
+#[derive(Clone, Debug, PartialEq)]
+pub struct Local {
+    #[allow(missing_docs)]
+    pub variable: Variable,
+    #[allow(missing_docs)]
+    pub ty: Type,
+}
+
+impl Local {
+    /// Place of a field of this variable
+    #[must_use]
+    pub fn field(&self, index: usize) -> Place {
+        self.place().field(index)
+    }
+
+    /// Quickly convert to [Place]
+    #[must_use]
+    pub fn place(&self) -> Place {
+        self.variable.into()
+    }
+}
+
+impl From<&Local> for Place {
+    fn from(value: &Local) -> Self {
+        value.place()
+    }
+}
+
+/// Error building a struct value, see [`Builder::struct_of`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum StructError {
+    /// The type is not a struct (or an alias to one)
+    NotAStruct(Type),
+    /// The struct has a different number of fields
+    FieldCount {
+        #[allow(missing_docs)]
+        expected: usize,
+        #[allow(missing_docs)]
+        found: usize,
+    },
+}
+
+impl std::fmt::Display for StructError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StructError::NotAStruct(ty) => {
+                write!(f, "trying to build a struct value of non-struct type {ty}")
+            }
+            StructError::FieldCount { expected, found } => write!(
+                f,
+                "trying to build a struct of {expected} fields out of {found} values"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StructError {}
+
+/// Structured function builder. Derefs to the underlying codegen,
+/// so all of [`BodyCodegen`] is available as well.
+/// Variables declared inside a block are killed
+/// (see [`BodyCodegen::storage_dead`]) when the block ends
+pub struct Builder<CG: BodyCodegen> {
+    #[allow(missing_docs)]
+    pub codegen: CG,
+    scopes: Vec<Vec<Variable>>,
+}
+
+impl<CG: BodyCodegen> Builder<CG> {
+    #[allow(missing_docs)]
+    pub fn new(codegen: CG) -> Self {
+        Self {
+            codegen,
+            scopes: Vec::new(),
+        }
+    }
+
+    /// Get the underlying codegen back
+    pub fn finish(self) -> CG {
+        self.codegen
+    }
+
+    /// Handle to a function parameter
+    pub fn param(&self, index: usize) -> Local {
+        let variable = Variable(index);
+        Local {
+            variable,
+            ty: self.codegen.variable_type(variable),
+        }
+    }
+
+    /// Declare a variable
+    pub fn var(&mut self, ty: Type, name: Option<&str>) -> Local {
+        let variable = self.codegen.declare_var(ty.clone(), name);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(variable);
+        }
+        Local { variable, ty }
+    }
+
+    /// Declare a variable holding the value, type is inferred
+    pub fn let_(&mut self, name: Option<&str>, value: Value) -> Local {
+        let local = self.var(self.codegen.type_of(value.0), name);
+        self.codegen.assign(local.place(), value);
+        local
+    }
+
+    /// Read a variable
+    pub fn get(&mut self, local: &Local) -> Value {
+        self.codegen.read(local.place())
+    }
+
+    /// Assign a value into a place
+    pub fn set(&mut self, place: impl Into<Place>, value: Value) {
+        self.codegen.assign(place.into(), value);
+    }
+
+    /// Return the value of a variable
+    pub fn ret(&mut self, local: &Local) {
+        let value = self.get(local);
+        self.codegen.return_(Some(value));
+    }
+
+    /// Build an anonymous struct value out of field values,
+    /// field types are inferred
+    pub fn struct_(&mut self, fields: Vec<Value>) -> Value {
+        let ty = Type::Struct {
+            fields: fields
+                .iter()
+                .map(|value| (None, self.codegen.type_of(value.0)))
+                .collect(),
+        };
+        self.build_struct(ty, fields)
+    }
+
+    /// Build a value of a struct type (or an alias to one) out of field values.
+    /// Fails if the type is not a struct or the number of fields doesn't match
+    pub fn struct_of(&mut self, ty: Type, fields: Vec<Value>) -> Result<Value, StructError> {
+        match self.codegen.inline_type(ty.clone()) {
+            Type::Struct { fields: ref types } if types.len() == fields.len() => (),
+            Type::Struct { fields: types } => {
+                return Err(StructError::FieldCount {
+                    expected: types.len(),
+                    found: fields.len(),
+                });
+            }
+            ty => return Err(StructError::NotAStruct(ty)),
+        }
+        Ok(self.build_struct(ty, fields))
+    }
+
+    fn build_struct(&mut self, ty: Type, fields: Vec<Value>) -> Value {
+        let tmp = self.var(ty, None);
+        for (index, value) in fields.into_iter().enumerate() {
+            self.codegen.assign(tmp.field(index), value);
+        }
+        self.get(&tmp)
+    }
+
+    /// Call a global function
+    pub fn call_global(
+        &mut self,
+        name: Symbol,
+        generics: Vec<Type>,
+        args: Vec<Value>,
+    ) -> Option<Value> {
+        let func = self.codegen.read(Place::Global(name, generics));
+        self.codegen.call(func, args)
+    }
+
+    /// Run `f` in a new scope, ending it with [`BcfCodegen::end`]
+    fn block(&mut self, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(Vec::new());
+        f(self);
+        self.kill_scope();
+        self.codegen.end();
+    }
+
+    fn kill_scope(&mut self) {
+        let scope = self.scopes.pop().expect("[bug?] no scope to end");
+        for variable in scope.into_iter().rev() {
+            self.codegen.storage_dead(variable);
+        }
+    }
+
+    /// Execute `then` if the condition is true
+    pub fn if_(&mut self, condition: Value, then: impl FnOnce(&mut Self)) {
+        self.codegen.if_(condition);
+        self.block(then);
+    }
+
+    /// Execute `then` if the condition is true, `otherwise` if not
+    pub fn if_else(
+        &mut self,
+        condition: Value,
+        then: impl FnOnce(&mut Self),
+        otherwise: impl FnOnce(&mut Self),
+    ) {
+        self.codegen.if_(condition);
+        self.scopes.push(Vec::new());
+        then(self);
+        self.kill_scope();
+        self.codegen.else_();
+        self.block(otherwise);
+    }
+
+    /// Repeat `body` until a [`BcfCodegen::break_`] (or a return)
+    pub fn loop_(&mut self, body: impl FnOnce(&mut Self)) {
+        self.codegen.loop_();
+        self.block(body);
+    }
+
+    /// Repeat `body` while `condition` yields true.
+    /// The condition is evaluated before each iteration
+    pub fn while_(
+        &mut self,
+        condition: impl FnOnce(&mut Self) -> Value,
+        body: impl FnOnce(&mut Self),
+    ) {
+        self.loop_(|b| {
+            let condition = condition(b);
+            let condition = b.codegen.not(condition);
+            b.codegen.cbreak(condition);
+            body(b);
+        });
+    }
+}
+
+impl<CG: BodyCodegen> std::ops::Deref for Builder<CG> {
+    type Target = CG;
+
+    fn deref(&self) -> &Self::Target {
+        &self.codegen
+    }
+}
+
+impl<CG: BodyCodegen> std::ops::DerefMut for Builder<CG> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.codegen
+    }
+}
+
+/// Define a function with a [Builder], see [`CodegenBackend::cg_function`]
+pub fn define<B: CodegenBackend + ?Sized>(
+    backend: &B,
+    name: Symbol,
+    generic_params: Vec<Type>,
+    body: impl FnOnce(&mut Builder<Box<dyn BodyCodegen + '_>>),
+) -> Result<(), crate::Error> {
+    let mut builder = Builder::new(backend.cg_function(name, generic_params)?);
+    body(&mut builder);
+    Ok(())
+}
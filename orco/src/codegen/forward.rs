@@ -0,0 +1,292 @@
+//! Codegen traits are implemented for `&mut CG` and `Box<CG>` (including `Box<dyn BodyCodegen>`),
+//! so generic code can work with a backend picked at runtime.
+//! Same goes for [`CodegenBackend`] behind `&` and `Box`.
+//! Every method is forwarded, so overridden default methods of the backend are still used.
+//! Wrapping codegens forward the methods they don't change with `forward_methods!`
+use super::*;
+
+/// Implements methods of the codegen traits by forwarding them, inside of an `impl` block.
+/// Takes the receiver (`self`, it can't be named by the macro itself), the expression
+/// to forward to in brackets and names of the methods:
+/// ```ignore
+/// impl cg::AcfCodegen for Wrapper {
+///     forward_methods!(self => [self.codegen] alloc_label, jump, cjump);
+///
+///     fn label(&mut self, label: cg::Label) { ... }
+/// }
+/// ```
+macro_rules! forward_methods {
+    ($self:ident => $inner:tt $($method:ident),* $(,)?) => {
+        $($crate::codegen::forward::forward_methods!(@method $self $inner $method);)*
+    };
+
+    // Intrinsics
+    (@method $self:ident [$($inner:tt)+] add) => {
+        fn add(&mut $self, a: $crate::codegen::Value, b: $crate::codegen::Value) -> $crate::codegen::Value {
+            $($inner)+.add(a, b)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] mul) => {
+        fn mul(&mut $self, a: $crate::codegen::Value, b: $crate::codegen::Value) -> $crate::codegen::Value {
+            $($inner)+.mul(a, b)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] eq) => {
+        fn eq(&mut $self, a: $crate::codegen::Value, b: $crate::codegen::Value) -> $crate::codegen::Value {
+            $($inner)+.eq(a, b)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] not) => {
+        fn not(&mut $self, a: $crate::codegen::Value) -> $crate::codegen::Value {
+            $($inner)+.not(a)
+        }
+    };
+
+    // AcfCodegen
+    (@method $self:ident [$($inner:tt)+] alloc_label) => {
+        fn alloc_label(&mut $self) -> $crate::codegen::Label {
+            $($inner)+.alloc_label()
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] label) => {
+        fn label(&mut $self, label: $crate::codegen::Label) {
+            $($inner)+.label(label);
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] jump) => {
+        fn jump(&mut $self, label: $crate::codegen::Label) {
+            $($inner)+.jump(label);
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] cjump) => {
+        fn cjump(&mut $self, condition: $crate::codegen::Value, label: $crate::codegen::Label) {
+            $($inner)+.cjump(condition, label);
+        }
+    };
+
+    // BcfCodegen
+    (@method $self:ident [$($inner:tt)+] if_) => {
+        fn if_(&mut $self, condition: $crate::codegen::Value) {
+            $($inner)+.if_(condition);
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] else_) => {
+        fn else_(&mut $self) {
+            $($inner)+.else_();
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] end) => {
+        fn end(&mut $self) {
+            $($inner)+.end();
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] loop_) => {
+        fn loop_(&mut $self) {
+            $($inner)+.loop_();
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] break_) => {
+        fn break_(&mut $self) {
+            $($inner)+.break_();
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] continue_) => {
+        fn continue_(&mut $self) {
+            $($inner)+.continue_();
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] cbreak) => {
+        fn cbreak(&mut $self, condition: $crate::codegen::Value) {
+            $($inner)+.cbreak(condition);
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] ccontinue) => {
+        fn ccontinue(&mut $self, condition: $crate::codegen::Value) {
+            $($inner)+.ccontinue(condition);
+        }
+    };
+
+    // BodyCodegen
+    (@method $self:ident [$($inner:tt)+] comment) => {
+        fn comment(&mut $self, comment: &str) {
+            $($inner)+.comment(comment);
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] set_location) => {
+        fn set_location(&mut $self, location: Option<$crate::attrs::SourceLocation>) {
+            $($inner)+.set_location(location);
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] loop_attributes) => {
+        fn loop_attributes(&mut $self, attrs: $crate::attrs::LoopAttributes) {
+            $($inner)+.loop_attributes(attrs);
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] type_of) => {
+        fn type_of(&$self, id: usize) -> $crate::Type {
+            $($inner)+.type_of(id)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] variable_type) => {
+        fn variable_type(&$self, variable: $crate::codegen::Variable) -> $crate::Type {
+            $($inner)+.variable_type(variable)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] global_type) => {
+        fn global_type(&$self, name: $crate::Symbol, generics: &[$crate::Type]) -> $crate::Type {
+            $($inner)+.global_type(name, generics)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] symbol_kind) => {
+        fn symbol_kind(
+            &$self,
+            name: $crate::Symbol,
+            generics: &[$crate::Type],
+        ) -> Option<$crate::error::SymbolKind> {
+            $($inner)+.symbol_kind(name, generics)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] inline_type) => {
+        fn inline_type(&$self, ty: $crate::Type) -> $crate::Type {
+            $($inner)+.inline_type(ty)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] place_type) => {
+        fn place_type(&$self, place: &$crate::codegen::Place) -> $crate::Type {
+            $($inner)+.place_type(place)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] declare_var) => {
+        fn declare_var(&mut $self, ty: $crate::Type, name: Option<&str>) -> $crate::codegen::Variable {
+            $($inner)+.declare_var(ty, name)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] storage_live) => {
+        fn storage_live(&mut $self, variable: $crate::codegen::Variable) {
+            $($inner)+.storage_live(variable);
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] storage_dead) => {
+        fn storage_dead(&mut $self, variable: $crate::codegen::Variable) {
+            $($inner)+.storage_dead(variable);
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] assign) => {
+        fn assign(&mut $self, target: $crate::codegen::Place, value: $crate::codegen::Value) {
+            $($inner)+.assign(target, value);
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] mk_tmp) => {
+        fn mk_tmp(&mut $self, value: $crate::codegen::Value) -> $crate::codegen::Variable {
+            $($inner)+.mk_tmp(value)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] dup) => {
+        fn dup(&mut $self, value: $crate::codegen::Value) -> ($crate::codegen::Value, $crate::codegen::Value) {
+            $($inner)+.dup(value)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] flush) => {
+        fn flush(&mut $self) {
+            $($inner)+.flush();
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] abort) => {
+        fn abort(&mut $self) {
+            $($inner)+.abort();
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] iconst) => {
+        fn iconst(&mut $self, value: i128, size: $crate::types::IntegerSize) -> $crate::codegen::Value {
+            $($inner)+.iconst(value, size)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] uconst) => {
+        fn uconst(&mut $self, value: u128, size: $crate::types::IntegerSize) -> $crate::codegen::Value {
+            $($inner)+.uconst(value, size)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] fconst) => {
+        fn fconst(&mut $self, value: f64, size: u16) -> $crate::codegen::Value {
+            $($inner)+.fconst(value, size)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] bconst) => {
+        fn bconst(&mut $self, value: bool) -> $crate::codegen::Value {
+            $($inner)+.bconst(value)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] read) => {
+        fn read(&mut $self, place: $crate::codegen::Place) -> $crate::codegen::Value {
+            $($inner)+.read(place)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] reference) => {
+        fn reference(&mut $self, place: $crate::codegen::Place, mutable: bool) -> $crate::codegen::Value {
+            $($inner)+.reference(place, mutable)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] call) => {
+        fn call(
+            &mut $self,
+            func: $crate::codegen::Value,
+            args: Vec<$crate::codegen::Value>,
+        ) -> Option<$crate::codegen::Value> {
+            $($inner)+.call(func, args)
+        }
+    };
+    (@method $self:ident [$($inner:tt)+] return_) => {
+        fn return_(&mut $self, value: Option<$crate::codegen::Value>) {
+            $($inner)+.return_(value);
+        }
+    };
+}
+pub(crate) use forward_methods;
+
+macro_rules! forward {
+    ($ty:ty) => {
+        impl<CG: Intrinsics + ?Sized> Intrinsics for $ty {
+            forward_methods!(self => [(**self)] add, mul, eq, not);
+        }
+
+        impl<CG: AcfCodegen + ?Sized> AcfCodegen for $ty {
+            forward_methods!(self => [(**self)] alloc_label, label, jump, cjump);
+        }
+
+        impl<CG: BcfCodegen + ?Sized> BcfCodegen for $ty {
+            forward_methods!(self => [(**self)]
+                if_, else_, end, loop_, break_, continue_, cbreak, ccontinue);
+        }
+
+        impl<CG: BodyCodegen + ?Sized> BodyCodegen for $ty {
+            forward_methods!(self => [(**self)]
+                comment, set_location, loop_attributes,
+                type_of, variable_type, global_type, symbol_kind, inline_type, place_type,
+                declare_var, storage_live, storage_dead, assign, mk_tmp, dup, flush, abort,
+                iconst, uconst, fconst, bconst, read, reference, call, return_);
+        }
+    };
+}
+
+forward!(&mut CG);
+forward!(Box<CG>);
+
+impl<B: CodegenBackend + ?Sized> CodegenBackend for &B {
+    fn cg_function(
+        &self,
+        name: Symbol,
+        generic_params: Vec<Type>,
+    ) -> Result<Box<dyn BodyCodegen + '_>, crate::Error> {
+        (**self).cg_function(name, generic_params)
+    }
+}
+
+impl<B: CodegenBackend + ?Sized> CodegenBackend for Box<B> {
+    fn cg_function(
+        &self,
+        name: Symbol,
+        generic_params: Vec<Type>,
+    ) -> Result<Box<dyn BodyCodegen + '_>, crate::Error> {
+        (**self).cg_function(name, generic_params)
+    }
+}
@@ -0,0 +1,904 @@
+use crate::codegen as cg;
+use cg::{AcfCodegen as _, BodyCodegen as _};
+use std::collections::{BTreeMap, HashMap};
+
+mod structurize;
+use structurize::{Edges, Node};
+
+/// A buffered operation that yields a value
+#[derive(Debug)]
+enum ValueOp {
+    IConst(i128, crate::types::IntegerSize),
+    UConst(u128, crate::types::IntegerSize),
+    FConst(f64, u16),
+    BConst(bool),
+    Read(cg::Place),
+    Reference(cg::Place, bool),
+    Call(cg::Value, Vec<cg::Value>),
+    Add(cg::Value, cg::Value),
+    Mul(cg::Value, cg::Value),
+    Eq(cg::Value, cg::Value),
+    Not(cg::Value),
+}
+
+impl ValueOp {
+    /// Call `f` on every value the operation uses
+    fn values_mut(&mut self, f: &mut impl FnMut(&mut cg::Value)) {
+        match self {
+            ValueOp::IConst(..)
+            | ValueOp::UConst(..)
+            | ValueOp::FConst(..)
+            | ValueOp::BConst(_) => {}
+            ValueOp::Read(place) | ValueOp::Reference(place, _) => place_values_mut(place, f),
+            ValueOp::Call(func, args) => {
+                f(func);
+                args.iter_mut().for_each(f);
+            }
+            ValueOp::Add(a, b) | ValueOp::Mul(a, b) | ValueOp::Eq(a, b) => {
+                f(a);
+                f(b);
+            }
+            ValueOp::Not(a) => f(a),
+        }
+    }
+}
+
+/// Call `f` on every value the place uses
+fn place_values_mut(place: &mut cg::Place, f: &mut impl FnMut(&mut cg::Value)) {
+    match place {
+        cg::Place::Variable(_) | cg::Place::Global(..) => (),
+        cg::Place::Deref(value) => f(value),
+        cg::Place::Field(place, _) => place_values_mut(place, f),
+        cg::Place::Index(place, index) => {
+            place_values_mut(place, f);
+            f(index);
+        }
+    }
+}
+
+/// A buffered statement. Values used here are values of the wrapper,
+/// they are converted to values of the wrapped codegen during replay
+#[derive(Debug)]
+enum Statement {
+    Comment(String),
+    Location(Option<crate::attrs::SourceLocation>),
+    LoopAttributes(crate::attrs::LoopAttributes),
+    /// Creates a value with the given id
+    Value(usize, ValueOp),
+    StorageLive(cg::Variable),
+    StorageDead(cg::Variable),
+    Assign(cg::Place, cg::Value),
+    /// A call to a function without a return value
+    Call(cg::Value, Vec<cg::Value>),
+    Flush,
+}
+
+impl Statement {
+    /// Call `f` on every value the statement uses
+    fn values_mut(&mut self, f: &mut impl FnMut(&mut cg::Value)) {
+        match self {
+            Statement::Value(_, op) => op.values_mut(f),
+            Statement::Assign(target, value) => {
+                place_values_mut(target, f);
+                f(value);
+            }
+            Statement::Call(func, args) => {
+                f(func);
+                args.iter_mut().for_each(f);
+            }
+            Statement::Comment(_)
+            | Statement::Location(_)
+            | Statement::LoopAttributes(_)
+            | Statement::StorageLive(_)
+            | Statement::StorageDead(_)
+            | Statement::Flush => (),
+        }
+    }
+}
+
+/// How a basic block ends
+#[derive(Debug)]
+enum Terminator {
+    Jump(cg::Label),
+    /// Jumps to the first label if the condition is true, to the second one otherwise
+    Branch(cg::Value, cg::Label, cg::Label),
+    Return(Option<cg::Value>),
+    /// Falls off the end of the function
+    Exit,
+}
+
+impl Terminator {
+    /// Call `f` on every value the terminator uses
+    fn values_mut(&mut self, f: &mut impl FnMut(&mut cg::Value)) {
+        match self {
+            Terminator::Branch(condition, ..) => f(condition),
+            Terminator::Return(Some(value)) => f(value),
+            Terminator::Jump(_) | Terminator::Return(None) | Terminator::Exit => (),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Block {
+    statements: Vec<Statement>,
+    terminator: Option<Terminator>,
+}
+
+/// BCF block types, lowered to labels and jumps
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum BlockType {
+    If { end: cg::Label },
+    Else { end: cg::Label },
+    Loop { start: cg::Label, end: cg::Label },
+}
+
+/// Convert arbitrary control flow to block control flow.
+/// Wraps a codegen which only supports [`cg::BcfCodegen`],
+/// buffers all labels, jumps and statements of a function,
+/// then reconstructs loops and if/else structure and replays it into
+/// the wrapped codegen, see [`Self::finish`].
+///
+/// Operations that yield values are buffered too, and replayed in the order they were
+/// created, so sequencing rules (see [`cg::BodyCodegen`]) still hold.
+/// Because blocks get reordered, a value used outside of the basic block it was created in
+/// is stored in a temporary right after it is created and read back where it is used.
+///
+/// Blocks reachable from several branches are duplicated into each of them.
+/// Loops with several exits store the exit taken in a variable and break,
+/// the code after the loop then picks the right continuation:
+/// ```text
+/// loop { ...; if a { exit = 0; break; } ...; if b { exit = 1; break; } }
+/// if exit == 0 { ... } else { ... }
+/// ```
+///
+/// Irreducible control flow can't be expressed with blocks,
+/// it falls back to a dispatcher loop:
+/// ```text
+/// state = 0;
+/// loop { if state == 0 { ...; state = 2; continue; } if state == 1 { ... } ... }
+/// ```
+///
+/// Block control flow used on this codegen is lowered to ACF first.
+#[derive(Debug)]
+pub struct AcfToBcf<CG: cg::BodyCodegen> {
+    /// Taken out by [`Self::finish`]
+    codegen: Option<CG>,
+    /// Block index for each label, [None] if the label wasn't placed yet
+    labels: Vec<Option<usize>>,
+    blocks: Vec<Block>,
+    stack: Vec<BlockType>,
+    /// Types of the buffered values
+    types: HashMap<usize, crate::Type>,
+    next_value_id: usize,
+    /// Values of the wrapped codegen, created while replaying
+    values: HashMap<usize, cg::Value>,
+    /// Variable of the wrapped codegen holding the exit taken out of a loop,
+    /// see [`Node::Leave`]
+    exit: Option<cg::Variable>,
+}
+
+/// Errors found in the buffered function, see [`AcfToBcf::finish`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FinishError {
+    /// The function ended with this many blocks still open
+    UnclosedBlocks(usize),
+    /// A jump to a label that was never placed
+    UnplacedLabel(cg::Label),
+}
+
+impl std::fmt::Display for FinishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FinishError::UnclosedBlocks(count) => {
+                write!(f, "function ended with {count} unclosed block(s)")
+            }
+            FinishError::UnplacedLabel(label) => {
+                write!(f, "jump to label {} that was never placed", label.0)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FinishError {}
+
+impl<CG: cg::BodyCodegen> AcfToBcf<CG> {
+    #[allow(missing_docs)]
+    #[must_use]
+    pub fn new(codegen: CG) -> Self {
+        Self {
+            codegen: Some(codegen),
+            labels: Vec::new(),
+            blocks: Vec::new(),
+            stack: Vec::new(),
+            types: HashMap::new(),
+            next_value_id: 0,
+            values: HashMap::new(),
+            exit: None,
+        }
+    }
+
+    fn wrapped(&self) -> &CG {
+        self.codegen
+            .as_ref()
+            .expect("[bug?] codegen used after finish")
+    }
+
+    fn wrapped_mut(&mut self) -> &mut CG {
+        self.codegen
+            .as_mut()
+            .expect("[bug?] codegen used after finish")
+    }
+
+    /// Buffer an operation that yields a value of type `ty`
+    fn mk_value(&mut self, op: ValueOp, ty: crate::Type) -> cg::Value {
+        let id = self.next_value_id;
+        self.next_value_id += 1;
+        self.types.insert(id, ty);
+        self.current().statements.push(Statement::Value(id, op));
+        cg::Value(id)
+    }
+
+    /// Get the block statements should be added to.
+    /// Starts a new (unreachable) block if the last one was terminated
+    fn current(&mut self) -> &mut Block {
+        if self
+            .blocks
+            .last()
+            .is_none_or(|block| block.terminator.is_some())
+        {
+            self.blocks.push(Block::default());
+        }
+        self.blocks.last_mut().unwrap()
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        self.current().terminator = Some(terminator);
+    }
+
+    fn last_loop(&self) -> Option<(cg::Label, cg::Label)> {
+        for block in self.stack.iter().rev() {
+            let BlockType::Loop { start, end } = block else {
+                continue;
+            };
+
+            return Some((*start, *end));
+        }
+
+        None
+    }
+
+    /// Get the block a jump to `label` ends up in,
+    /// skipping empty blocks that only jump further
+    fn block_index(&self, label: cg::Label) -> Result<usize, FinishError> {
+        let placed =
+            |label: cg::Label| self.labels[label.0].ok_or(FinishError::UnplacedLabel(label));
+        let mut index = placed(label)?;
+        for _ in 0..self.blocks.len() {
+            match &self.blocks[index] {
+                Block {
+                    statements,
+                    terminator: Some(Terminator::Jump(target)),
+                } if statements.is_empty() => index = placed(*target)?,
+                _ => break,
+            }
+        }
+        Ok(index)
+    }
+
+    /// Structurize the buffered function and replay it into the wrapped codegen.
+    /// Returns the wrapped codegen, so that it can be finished too.
+    /// Dropping this codegen without finishing does the same, but panics on errors
+    pub fn finish(mut self) -> Result<CG, FinishError> {
+        let result = self.structurize();
+        let codegen = self.codegen.take().unwrap();
+        result.map(|()| codegen)
+    }
+
+    fn structurize(&mut self) -> Result<(), FinishError> {
+        if !self.stack.is_empty() {
+            return Err(FinishError::UnclosedBlocks(self.stack.len()));
+        }
+        if self.blocks.is_empty() {
+            self.blocks.push(Block::default());
+        }
+        if let Some(block) = self.blocks.last_mut()
+            && block.terminator.is_none()
+        {
+            block.terminator = Some(Terminator::Exit);
+        }
+        self.materialize();
+
+        let edges = self
+            .blocks
+            .iter()
+            .map(|block| {
+                Ok(match block.terminator.as_ref().unwrap() {
+                    Terminator::Jump(target) => Edges::Jump(self.block_index(*target)?),
+                    Terminator::Branch(_, taken, not_taken) => {
+                        Edges::Branch(self.block_index(*taken)?, self.block_index(*not_taken)?)
+                    }
+                    Terminator::Return(_) => Edges::Return,
+                    Terminator::Exit => Edges::Exit,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let blocks = std::mem::take(&mut self.blocks);
+        match structurize::structurize(&edges) {
+            Some(nodes) => self.replay(&blocks, nodes),
+            None => self.dispatch(&blocks, &edges),
+        }
+        Ok(())
+    }
+
+    /// Store values used outside of the block they were created in in temporaries
+    /// of the wrapped codegen, reading them back right before their use
+    fn materialize(&mut self) {
+        let mut defined = HashMap::new();
+        for (idx, block) in self.blocks.iter().enumerate() {
+            for statement in &block.statements {
+                if let Statement::Value(id, _) = statement {
+                    defined.insert(*id, idx);
+                }
+            }
+        }
+
+        let mut foreign = Vec::new();
+        for (idx, block) in self.blocks.iter_mut().enumerate() {
+            let mut visit = |value: &mut cg::Value| {
+                if defined.get(&value.0).is_some_and(|&block| block != idx) {
+                    foreign.push(value.0);
+                }
+            };
+            for statement in &mut block.statements {
+                statement.values_mut(&mut visit);
+            }
+            if let Some(terminator) = &mut block.terminator {
+                terminator.values_mut(&mut visit);
+            }
+        }
+        foreign.sort_unstable();
+        let tmps = foreign
+            .into_iter()
+            .map(|id| {
+                let ty = self.types[&id].clone();
+                (id, self.wrapped_mut().declare_var(ty, None))
+            })
+            .collect::<BTreeMap<_, _>>();
+        if tmps.is_empty() {
+            return;
+        }
+
+        for (idx, block) in self.blocks.iter_mut().enumerate() {
+            let mut load = |value: &mut cg::Value, statements: &mut Vec<Statement>| {
+                if let Some(&tmp) = tmps.get(&value.0)
+                    && defined[&value.0] != idx
+                {
+                    let id = self.next_value_id;
+                    self.next_value_id += 1;
+                    self.types.insert(id, self.types[&value.0].clone());
+                    statements.push(Statement::Value(id, ValueOp::Read(tmp.into())));
+                    *value = cg::Value(id);
+                }
+            };
+
+            let mut statements = Vec::with_capacity(block.statements.len());
+            for mut statement in std::mem::take(&mut block.statements) {
+                statement.values_mut(&mut |value| load(value, &mut statements));
+                let stored = match &statement {
+                    Statement::Value(id, _) => tmps.get(id).map(|&tmp| (*id, tmp)),
+                    _ => None,
+                };
+                statements.push(statement);
+                if let Some((id, tmp)) = stored {
+                    statements.push(Statement::Assign(tmp.into(), cg::Value(id)));
+                }
+            }
+            if let Some(terminator) = &mut block.terminator {
+                terminator.values_mut(&mut |value| load(value, &mut statements));
+            }
+            block.statements = statements;
+        }
+    }
+
+    /// Get the value of the wrapped codegen, created while replaying
+    fn inner(&mut self, value: &cg::Value) -> cg::Value {
+        self.values
+            .remove(&value.0)
+            .unwrap_or_else(|| panic!("value #{} is used more than once", value.0))
+    }
+
+    fn inner_place(&mut self, place: &cg::Place) -> cg::Place {
+        match place {
+            cg::Place::Variable(variable) => cg::Place::Variable(*variable),
+            cg::Place::Global(name, generics) => cg::Place::Global(*name, generics.clone()),
+            cg::Place::Deref(value) => cg::Place::Deref(self.inner(value)),
+            cg::Place::Field(place, idx) => self.inner_place(place).field(*idx),
+            cg::Place::Index(place, index) => {
+                let place = self.inner_place(place);
+                place.index(self.inner(index))
+            }
+        }
+    }
+
+    /// Variable holding the exit taken out of a loop with several exits
+    fn exit_variable(&mut self) -> cg::Variable {
+        if let Some(exit) = self.exit {
+            return exit;
+        }
+        let ty = crate::Type::Unsigned(crate::types::IntegerSize::Size);
+        let exit = self.wrapped_mut().declare_var(ty, Some("exit"));
+        self.exit = Some(exit);
+        exit
+    }
+
+    fn replay_value(&mut self, op: &ValueOp) -> cg::Value {
+        match op {
+            ValueOp::IConst(value, size) => self.wrapped_mut().iconst(*value, *size),
+            ValueOp::UConst(value, size) => self.wrapped_mut().uconst(*value, *size),
+            ValueOp::FConst(value, size) => self.wrapped_mut().fconst(*value, *size),
+            ValueOp::BConst(value) => self.wrapped_mut().bconst(*value),
+            ValueOp::Read(place) => {
+                let place = self.inner_place(place);
+                self.wrapped_mut().read(place)
+            }
+            ValueOp::Reference(place, mutable) => {
+                let place = self.inner_place(place);
+                self.wrapped_mut().reference(place, *mutable)
+            }
+            ValueOp::Call(func, args) => {
+                let func = self.inner(func);
+                let args = args.iter().map(|arg| self.inner(arg)).collect();
+                self.wrapped_mut()
+                    .call(func, args)
+                    .expect("call to a function with a return type didn't yield a value")
+            }
+            ValueOp::Add(a, b) => {
+                let (a, b) = (self.inner(a), self.inner(b));
+                self.wrapped_mut().add(a, b)
+            }
+            ValueOp::Mul(a, b) => {
+                let (a, b) = (self.inner(a), self.inner(b));
+                self.wrapped_mut().mul(a, b)
+            }
+            ValueOp::Eq(a, b) => {
+                let (a, b) = (self.inner(a), self.inner(b));
+                self.wrapped_mut().eq(a, b)
+            }
+            ValueOp::Not(a) => {
+                let a = self.inner(a);
+                self.wrapped_mut().not(a)
+            }
+        }
+    }
+
+    /// Replay statements of a block. Blocks can be replayed more than once
+    fn replay_statements(&mut self, block: &Block) {
+        for statement in &block.statements {
+            match statement {
+                Statement::Comment(comment) => self.wrapped_mut().comment(comment),
+                Statement::Location(location) => self.wrapped_mut().set_location(location.clone()),
+                Statement::LoopAttributes(attrs) => {
+                    self.wrapped_mut().loop_attributes(attrs.clone())
+                }
+                Statement::Value(id, op) => {
+                    let value = self.replay_value(op);
+                    self.values.insert(*id, value);
+                }
+                Statement::StorageLive(variable) => self.wrapped_mut().storage_live(*variable),
+                Statement::StorageDead(variable) => self.wrapped_mut().storage_dead(*variable),
+                Statement::Assign(target, value) => {
+                    let target = self.inner_place(target);
+                    let value = self.inner(value);
+                    self.wrapped_mut().assign(target, value);
+                }
+                Statement::Call(func, args) => {
+                    let func = self.inner(func);
+                    let args = args.iter().map(|arg| self.inner(arg)).collect();
+                    self.wrapped_mut().call(func, args);
+                }
+                Statement::Flush => self.wrapped_mut().flush(),
+            }
+        }
+    }
+
+    fn replay(&mut self, blocks: &[Block], nodes: Vec<Node>) {
+        for node in nodes {
+            match node {
+                Node::Block(block) => {
+                    let block = &blocks[block];
+                    self.replay_statements(block);
+                    if let Some(Terminator::Return(value)) = &block.terminator {
+                        let value = value.as_ref().map(|value| self.inner(value));
+                        self.wrapped_mut().return_(value);
+                    }
+                }
+                Node::If {
+                    block,
+                    negate,
+                    then,
+                    else_,
+                } => {
+                    let Some(Terminator::Branch(condition, ..)) = &blocks[block].terminator else {
+                        unreachable!("if node on a block that doesn't end with a branch");
+                    };
+                    let condition = self.inner(condition);
+                    let condition = match negate {
+                        true => self.wrapped_mut().not(condition),
+                        false => condition,
+                    };
+
+                    self.wrapped_mut().if_(condition);
+                    self.replay(blocks, then);
+                    if !else_.is_empty() {
+                        self.wrapped_mut().else_();
+                        self.replay(blocks, else_);
+                    }
+                    self.wrapped_mut().end();
+                }
+                Node::Loop(body) => {
+                    self.wrapped_mut().loop_();
+                    self.replay(blocks, body);
+                    self.wrapped_mut().end();
+                }
+                Node::Break => self.wrapped_mut().break_(),
+                Node::Leave(exit) => {
+                    let variable = self.exit_variable();
+                    let exit = self
+                        .wrapped_mut()
+                        .uconst(exit as _, crate::types::IntegerSize::Size);
+                    self.wrapped_mut().assign(variable.into(), exit);
+                    self.wrapped_mut().break_();
+                }
+                Node::Select(arms) => {
+                    let variable = self.exit_variable();
+                    let count = arms.len();
+                    for (idx, arm) in arms.into_iter().enumerate() {
+                        if idx + 1 < count {
+                            let current = self.wrapped_mut().read(variable.into());
+                            let exit = self
+                                .wrapped_mut()
+                                .uconst(idx as _, crate::types::IntegerSize::Size);
+                            let condition = self.wrapped_mut().eq(current, exit);
+                            self.wrapped_mut().if_(condition);
+                            self.replay(blocks, arm);
+                            self.wrapped_mut().else_();
+                        } else {
+                            self.replay(blocks, arm);
+                        }
+                    }
+                    for _ in 1..count {
+                        self.wrapped_mut().end();
+                    }
+                }
+                Node::Continue => self.wrapped_mut().continue_(),
+                Node::Exit => self.wrapped_mut().return_(None),
+            }
+        }
+    }
+
+    /// Fallback for unstructured control flow: a loop over a state variable
+    fn dispatch(&mut self, blocks: &[Block], edges: &[Edges]) {
+        let state_ty = crate::Type::Unsigned(crate::types::IntegerSize::Size);
+        let state = self.wrapped_mut().declare_var(state_ty, Some("state"));
+        let entry = self
+            .wrapped_mut()
+            .uconst(0, crate::types::IntegerSize::Size);
+        self.wrapped_mut().assign(state.into(), entry);
+
+        let reachable = structurize::reachable(edges);
+        self.wrapped_mut().loop_();
+        for (idx, block) in blocks.iter().enumerate() {
+            if !reachable[idx] {
+                continue;
+            }
+
+            let current = self.wrapped_mut().read(state.into());
+            let idx_value = self
+                .wrapped_mut()
+                .uconst(idx as _, crate::types::IntegerSize::Size);
+            let condition = self.wrapped_mut().eq(current, idx_value);
+            self.wrapped_mut().if_(condition);
+            self.replay_statements(block);
+
+            let goto = |this: &mut Self, target: usize| {
+                let target = this
+                    .wrapped_mut()
+                    .uconst(target as _, crate::types::IntegerSize::Size);
+                this.wrapped_mut().assign(state.into(), target);
+            };
+            match (block.terminator.as_ref().unwrap(), edges[idx]) {
+                (Terminator::Jump(_), Edges::Jump(target)) => {
+                    goto(self, target);
+                    self.wrapped_mut().continue_();
+                }
+                (Terminator::Branch(condition, ..), Edges::Branch(taken, not_taken)) => {
+                    let condition = self.inner(condition);
+                    self.wrapped_mut().if_(condition);
+                    goto(self, taken);
+                    self.wrapped_mut().else_();
+                    goto(self, not_taken);
+                    self.wrapped_mut().end();
+                    self.wrapped_mut().continue_();
+                }
+                (Terminator::Return(value), _) => {
+                    let value = value.as_ref().map(|value| self.inner(value));
+                    self.wrapped_mut().return_(value);
+                }
+                (Terminator::Exit, _) => self.wrapped_mut().break_(),
+                (terminator, edges) => {
+                    unreachable!("terminator {terminator:?} doesn't match edges {edges:?}")
+                }
+            }
+            self.wrapped_mut().end();
+        }
+        self.wrapped_mut().end();
+    }
+}
+
+impl<CG: cg::BodyCodegen> Drop for AcfToBcf<CG> {
+    fn drop(&mut self) {
+        if self.codegen.is_none() || std::thread::panicking() {
+            return;
+        }
+        if let Err(err) = self.structurize() {
+            panic!("{err} (while dropping AcfToBcf without finish)");
+        }
+    }
+}
+
+impl<CG: cg::BodyCodegen> cg::BodyCodegen for AcfToBcf<CG> {
+    fn comment(&mut self, comment: &str) {
+        self.current()
+            .statements
+            .push(Statement::Comment(comment.to_owned()));
+    }
+
+    fn set_location(&mut self, location: Option<crate::attrs::SourceLocation>) {
+        self.current()
+            .statements
+            .push(Statement::Location(location));
+    }
+
+    fn loop_attributes(&mut self, attrs: crate::attrs::LoopAttributes) {
+        self.current()
+            .statements
+            .push(Statement::LoopAttributes(attrs));
+    }
+
+    fn type_of(&self, id: usize) -> crate::Type {
+        self.types
+            .get(&id)
+            .unwrap_or_else(|| panic!("invalid value id {id}"))
+            .clone()
+    }
+
+    fn variable_type(&self, variable: cg::Variable) -> crate::Type {
+        self.wrapped().variable_type(variable)
+    }
+
+    fn global_type(&self, name: crate::Symbol, generics: &[crate::Type]) -> crate::Type {
+        self.wrapped().global_type(name, generics)
+    }
+
+    fn symbol_kind(
+        &self,
+        name: crate::Symbol,
+        generics: &[crate::Type],
+    ) -> Option<crate::error::SymbolKind> {
+        self.wrapped().symbol_kind(name, generics)
+    }
+
+    fn inline_type(&self, ty: crate::Type) -> crate::Type {
+        self.wrapped().inline_type(ty)
+    }
+
+    fn declare_var(&mut self, ty: crate::Type, name: Option<&str>) -> cg::Variable {
+        self.wrapped_mut().declare_var(ty, name)
+    }
+
+    fn storage_live(&mut self, variable: cg::Variable) {
+        self.current()
+            .statements
+            .push(Statement::StorageLive(variable));
+    }
+
+    fn storage_dead(&mut self, variable: cg::Variable) {
+        self.current()
+            .statements
+            .push(Statement::StorageDead(variable));
+    }
+
+    fn assign(&mut self, target: cg::Place, value: cg::Value) {
+        self.current()
+            .statements
+            .push(Statement::Assign(target, value));
+    }
+
+    fn flush(&mut self) {
+        self.current().statements.push(Statement::Flush);
+    }
+
+    fn abort(&mut self) {
+        if let Some(mut codegen) = self.codegen.take() {
+            codegen.abort();
+        }
+    }
+
+    fn iconst(&mut self, value: i128, size: crate::types::IntegerSize) -> cg::Value {
+        self.mk_value(ValueOp::IConst(value, size), crate::Type::Integer(size))
+    }
+
+    fn uconst(&mut self, value: u128, size: crate::types::IntegerSize) -> cg::Value {
+        self.mk_value(ValueOp::UConst(value, size), crate::Type::Unsigned(size))
+    }
+
+    fn fconst(&mut self, value: f64, size: u16) -> cg::Value {
+        self.mk_value(ValueOp::FConst(value, size), crate::Type::Float(size))
+    }
+
+    fn bconst(&mut self, value: bool) -> cg::Value {
+        self.mk_value(ValueOp::BConst(value), crate::Type::Bool)
+    }
+
+    fn read(&mut self, place: cg::Place) -> cg::Value {
+        let ty = self.place_type(&place);
+        self.mk_value(ValueOp::Read(place), ty)
+    }
+
+    fn reference(&mut self, place: cg::Place, mutable: bool) -> cg::Value {
+        let ty = crate::Type::Ptr(Box::new(self.place_type(&place)), mutable);
+        self.mk_value(ValueOp::Reference(place, mutable), ty)
+    }
+
+    fn call(&mut self, func: cg::Value, args: Vec<cg::Value>) -> Option<cg::Value> {
+        match self.inline_type(self.type_of(func.0)) {
+            crate::Type::FnPtr {
+                return_type: None, ..
+            } => {
+                self.current().statements.push(Statement::Call(func, args));
+                None
+            }
+            crate::Type::FnPtr {
+                return_type: Some(ty),
+                ..
+            } => Some(self.mk_value(ValueOp::Call(func, args), *ty)),
+            ty => panic!("trying to call non-function of type {ty}"),
+        }
+    }
+
+    fn return_(&mut self, value: Option<cg::Value>) {
+        self.terminate(Terminator::Return(value));
+    }
+}
+
+impl<CG: cg::BodyCodegen> cg::Intrinsics for AcfToBcf<CG> {
+    fn add(&mut self, a: cg::Value, b: cg::Value) -> cg::Value {
+        let ty = self.type_of(a.0);
+        self.mk_value(ValueOp::Add(a, b), ty)
+    }
+
+    fn mul(&mut self, a: cg::Value, b: cg::Value) -> cg::Value {
+        let ty = self.type_of(a.0);
+        self.mk_value(ValueOp::Mul(a, b), ty)
+    }
+
+    fn eq(&mut self, a: cg::Value, b: cg::Value) -> cg::Value {
+        self.mk_value(ValueOp::Eq(a, b), crate::Type::Bool)
+    }
+
+    fn not(&mut self, a: cg::Value) -> cg::Value {
+        let ty = self.type_of(a.0);
+        self.mk_value(ValueOp::Not(a), ty)
+    }
+}
+
+impl<CG: cg::BodyCodegen> cg::AcfCodegen for AcfToBcf<CG> {
+    fn alloc_label(&mut self) -> cg::Label {
+        self.labels.push(None);
+        cg::Label(self.labels.len() - 1)
+    }
+
+    fn label(&mut self, label: cg::Label) {
+        if self
+            .blocks
+            .last()
+            .is_some_and(|block| block.terminator.is_none())
+        {
+            self.terminate(Terminator::Jump(label));
+        }
+        assert!(
+            self.labels[label.0].is_none(),
+            "label {} is placed twice",
+            label.0
+        );
+        self.labels[label.0] = Some(self.blocks.len());
+        self.blocks.push(Block::default());
+    }
+
+    fn jump(&mut self, label: cg::Label) {
+        self.terminate(Terminator::Jump(label));
+    }
+
+    fn cjump(&mut self, condition: cg::Value, label: cg::Label) {
+        let not_taken = self.alloc_label();
+        self.terminate(Terminator::Branch(condition, label, not_taken));
+        self.label(not_taken);
+    }
+}
+
+impl<CG: cg::BodyCodegen> cg::BcfCodegen for AcfToBcf<CG> {
+    fn if_(&mut self, condition: cg::Value) {
+        let then = self.alloc_label();
+        let end = self.alloc_label();
+        self.terminate(Terminator::Branch(condition, then, end));
+        self.label(then);
+        self.stack.push(BlockType::If { end });
+    }
+
+    fn else_(&mut self) {
+        match self.stack.pop() {
+            Some(BlockType::If { end }) => {
+                let end2 = self.alloc_label();
+                self.jump(end2);
+                self.label(end);
+                self.stack.push(BlockType::Else { end: end2 });
+            }
+            block => {
+                panic!("expected last block to be `if` while generating else, but it was {block:?}")
+            }
+        }
+    }
+
+    fn end(&mut self) {
+        let Some(block) = self.stack.pop() else {
+            panic!("calling end() on an empty stack");
+        };
+        match block {
+            BlockType::If { end } | BlockType::Else { end } => self.label(end),
+            BlockType::Loop { start, end } => {
+                self.jump(start);
+                self.label(end)
+            }
+        }
+    }
+
+    fn loop_(&mut self) {
+        let start = self.alloc_label();
+        let end = self.alloc_label();
+        self.label(start);
+        self.stack.push(BlockType::Loop { start, end });
+    }
+
+    fn break_(&mut self) {
+        let Some((_, end)) = self.last_loop() else {
+            panic!("can't break() here, no loop blocks are open")
+        };
+
+        self.jump(end);
+    }
+
+    fn continue_(&mut self) {
+        let Some((start, _)) = self.last_loop() else {
+            panic!("can't continue() here, no loop blocks are open")
+        };
+
+        self.jump(start);
+    }
+
+    fn cbreak(&mut self, condition: cg::Value) {
+        let Some((_, end)) = self.last_loop() else {
+            panic!("can't cbreak() here, no loop blocks are open")
+        };
+
+        self.cjump(condition, end);
+    }
+
+    fn ccontinue(&mut self, condition: cg::Value) {
+        let Some((start, _)) = self.last_loop() else {
+            panic!("can't ccontinue() here, no loop blocks are open")
+        };
+
+        self.cjump(condition, start);
+    }
+}
@@ -0,0 +1,380 @@
+use crate::graph::{Dominators, Loop, Loops};
+use std::collections::HashSet;
+
+/// Outgoing edges of a basic block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Edges {
+    /// Unconditional jump
+    Jump(usize),
+    /// Conditional jump (taken, not taken)
+    Branch(usize, usize),
+    /// Returns from the function
+    Return,
+    /// Falls off the end of the function
+    Exit,
+}
+
+impl Edges {
+    fn successors(self) -> impl Iterator<Item = usize> {
+        let (a, b) = match self {
+            Edges::Jump(target) => (Some(target), None),
+            Edges::Branch(taken, not_taken) => (Some(taken), Some(not_taken)),
+            Edges::Return | Edges::Exit => (None, None),
+        };
+        a.into_iter().chain(b)
+    }
+}
+
+/// Structured control flow tree, see [`structurize`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) enum Node {
+    /// Statements of a basic block (including the return, if the block ends with one)
+    Block(usize),
+    /// An if/else on the condition of the branch that ends `block`
+    If {
+        block: usize,
+        /// Whether the condition should be inverted (when the taken branch is empty)
+        negate: bool,
+        then: Vec<Node>,
+        else_: Vec<Node>,
+    },
+    /// An infinite loop, exited with [`Node::Break`] or [`Node::Leave`]
+    Loop(Vec<Node>),
+    Break,
+    /// Leaves a loop with several exits: stores the index of the exit
+    /// in the exit selector and breaks, see [`Node::Select`]
+    Leave(usize),
+    /// Code following a loop with several exits, one sequence for each exit.
+    /// The sequence to run is picked by the exit selector
+    Select(Vec<Vec<Node>>),
+    Continue,
+    /// Falling off the end of the function from inside a loop
+    Exit,
+}
+
+/// Which blocks can be reached from the entry (block 0)
+pub(super) fn reachable(edges: &[Edges]) -> Vec<bool> {
+    let succs = edges
+        .iter()
+        .map(|edges| edges.successors().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let dominators = Dominators::new(&succs[..], 0);
+    (0..edges.len())
+        .map(|block| dominators.is_reachable(block))
+        .collect()
+}
+
+/// Blocks shared between branches are duplicated. Structurizing gives up (and
+/// the dispatcher is used instead) once it emits this many blocks per block of the function
+const DUPLICATION_LIMIT: usize = 4;
+
+#[derive(Clone, Copy)]
+struct LoopCtx<'a> {
+    header: usize,
+    body: &'a Loop,
+    /// Blocks outside of the loop control goes to when leaving it, sorted
+    exits: &'a [usize],
+}
+
+#[derive(Clone, Copy)]
+struct Ctx<'a> {
+    /// Block where the current sequence ends (merge point of the parent if)
+    stop: Option<usize>,
+    /// The innermost loop
+    loop_: Option<LoopCtx<'a>>,
+    /// Immediate post-dominators within the current region
+    ipdom: &'a [Option<usize>],
+}
+
+struct Structurizer<'a> {
+    edges: &'a [Edges],
+    dominators: Dominators,
+    loops: Loops,
+    /// How many more blocks can be emitted, see [`DUPLICATION_LIMIT`]
+    budget: usize,
+}
+
+impl Structurizer<'_> {
+    /// Immediate post-dominators of a region. Edges leaving the region
+    /// or going back to the `header` lead to a virtual exit node
+    fn postdominators(
+        &self,
+        header: Option<usize>,
+        region: impl Fn(usize) -> bool,
+    ) -> Vec<Option<usize>> {
+        let exit = self.edges.len();
+        let mut reverse = vec![Vec::new(); exit + 1];
+        for (node, edges) in self.edges.iter().enumerate() {
+            if !region(node) || !self.dominators.is_reachable(node) {
+                continue;
+            }
+
+            if matches!(edges, Edges::Return | Edges::Exit) {
+                reverse[exit].push(node);
+            }
+            for succ in edges.successors() {
+                if region(succ) && Some(succ) != header {
+                    reverse[succ].push(node);
+                } else {
+                    reverse[exit].push(node);
+                }
+            }
+        }
+
+        let postdominators = Dominators::new(&reverse[..], exit);
+        (0..exit)
+            .map(|node| postdominators.immediate_dominator(node))
+            .map(|ipdom| ipdom.filter(|ipdom| *ipdom != exit))
+            .collect()
+    }
+
+    /// Plans a sequence of blocks, starting at `block` and continuing
+    /// until control flow reaches [`Ctx::stop`] or leaves the innermost loop.
+    /// Blocks reachable from several branches without being their merge point
+    /// are emitted in each of them
+    fn sequence(&mut self, mut block: usize, ctx: Ctx, mut entering: bool) -> Option<Vec<Node>> {
+        let mut nodes = Vec::new();
+        loop {
+            if !entering {
+                if Some(block) == ctx.stop {
+                    return Some(nodes);
+                }
+                if let Some(loop_) = ctx.loop_ {
+                    if block == loop_.header {
+                        nodes.push(Node::Continue);
+                        return Some(nodes);
+                    }
+                    if !loop_.body.contains(block) {
+                        nodes.push(match loop_.exits {
+                            [_] => Node::Break,
+                            exits => Node::Leave(exits.binary_search(&block).ok()?),
+                        });
+                        return Some(nodes);
+                    }
+                }
+
+                if let Some(body) = self.loops.with_header(block) {
+                    let body = body.clone();
+                    let mut exits = body
+                        .nodes
+                        .iter()
+                        .flat_map(|node| self.edges[*node].successors())
+                        .filter(|succ| !body.contains(*succ))
+                        .collect::<HashSet<_>>()
+                        .into_iter()
+                        .collect::<Vec<_>>();
+                    exits.sort_unstable();
+
+                    let ipdom = self.postdominators(Some(block), |node| body.contains(node));
+                    let inner = Ctx {
+                        stop: None,
+                        loop_: Some(LoopCtx {
+                            header: block,
+                            body: &body,
+                            exits: &exits,
+                        }),
+                        ipdom: &ipdom,
+                    };
+                    nodes.push(Node::Loop(self.sequence(block, inner, true)?));
+                    match exits[..] {
+                        [] => return Some(nodes),
+                        [exit] => {
+                            block = exit;
+                            continue;
+                        }
+                        _ => {
+                            // Paths from all the exits meet at the first post-dominator
+                            // of the header outside of the loop
+                            let mut follow = ctx.ipdom[block];
+                            while let Some(node) = follow
+                                && body.contains(node)
+                            {
+                                follow = ctx.ipdom[node];
+                            }
+
+                            let arm = Ctx {
+                                stop: follow.or(ctx.stop),
+                                ..ctx
+                            };
+                            let arms = exits
+                                .iter()
+                                .map(|exit| self.sequence(*exit, arm, false))
+                                .collect::<Option<Vec<_>>>()?;
+                            nodes.push(Node::Select(arms));
+                            match follow {
+                                Some(follow) => {
+                                    block = follow;
+                                    continue;
+                                }
+                                None => return Some(nodes),
+                            }
+                        }
+                    }
+                }
+            }
+            entering = false;
+
+            self.budget = self.budget.checked_sub(1)?;
+            nodes.push(Node::Block(block));
+            match self.edges[block] {
+                Edges::Jump(target) => block = target,
+                Edges::Branch(taken, not_taken) => {
+                    let merge = ctx.ipdom[block];
+                    let inner = Ctx { stop: merge, ..ctx };
+                    let then = self.sequence(taken, inner, false)?;
+                    let else_ = self.sequence(not_taken, inner, false)?;
+                    nodes.push(if then.is_empty() {
+                        Node::If {
+                            block,
+                            negate: true,
+                            then: else_,
+                            else_: Vec::new(),
+                        }
+                    } else {
+                        Node::If {
+                            block,
+                            negate: false,
+                            then,
+                            else_,
+                        }
+                    });
+
+                    match merge {
+                        Some(merge) => block = merge,
+                        None => return Some(nodes),
+                    }
+                }
+                Edges::Return => return Some(nodes),
+                Edges::Exit => {
+                    if ctx.loop_.is_some() {
+                        nodes.push(Node::Exit);
+                    }
+                    return Some(nodes);
+                }
+            }
+        }
+    }
+}
+
+/// Reconstructs loops and if/else structure of a function, starting at block 0.
+/// Returns [None] if control flow is irreducible (or structuring it would duplicate
+/// too much code), so it can't be expressed using [`crate::codegen::BcfCodegen`]
+pub(super) fn structurize(edges: &[Edges]) -> Option<Vec<Node>> {
+    let succs = edges
+        .iter()
+        .map(|edges| edges.successors().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let dominators = Dominators::new(&succs[..], 0);
+    if !dominators.is_reducible(&succs[..], 0) {
+        return None;
+    }
+
+    let mut structurizer = Structurizer {
+        edges,
+        loops: Loops::new(&succs[..], &dominators),
+        dominators,
+        budget: edges.len() * DUPLICATION_LIMIT,
+    };
+
+    let ipdom = structurizer.postdominators(None, |_| true);
+    let ctx = Ctx {
+        stop: None,
+        loop_: None,
+        ipdom: &ipdom,
+    };
+    structurizer.sequence(0, ctx, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Edges::*;
+    use super::Node::*;
+    use super::*;
+
+    #[test]
+    fn diamond() {
+        let edges = [Branch(1, 2), Jump(3), Jump(3), Return];
+        assert_eq!(
+            structurize(&edges),
+            Some(vec![
+                Block(0),
+                If {
+                    block: 0,
+                    negate: false,
+                    then: vec![Block(1)],
+                    else_: vec![Block(2)],
+                },
+                Block(3),
+            ])
+        );
+    }
+
+    #[test]
+    fn loop_with_several_exits() {
+        // 1 -> 2 -> 3 -> 1, leaving to 4 from 1 and to 5 from 2, both continue at 6
+        let edges = [
+            Jump(1),
+            Branch(4, 2),
+            Branch(5, 3),
+            Jump(1),
+            Jump(6),
+            Jump(6),
+            Return,
+        ];
+        assert_eq!(
+            structurize(&edges),
+            Some(vec![
+                Block(0),
+                Loop(vec![
+                    Block(1),
+                    If {
+                        block: 1,
+                        negate: false,
+                        then: vec![Leave(0)],
+                        else_: vec![
+                            Block(2),
+                            If {
+                                block: 2,
+                                negate: false,
+                                then: vec![Leave(1)],
+                                else_: vec![Block(3), Continue],
+                            },
+                        ],
+                    },
+                ]),
+                Select(vec![vec![Block(4)], vec![Block(5)]]),
+                Block(6),
+            ])
+        );
+    }
+
+    #[test]
+    fn irreducible() {
+        // A loop between 1 and 2, entered at both of them
+        let edges = [Branch(1, 2), Jump(2), Branch(1, 3), Return];
+        assert_eq!(structurize(&edges), None);
+    }
+
+    /// `stages` blocks, each branching to a block that may return
+    /// and to one that doesn't, both going to the next stage.
+    /// Stages don't have a merge point, so each one doubles the code
+    fn ladder(stages: usize) -> Vec<Edges> {
+        let mut edges = Vec::new();
+        for stage in 0..stages {
+            let next = (stage + 1) * 3;
+            edges.extend([
+                Branch(next - 2, next - 1),
+                Branch(next, stages * 3 + 1),
+                Jump(next),
+            ]);
+        }
+        edges.extend([Return, Return]);
+        edges
+    }
+
+    #[test]
+    fn duplication_budget() {
+        assert!(structurize(&ladder(2)).is_some());
+        assert_eq!(structurize(&ladder(8)), None);
+    }
+}
@@ -63,18 +63,18 @@ impl<CG: cg::BodyCodegen> Wrapper<'_, CG> {
 
 impl<CG: cg::BodyCodegen> cg::BcfCodegen for Wrapper<'_, CG> {
     fn if_(&mut self, condition: cg::Value) {
-        let end = self.codegen.acf().alloc_label();
-        let uncondition = self.codegen.intrinsics().not(condition);
-        self.codegen.acf().cjump(uncondition, end);
+        let end = self.codegen.alloc_label();
+        let uncondition = self.codegen.not(condition);
+        self.codegen.cjump(uncondition, end);
         self.state().stack.push(BlockType::If { end });
     }
 
     fn else_(&mut self) {
         match self.state().stack.pop() {
             Some(BlockType::If { end }) => {
-                let end2 = self.codegen.acf().alloc_label();
-                self.codegen.acf().jump(end2);
-                self.codegen.acf().label(end);
+                let end2 = self.codegen.alloc_label();
+                self.codegen.jump(end2);
+                self.codegen.label(end);
                 self.state().stack.push(BlockType::Else { end: end2 });
             }
             block => {
@@ -88,19 +88,19 @@ impl<CG: cg::BodyCodegen> cg::BcfCodegen for Wrapper<'_, CG> {
             panic!("calling end() on an empty stack");
         };
         match block {
-            BlockType::If { end } => self.codegen.acf().label(end),
-            BlockType::Else { end } => self.codegen.acf().label(end),
+            BlockType::If { end } => self.codegen.label(end),
+            BlockType::Else { end } => self.codegen.label(end),
             BlockType::Loop { start, end } => {
-                self.codegen.acf().jump(start);
-                self.codegen.acf().label(end)
+                self.codegen.jump(start);
+                self.codegen.label(end)
             }
         }
     }
 
     fn loop_(&mut self) {
-        let start = self.codegen.acf().alloc_label();
-        let end = self.codegen.acf().alloc_label();
-        self.codegen.acf().label(start);
+        let start = self.codegen.alloc_label();
+        let end = self.codegen.alloc_label();
+        self.codegen.label(start);
         self.state().stack.push(BlockType::Loop { start, end });
     }
 
@@ -109,7 +109,7 @@ impl<CG: cg::BodyCodegen> cg::BcfCodegen for Wrapper<'_, CG> {
             panic!("can't break() here, no loop blocks are open")
         };
 
-        self.codegen.acf().jump(end);
+        self.codegen.jump(end);
     }
 
     fn continue_(&mut self) {
@@ -117,7 +117,7 @@ impl<CG: cg::BodyCodegen> cg::BcfCodegen for Wrapper<'_, CG> {
             panic!("can't continue() here, no loop blocks are open")
         };
 
-        self.codegen.acf().jump(start);
+        self.codegen.jump(start);
     }
 
     fn cbreak(&mut self, condition: cg::Value) {
@@ -125,7 +125,7 @@ impl<CG: cg::BodyCodegen> cg::BcfCodegen for Wrapper<'_, CG> {
             panic!("can't cbreak() here, no loop blocks are open")
         };
 
-        self.codegen.acf().cjump(condition, end);
+        self.codegen.cjump(condition, end);
     }
 
     fn ccontinue(&mut self, condition: cg::Value) {
@@ -133,6 +133,6 @@ impl<CG: cg::BodyCodegen> cg::BcfCodegen for Wrapper<'_, CG> {
             panic!("can't ccontinue() here, no loop blocks are open")
         };
 
-        self.codegen.acf().cjump(condition, start);
+        self.codegen.cjump(condition, start);
     }
 }
@@ -0,0 +1,410 @@
+//! Coverage instrumentation, see [Coverage].
+//!
+//! # Formats
+//! The counter map ([`CoverageMap`]'s [`Display`](std::fmt::Display)) has a line per counter,
+//! fields are separated by tabs:
+//! `<counter>\t<function>\t<kind>\t<location>`, where `function` is a mangled symbol
+//! (see [`Symbol::mangle`]), `kind` is `entry`, `block` or `label <id>` and `location` is
+//! `file:line:column` or `-` if unknown.
+//!
+//! Counts, written by the host at process exit with [`WRITE`], have a line per counter:
+//! `<counter> <count>`, separated by a single space. Counters that are missing are zero
+use crate::attrs::SourceLocation;
+use crate::codegen as cg;
+use crate::symbol::Path;
+use crate::types::IntegerSize;
+use crate::{Symbol, Type};
+use std::sync::{Mutex, OnceLock};
+
+/// Function writing out a single counter, provided by the host:
+/// `__orco_coverage_write(counter: usize, count: u64)`
+pub const WRITE: &str = "__orco_coverage_write";
+
+/// Function calling [`WRITE`] for every counter in a loop, defined by [`Coverage::finish`].
+/// It is a destructor (see [`crate::attrs::FunctionAttributes::destructor`]),
+/// so it runs at process exit on its own
+pub const DUMP: &str = "__orco_coverage_dump";
+
+/// Function incrementing a counter, called by instrumented code and defined by [`Coverage::finish`]:
+/// `__orco_coverage_hit(counter: usize)`
+pub const HIT: &str = "__orco_coverage_hit";
+
+/// What a counter counts
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CounterKind {
+    /// Function calls
+    Entry,
+    /// Executions of code following a [`cg::AcfCodegen::label`]
+    Label(cg::Label),
+    /// Executions of a [`cg::BcfCodegen`] block, code following one
+    /// or code following a conditional branch
+    Block,
+}
+
+impl std::fmt::Display for CounterKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CounterKind::Entry => write!(f, "entry"),
+            CounterKind::Label(label) => write!(f, "label {}", label.0),
+            CounterKind::Block => write!(f, "block"),
+        }
+    }
+}
+
+/// A counter, see [`CoverageMap`]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Counter {
+    /// Function the counter is in, with generics (see [`Symbol::with_generics`])
+    pub function: Symbol,
+    #[allow(missing_docs)]
+    pub kind: CounterKind,
+    /// First source location set after the counter
+    pub location: Option<SourceLocation>,
+}
+
+/// Maps counters (by index) back to functions and source locations
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CoverageMap {
+    #[allow(missing_docs)]
+    pub counters: Vec<Counter>,
+}
+
+impl CoverageMap {
+    /// Parse a map, see module docs for the format
+    #[must_use]
+    pub fn parse(map: &str) -> Option<Self> {
+        let mut counters = Vec::new();
+        for (idx, line) in map.lines().enumerate() {
+            let mut fields = line.split('\t');
+            if fields.next()?.parse::<usize>().ok()? != idx {
+                return None;
+            }
+            let function = Symbol::demangle(fields.next()?)?;
+            let kind = match fields.next()? {
+                "entry" => CounterKind::Entry,
+                "block" => CounterKind::Block,
+                kind => CounterKind::Label(cg::Label(kind.strip_prefix("label ")?.parse().ok()?)),
+            };
+            let location = match fields.next()? {
+                "-" => None,
+                location => {
+                    let mut parts = location.rsplitn(3, ':');
+                    let column = parts.next()?.parse().ok()?;
+                    let line = parts.next()?.parse().ok()?;
+                    Some(SourceLocation {
+                        file: parts.next()?.into(),
+                        line,
+                        column,
+                    })
+                }
+            };
+            counters.push(Counter {
+                function,
+                kind,
+                location,
+            });
+        }
+        Some(Self { counters })
+    }
+
+    /// Read counts written by the host (see module docs),
+    /// returning each counter with its count
+    #[must_use]
+    pub fn read_counts(&self, counts: &str) -> Option<Vec<(&Counter, u64)>> {
+        let mut result = self
+            .counters
+            .iter()
+            .map(|counter| (counter, 0))
+            .collect::<Vec<_>>();
+        for line in counts.lines().filter(|line| !line.is_empty()) {
+            let (counter, count) = line.split_once(' ')?;
+            result.get_mut(counter.parse::<usize>().ok()?)?.1 += count.parse::<u64>().ok()?;
+        }
+        Some(result)
+    }
+}
+
+impl std::fmt::Display for CoverageMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, counter) in self.counters.iter().enumerate() {
+            write!(
+                f,
+                "{idx}\t{}\t{}\t",
+                counter.function.mangle(),
+                counter.kind
+            )?;
+            match &counter.location {
+                Some(location) => writeln!(f, "{location}")?,
+                None => writeln!(f, "-")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Wraps a backend, counting function calls and executions of every basic block:
+/// code following a label, every [`cg::BcfCodegen`] block (along with code following it)
+/// and code following a conditional branch.
+/// Counters are elements of a zero-initialized `u64` array global (see [`Coverage::counters_symbol`]),
+/// incremented through [`HIT`], so the array is only declared by [`Coverage::finish`],
+/// once the number of counters is known.
+/// Call [`Coverage::finish`] once all functions are defined to get them written out.
+/// Declarations are forwarded as is
+pub struct Coverage<B> {
+    #[allow(missing_docs)]
+    pub backend: B,
+    counters: Mutex<Vec<Counter>>,
+    /// Result of declaring [`HIT`], done along with the first function
+    hit: OnceLock<Result<(), crate::Error>>,
+}
+
+impl<B: crate::Backend> Coverage<B> {
+    #[allow(missing_docs)]
+    #[must_use]
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            counters: Mutex::new(Vec::new()),
+            hit: OnceLock::new(),
+        }
+    }
+
+    /// Global array holding the counters, `orco::coverage::counters`
+    #[must_use]
+    pub fn counters_symbol() -> Symbol {
+        Symbol::new(Path {
+            krate: Some("orco".into()),
+            segments: vec!["coverage".into()],
+            ..Path::bare("counters")
+        })
+    }
+
+    fn declare_hit(&self) -> Result<(), crate::Error> {
+        self.hit
+            .get_or_init(|| {
+                self.backend.function(
+                    HIT.into(),
+                    Vec::new(),
+                    vec![(
+                        Some("counter".to_owned()),
+                        Type::Unsigned(IntegerSize::Size),
+                    )],
+                    None,
+                    Default::default(),
+                )
+            })
+            .clone()
+    }
+
+    fn add_counter(&self, function: Symbol, kind: CounterKind) -> usize {
+        let mut counters = self.counters.lock().unwrap();
+        counters.push(Counter {
+            function,
+            kind,
+            location: None,
+        });
+        counters.len() - 1
+    }
+
+    /// Declare the counters, [`WRITE`] and [`DUMP`], define [`HIT`] and [`DUMP`]
+    /// and get the map of all counters
+    pub fn finish(&self) -> Result<CoverageMap, crate::Error> {
+        let usize = Type::Unsigned(IntegerSize::Size);
+        let u64 = Type::Unsigned(IntegerSize::Bits(64));
+        let counters = self.counters.lock().unwrap().clone();
+        self.backend.global(
+            Self::counters_symbol(),
+            Type::Array(Box::new(u64.clone()), counters.len().max(1)),
+        )?;
+        self.backend.function(
+            WRITE.into(),
+            Vec::new(),
+            vec![
+                (Some("counter".to_owned()), usize),
+                (Some("count".to_owned()), u64),
+            ],
+            None,
+            Default::default(),
+        )?;
+        self.backend.function(
+            DUMP.into(),
+            Vec::new(),
+            Vec::new(),
+            None,
+            crate::attrs::FunctionAttributes {
+                destructor: true,
+                ..Default::default()
+            },
+        )?;
+
+        if let Some(Ok(())) = self.hit.get() {
+            cg::builder::define(&self.backend, HIT.into(), Vec::new(), |b| {
+                let counter = b.param(0);
+                let index = b.get(&counter);
+                let count = b.read(Self::counters_place().index(index));
+                let one = b.uconst(1, IntegerSize::Bits(64));
+                let count = b.add(count, one);
+                let index = b.get(&counter);
+                b.set(Self::counters_place().index(index), count);
+                b.return_(None);
+            })?;
+        }
+
+        cg::builder::define(&self.backend, DUMP.into(), Vec::new(), |b| {
+            let zero = b.uconst(0, IntegerSize::Size);
+            let counter = b.let_(Some("counter"), zero);
+            b.loop_(|b| {
+                let index = b.get(&counter);
+                let len = b.uconst(counters.len() as u128, IntegerSize::Size);
+                let done = b.eq(index, len);
+                b.cbreak(done);
+                let index = b.get(&counter);
+                let count = b.read(Self::counters_place().index(index));
+                let index = b.get(&counter);
+                b.call_global(WRITE.into(), Vec::new(), vec![index, count]);
+                let index = b.get(&counter);
+                let one = b.uconst(1, IntegerSize::Size);
+                let next = b.add(index, one);
+                b.set(&counter, next);
+            });
+            b.return_(None);
+        })?;
+
+        Ok(CoverageMap { counters })
+    }
+
+    fn counters_place() -> cg::Place {
+        cg::Place::Global(Self::counters_symbol(), Vec::new())
+    }
+}
+
+impl<B: crate::DeclarationBackend> crate::DeclarationBackend for Coverage<B> {
+    fn function(
+        &self,
+        name: Symbol,
+        generic_params: Vec<Type>,
+        params: Vec<(Option<String>, Type)>,
+        return_type: Option<Type>,
+        attrs: crate::attrs::FunctionAttributes,
+    ) -> Result<(), crate::Error> {
+        self.backend
+            .function(name, generic_params, params, return_type, attrs)
+    }
+
+    fn type_(&self, name: Symbol, generic_params: Vec<Type>, ty: Type) -> Result<(), crate::Error> {
+        self.backend.type_(name, generic_params, ty)
+    }
+
+    fn global(&self, name: Symbol, ty: Type) -> Result<(), crate::Error> {
+        self.backend.global(name, ty)
+    }
+}
+
+impl<B: crate::Backend> cg::CodegenBackend for Coverage<B> {
+    fn cg_function(
+        &self,
+        name: Symbol,
+        generic_params: Vec<Type>,
+    ) -> Result<Box<dyn cg::BodyCodegen + '_>, crate::Error> {
+        self.declare_hit()?;
+        let codegen = self.backend.cg_function(name, generic_params.clone())?;
+        let mut codegen = CoverageCodegen {
+            codegen,
+            coverage: self,
+            function: name.with_generics(&generic_params),
+            unlocated: None,
+        };
+        codegen.count(CounterKind::Entry);
+        Ok(Box::new(codegen))
+    }
+}
+
+/// [`cg::BodyCodegen`] of [Coverage]. Values, variables and labels
+/// are the ones of the wrapped codegen
+pub struct CoverageCodegen<'a, B> {
+    codegen: Box<dyn cg::BodyCodegen + 'a>,
+    coverage: &'a Coverage<B>,
+    function: Symbol,
+    /// Last counter, which doesn't have a location yet
+    unlocated: Option<usize>,
+}
+
+impl<B: crate::Backend> CoverageCodegen<'_, B> {
+    /// Add a counter and increment it at this point
+    fn count(&mut self, kind: CounterKind) {
+        let counter = self.coverage.add_counter(self.function, kind);
+        self.unlocated = Some(counter);
+
+        let hit = self.codegen.read(cg::Place::Global(HIT.into(), Vec::new()));
+        let index = self.codegen.uconst(counter as u128, IntegerSize::Size);
+        self.codegen.call(hit, vec![index]);
+    }
+}
+
+impl<B: crate::Backend> cg::BodyCodegen for CoverageCodegen<'_, B> {
+    cg::forward::forward_methods!(self => [self.codegen]
+        comment, loop_attributes,
+        type_of, variable_type, global_type, symbol_kind, inline_type, place_type,
+        declare_var, storage_live, storage_dead, assign, mk_tmp, dup, flush, abort,
+        iconst, uconst, fconst, bconst, read, reference, call, return_);
+
+    fn set_location(&mut self, location: Option<SourceLocation>) {
+        if let (Some(counter), Some(location)) = (self.unlocated, &location) {
+            self.coverage.counters.lock().unwrap()[counter].location = Some(location.clone());
+            self.unlocated = None;
+        }
+        self.codegen.set_location(location);
+    }
+}
+
+impl<B: crate::Backend> cg::Intrinsics for CoverageCodegen<'_, B> {
+    cg::forward::forward_methods!(self => [self.codegen] add, mul, eq, not);
+}
+
+impl<B: crate::Backend> cg::AcfCodegen for CoverageCodegen<'_, B> {
+    cg::forward::forward_methods!(self => [self.codegen] alloc_label, jump);
+
+    fn cjump(&mut self, condition: cg::Value, label: cg::Label) {
+        self.codegen.cjump(condition, label);
+        self.count(CounterKind::Block);
+    }
+
+    fn label(&mut self, label: cg::Label) {
+        self.codegen.label(label);
+        self.count(CounterKind::Label(label));
+    }
+}
+
+impl<B: crate::Backend> cg::BcfCodegen for CoverageCodegen<'_, B> {
+    cg::forward::forward_methods!(self => [self.codegen] break_, continue_);
+
+    fn cbreak(&mut self, condition: cg::Value) {
+        self.codegen.cbreak(condition);
+        self.count(CounterKind::Block);
+    }
+
+    fn ccontinue(&mut self, condition: cg::Value) {
+        self.codegen.ccontinue(condition);
+        self.count(CounterKind::Block);
+    }
+
+    fn if_(&mut self, condition: cg::Value) {
+        self.codegen.if_(condition);
+        self.count(CounterKind::Block);
+    }
+
+    fn else_(&mut self) {
+        self.codegen.else_();
+        self.count(CounterKind::Block);
+    }
+
+    fn end(&mut self) {
+        self.codegen.end();
+        self.count(CounterKind::Block);
+    }
+
+    fn loop_(&mut self) {
+        self.codegen.loop_();
+        self.count(CounterKind::Block);
+    }
+}
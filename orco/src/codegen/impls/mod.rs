@@ -1,2 +1,17 @@
 // mod bcf_to_acf;
 // pub use bcf_to_acf::BcfToAcf;
+
+mod acf_to_bcf;
+pub use acf_to_bcf::{AcfToBcf, FinishError};
+
+mod validator;
+pub use validator::{Diagnostic, Validator, Violation};
+
+mod multiplex;
+pub use multiplex::{MultiplexCodegen, Multiplexer};
+
+mod trace;
+pub use trace::{Hook, TraceCodegen, TraceHooks, Tracer};
+
+pub mod coverage;
+pub use coverage::{Coverage, CoverageCodegen};
@@ -0,0 +1,577 @@
+use crate::codegen as cg;
+use crate::{Symbol, Type};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Forwards every declaration and definition to several backends,
+/// f.e. to get both an IR dump and C code, or to compare a new backend against cgen.
+/// Children can be of different types when used as `&dyn Backend`/`Box<dyn Backend>`,
+/// see [`crate::Backend`].
+///
+/// Functions the children disagree on (see [`MultiplexCodegen`]) are left undefined
+/// in all of them, call [`Multiplexer::finish`] once all functions are defined
+/// to get the errors
+#[derive(Debug, Default)]
+pub struct Multiplexer<B> {
+    /// Child backends, in the order calls are forwarded to them
+    pub backends: Vec<B>,
+    /// Errors of functions that are done, see [`Multiplexer::finish`]
+    errors: Mutex<Vec<crate::Error>>,
+}
+
+impl<B> Multiplexer<B> {
+    #[allow(missing_docs)]
+    #[must_use]
+    pub fn new(backends: Vec<B>) -> Self {
+        Self {
+            backends,
+            errors: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Take errors of the functions defined so far
+    pub fn finish(&self) -> Result<(), crate::Error> {
+        let errors = std::mem::take(&mut *self.errors.lock().unwrap());
+        combine(errors.into_iter().map(Err))
+    }
+}
+
+/// Combine errors of the children: [`crate::Error::Multiple`] if several of them failed
+fn combine(
+    results: impl IntoIterator<Item = Result<(), crate::Error>>,
+) -> Result<(), crate::Error> {
+    let mut errors = results
+        .into_iter()
+        .filter_map(Result::err)
+        .collect::<Vec<_>>();
+    match errors.len() {
+        0 => Ok(()),
+        1 => Err(errors.pop().unwrap()),
+        _ => Err(crate::Error::Multiple(errors)),
+    }
+}
+
+impl<B: crate::DeclarationBackend> crate::DeclarationBackend for Multiplexer<B> {
+    fn function(
+        &self,
+        name: Symbol,
+        generic_params: Vec<Type>,
+        params: Vec<(Option<String>, Type)>,
+        return_type: Option<Type>,
+        attrs: crate::attrs::FunctionAttributes,
+    ) -> Result<(), crate::Error> {
+        combine(self.backends.iter().map(|backend| {
+            backend.function(
+                name,
+                generic_params.clone(),
+                params.clone(),
+                return_type.clone(),
+                attrs.clone(),
+            )
+        }))
+    }
+
+    fn type_(&self, name: Symbol, generic_params: Vec<Type>, ty: Type) -> Result<(), crate::Error> {
+        combine(
+            self.backends
+                .iter()
+                .map(|backend| backend.type_(name, generic_params.clone(), ty.clone())),
+        )
+    }
+
+    fn global(&self, name: Symbol, ty: Type) -> Result<(), crate::Error> {
+        combine(
+            self.backends
+                .iter()
+                .map(|backend| backend.global(name, ty.clone())),
+        )
+    }
+}
+
+impl<B: cg::CodegenBackend> cg::CodegenBackend for Multiplexer<B> {
+    fn cg_function(
+        &self,
+        name: Symbol,
+        generic_params: Vec<Type>,
+    ) -> Result<Box<dyn cg::BodyCodegen + '_>, crate::Error> {
+        let mut children = Vec::with_capacity(self.backends.len());
+        let mut errors = Vec::new();
+        for backend in &self.backends {
+            match backend.cg_function(name, generic_params.clone()) {
+                Ok(child) => children.push(child),
+                Err(err) => errors.push(Err(err)),
+            }
+        }
+        if !errors.is_empty() {
+            // So that children which did start the function don't define an empty body
+            for child in &mut children {
+                child.abort();
+            }
+            return Err(combine(errors).unwrap_err());
+        }
+        Ok(Box::new(MultiplexCodegen::new(
+            children,
+            name,
+            generic_params,
+            &self.errors,
+        )))
+    }
+}
+
+/// Forwards every call to several [`cg::BodyCodegen`]s, see [Multiplexer].
+///
+/// Values, variables and labels of the multiplexer are mapped
+/// to the ones of each child. Variable ids are the ones of the first child,
+/// variables that weren't declared through the multiplexer are arguments,
+/// and are passed as is.
+///
+/// Type queries are asked of every child, the answer of the first one is returned.
+/// When children disagree (on a type or on whether a call returns a value)
+/// or a value is used twice, the problem is recorded and nothing but declarations
+/// of variables and labels is forwarded anymore. When dropped, such a function is aborted
+/// in all the children (see [`cg::BodyCodegen::abort`]) and the problem is reported
+/// through [`Multiplexer::finish`]
+pub struct MultiplexCodegen<'a> {
+    children: Vec<Box<dyn cg::BodyCodegen + 'a>>,
+    values: HashMap<usize, Vec<cg::Value>>,
+    next_value_id: usize,
+    variables: HashMap<cg::Variable, Vec<cg::Variable>>,
+    labels: Vec<Vec<cg::Label>>,
+
+    name: Symbol,
+    generics: Vec<Type>,
+    errors: &'a Mutex<Vec<crate::Error>>,
+    /// The first problem found, type queries can find one too
+    problem: RefCell<Option<String>>,
+    aborted: bool,
+}
+
+impl<'a> MultiplexCodegen<'a> {
+    /// Panics if there are no children: at least one is needed to answer type queries
+    fn new(
+        children: Vec<Box<dyn cg::BodyCodegen + 'a>>,
+        name: Symbol,
+        generics: Vec<Type>,
+        errors: &'a Mutex<Vec<crate::Error>>,
+    ) -> Self {
+        assert!(
+            !children.is_empty(),
+            "multiplexing codegen needs at least one child"
+        );
+        Self {
+            children,
+            values: HashMap::new(),
+            next_value_id: 0,
+            variables: HashMap::new(),
+            labels: Vec::new(),
+
+            name,
+            generics,
+            errors,
+            problem: RefCell::new(None),
+            aborted: false,
+        }
+    }
+
+    /// Record a problem, only the first one is kept
+    fn fail(&self, problem: impl FnOnce() -> String) {
+        self.problem.borrow_mut().get_or_insert_with(problem);
+    }
+
+    fn failed(&self) -> bool {
+        self.problem.borrow().is_some()
+    }
+
+    /// Ask every child, recording a problem if they don't agree.
+    /// Returns the answer of the first child
+    fn query<T: PartialEq + std::fmt::Debug>(
+        &self,
+        what: impl FnOnce() -> String,
+        f: impl Fn(usize, &dyn cg::BodyCodegen) -> T,
+    ) -> T {
+        let mut answers = self
+            .children
+            .iter()
+            .enumerate()
+            .map(|(idx, child)| f(idx, child.as_ref()))
+            .collect::<Vec<_>>();
+        if answers.iter().any(|answer| *answer != answers[0]) {
+            self.fail(|| format!("children disagree on {}: {answers:?}", what()));
+        }
+        answers.swap_remove(0)
+    }
+
+    fn mk_value(&mut self, values: Vec<cg::Value>) -> cg::Value {
+        let id = self.next_value_id;
+        self.next_value_id += 1;
+        self.values.insert(id, values);
+        cg::Value(id)
+    }
+
+    /// Values of the children, one per child. Empty once a problem is found
+    fn take(&mut self, value: cg::Value) -> Vec<cg::Value> {
+        let values = self.values.remove(&value.0);
+        if values.is_none() {
+            self.fail(|| format!("value #{} is used twice or was never created", value.0));
+        }
+        values.unwrap_or_default()
+    }
+
+    fn variables(&self, variable: cg::Variable) -> Vec<cg::Variable> {
+        self.variables
+            .get(&variable)
+            .cloned()
+            .unwrap_or_else(|| vec![variable; self.children.len()])
+    }
+
+    fn labels(&self, label: cg::Label) -> Vec<cg::Label> {
+        self.labels[label.0].clone()
+    }
+
+    /// Places of the children, one per child. Consumes values used in the place
+    fn places(&mut self, place: cg::Place) -> Vec<cg::Place> {
+        match place {
+            cg::Place::Variable(variable) => self
+                .variables(variable)
+                .into_iter()
+                .map(cg::Place::Variable)
+                .collect(),
+            cg::Place::Global(name, generics) => (0..self.children.len())
+                .map(|_| cg::Place::Global(name, generics.clone()))
+                .collect(),
+            cg::Place::Deref(value) => self.take(value).into_iter().map(cg::Place::Deref).collect(),
+            cg::Place::Field(place, idx) => self
+                .places(*place)
+                .into_iter()
+                .map(|place| place.field(idx))
+                .collect(),
+            cg::Place::Index(place, index) => self
+                .places(*place)
+                .into_iter()
+                .zip(self.take(index))
+                .map(|(place, index)| place.index(index))
+                .collect(),
+        }
+    }
+
+    /// Call `f` for each child, with the matching item of `args`.
+    /// Nothing is called once a problem is found
+    fn each<T, R>(
+        &mut self,
+        args: Vec<T>,
+        mut f: impl FnMut(&mut dyn cg::BodyCodegen, T) -> R,
+    ) -> Vec<R> {
+        if self.failed() {
+            return Vec::new();
+        }
+        self.children
+            .iter_mut()
+            .zip(args)
+            .map(|(child, arg)| f(child.as_mut(), arg))
+            .collect()
+    }
+
+    /// Call `f` for each child. Nothing is called once a problem is found
+    fn all<R>(&mut self, mut f: impl FnMut(&mut dyn cg::BodyCodegen) -> R) -> Vec<R> {
+        if self.failed() {
+            return Vec::new();
+        }
+        self.children
+            .iter_mut()
+            .map(|child| f(child.as_mut()))
+            .collect()
+    }
+}
+
+impl cg::BodyCodegen for MultiplexCodegen<'_> {
+    fn comment(&mut self, comment: &str) {
+        self.all(|child| child.comment(comment));
+    }
+
+    fn set_location(&mut self, location: Option<crate::attrs::SourceLocation>) {
+        self.all(|child| child.set_location(location.clone()));
+    }
+
+    fn loop_attributes(&mut self, attrs: crate::attrs::LoopAttributes) {
+        self.all(|child| child.loop_attributes(attrs.clone()));
+    }
+
+    fn type_of(&self, id: usize) -> Type {
+        match self.values.get(&id) {
+            Some(values) if !values.is_empty() => self.query(
+                || format!("type of value #{id}"),
+                |idx, child| child.type_of(values[idx].0),
+            ),
+            _ => Type::Error,
+        }
+    }
+
+    fn variable_type(&self, variable: cg::Variable) -> Type {
+        let variables = self.variables(variable);
+        self.query(
+            || format!("type of variable _{}", variable.0),
+            |idx, child| child.variable_type(variables[idx]),
+        )
+    }
+
+    fn global_type(&self, name: Symbol, generics: &[Type]) -> Type {
+        self.query(
+            || format!("type of {name}"),
+            |_, child| child.global_type(name, generics),
+        )
+    }
+
+    fn symbol_kind(&self, name: Symbol, generics: &[Type]) -> Option<crate::error::SymbolKind> {
+        self.query(
+            || format!("kind of {name}"),
+            |_, child| child.symbol_kind(name, generics),
+        )
+    }
+
+    fn inline_type(&self, ty: Type) -> Type {
+        self.query(
+            || format!("what {ty} is"),
+            |_, child| child.inline_type(ty.clone()),
+        )
+    }
+
+    fn declare_var(&mut self, ty: Type, name: Option<&str>) -> cg::Variable {
+        // Declared even after a problem, variable ids are the ones of the first child
+        let variables = (self.children.iter_mut())
+            .map(|child| child.declare_var(ty.clone(), name))
+            .collect::<Vec<_>>();
+        let variable = variables[0];
+        self.variables.insert(variable, variables);
+        variable
+    }
+
+    fn storage_live(&mut self, variable: cg::Variable) {
+        let variables = self.variables(variable);
+        self.each(variables, |child, variable| child.storage_live(variable));
+    }
+
+    fn storage_dead(&mut self, variable: cg::Variable) {
+        let variables = self.variables(variable);
+        self.each(variables, |child, variable| child.storage_dead(variable));
+    }
+
+    fn assign(&mut self, target: cg::Place, value: cg::Value) {
+        let args = self
+            .places(target)
+            .into_iter()
+            .zip(self.take(value))
+            .collect();
+        self.each(args, |child, (target, value)| child.assign(target, value));
+    }
+
+    fn dup(&mut self, value: cg::Value) -> (cg::Value, cg::Value) {
+        let values = self.take(value);
+        let (a, b) = self
+            .each(values, |child, value| child.dup(value))
+            .into_iter()
+            .unzip();
+        (self.mk_value(a), self.mk_value(b))
+    }
+
+    fn flush(&mut self) {
+        self.all(|child| child.flush());
+    }
+
+    fn abort(&mut self) {
+        self.aborted = true;
+        for child in &mut self.children {
+            child.abort();
+        }
+    }
+
+    fn iconst(&mut self, value: i128, size: crate::types::IntegerSize) -> cg::Value {
+        let values = self.all(|child| child.iconst(value, size));
+        self.mk_value(values)
+    }
+
+    fn uconst(&mut self, value: u128, size: crate::types::IntegerSize) -> cg::Value {
+        let values = self.all(|child| child.uconst(value, size));
+        self.mk_value(values)
+    }
+
+    fn fconst(&mut self, value: f64, size: u16) -> cg::Value {
+        let values = self.all(|child| child.fconst(value, size));
+        self.mk_value(values)
+    }
+
+    fn bconst(&mut self, value: bool) -> cg::Value {
+        let values = self.all(|child| child.bconst(value));
+        self.mk_value(values)
+    }
+
+    fn read(&mut self, place: cg::Place) -> cg::Value {
+        let places = self.places(place);
+        let values = self.each(places, |child, place| child.read(place));
+        self.mk_value(values)
+    }
+
+    fn reference(&mut self, place: cg::Place, mutable: bool) -> cg::Value {
+        let places = self.places(place);
+        let values = self.each(places, |child, place| child.reference(place, mutable));
+        self.mk_value(values)
+    }
+
+    fn call(&mut self, func: cg::Value, args: Vec<cg::Value>) -> Option<cg::Value> {
+        // Whether to give a value back once a problem is found, assume one if unsure
+        let returns = !matches!(
+            self.inline_type(self.type_of(func.0)),
+            Type::FnPtr {
+                return_type: None,
+                ..
+            }
+        );
+        let mut child_args = (0..self.children.len())
+            .map(|_| Vec::with_capacity(args.len()))
+            .collect::<Vec<_>>();
+        for arg in args {
+            for (child_args, arg) in child_args.iter_mut().zip(self.take(arg)) {
+                child_args.push(arg);
+            }
+        }
+
+        let calls = self.take(func).into_iter().zip(child_args).collect();
+        let values = self.each(calls, |child, (func, args)| child.call(func, args));
+        if self.failed() {
+            return returns.then(|| self.mk_value(Vec::new()));
+        }
+        if values.iter().all(Option::is_none) {
+            return None;
+        }
+        match values.into_iter().collect::<Option<Vec<_>>>() {
+            Some(values) => Some(self.mk_value(values)),
+            None => {
+                self.fail(|| "children disagree on whether a call returns a value".to_owned());
+                returns.then(|| self.mk_value(Vec::new()))
+            }
+        }
+    }
+
+    fn return_(&mut self, value: Option<cg::Value>) {
+        match value {
+            Some(value) => {
+                let values = self.take(value);
+                self.each(values, |child, value| child.return_(Some(value)));
+            }
+            None => {
+                self.all(|child| child.return_(None));
+            }
+        }
+    }
+}
+
+impl cg::Intrinsics for MultiplexCodegen<'_> {
+    fn add(&mut self, a: cg::Value, b: cg::Value) -> cg::Value {
+        let args = self.take(a).into_iter().zip(self.take(b)).collect();
+        let values = self.each(args, |child, (a, b)| child.add(a, b));
+        self.mk_value(values)
+    }
+
+    fn mul(&mut self, a: cg::Value, b: cg::Value) -> cg::Value {
+        let args = self.take(a).into_iter().zip(self.take(b)).collect();
+        let values = self.each(args, |child, (a, b)| child.mul(a, b));
+        self.mk_value(values)
+    }
+
+    fn eq(&mut self, a: cg::Value, b: cg::Value) -> cg::Value {
+        let args = self.take(a).into_iter().zip(self.take(b)).collect();
+        let values = self.each(args, |child, (a, b)| child.eq(a, b));
+        self.mk_value(values)
+    }
+
+    fn not(&mut self, a: cg::Value) -> cg::Value {
+        let args = self.take(a);
+        let values = self.each(args, |child, a| child.not(a));
+        self.mk_value(values)
+    }
+}
+
+impl cg::AcfCodegen for MultiplexCodegen<'_> {
+    fn alloc_label(&mut self) -> cg::Label {
+        // Allocated even after a problem, so labels stay valid
+        let labels = (self.children.iter_mut())
+            .map(|child| child.alloc_label())
+            .collect();
+        self.labels.push(labels);
+        cg::Label(self.labels.len() - 1)
+    }
+
+    fn label(&mut self, label: cg::Label) {
+        let labels = self.labels(label);
+        self.each(labels, |child, label| child.label(label));
+    }
+
+    fn jump(&mut self, label: cg::Label) {
+        let labels = self.labels(label);
+        self.each(labels, |child, label| child.jump(label));
+    }
+
+    fn cjump(&mut self, condition: cg::Value, label: cg::Label) {
+        let args = self
+            .take(condition)
+            .into_iter()
+            .zip(self.labels(label))
+            .collect();
+        self.each(args, |child, (condition, label)| {
+            child.cjump(condition, label)
+        });
+    }
+}
+
+impl cg::BcfCodegen for MultiplexCodegen<'_> {
+    fn if_(&mut self, condition: cg::Value) {
+        let conditions = self.take(condition);
+        self.each(conditions, |child, condition| child.if_(condition));
+    }
+
+    fn else_(&mut self) {
+        self.all(|child| child.else_());
+    }
+
+    fn end(&mut self) {
+        self.all(|child| child.end());
+    }
+
+    fn loop_(&mut self) {
+        self.all(|child| child.loop_());
+    }
+
+    fn break_(&mut self) {
+        self.all(|child| child.break_());
+    }
+
+    fn continue_(&mut self) {
+        self.all(|child| child.continue_());
+    }
+
+    fn cbreak(&mut self, condition: cg::Value) {
+        let conditions = self.take(condition);
+        self.each(conditions, |child, condition| child.cbreak(condition));
+    }
+
+    fn ccontinue(&mut self, condition: cg::Value) {
+        let conditions = self.take(condition);
+        self.each(conditions, |child, condition| child.ccontinue(condition));
+    }
+}
+
+impl Drop for MultiplexCodegen<'_> {
+    fn drop(&mut self) {
+        let Some(problem) = self.problem.get_mut().take() else {
+            return;
+        };
+        if !self.aborted {
+            cg::BodyCodegen::abort(self);
+        }
+        self.errors.lock().unwrap().push(crate::Error::Codegen(
+            self.name,
+            std::mem::take(&mut self.generics),
+            problem,
+        ));
+    }
+}
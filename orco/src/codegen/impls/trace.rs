@@ -0,0 +1,231 @@
+use crate::codegen as cg;
+use crate::error::SymbolKind;
+use crate::{Symbol, Type};
+use std::collections::HashMap;
+
+/// Code generated at a trace point, see [`TraceHooks`].
+/// Receives the codegen of the traced function, the symbol of the function
+/// the event is about (with generics, see [`Symbol::with_generics`])
+/// and copies of the values involved. Every value passed must be consumed,
+/// f.e. by passing it to a call of a runtime function
+pub type Hook = Box<dyn Fn(&mut dyn cg::BodyCodegen, Symbol, Vec<cg::Value>) + Send + Sync>;
+
+/// Hooks injected by [`Tracer`], each one is optional
+#[derive(Default)]
+pub struct TraceHooks {
+    /// At function entry, with the arguments
+    pub enter: Option<Hook>,
+    /// At every return, with the return value (if any)
+    pub exit: Option<Hook>,
+    /// Before a call to a global function, with the callee and the arguments
+    pub before_call: Option<Hook>,
+    /// After a call to a global function, with the callee and the return value (if any)
+    pub after_call: Option<Hook>,
+}
+
+/// Wraps a backend, injecting code at function entry, every return
+/// and (optionally) around calls, f.e. to get call traces without a debugger.
+/// Hooks generate code (usually a call to a runtime function) with the codegen
+/// of the wrapped backend, so they work with any backend and aren't traced themselves:
+/// ```ignore
+/// let trace = |hook: &'static str| -> Hook {
+///     Box::new(move |codegen, function, args| {
+///         codegen.comment(&format!("trace {function}"));
+///         let hook = codegen.read(Place::Global(hook.into(), Vec::new()));
+///         codegen.call(hook, args);
+///     })
+/// };
+/// ```
+/// Declarations are forwarded as is
+pub struct Tracer<B> {
+    #[allow(missing_docs)]
+    pub backend: B,
+    #[allow(missing_docs)]
+    pub hooks: TraceHooks,
+}
+
+impl<B> Tracer<B> {
+    #[allow(missing_docs)]
+    #[must_use]
+    pub fn new(backend: B, hooks: TraceHooks) -> Self {
+        Self { backend, hooks }
+    }
+}
+
+impl<B: crate::DeclarationBackend> crate::DeclarationBackend for Tracer<B> {
+    fn function(
+        &self,
+        name: Symbol,
+        generic_params: Vec<Type>,
+        params: Vec<(Option<String>, Type)>,
+        return_type: Option<Type>,
+        attrs: crate::attrs::FunctionAttributes,
+    ) -> Result<(), crate::Error> {
+        self.backend
+            .function(name, generic_params, params, return_type, attrs)
+    }
+
+    fn type_(&self, name: Symbol, generic_params: Vec<Type>, ty: Type) -> Result<(), crate::Error> {
+        self.backend.type_(name, generic_params, ty)
+    }
+
+    fn global(&self, name: Symbol, ty: Type) -> Result<(), crate::Error> {
+        self.backend.global(name, ty)
+    }
+}
+
+impl<B: cg::CodegenBackend> cg::CodegenBackend for Tracer<B> {
+    fn cg_function(
+        &self,
+        name: Symbol,
+        generic_params: Vec<Type>,
+    ) -> Result<Box<dyn cg::BodyCodegen + '_>, crate::Error> {
+        let mut codegen = self.backend.cg_function(name, generic_params.clone())?;
+        let params = match codegen.global_type(name, &generic_params) {
+            Type::FnPtr { params, .. } => params.len(),
+            _ => return Err(crate::Error::Undeclared(SymbolKind::Function, name)),
+        };
+        let function = name.with_generics(&generic_params);
+
+        if let Some(enter) = &self.hooks.enter {
+            let args = (0..params)
+                .map(|idx| codegen.read(cg::Variable(idx).into()))
+                .collect();
+            enter(codegen.as_mut(), function, args);
+        }
+        Ok(Box::new(TraceCodegen {
+            codegen,
+            hooks: &self.hooks,
+            function,
+            globals: HashMap::new(),
+            global_variables: HashMap::new(),
+        }))
+    }
+}
+
+/// [`cg::BodyCodegen`] of a [Tracer]. Values, variables and labels
+/// are the ones of the wrapped codegen
+pub struct TraceCodegen<'a> {
+    codegen: Box<dyn cg::BodyCodegen + 'a>,
+    hooks: &'a TraceHooks,
+    function: Symbol,
+    /// Values which are reads of global symbols, to know the callee of a call
+    globals: HashMap<usize, Symbol>,
+    /// Variables holding a global symbol, see [`Self::globals`]
+    global_variables: HashMap<usize, Symbol>,
+}
+
+impl TraceCodegen<'_> {
+    /// Duplicate values, giving them back along with copies for a hook
+    fn copies(&mut self, values: Vec<cg::Value>) -> (Vec<cg::Value>, Vec<cg::Value>) {
+        values
+            .into_iter()
+            .map(|value| self.codegen.dup(value))
+            .unzip()
+    }
+}
+
+impl cg::BodyCodegen for TraceCodegen<'_> {
+    cg::forward::forward_methods!(self => [self.codegen]
+        comment, set_location, loop_attributes,
+        type_of, variable_type, global_type, symbol_kind, inline_type, place_type,
+        declare_var, storage_live, storage_dead, flush, abort,
+        iconst, uconst, fconst, bconst, reference);
+
+    fn assign(&mut self, target: cg::Place, value: cg::Value) {
+        if let cg::Place::Variable(variable) = &target {
+            match self.globals.remove(&value.0) {
+                Some(global) => self.global_variables.insert(variable.0, global),
+                None => self.global_variables.remove(&variable.0),
+            };
+        }
+        self.codegen.assign(target, value);
+    }
+
+    fn mk_tmp(&mut self, value: cg::Value) -> cg::Variable {
+        let global = self.globals.remove(&value.0);
+        let variable = self.codegen.mk_tmp(value);
+        if let Some(global) = global {
+            self.global_variables.insert(variable.0, global);
+        }
+        variable
+    }
+
+    fn dup(&mut self, value: cg::Value) -> (cg::Value, cg::Value) {
+        let global = self.globals.remove(&value.0);
+        let (a, b) = self.codegen.dup(value);
+        if let Some(global) = global {
+            self.globals.insert(a.0, global);
+            self.globals.insert(b.0, global);
+        }
+        (a, b)
+    }
+
+    fn read(&mut self, place: cg::Place) -> cg::Value {
+        let global = match &place {
+            cg::Place::Global(name, generics) => Some(name.with_generics(generics)),
+            cg::Place::Variable(variable) => self.global_variables.get(&variable.0).copied(),
+            _ => None,
+        };
+        let value = self.codegen.read(place);
+        if let Some(global) = global {
+            self.globals.insert(value.0, global);
+        }
+        value
+    }
+
+    fn call(&mut self, func: cg::Value, args: Vec<cg::Value>) -> Option<cg::Value> {
+        let Some(callee) = self.globals.remove(&func.0) else {
+            return self.codegen.call(func, args);
+        };
+
+        let args = match &self.hooks.before_call {
+            Some(before_call) => {
+                let (args, copies) = self.copies(args);
+                before_call(self.codegen.as_mut(), callee, copies);
+                args
+            }
+            None => args,
+        };
+        let value = self.codegen.call(func, args);
+        match &self.hooks.after_call {
+            Some(after_call) => {
+                let (value, copy) = match value {
+                    Some(value) => {
+                        let (value, copy) = self.codegen.dup(value);
+                        (Some(value), Some(copy))
+                    }
+                    None => (None, None),
+                };
+                after_call(self.codegen.as_mut(), callee, copy.into_iter().collect());
+                value
+            }
+            None => value,
+        }
+    }
+
+    fn return_(&mut self, value: Option<cg::Value>) {
+        let value = match &self.hooks.exit {
+            Some(exit) => {
+                let (value, copies) = self.copies(value.into_iter().collect());
+                exit(self.codegen.as_mut(), self.function, copies);
+                value.into_iter().next()
+            }
+            None => value,
+        };
+        self.codegen.return_(value);
+    }
+}
+
+impl cg::Intrinsics for TraceCodegen<'_> {
+    cg::forward::forward_methods!(self => [self.codegen] add, mul, eq, not);
+}
+
+impl cg::AcfCodegen for TraceCodegen<'_> {
+    cg::forward::forward_methods!(self => [self.codegen] alloc_label, label, jump, cjump);
+}
+
+impl cg::BcfCodegen for TraceCodegen<'_> {
+    cg::forward::forward_methods!(self => [self.codegen]
+        if_, else_, end, loop_, break_, continue_, cbreak, ccontinue);
+}
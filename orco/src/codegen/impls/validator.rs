@@ -0,0 +1,768 @@
+use crate::Type;
+use crate::attrs::SourceLocation;
+use crate::codegen as cg;
+use crate::error::SymbolKind;
+use std::collections::HashMap;
+
+/// A misuse of the codegen API, see [`Diagnostic`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// A value that was never created
+    UnknownValue(usize),
+    /// A value was consumed twice, `first_use` is the index of the call that consumed it first
+    ValueReused {
+        /// Id of the value
+        value: usize,
+        /// See [`Diagnostic::call`]
+        first_use: usize,
+    },
+    /// A variable that was never declared
+    UnknownVariable(cg::Variable),
+    /// A global symbol (with generics) that was never declared
+    UndeclaredSymbol(crate::Symbol, Vec<Type>),
+    /// A label that was never allocated
+    UnknownLabel(cg::Label),
+    /// A label was placed more than once
+    LabelPlacedTwice(cg::Label),
+    /// A label was jumped to, but never placed
+    LabelNotPlaced(cg::Label),
+    /// Type of a value doesn't match the type expected
+    TypeMismatch {
+        #[allow(missing_docs)]
+        expected: Type,
+        #[allow(missing_docs)]
+        found: Type,
+    },
+    /// Dereference of a non-pointer
+    NotAPointer(Type),
+    /// Field access on a non-struct
+    NotAStruct(Type),
+    /// Field index is out of range
+    FieldOutOfRange {
+        #[allow(missing_docs)]
+        index: usize,
+        /// Number of fields in the struct
+        count: usize,
+    },
+    /// Indexing of a non-array
+    NotAnArray(Type),
+    /// Array index of a non-integer type
+    NotAnIndex(Type),
+    /// Call of a value which is not a function pointer
+    NotAFunction(Type),
+    /// Wrong number of arguments passed to a function
+    ArityMismatch {
+        #[allow(missing_docs)]
+        expected: usize,
+        #[allow(missing_docs)]
+        found: usize,
+    },
+    /// Assignment to (or a mutable reference of) a place, which is not mutable
+    ImmutablePlace,
+    /// Return with a value from a function without a return type or vice versa
+    ReturnMismatch {
+        #[allow(missing_docs)]
+        expected: Option<Type>,
+        #[allow(missing_docs)]
+        found: Option<Type>,
+    },
+    /// [`cg::BcfCodegen::else_`] outside of an if block (or after another else)
+    ElseWithoutIf,
+    /// [`cg::BcfCodegen::end`] without an open block
+    EndWithoutBlock,
+    /// Break or continue outside of a loop
+    OutsideLoop,
+    /// Function ended with this many blocks left open
+    UnclosedBlocks(usize),
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::UnknownValue(value) => write!(f, "value #{value} was never created"),
+            Violation::ValueReused { value, first_use } => write!(
+                f,
+                "value #{value} was already consumed by call #{first_use}"
+            ),
+            Violation::UnknownVariable(variable) => {
+                write!(f, "variable _{} was never declared", variable.0)
+            }
+            Violation::UndeclaredSymbol(name, generics) => write!(
+                f,
+                "undeclared symbol {name}{}",
+                crate::types::fmt_generics(generics)
+            ),
+            Violation::UnknownLabel(label) => write!(f, "label {} was never allocated", label.0),
+            Violation::LabelPlacedTwice(label) => {
+                write!(f, "label {} is placed more than once", label.0)
+            }
+            Violation::LabelNotPlaced(label) => {
+                write!(f, "label {} is jumped to, but never placed", label.0)
+            }
+            Violation::TypeMismatch { expected, found } => {
+                write!(f, "expected type {expected}, found {found}")
+            }
+            Violation::NotAPointer(ty) => write!(f, "trying to dereference non-pointer type {ty}"),
+            Violation::NotAStruct(ty) => {
+                write!(f, "trying to access a field on non-struct type {ty}")
+            }
+            Violation::FieldOutOfRange { index, count } => write!(
+                f,
+                "field _{index} is out of range for a struct with {count} fields"
+            ),
+            Violation::NotAnArray(ty) => write!(f, "trying to index non-array type {ty}"),
+            Violation::NotAnIndex(ty) => write!(f, "array index has non-integer type {ty}"),
+            Violation::NotAFunction(ty) => write!(f, "trying to call non-function type {ty}"),
+            Violation::ArityMismatch { expected, found } => write!(
+                f,
+                "function takes {expected} arguments, but {found} were passed"
+            ),
+            Violation::ImmutablePlace => write!(f, "place is not mutable"),
+            Violation::ReturnMismatch { expected, found } => {
+                let fmt = |ty: &Option<Type>| {
+                    ty.as_ref()
+                        .map_or_else(|| "no value".to_owned(), |ty| format!("{ty}"))
+                };
+                write!(
+                    f,
+                    "function returns {}, but return has {}",
+                    fmt(expected),
+                    fmt(found)
+                )
+            }
+            Violation::ElseWithoutIf => write!(f, "else without an if block"),
+            Violation::EndWithoutBlock => write!(f, "end without an open block"),
+            Violation::OutsideLoop => write!(f, "break or continue outside of a loop"),
+            Violation::UnclosedBlocks(count) => write!(f, "{count} blocks are left open"),
+        }
+    }
+}
+
+/// A violation, with context of where it happened
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Zero-based index of the offending call, counting every call made through the validator
+    pub call: usize,
+    /// Name of the method called, f.e. `"assign"`.
+    /// Problems found at the end of a function are reported with `"finish"`
+    pub method: &'static str,
+    /// Source location at the time of the call, see [`cg::BodyCodegen::set_location`]
+    pub location: Option<SourceLocation>,
+    #[allow(missing_docs)]
+    pub violation: Violation,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "call #{} ({})", self.call, self.method)?;
+        if let Some(location) = &self.location {
+            write!(f, " at {location}")?;
+        }
+        write!(f, ": {}", self.violation)
+    }
+}
+
+#[derive(Debug)]
+struct ValueInfo {
+    ty: Type,
+    /// Value of the wrapped codegen, [None] once the validator is poisoned
+    inner: Option<cg::Value>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct LabelInfo {
+    placed: bool,
+    jumped: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BlockType {
+    If,
+    Else,
+    Loop,
+}
+
+/// Checks the usage of codegen API, wraps any [`cg::BodyCodegen`].
+/// Tracks value types and makes sure each value is used once,
+/// checks types of assignments, calls and returns, label allocation and placement
+/// and balance of [`cg::BcfCodegen`] blocks.
+///
+/// Instead of panicking on the first problem, every violation is recorded as a [`Diagnostic`]
+/// and the validator keeps checking the rest of the function.
+/// After the first violation nothing is forwarded to the wrapped codegen anymore
+/// (so it doesn't panic on its own).
+///
+/// Call [`Validator::finish`] to run the end-of-function checks and collect diagnostics.
+/// Dropping the validator drops (and so finishes) the wrapped codegen, whatever was forwarded
+/// to it so far gets defined, so check diagnostics before using the result.
+/// Symbols and type aliases are resolved by the wrapped codegen
+pub struct Validator<CG: cg::BodyCodegen> {
+    codegen: CG,
+    return_type: Option<Type>,
+    variables: HashMap<usize, Type>,
+    values: HashMap<usize, ValueInfo>,
+    /// Consumed values with the call that consumed them
+    used: HashMap<usize, usize>,
+    next_value_id: usize,
+    labels: HashMap<cg::Label, LabelInfo>,
+    blocks: Vec<BlockType>,
+
+    calls: usize,
+    method: &'static str,
+    location: Option<SourceLocation>,
+    poisoned: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<CG: cg::BodyCodegen> Validator<CG> {
+    /// Wrap a codegen for a function with the given signature
+    #[must_use]
+    pub fn new(codegen: CG, signature: &crate::types::FunctionSignature) -> Self {
+        Self {
+            codegen,
+            return_type: signature.return_type.clone(),
+            variables: signature
+                .params
+                .iter()
+                .map(|(_, ty)| ty.clone())
+                .enumerate()
+                .collect(),
+            values: HashMap::new(),
+            used: HashMap::new(),
+            next_value_id: 0,
+            labels: HashMap::new(),
+            blocks: Vec::new(),
+
+            calls: 0,
+            method: "new",
+            location: None,
+            poisoned: false,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Diagnostics reported so far
+    #[must_use]
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Run the checks for the end of a function (unplaced labels, unclosed blocks),
+    /// finish the wrapped codegen and return all the diagnostics.
+    /// An empty list means the function was generated correctly
+    #[must_use]
+    pub fn finish(mut self) -> Vec<Diagnostic> {
+        self.enter("finish");
+        let mut unplaced = self
+            .labels
+            .iter()
+            .filter(|(_, info)| info.jumped && !info.placed)
+            .map(|(label, _)| *label)
+            .collect::<Vec<_>>();
+        unplaced.sort();
+        for label in unplaced {
+            self.report(Violation::LabelNotPlaced(label));
+        }
+        if !self.blocks.is_empty() {
+            self.report(Violation::UnclosedBlocks(self.blocks.len()));
+        }
+        self.diagnostics
+    }
+
+    fn enter(&mut self, method: &'static str) {
+        self.calls += 1;
+        self.method = method;
+    }
+
+    fn report(&mut self, violation: Violation) {
+        self.poisoned = true;
+        self.diagnostics.push(Diagnostic {
+            call: self.calls - 1,
+            method: self.method,
+            location: self.location.clone(),
+            violation,
+        });
+    }
+
+    /// Call the wrapped codegen, unless a violation was found.
+    /// While not poisoned, all inner values and places are [Some]
+    fn forward<T>(&mut self, f: impl FnOnce(&mut CG) -> T) -> Option<T> {
+        if self.poisoned {
+            None
+        } else {
+            Some(f(&mut self.codegen))
+        }
+    }
+
+    fn mk_value(&mut self, ty: Type, inner: Option<cg::Value>) -> cg::Value {
+        let id = self.next_value_id;
+        self.next_value_id += 1;
+        self.values.insert(id, ValueInfo { ty, inner });
+        cg::Value(id)
+    }
+
+    /// Consume a value. Yields [`Type::Error`] for invalid values
+    fn use_value(&mut self, value: cg::Value) -> (Type, Option<cg::Value>) {
+        if let Some(info) = self.values.remove(&value.0) {
+            self.used.insert(value.0, self.calls - 1);
+            return (info.ty, info.inner);
+        }
+        match self.used.get(&value.0) {
+            Some(&first_use) => self.report(Violation::ValueReused {
+                value: value.0,
+                first_use,
+            }),
+            None => self.report(Violation::UnknownValue(value.0)),
+        }
+        (Type::Error, None)
+    }
+
+    /// Whether a value of type `found` can be used where `expected` is expected.
+    /// [`Type::Error`] is compatible with anything, to not report the same problem twice
+    fn compatible(&self, expected: &Type, found: &Type) -> bool {
+        if expected == found {
+            return true;
+        }
+        match (
+            self.codegen.inline_type(expected.clone()),
+            self.codegen.inline_type(found.clone()),
+        ) {
+            (Type::Error, _) | (_, Type::Error) => true,
+            (Type::Ptr(expected, expected_mut), Type::Ptr(found, found_mut)) => {
+                expected_mut == found_mut && self.compatible(&expected, &found)
+            }
+            (expected, found) => expected == found,
+        }
+    }
+
+    fn expect_type(&mut self, expected: &Type, found: Type) {
+        if !self.compatible(expected, &found) {
+            self.report(Violation::TypeMismatch {
+                expected: expected.clone(),
+                found,
+            });
+        }
+    }
+
+    fn check_variable(&mut self, variable: cg::Variable) -> Type {
+        if let Some(ty) = self.variables.get(&variable.0) {
+            ty.clone()
+        } else {
+            self.report(Violation::UnknownVariable(variable));
+            Type::Error
+        }
+    }
+
+    /// Check a place, consuming values in it.
+    /// Returns type of the place, whether it's mutable and the place for the wrapped codegen
+    fn place(&mut self, place: cg::Place) -> (Type, bool, Option<cg::Place>) {
+        match place {
+            cg::Place::Variable(variable) => {
+                let ty = self.check_variable(variable);
+                (ty, true, Some(cg::Place::Variable(variable)))
+            }
+            cg::Place::Global(name, generics) => match self.codegen.symbol_kind(name, &generics) {
+                Some(kind) => {
+                    // Functions are immutable, global variables are not
+                    let ty = self.codegen.global_type(name, &generics);
+                    let mutable = kind == SymbolKind::Global;
+                    (ty, mutable, Some(cg::Place::Global(name, generics)))
+                }
+                None => {
+                    self.report(Violation::UndeclaredSymbol(name, generics));
+                    (Type::Error, true, None)
+                }
+            },
+            cg::Place::Deref(value) => {
+                let (ty, inner) = self.use_value(value);
+                match self.codegen.inline_type(ty) {
+                    Type::Ptr(ty, mutable) => (*ty, mutable, inner.map(cg::Place::Deref)),
+                    Type::Error => (Type::Error, true, None),
+                    ty => {
+                        self.report(Violation::NotAPointer(ty));
+                        (Type::Error, true, None)
+                    }
+                }
+            }
+            cg::Place::Field(place, index) => {
+                let (ty, mutable, inner) = self.place(*place);
+                match self.codegen.inline_type(ty) {
+                    Type::Struct { mut fields } if index < fields.len() => (
+                        fields.swap_remove(index).1,
+                        mutable,
+                        inner.map(|place| place.field(index)),
+                    ),
+                    Type::Struct { fields } => {
+                        self.report(Violation::FieldOutOfRange {
+                            index,
+                            count: fields.len(),
+                        });
+                        (Type::Error, true, None)
+                    }
+                    Type::Error => (Type::Error, true, None),
+                    ty => {
+                        self.report(Violation::NotAStruct(ty));
+                        (Type::Error, true, None)
+                    }
+                }
+            }
+            cg::Place::Index(place, index) => {
+                let (ty, mutable, inner) = self.place(*place);
+                let (index_ty, index) = self.use_value(index);
+                match self.codegen.inline_type(index_ty) {
+                    Type::Integer(..) | Type::Unsigned(..) | Type::Error => (),
+                    ty => self.report(Violation::NotAnIndex(ty)),
+                }
+                match self.codegen.inline_type(ty) {
+                    Type::Array(ty, _) => (
+                        *ty,
+                        mutable,
+                        inner.zip(index).map(|(place, index)| place.index(index)),
+                    ),
+                    Type::Error => (Type::Error, true, None),
+                    ty => {
+                        self.report(Violation::NotAnArray(ty));
+                        (Type::Error, true, None)
+                    }
+                }
+            }
+        }
+    }
+
+    fn condition(&mut self, condition: cg::Value) -> Option<cg::Value> {
+        let (ty, inner) = self.use_value(condition);
+        self.expect_type(&Type::Bool, ty);
+        inner
+    }
+
+    fn check_label(&mut self, label: cg::Label) -> Option<&mut LabelInfo> {
+        if !self.labels.contains_key(&label) {
+            self.report(Violation::UnknownLabel(label));
+        }
+        self.labels.get_mut(&label)
+    }
+
+    fn in_loop(&mut self) {
+        if !self.blocks.contains(&BlockType::Loop) {
+            self.report(Violation::OutsideLoop);
+        }
+    }
+}
+
+impl<CG: cg::BodyCodegen> cg::BodyCodegen for Validator<CG> {
+    fn comment(&mut self, comment: &str) {
+        self.enter("comment");
+        self.forward(|cg| cg.comment(comment));
+    }
+
+    fn set_location(&mut self, location: Option<SourceLocation>) {
+        self.enter("set_location");
+        self.location.clone_from(&location);
+        self.forward(|cg| cg.set_location(location));
+    }
+
+    fn loop_attributes(&mut self, attrs: crate::attrs::LoopAttributes) {
+        self.enter("loop_attributes");
+        self.forward(|cg| cg.loop_attributes(attrs));
+    }
+
+    fn type_of(&self, id: usize) -> Type {
+        self.values
+            .get(&id)
+            .map_or(Type::Error, |value| value.ty.clone())
+    }
+
+    fn variable_type(&self, variable: cg::Variable) -> Type {
+        self.variables
+            .get(&variable.0)
+            .cloned()
+            .unwrap_or(Type::Error)
+    }
+
+    cg::forward::forward_methods!(self => [self.codegen] global_type, symbol_kind, inline_type);
+
+    fn declare_var(&mut self, ty: Type, name: Option<&str>) -> cg::Variable {
+        self.enter("declare_var");
+        let variable = self.codegen.declare_var(ty.clone(), name);
+        self.variables.insert(variable.0, ty);
+        variable
+    }
+
+    fn storage_live(&mut self, variable: cg::Variable) {
+        self.enter("storage_live");
+        self.check_variable(variable);
+        self.forward(|cg| cg.storage_live(variable));
+    }
+
+    fn storage_dead(&mut self, variable: cg::Variable) {
+        self.enter("storage_dead");
+        self.check_variable(variable);
+        self.forward(|cg| cg.storage_dead(variable));
+    }
+
+    fn assign(&mut self, target: cg::Place, value: cg::Value) {
+        self.enter("assign");
+        let (target_ty, mutable, target) = self.place(target);
+        let (ty, value) = self.use_value(value);
+        if !mutable {
+            self.report(Violation::ImmutablePlace);
+        }
+        self.expect_type(&target_ty, ty);
+        self.forward(|cg| cg.assign(target.unwrap(), value.unwrap()));
+    }
+
+    fn mk_tmp(&mut self, value: cg::Value) -> cg::Variable {
+        let tmp = self.declare_var(self.type_of(value.0), None);
+        self.assign(tmp.into(), value);
+        tmp
+    }
+
+    fn dup(&mut self, value: cg::Value) -> (cg::Value, cg::Value) {
+        self.enter("dup");
+        let (ty, value) = self.use_value(value);
+        let (a, b) = self.forward(|cg| cg.dup(value.unwrap())).unzip();
+        (self.mk_value(ty.clone(), a), self.mk_value(ty, b))
+    }
+
+    fn flush(&mut self) {
+        self.enter("flush");
+        self.forward(|cg| cg.flush());
+    }
+
+    fn abort(&mut self) {
+        // Forwarded even when poisoned, the wrapped codegen is only dropped afterwards
+        self.enter("abort");
+        self.codegen.abort();
+    }
+
+    fn iconst(&mut self, value: i128, size: crate::types::IntegerSize) -> cg::Value {
+        self.enter("iconst");
+        let inner = self.forward(|cg| cg.iconst(value, size));
+        self.mk_value(Type::Integer(size), inner)
+    }
+
+    fn uconst(&mut self, value: u128, size: crate::types::IntegerSize) -> cg::Value {
+        self.enter("uconst");
+        let inner = self.forward(|cg| cg.uconst(value, size));
+        self.mk_value(Type::Unsigned(size), inner)
+    }
+
+    fn fconst(&mut self, value: f64, size: u16) -> cg::Value {
+        self.enter("fconst");
+        let inner = self.forward(|cg| cg.fconst(value, size));
+        self.mk_value(Type::Float(size), inner)
+    }
+
+    fn bconst(&mut self, value: bool) -> cg::Value {
+        self.enter("bconst");
+        let inner = self.forward(|cg| cg.bconst(value));
+        self.mk_value(Type::Bool, inner)
+    }
+
+    fn read(&mut self, place: cg::Place) -> cg::Value {
+        self.enter("read");
+        let (ty, _, place) = self.place(place);
+        let inner = self.forward(|cg| cg.read(place.unwrap()));
+        self.mk_value(ty, inner)
+    }
+
+    fn reference(&mut self, place: cg::Place, mutable: bool) -> cg::Value {
+        self.enter("reference");
+        let (ty, place_mutable, place) = self.place(place);
+        if mutable && !place_mutable {
+            self.report(Violation::ImmutablePlace);
+        }
+        let inner = self.forward(|cg| cg.reference(place.unwrap(), mutable));
+        self.mk_value(Type::Ptr(Box::new(ty), mutable), inner)
+    }
+
+    fn call(&mut self, func: cg::Value, args: Vec<cg::Value>) -> Option<cg::Value> {
+        self.enter("call");
+        let (ty, func) = self.use_value(func);
+        let (arg_types, args): (Vec<_>, Vec<_>) =
+            args.into_iter().map(|arg| self.use_value(arg)).unzip();
+
+        let return_type = match self.codegen.inline_type(ty) {
+            Type::FnPtr {
+                params,
+                return_type,
+            } => {
+                if params.len() == arg_types.len() {
+                    for (param, arg) in params.iter().zip(arg_types) {
+                        self.expect_type(param, arg);
+                    }
+                } else {
+                    self.report(Violation::ArityMismatch {
+                        expected: params.len(),
+                        found: arg_types.len(),
+                    });
+                }
+                return_type.map(|ty| *ty)
+            }
+            Type::Error => None,
+            ty => {
+                self.report(Violation::NotAFunction(ty));
+                None
+            }
+        };
+
+        let inner = self
+            .forward(|cg| {
+                cg.call(
+                    func.unwrap(),
+                    args.into_iter().map(Option::unwrap).collect(),
+                )
+            })
+            .flatten();
+        return_type.map(|ty| self.mk_value(ty, inner))
+    }
+
+    fn return_(&mut self, value: Option<cg::Value>) {
+        self.enter("return_");
+        let value = value.map(|value| self.use_value(value));
+        match (self.return_type.clone(), value) {
+            (Some(expected), Some((ty, value))) => {
+                self.expect_type(&expected, ty);
+                self.forward(|cg| cg.return_(Some(value.unwrap())));
+            }
+            (None, None) => {
+                self.forward(|cg| cg.return_(None));
+            }
+            (expected, found) => self.report(Violation::ReturnMismatch {
+                expected,
+                found: found.map(|(ty, _)| ty),
+            }),
+        }
+    }
+}
+
+impl<CG: cg::BodyCodegen> cg::Intrinsics for Validator<CG> {
+    fn add(&mut self, a: cg::Value, b: cg::Value) -> cg::Value {
+        self.enter("add");
+        let (ty, a) = self.use_value(a);
+        let (b_ty, b) = self.use_value(b);
+        self.expect_type(&ty, b_ty);
+        let inner = self.forward(|cg| cg.add(a.unwrap(), b.unwrap()));
+        self.mk_value(ty, inner)
+    }
+
+    fn mul(&mut self, a: cg::Value, b: cg::Value) -> cg::Value {
+        self.enter("mul");
+        let (ty, a) = self.use_value(a);
+        let (b_ty, b) = self.use_value(b);
+        self.expect_type(&ty, b_ty);
+        let inner = self.forward(|cg| cg.mul(a.unwrap(), b.unwrap()));
+        self.mk_value(ty, inner)
+    }
+
+    fn eq(&mut self, a: cg::Value, b: cg::Value) -> cg::Value {
+        self.enter("eq");
+        let (ty, a) = self.use_value(a);
+        let (b_ty, b) = self.use_value(b);
+        self.expect_type(&ty, b_ty);
+        let inner = self.forward(|cg| cg.eq(a.unwrap(), b.unwrap()));
+        self.mk_value(Type::Bool, inner)
+    }
+
+    fn not(&mut self, a: cg::Value) -> cg::Value {
+        self.enter("not");
+        let (ty, a) = self.use_value(a);
+        let inner = self.forward(|cg| cg.not(a.unwrap()));
+        self.mk_value(ty, inner)
+    }
+}
+
+impl<CG: cg::BodyCodegen> cg::AcfCodegen for Validator<CG> {
+    fn alloc_label(&mut self) -> cg::Label {
+        self.enter("alloc_label");
+        let label = self.codegen.alloc_label();
+        self.labels.insert(label, LabelInfo::default());
+        label
+    }
+
+    fn label(&mut self, label: cg::Label) {
+        self.enter("label");
+        if let Some(info) = self.check_label(label) {
+            let placed = std::mem::replace(&mut info.placed, true);
+            if placed {
+                self.report(Violation::LabelPlacedTwice(label));
+            }
+        }
+        self.forward(|cg| cg.label(label));
+    }
+
+    fn jump(&mut self, label: cg::Label) {
+        self.enter("jump");
+        if let Some(info) = self.check_label(label) {
+            info.jumped = true;
+        }
+        self.forward(|cg| cg.jump(label));
+    }
+
+    fn cjump(&mut self, condition: cg::Value, label: cg::Label) {
+        self.enter("cjump");
+        let condition = self.condition(condition);
+        if let Some(info) = self.check_label(label) {
+            info.jumped = true;
+        }
+        self.forward(|cg| cg.cjump(condition.unwrap(), label));
+    }
+}
+
+impl<CG: cg::BodyCodegen> cg::BcfCodegen for Validator<CG> {
+    fn if_(&mut self, condition: cg::Value) {
+        self.enter("if_");
+        let condition = self.condition(condition);
+        self.blocks.push(BlockType::If);
+        self.forward(|cg| cg.if_(condition.unwrap()));
+    }
+
+    fn else_(&mut self) {
+        self.enter("else_");
+        match self.blocks.last_mut() {
+            Some(block @ BlockType::If) => *block = BlockType::Else,
+            _ => self.report(Violation::ElseWithoutIf),
+        }
+        self.forward(|cg| cg.else_());
+    }
+
+    fn end(&mut self) {
+        self.enter("end");
+        if self.blocks.pop().is_none() {
+            self.report(Violation::EndWithoutBlock);
+        }
+        self.forward(|cg| cg.end());
+    }
+
+    fn loop_(&mut self) {
+        self.enter("loop_");
+        self.blocks.push(BlockType::Loop);
+        self.forward(|cg| cg.loop_());
+    }
+
+    fn break_(&mut self) {
+        self.enter("break_");
+        self.in_loop();
+        self.forward(|cg| cg.break_());
+    }
+
+    fn continue_(&mut self) {
+        self.enter("continue_");
+        self.in_loop();
+        self.forward(|cg| cg.continue_());
+    }
+
+    fn cbreak(&mut self, condition: cg::Value) {
+        self.enter("cbreak");
+        let condition = self.condition(condition);
+        self.in_loop();
+        self.forward(|cg| cg.cbreak(condition.unwrap()));
+    }
+
+    fn ccontinue(&mut self, condition: cg::Value) {
+        self.enter("ccontinue");
+        let condition = self.condition(condition);
+        self.in_loop();
+        self.forward(|cg| cg.ccontinue(condition.unwrap()));
+    }
+}
@@ -14,25 +14,102 @@ pub use intrinsics::*;
 mod control_flow;
 pub use control_flow::*;
 
+mod forward;
+
+pub mod builder;
+pub use builder::Builder;
+
 /// Trait for generating code within a function.
-/// NOTE: whenever an instruction yields a value,
-/// it may be reordered or removed, until the value gets used.
 /// Use [`Self::mk_tmp`] to convert values to variables
+/// and [`Self::dup`] to use a value more than once.
+///
+/// All codegen traits are object-safe: generic code (`impl BodyCodegen`) works
+/// with a concrete backend at no cost, as well as with a `Box<dyn BodyCodegen>`
+/// (or `&mut dyn BodyCodegen`) of a backend picked at runtime.
+///
+/// # Sequencing
+/// Statements (assignments, calls without a return value, returns and control flow)
+/// are executed in the order they are generated. A value observes the state of the program
+/// at the point it was created: a call happens when [`Self::call`] is invoked, a [`Self::read`]
+/// sees every assignment generated before it, but none generated after it.
+///
+/// Backends are free to delay evaluation of values (f.e. to inline them at their use site),
+/// as long as that can't be observed. So before generating a statement that could
+/// change what a pending value observes (a write to the place it reads, a call or control flow),
+/// a backend must materialize that value first, see [`Self::flush`].
+/// Calls are evaluated in the order they were created, even when used
+/// in a single expression
 pub trait BodyCodegen: Intrinsics + AcfCodegen + BcfCodegen {
     /// Leave a comment. Mainly for source2source backends
     fn comment(&mut self, comment: &str) {
         let _ = comment;
     }
 
+    /// Set source location of the code generated after this call ([None] if unknown).
+    /// Used for debug info, f.e. `#line` directives
+    fn set_location(&mut self, location: Option<crate::attrs::SourceLocation>) {
+        let _ = location;
+    }
+
+    /// Set attributes of the loop whose header is the current position:
+    /// call right after [`BcfCodegen::loop_`] or after placing the label
+    /// a backward jump goes to (see [`AcfCodegen::label`]).
+    /// Only a hint for optimizations, so backends are free to ignore it
+    fn loop_attributes(&mut self, attrs: crate::attrs::LoopAttributes) {
+        let _ = attrs;
+    }
+
     /// Get type of the value. Takes in [`Value::0`] to not consume the value.
     /// Only applicable to unused values (rust type system will make sure)
     fn type_of(&self, id: usize) -> Type;
+    /// Get type of a variable (arguments included)
+    fn variable_type(&self, variable: Variable) -> Type;
+    /// Get type of a global symbol. For functions, this is a [`Type::FnPtr`]
+    fn global_type(&self, name: Symbol, generics: &[Type]) -> Type;
+    /// Whether a global symbol is a function or a global variable
+    /// (see [`crate::DeclarationBackend::global`]), [None] if it wasn't declared.
+    /// Never [`crate::error::SymbolKind::Type`]
+    fn symbol_kind(&self, name: Symbol, generics: &[Type]) -> Option<crate::error::SymbolKind>;
+    /// Resolve [`Type::Symbol`] aliases until the type is not an alias.
+    /// Inner types are left as is
+    fn inline_type(&self, ty: Type) -> Type;
+    /// Get type of the value stored in a [Place]. Utility function
+    fn place_type(&self, place: &Place) -> Type {
+        match place {
+            Place::Variable(variable) => self.variable_type(*variable),
+            Place::Global(name, generics) => self.global_type(*name, generics),
+            Place::Deref(value) => match self.inline_type(self.type_of(value.0)) {
+                Type::Ptr(ty, _) => *ty,
+                ty => panic!("trying to dereference non-pointer type {ty}"),
+            },
+            Place::Field(place, idx) => match self.inline_type(self.place_type(place)) {
+                Type::Struct { mut fields } => fields.swap_remove(*idx).1,
+                ty => panic!("trying to access field _{idx} on non-struct type {ty}"),
+            },
+            Place::Index(place, _) => match self.inline_type(self.place_type(place)) {
+                Type::Array(ty, _) => *ty,
+                ty => panic!("trying to index non-array type {ty}"),
+            },
+        }
+    }
 
     /// Declare a variable, see [Variable].
     /// Takes optional name (for debugging purposes),
     /// which does not have to be unique
     fn declare_var(&mut self, ty: Type, name: Option<&str>) -> Variable;
 
+    /// Start storage lifetime of a variable. Variables are live from their declaration,
+    /// so this is only needed to bring a variable back after [`Self::storage_dead`]
+    fn storage_live(&mut self, variable: Variable) {
+        let _ = variable;
+    }
+    /// End storage lifetime of a variable: until [`Self::storage_live`] is called,
+    /// the variable must not be read, written or referenced (even through a pointer).
+    /// Lets backends reuse storage of dead variables and scope their declarations
+    fn storage_dead(&mut self, variable: Variable) {
+        let _ = variable;
+    }
+
     /// Assign a value into a place, which makes it reusable
     fn assign(&mut self, target: Place, value: Value);
     /// Makes a temporary variable and assigns the value to it. Utility function
@@ -41,6 +118,21 @@ pub trait BodyCodegen: Intrinsics + AcfCodegen + BcfCodegen {
         self.assign(tmp.into(), value);
         tmp
     }
+    /// Duplicate a value, so it can be used twice. The value is evaluated only once,
+    /// both copies yield the same result. Backends can implement this as a
+    /// temporary (the default) or an SSA register, or simply copy constants
+    fn dup(&mut self, value: Value) -> (Value, Value) {
+        let tmp = self.mk_tmp(value);
+        (self.read(tmp.into()), self.read(tmp.into()))
+    }
+    /// Materialize all pending values, which are not constants, so that statements
+    /// generated after this point can't affect them (see "Sequencing" above).
+    /// Backends that evaluate values right away don't need to do anything
+    fn flush(&mut self) {}
+    /// Discard the function: nothing generated so far gets defined, and it stays undefined
+    /// when the codegen is dropped, so it can be defined again. Only dropping the codegen
+    /// is allowed afterwards. Wrappers abort the codegen they wrap
+    fn abort(&mut self);
 
     /// Signed integer constant
     fn iconst(&mut self, value: i128, size: IntegerSize) -> Value;
@@ -65,7 +157,15 @@ pub trait BodyCodegen: Intrinsics + AcfCodegen + BcfCodegen {
 
 /// Interface for generating actual code.
 /// All the items defined must be declared using [`crate::DeclarationBackend`] first.
+/// Backends usually provide an inherent method returning a concrete [`BodyCodegen`] too,
+/// this one is for using a backend as `dyn CodegenBackend`
 pub trait CodegenBackend: Sync {
-    /// Define a function
-    fn cg_function(&self, name: Symbol, generic_params: Vec<Type>) -> Box<dyn BodyCodegen>;
+    /// Define a function. Fails if the function is not declared
+    /// or this specialization is already defined.
+    /// The function is defined once the codegen is dropped
+    fn cg_function(
+        &self,
+        name: Symbol,
+        generic_params: Vec<Type>,
+    ) -> Result<Box<dyn BodyCodegen + '_>, crate::Error>;
 }
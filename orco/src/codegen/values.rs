@@ -2,12 +2,16 @@ use super::Symbol;
 
 /// Variable is a mutable storage, either in RAM or CPU registers
 /// Arguments are declared as variables before codegen, and so they
-/// can be accessed using `Variable(<zero-based argument index>)`
+/// can be accessed using `Variable(<zero-based argument index>)`.
+/// Variables are live from their declaration, see [`super::BodyCodegen::storage_dead`]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Variable(pub usize);
 
-/// Values are immutable results of operations. They can't be reused
-/// unless stored in temporary variables, see [`BodyCodegen::mk_tmp`]
+/// Values are immutable results of operations. Each value is consumed exactly once.
+/// To use a value more than once, duplicate it with [`super::BodyCodegen::dup`]
+/// (or store it in a variable, see [`super::BodyCodegen::mk_tmp`]).
+/// Copies made with [`super::BodyCodegen::dup`] share a single evaluation:
+/// side effects happen once and every copy yields the same result
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Value(pub usize);
 
@@ -22,6 +26,8 @@ pub enum Place {
     Deref(Value),
     /// Field access, using 0-based field index
     Field(Box<Place>, usize),
+    /// Array element access, index is an integer (signed or unsigned)
+    Index(Box<Place>, Value),
 }
 
 impl Place {
@@ -30,6 +36,12 @@ impl Place {
     pub fn field(self, index: usize) -> Self {
         Self::Field(Box::new(self), index)
     }
+
+    /// A helper function to create [`Self::Index`]
+    #[must_use]
+    pub fn index(self, index: Value) -> Self {
+        Self::Index(Box::new(self), index)
+    }
 }
 
 impl From<Variable> for Place {
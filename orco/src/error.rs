@@ -0,0 +1,90 @@
+use crate::{Symbol, Type};
+
+/// Kind of a symbol, for error reporting
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SymbolKind {
+    #[allow(missing_docs)]
+    Function,
+    /// Type alias
+    Type,
+    /// Global variable
+    Global,
+}
+
+impl std::fmt::Display for SymbolKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SymbolKind::Function => write!(f, "function"),
+            SymbolKind::Type => write!(f, "type"),
+            SymbolKind::Global => write!(f, "global"),
+        }
+    }
+}
+
+/// An error reported by a backend when declaring or defining an item.
+/// The item in question is skipped, so a frontend can report the error and keep going
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Error {
+    /// A symbol (or a specialization of it) was declared more than once
+    AlreadyDeclared(SymbolKind, Symbol, Vec<Type>),
+    /// A function (or a specialization of it) was defined more than once
+    AlreadyDefined(Symbol, Vec<Type>),
+    /// A symbol is used, but was never declared
+    Undeclared(SymbolKind, Symbol),
+    /// None of the declared specializations match the generics
+    NoSpecialization(SymbolKind, Symbol, Vec<Type>),
+    /// A type contains itself (not through a pointer), so it would be infinitely big
+    DependencyCycle(Symbol),
+    /// Generating code of a function (with generics) went wrong, so it was left undefined.
+    /// F.e. children of [`crate::codegen::impls::Multiplexer`] disagreed on a type
+    Codegen(Symbol, Vec<Type>, String),
+    /// Several errors about the same item,
+    /// f.e. from different children of [`crate::codegen::impls::Multiplexer`]
+    Multiple(Vec<Error>),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use crate::types::fmt_generics;
+        match self {
+            Error::AlreadyDeclared(kind, name, generics) => {
+                write!(
+                    f,
+                    "{kind} {name}{} is already declared",
+                    fmt_generics(generics)
+                )
+            }
+            Error::AlreadyDefined(name, generics) => {
+                write!(
+                    f,
+                    "function {name}{} is already defined",
+                    fmt_generics(generics)
+                )
+            }
+            Error::Undeclared(kind, name) => write!(f, "undeclared {kind} {name}"),
+            Error::NoSpecialization(kind, name, generics) => write!(
+                f,
+                "no matching specialization for {kind} {name}{}",
+                fmt_generics(generics)
+            ),
+            Error::DependencyCycle(name) => write!(
+                f,
+                "type dependency cycle detected on {name}, possibly an infinitely-recursive type"
+            ),
+            Error::Codegen(name, generics, message) => {
+                write!(f, "function {name}{}: {message}", fmt_generics(generics))
+            }
+            Error::Multiple(errors) => {
+                for (idx, err) in errors.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{err}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
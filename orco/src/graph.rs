@@ -0,0 +1,319 @@
+//! Generic control flow graph analyses: traversal order, dominators and natural loops.
+//! Used by [`crate::codegen::impls::AcfToBcf`] and backends that optimize code themselves
+
+/// A directed graph with nodes `0..node_count()`.
+/// Successors outside of that range (f.e. a virtual exit node) are ignored
+pub trait Graph {
+    /// Number of nodes
+    fn node_count(&self) -> usize;
+
+    /// Nodes reachable through one edge from `node`
+    fn successors(&self, node: usize) -> &[usize];
+}
+
+/// Successor lists, indexed by node
+impl<T: AsRef<[usize]>> Graph for [T] {
+    fn node_count(&self) -> usize {
+        self.len()
+    }
+
+    fn successors(&self, node: usize) -> &[usize] {
+        self[node].as_ref()
+    }
+}
+
+/// Predecessors of every node, see [`Graph`]
+#[must_use]
+pub fn predecessors(graph: &(impl Graph + ?Sized)) -> Vec<Vec<usize>> {
+    let mut predecessors = vec![Vec::new(); graph.node_count()];
+    for node in 0..graph.node_count() {
+        for &successor in graph.successors(node) {
+            if successor < graph.node_count() {
+                predecessors[successor].push(node);
+            }
+        }
+    }
+    predecessors
+}
+
+/// Nodes reachable from the entry, in reverse postorder
+/// (every node comes before its successors, unless the edge is a back edge)
+#[must_use]
+pub fn reverse_postorder(graph: &(impl Graph + ?Sized), entry: usize) -> Vec<usize> {
+    let mut order = Vec::with_capacity(graph.node_count());
+    if entry >= graph.node_count() {
+        return order;
+    }
+    let mut visited = vec![false; graph.node_count()];
+    // Node and the index of the next successor to visit
+    let mut stack = vec![(entry, 0)];
+    visited[entry] = true;
+    while let Some((node, next)) = stack.last_mut() {
+        match graph.successors(*node).get(*next) {
+            Some(&successor) => {
+                *next += 1;
+                if successor < graph.node_count()
+                    && !std::mem::replace(&mut visited[successor], true)
+                {
+                    stack.push((successor, 0));
+                }
+            }
+            None => {
+                order.push(*node);
+                stack.pop();
+            }
+        }
+    }
+    order.reverse();
+    order
+}
+
+/// Dominator tree: node `a` dominates node `b`
+/// if every path from the entry to `b` goes through `a`.
+/// Computed with the algorithm by Cooper, Harvey and Kennedy
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dominators {
+    /// Immediate dominator of each node, the entry is its own.
+    /// [None] for unreachable nodes
+    idom: Vec<Option<usize>>,
+}
+
+impl Dominators {
+    #[allow(missing_docs)]
+    #[must_use]
+    pub fn new(graph: &(impl Graph + ?Sized), entry: usize) -> Self {
+        let mut idom = vec![None; graph.node_count()];
+        let order = reverse_postorder(graph, entry);
+        if order.is_empty() {
+            return Self { idom };
+        }
+        let mut position = vec![usize::MAX; graph.node_count()];
+        for (idx, node) in order.iter().enumerate() {
+            position[*node] = idx;
+        }
+        let predecessors = predecessors(graph);
+
+        idom[entry] = Some(entry);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &node in &order[1..] {
+                let mut new_idom = None;
+                for &predecessor in &predecessors[node] {
+                    if idom[predecessor].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => predecessor,
+                        Some(mut other) => {
+                            // Walk both up the tree until they meet
+                            let mut predecessor = predecessor;
+                            while predecessor != other {
+                                while position[predecessor] > position[other] {
+                                    predecessor = idom[predecessor].unwrap();
+                                }
+                                while position[other] > position[predecessor] {
+                                    other = idom[other].unwrap();
+                                }
+                            }
+                            other
+                        }
+                    });
+                }
+                if idom[node] != new_idom {
+                    idom[node] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        Self { idom }
+    }
+
+    /// Immediate dominator of a node, [None] for the entry and unreachable nodes
+    #[must_use]
+    pub fn immediate_dominator(&self, node: usize) -> Option<usize> {
+        self.idom[node].filter(|idom| *idom != node)
+    }
+
+    /// Whether control can reach the node from the entry
+    #[must_use]
+    pub fn is_reachable(&self, node: usize) -> bool {
+        self.idom[node].is_some()
+    }
+
+    /// Whether `a` dominates `b`. Every node dominates itself,
+    /// unreachable nodes are dominated by nothing
+    #[must_use]
+    pub fn dominates(&self, a: usize, mut b: usize) -> bool {
+        if !self.is_reachable(b) {
+            return false;
+        }
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.immediate_dominator(b) {
+                Some(idom) => b = idom,
+                None => return false,
+            }
+        }
+    }
+
+    /// Whether every cycle has a header dominating the rest of it, so all cycles
+    /// are natural loops: every edge going back in depth-first order
+    /// leads to a node that dominates its source
+    #[must_use]
+    pub fn is_reducible(&self, graph: &(impl Graph + ?Sized), entry: usize) -> bool {
+        if entry >= graph.node_count() {
+            return true;
+        }
+        let mut visited = vec![false; graph.node_count()];
+        let mut on_stack = vec![false; graph.node_count()];
+        let mut stack = vec![(entry, 0)];
+        visited[entry] = true;
+        on_stack[entry] = true;
+        while let Some((node, next)) = stack.last_mut() {
+            let node = *node;
+            let Some(&successor) = graph.successors(node).get(*next) else {
+                on_stack[node] = false;
+                stack.pop();
+                continue;
+            };
+            *next += 1;
+            if successor >= graph.node_count() {
+                continue;
+            }
+            if on_stack[successor] {
+                if !self.dominates(successor, node) {
+                    return false;
+                }
+            } else if !std::mem::replace(&mut visited[successor], true) {
+                on_stack[successor] = true;
+                stack.push((successor, 0));
+            }
+        }
+        true
+    }
+}
+
+/// Natural loop: a header node, which dominates the rest of the loop,
+/// and nodes that can reach a back edge to the header without going through it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Loop {
+    /// The only node control enters the loop through
+    pub header: usize,
+    /// Nodes with a back edge to the header
+    pub latches: Vec<usize>,
+    /// All nodes of the loop, including the header and nested loops, sorted
+    pub nodes: Vec<usize>,
+    /// Innermost loop containing this one, index into [`Loops::loops`]
+    pub parent: Option<usize>,
+}
+
+impl Loop {
+    #[allow(missing_docs)]
+    #[must_use]
+    pub fn contains(&self, node: usize) -> bool {
+        self.nodes.binary_search(&node).is_ok()
+    }
+
+    /// Edges leaving the loop: node inside and node outside
+    /// (can be outside of the graph, see [`Graph`])
+    #[must_use]
+    pub fn exit_edges(&self, graph: &(impl Graph + ?Sized)) -> Vec<(usize, usize)> {
+        self.nodes
+            .iter()
+            .flat_map(|node| {
+                graph
+                    .successors(*node)
+                    .iter()
+                    .filter(|successor| !self.contains(**successor))
+                    .map(|successor| (*node, *successor))
+            })
+            .collect()
+    }
+}
+
+/// All natural loops of a graph. Loops sharing a header are merged.
+/// Irreducible cycles (without a dominating header) are not loops,
+/// see [`Dominators::is_reducible`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Loops {
+    /// Outer loops come before loops nested in them
+    pub loops: Vec<Loop>,
+    /// Innermost loop of each node
+    innermost: Vec<Option<usize>>,
+}
+
+impl Loops {
+    #[allow(missing_docs)]
+    #[must_use]
+    pub fn new(graph: &(impl Graph + ?Sized), dominators: &Dominators) -> Self {
+        let mut loops = Vec::<Loop>::new();
+        for node in 0..graph.node_count() {
+            for &header in graph.successors(node) {
+                if header >= graph.node_count() || !dominators.dominates(header, node) {
+                    continue;
+                }
+                match loops.iter_mut().find(|other| other.header == header) {
+                    Some(other) => other.latches.push(node),
+                    None => loops.push(Loop {
+                        header,
+                        latches: vec![node],
+                        nodes: Vec::new(),
+                        parent: None,
+                    }),
+                }
+            }
+        }
+
+        let predecessors = predecessors(graph);
+        for data in &mut loops {
+            let mut nodes = vec![false; graph.node_count()];
+            nodes[data.header] = true;
+            let mut worklist = data.latches.clone();
+            while let Some(node) = worklist.pop() {
+                if std::mem::replace(&mut nodes[node], true) {
+                    continue;
+                }
+                worklist.extend(
+                    predecessors[node]
+                        .iter()
+                        .filter(|predecessor| dominators.is_reachable(**predecessor)),
+                );
+            }
+            data.nodes = (0..graph.node_count())
+                .filter(|node| nodes[*node])
+                .collect();
+        }
+
+        // Loops contain each other entirely or not at all, so bigger ones are outer
+        loops.sort_by_key(|data| std::cmp::Reverse(data.nodes.len()));
+        let mut innermost = vec![None; graph.node_count()];
+        for idx in 0..loops.len() {
+            loops[idx].parent = innermost[loops[idx].header];
+            for node in &loops[idx].nodes {
+                innermost[*node] = Some(idx);
+            }
+        }
+        Self { loops, innermost }
+    }
+
+    /// Loop with the given header, if there is one
+    #[must_use]
+    pub fn with_header(&self, header: usize) -> Option<&Loop> {
+        self.loops.iter().find(|data| data.header == header)
+    }
+
+    /// Innermost loop a node is in, index into [`Self::loops`]
+    #[must_use]
+    pub fn innermost(&self, node: usize) -> Option<usize> {
+        self.innermost[node]
+    }
+
+    /// Number of loops a node is in
+    #[must_use]
+    pub fn depth(&self, node: usize) -> usize {
+        std::iter::successors(self.innermost(node), |idx| self.loops[*idx].parent).count()
+    }
+}
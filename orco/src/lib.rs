@@ -2,7 +2,10 @@
 #![doc = include_str!("../../README.md")]
 
 pub use sinter;
-pub use sinter::IStr as Symbol;
+
+/// Structured symbol paths
+pub mod symbol;
+pub use symbol::Symbol;
 
 /// Code generation, outside of declaration
 pub mod codegen;
@@ -15,13 +18,21 @@ pub use types::Type;
 /// Attributes are a way to pass information about symbols to the backend
 pub mod attrs;
 
+/// Errors reported by backends
+pub mod error;
+
+/// Dominators and natural loops of control flow graphs
+pub mod graph;
+pub use error::Error;
+
 /// Declare items before defining them.
 /// Think of it as an interface to generate C headers (uh oh generics...).
 /// For adding generic params, see [`Type::Param`]
 pub trait DeclarationBackend {
     /// Declare a function (does not have to be defined within this linker unit).
     /// Set `return_type` to [None] if require no return value.
-    /// Specializations declared during codegen
+    /// Specializations declared during codegen.
+    /// Fails if the function (or this specialization) is already declared
     fn function(
         &self,
         name: Symbol,
@@ -29,9 +40,61 @@ pub trait DeclarationBackend {
         params: Vec<(Option<String>, Type)>,
         return_type: Option<Type>,
         attrs: attrs::FunctionAttributes,
-    );
+    ) -> Result<(), Error>;
 
     /// Declre a type alias, can be used to declare compound types as well.
-    /// Specializations declared using this function as well
-    fn type_(&self, name: Symbol, generic_params: Vec<Type>, ty: Type);
+    /// Specializations declared using this function as well.
+    /// Fails if the type (or this specialization) is already declared
+    fn type_(&self, name: Symbol, generic_params: Vec<Type>, ty: Type) -> Result<(), Error>;
+
+    /// Declare a zero-initialized global variable, accessed with [`codegen::Place::Global`].
+    /// Fails if the symbol is already declared
+    fn global(&self, name: Symbol, ty: Type) -> Result<(), Error>;
+}
+
+/// A backend that can both declare and define items,
+/// so it can be used as `dyn Backend`
+pub trait Backend: DeclarationBackend + CodegenBackend {}
+impl<B: DeclarationBackend + CodegenBackend + ?Sized> Backend for B {}
+
+impl<B: DeclarationBackend + ?Sized> DeclarationBackend for &B {
+    fn function(
+        &self,
+        name: Symbol,
+        generic_params: Vec<Type>,
+        params: Vec<(Option<String>, Type)>,
+        return_type: Option<Type>,
+        attrs: attrs::FunctionAttributes,
+    ) -> Result<(), Error> {
+        (**self).function(name, generic_params, params, return_type, attrs)
+    }
+
+    fn type_(&self, name: Symbol, generic_params: Vec<Type>, ty: Type) -> Result<(), Error> {
+        (**self).type_(name, generic_params, ty)
+    }
+
+    fn global(&self, name: Symbol, ty: Type) -> Result<(), Error> {
+        (**self).global(name, ty)
+    }
+}
+
+impl<B: DeclarationBackend + ?Sized> DeclarationBackend for Box<B> {
+    fn function(
+        &self,
+        name: Symbol,
+        generic_params: Vec<Type>,
+        params: Vec<(Option<String>, Type)>,
+        return_type: Option<Type>,
+        attrs: attrs::FunctionAttributes,
+    ) -> Result<(), Error> {
+        (**self).function(name, generic_params, params, return_type, attrs)
+    }
+
+    fn type_(&self, name: Symbol, generic_params: Vec<Type>, ty: Type) -> Result<(), Error> {
+        (**self).type_(name, generic_params, ty)
+    }
+
+    fn global(&self, name: Symbol, ty: Type) -> Result<(), Error> {
+        (**self).global(name, ty)
+    }
 }
@@ -0,0 +1,512 @@
+//! Symbols are interned [Path]s: cheap to copy, compare and hash.
+//! A symbol with only a name (f.e. `"printf".into()`) is "bare",
+//! backends should use bare names as is, so foreign items can be linked against.
+//! Everything else can be namespaced by backends that support it (C++, Rust) or mangled,
+//! see [`Symbol::mangle`]
+use crate::Type;
+use crate::types::IntegerSize;
+use sinter::IStr;
+
+/// Structured path of an item: `krate::segments::name::<generics>#disambiguator`
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Path {
+    /// Crate (library) the item is defined in
+    pub krate: Option<IStr>,
+    /// Module (namespace) path, outermost first
+    pub segments: Vec<IStr>,
+    /// Name of the item itself
+    pub name: IStr,
+    /// Generic args baked into the symbol, f.e. `Self` type of a trait method implementation.
+    /// Not to be confused with generic params of a declaration, which are passed separately
+    pub generics: Vec<Type>,
+    /// Tells apart items which would share the path otherwise
+    pub disambiguator: u32,
+}
+
+impl Path {
+    /// Bare path, just a name
+    #[must_use]
+    pub fn bare(name: impl Into<IStr>) -> Self {
+        Self {
+            krate: None,
+            segments: Vec::new(),
+            name: name.into(),
+            generics: Vec::new(),
+            disambiguator: 0,
+        }
+    }
+
+    /// Is this path only a name, see module docs
+    #[must_use]
+    pub fn is_bare(&self) -> bool {
+        self.krate.is_none()
+            && self.segments.is_empty()
+            && self.generics.is_empty()
+            && self.disambiguator == 0
+    }
+}
+
+/// An interned [Path]
+#[derive(Clone, Copy)]
+pub struct Symbol(&'static Path);
+
+static INTERNER: std::sync::LazyLock<std::sync::Mutex<std::collections::HashSet<&'static Path>>> =
+    std::sync::LazyLock::new(Default::default);
+
+impl Symbol {
+    /// Intern a path
+    #[must_use]
+    pub fn new(path: Path) -> Self {
+        let mut interner = INTERNER.lock().unwrap();
+        if let Some(path) = interner.get(&path) {
+            return Self(path);
+        }
+        let path = Box::leak(Box::new(path));
+        interner.insert(path);
+        Self(path)
+    }
+
+    #[allow(missing_docs)]
+    #[must_use]
+    pub fn path(self) -> &'static Path {
+        self.0
+    }
+
+    /// Same symbol with `generics` appended to its generic args
+    #[must_use]
+    pub fn with_generics(self, generics: &[Type]) -> Self {
+        if generics.is_empty() {
+            return self;
+        }
+        let mut path = self.0.clone();
+        path.generics.extend_from_slice(generics);
+        Self::new(path)
+    }
+
+    /// Mangle this symbol into a C identifier. Bare names are returned as is,
+    /// unless they start with `_O` themselves: those are mangled like any other path,
+    /// so they can't be mistaken for a mangled name.
+    /// Mangling is collision-free and can be reversed with [`Self::demangle`].
+    ///
+    /// Mangled names start with `_O`, followed by `C` and the crate name (if any),
+    /// then the segments and the name, then generic args (`G` ... `E`)
+    /// and the disambiguator (`D` number `_`), if any. Identifiers are
+    /// length-prefixed, with `_` escaped as `__` and other non-alphanumeric bytes
+    /// (and a leading digit) as `_` and two hex digits
+    #[must_use]
+    pub fn mangle(self) -> String {
+        if self.0.is_bare() && !self.0.name.starts_with("_O") {
+            return self.0.name.to_string();
+        }
+        let mut out = String::from("_O");
+        mangle_path(self.0, &mut out);
+        out
+    }
+
+    /// Reverse [`Self::mangle`]. Names that are not mangled are treated as bare
+    #[must_use]
+    pub fn demangle(name: &str) -> Option<Self> {
+        let Some(mangled) = name.strip_prefix("_O") else {
+            return Some(name.into());
+        };
+        let mut demangler = Demangler(mangled.as_bytes());
+        let path = demangler.path()?;
+        demangler.0.is_empty().then(|| Self::new(path))
+    }
+}
+
+impl std::ops::Deref for Symbol {
+    type Target = Path;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl std::hash::Hash for Symbol {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self.0, state);
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.cmp(other.0)
+    }
+}
+
+impl From<Path> for Symbol {
+    fn from(value: Path) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(value: &str) -> Self {
+        Self::new(Path::bare(value))
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(value: String) -> Self {
+        Self::new(Path::bare(value))
+    }
+}
+
+/// Words that have a meaning of their own in textual representations
+/// (types and orco-ir), so identifiers spelled like them are quoted
+const RESERVED: &[&str] = &[
+    "bool",
+    "achar",
+    "uchar",
+    "void",
+    "isize",
+    "usize",
+    "true",
+    "false",
+    "let",
+    "fn",
+    "type",
+    "static",
+    "for",
+    "return",
+    "if",
+    "else",
+    "loop",
+    "break",
+    "continue",
+    "jump",
+    "location",
+    "storage_live",
+    "storage_dead",
+];
+
+/// Whether an identifier can be written as is: `[A-Za-z_$][A-Za-z0-9_$]*`,
+/// not a reserved word, a primitive type (f.e. `i32`), a variable (f.e. `_0`)
+/// or a label (f.e. `label0`)
+#[must_use]
+pub fn is_plain_ident(ident: &str) -> bool {
+    let mut chars = ident.chars();
+    if !chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+    {
+        return false;
+    }
+    if !chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$') {
+        return false;
+    }
+    let numbered = |prefix: &str| {
+        ident
+            .strip_prefix(prefix)
+            .is_some_and(|rest| !rest.is_empty() && rest.bytes().all(|b| b.is_ascii_digit()))
+    };
+    !RESERVED.contains(&ident) && !["i", "u", "f", "_", "label"].into_iter().any(numbered)
+}
+
+/// Display an identifier, quoting (with Rust escapes) it unless it's plain,
+/// see [`is_plain_ident`]
+pub struct Ident<'a>(pub &'a str);
+
+impl std::fmt::Display for Ident<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if is_plain_ident(self.0) {
+            write!(f, "{}", self.0)
+        } else {
+            write!(f, "{:?}", self.0)
+        }
+    }
+}
+
+/// Paths are displayed as `krate::segments::name::<generics>#disambiguator`,
+/// paths without a crate, but with segments start with `::`.
+/// Identifiers are quoted if needed (see [Ident]), so the output can be parsed back
+impl std::fmt::Display for Path {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.krate.is_none() && !self.segments.is_empty() {
+            write!(f, "::")?;
+        }
+        for segment in self.krate.iter().chain(&self.segments) {
+            write!(f, "{}::", Ident(segment))?;
+        }
+        write!(f, "{}", Ident(&self.name))?;
+        if !self.generics.is_empty() {
+            write!(f, "::{}", crate::types::fmt_generics(&self.generics))?;
+        }
+        if self.disambiguator != 0 {
+            write!(f, "#{}", self.disambiguator)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}`", self.0)
+    }
+}
+
+fn mangle_ident(ident: &str, out: &mut String) {
+    use std::fmt::Write as _;
+    let mut encoded = String::with_capacity(ident.len());
+    for (idx, byte) in ident.bytes().enumerate() {
+        match byte {
+            b'_' => encoded.push_str("__"),
+            b'0'..=b'9' if idx == 0 => write!(encoded, "_{byte:02x}").unwrap(),
+            byte if byte.is_ascii_alphanumeric() => encoded.push(byte as char),
+            byte => write!(encoded, "_{byte:02x}").unwrap(),
+        }
+    }
+    write!(out, "{}{encoded}", encoded.len()).unwrap();
+}
+
+fn mangle_path(path: &Path, out: &mut String) {
+    if let Some(krate) = path.krate {
+        out.push('C');
+        mangle_ident(&krate, out);
+    }
+    for segment in &path.segments {
+        mangle_ident(segment, out);
+    }
+    mangle_ident(&path.name, out);
+    if !path.generics.is_empty() {
+        out.push('G');
+        mangle_types(&path.generics, out);
+    }
+    if path.disambiguator != 0 {
+        out.push_str(&format!("D{}_", path.disambiguator));
+    }
+    out.push('E');
+}
+
+fn mangle_types(types: &[Type], out: &mut String) {
+    for ty in types {
+        mangle_type(ty, out);
+    }
+    out.push('E');
+}
+
+fn mangle_type(ty: &Type, out: &mut String) {
+    let size = |size: &IntegerSize| match size {
+        IntegerSize::Bits(bits) => format!("{bits}_"),
+        IntegerSize::Size => "z".to_owned(),
+    };
+    match ty {
+        Type::Integer(sz) => out.push_str(&format!("i{}", size(sz))),
+        Type::Unsigned(sz) => out.push_str(&format!("u{}", size(sz))),
+        Type::Float(bits) => out.push_str(&format!("f{bits}_")),
+        Type::Bool => out.push('b'),
+        Type::Char(false) => out.push('c'),
+        Type::Char(true) => out.push('w'),
+        Type::Symbol(name, generics) => {
+            out.push('S');
+            mangle_path(name, out);
+            mangle_types(generics, out);
+        }
+        Type::Array(ty, len) => {
+            out.push_str(&format!("A{len}_"));
+            mangle_type(ty, out);
+        }
+        Type::Struct { fields } => {
+            out.push('T');
+            for (name, ty) in fields {
+                if let Some(name) = name {
+                    out.push('n');
+                    mangle_ident(name, out);
+                }
+                mangle_type(ty, out);
+            }
+            out.push('E');
+        }
+        Type::Ptr(ty, mutable) => {
+            out.push(if *mutable { 'M' } else { 'P' });
+            mangle_type(ty, out);
+        }
+        Type::FnPtr {
+            params,
+            return_type,
+        } => {
+            out.push('F');
+            mangle_types(params, out);
+            match return_type {
+                Some(ty) => mangle_type(ty, out),
+                None => out.push('v'),
+            }
+        }
+        Type::Param(name) => {
+            out.push('p');
+            mangle_ident(name, out);
+        }
+        Type::Error => out.push('x'),
+    }
+}
+
+struct Demangler<'a>(&'a [u8]);
+
+impl Demangler<'_> {
+    fn eat(&mut self, byte: u8) -> bool {
+        if self.0.first() == Some(&byte) {
+            self.0 = &self.0[1..];
+            true
+        } else {
+            false
+        }
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.0.split_first()?;
+        self.0 = rest;
+        Some(byte)
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Option<T> {
+        let len = self.0.iter().take_while(|c| c.is_ascii_digit()).count();
+        let (digits, rest) = self.0.split_at(len);
+        self.0 = rest;
+        std::str::from_utf8(digits).ok()?.parse().ok()
+    }
+
+    fn ident(&mut self) -> Option<IStr> {
+        let len: usize = self.number()?;
+        if len > self.0.len() {
+            return None;
+        }
+        let (encoded, rest) = self.0.split_at(len);
+        self.0 = rest;
+
+        let mut ident = Vec::with_capacity(len);
+        let mut encoded = encoded.iter();
+        while let Some(&byte) = encoded.next() {
+            if byte != b'_' {
+                ident.push(byte);
+            } else if encoded.as_slice().first() == Some(&b'_') {
+                encoded.next();
+                ident.push(b'_');
+            } else {
+                let hex = std::str::from_utf8(encoded.as_slice().get(..2)?).ok()?;
+                ident.push(u8::from_str_radix(hex, 16).ok()?);
+                encoded.nth(1);
+            }
+        }
+        Some(String::from_utf8(ident).ok()?.into())
+    }
+
+    fn path(&mut self) -> Option<Path> {
+        let krate = if self.eat(b'C') {
+            Some(self.ident()?)
+        } else {
+            None
+        };
+        let mut segments = Vec::new();
+        while self.0.first().is_some_and(u8::is_ascii_digit) {
+            segments.push(self.ident()?);
+        }
+        let name = segments.pop()?;
+        let generics = if self.eat(b'G') {
+            self.types()?
+        } else {
+            Vec::new()
+        };
+        let disambiguator = if self.eat(b'D') {
+            let disambiguator = self.number()?;
+            self.eat(b'_').then_some(disambiguator)?
+        } else {
+            0
+        };
+        self.eat(b'E').then_some(Path {
+            krate,
+            segments,
+            name,
+            generics,
+            disambiguator,
+        })
+    }
+
+    fn types(&mut self) -> Option<Vec<Type>> {
+        let mut types = Vec::new();
+        while !self.eat(b'E') {
+            types.push(self.type_()?);
+        }
+        Some(types)
+    }
+
+    fn size(&mut self) -> Option<IntegerSize> {
+        if self.eat(b'z') {
+            return Some(IntegerSize::Size);
+        }
+        let bits = self.number()?;
+        self.eat(b'_').then_some(IntegerSize::Bits(bits))
+    }
+
+    fn type_(&mut self) -> Option<Type> {
+        Some(match self.next()? {
+            b'i' => Type::Integer(self.size()?),
+            b'u' => Type::Unsigned(self.size()?),
+            b'f' => {
+                let bits = self.number()?;
+                self.eat(b'_').then_some(Type::Float(bits))?
+            }
+            b'b' => Type::Bool,
+            b'c' => Type::Char(false),
+            b'w' => Type::Char(true),
+            b'S' => {
+                let name = Symbol::new(self.path()?);
+                Type::Symbol(name, self.types()?)
+            }
+            b'A' => {
+                let len = self.number()?;
+                self.eat(b'_').then_some(())?;
+                Type::Array(Box::new(self.type_()?), len)
+            }
+            b'T' => {
+                let mut fields = Vec::new();
+                while !self.eat(b'E') {
+                    let name = if self.eat(b'n') {
+                        Some(self.ident()?.to_string())
+                    } else {
+                        None
+                    };
+                    fields.push((name, self.type_()?));
+                }
+                Type::Struct { fields }
+            }
+            b'P' => Type::Ptr(Box::new(self.type_()?), false),
+            b'M' => Type::Ptr(Box::new(self.type_()?), true),
+            b'F' => {
+                let params = self.types()?;
+                let return_type = if self.eat(b'v') {
+                    None
+                } else {
+                    Some(Box::new(self.type_()?))
+                };
+                Type::FnPtr {
+                    params,
+                    return_type,
+                }
+            }
+            b'p' => Type::Param(self.ident()?),
+            b'x' => Type::Error,
+            _ => return None,
+        })
+    }
+}
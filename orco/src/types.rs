@@ -1,5 +1,8 @@
 use super::Symbol;
 
+mod interned;
+pub use interned::{IType, TypeKind};
+
 /// Type of a variable, constant, part of a function signature, etc.
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Type {
@@ -33,14 +36,18 @@ pub enum Type {
         return_type: Option<Box<Type>>,
     },
     /// Type parameter (aka generic)
-    Param(Symbol),
+    Param(sinter::IStr),
     /// An error type, can also be used in a pointer to make it a pointer to anything
     Error,
 }
 
 impl Type {
-    /// Replace all instances of [`Type::Param`] with symbols from `map` (if present)
-    pub fn instantiate(&mut self, map: &std::collections::HashMap<Symbol, Type>) {
+    /// Replace all instances of [`Type::Param`] with types from `map` (if present).
+    /// The map can hold [Type]s or [IType]s
+    pub fn instantiate<T: Clone + Into<Type>>(
+        &mut self,
+        map: &std::collections::HashMap<sinter::IStr, T>,
+    ) {
         match self {
             Type::Integer(..)
             | Type::Unsigned(..)
@@ -74,7 +81,7 @@ impl Type {
             }
             Type::Param(name) => {
                 if let Some(ty) = map.get(name) {
-                    ty.clone_into(self);
+                    *self = ty.clone().into();
                 }
             }
             Type::Error => (),
@@ -82,7 +89,10 @@ impl Type {
     }
 
     /// Same as [`Self::instantiate`], but clones the type in the process
-    pub fn copy_instantiate(&self, map: &std::collections::HashMap<Symbol, Type>) -> Self {
+    pub fn copy_instantiate<T: Clone + Into<Type>>(
+        &self,
+        map: &std::collections::HashMap<sinter::IStr, T>,
+    ) -> Self {
         let mut instance = self.clone();
         instance.instantiate(map);
         instance
@@ -142,6 +152,10 @@ impl std::fmt::Display for Type {
             Type::Char(true) => write!(f, "uchar"),
 
             Type::Symbol(sym, generics) => write!(f, "{sym}{}", fmt_generics(generics)),
+            // `*mut T[4]` would be ambiguous
+            Type::Array(ty, len) if matches!(**ty, Type::Ptr(..) | Type::FnPtr { .. }) => {
+                write!(f, "({ty})[{len}]")
+            }
             Type::Array(ty, len) => write!(f, "{ty}[{len}]"),
             Type::Struct { fields } => {
                 write!(f, "{{{}", if f.alternate() { '\n' } else { ' ' })?;
@@ -153,7 +167,7 @@ impl std::fmt::Display for Type {
                     }
 
                     match name {
-                        Some(name) => write!(f, "{name}: ")?,
+                        Some(name) => write!(f, "{}: ", crate::symbol::Ident(name))?,
                         None if f.alternate() => write!(f, "_{idx}: ")?,
                         None => (),
                     }
@@ -199,14 +213,14 @@ impl std::fmt::Display for Type {
                     None => write!(f, ") -> void"),
                 }
             }
-            Type::Param(name) => write!(f, "#{name}"),
+            Type::Param(name) => write!(f, "#{}", crate::symbol::Ident(name)),
             Type::Error => write!(f, "<error>"),
         }
     }
 }
 
 /// Format generic args using <> notation
-pub fn fmt_generics(generics: &[Type]) -> String {
+pub fn fmt_generics(generics: &[impl std::fmt::Display]) -> String {
     if generics.is_empty() {
         return String::new();
     }
@@ -277,7 +291,10 @@ impl FunctionSignature {
     }
 
     /// See [Type::instantiate]
-    pub fn instantiate(&mut self, map: &std::collections::HashMap<Symbol, Type>) {
+    pub fn instantiate<T: Clone + Into<Type>>(
+        &mut self,
+        map: &std::collections::HashMap<sinter::IStr, T>,
+    ) {
         for (_, ty) in &mut self.params {
             ty.instantiate(map);
         }
@@ -297,7 +314,7 @@ impl std::fmt::Display for FunctionSignature {
             }
 
             match name {
-                Some(name) => write!(f, "{name:}: ")?,
+                Some(name) => write!(f, "{}: ", crate::symbol::Ident(name))?,
                 None => write!(f, "_{idx}: ")?,
             }
 
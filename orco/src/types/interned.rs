@@ -0,0 +1,240 @@
+use super::{IntegerSize, Type};
+use crate::Symbol;
+use sinter::IStr;
+use std::collections::HashMap;
+
+/// Hash-consed [Type]: a cheap copyable handle, compared and hashed in O(1).
+/// Inner types are interned as well, so converting, matching or instantiating
+/// a type never clones the whole tree
+#[derive(Clone, Copy)]
+pub struct IType(&'static Node);
+
+/// A single level of an interned type, inner types are [`IType`]s.
+/// See [Type] for variant docs
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[allow(missing_docs)]
+pub enum TypeKind {
+    Integer(IntegerSize),
+    Unsigned(IntegerSize),
+    Float(u16),
+    Bool,
+    Char(bool),
+    Symbol(Symbol, Vec<IType>),
+    Array(IType, usize),
+    Struct {
+        fields: Vec<(Option<IStr>, IType)>,
+    },
+    Ptr(IType, bool),
+    FnPtr {
+        params: Vec<IType>,
+        return_type: Option<IType>,
+    },
+    Param(IStr),
+    Error,
+}
+
+struct Node {
+    kind: TypeKind,
+    has_params: bool,
+}
+
+static INTERNER: std::sync::LazyLock<
+    std::sync::Mutex<std::collections::HashMap<&'static TypeKind, IType>>,
+> = std::sync::LazyLock::new(Default::default);
+
+impl IType {
+    /// Intern a type
+    #[must_use]
+    pub fn new(kind: TypeKind) -> Self {
+        let mut interner = INTERNER.lock().unwrap();
+        if let Some(ty) = interner.get(&kind) {
+            return *ty;
+        }
+
+        let has_params = match &kind {
+            TypeKind::Integer(..)
+            | TypeKind::Unsigned(..)
+            | TypeKind::Float(..)
+            | TypeKind::Bool
+            | TypeKind::Char(..)
+            | TypeKind::Error => false,
+            TypeKind::Symbol(_, generics) => generics.iter().any(|ty| ty.has_params()),
+            TypeKind::Array(ty, _) | TypeKind::Ptr(ty, _) => ty.has_params(),
+            TypeKind::Struct { fields } => fields.iter().any(|(_, ty)| ty.has_params()),
+            TypeKind::FnPtr {
+                params,
+                return_type,
+            } => params.iter().chain(return_type).any(|ty| ty.has_params()),
+            TypeKind::Param(..) => true,
+        };
+        let node: &'static Node = Box::leak(Box::new(Node { kind, has_params }));
+        interner.insert(&node.kind, Self(node));
+        Self(node)
+    }
+
+    #[allow(missing_docs)]
+    #[must_use]
+    pub fn kind(self) -> &'static TypeKind {
+        &self.0.kind
+    }
+
+    /// Check if this type contains type params, O(1)
+    #[must_use]
+    pub fn has_params(self) -> bool {
+        self.0.has_params
+    }
+
+    /// Convert back to a [Type]
+    #[must_use]
+    pub fn to_type(self) -> Type {
+        self.into()
+    }
+
+    /// Replace all instances of [`TypeKind::Param`] with types from `map` (if present).
+    /// Types without params are returned right away
+    #[must_use]
+    pub fn instantiate(self, map: &HashMap<IStr, IType>) -> Self {
+        if !self.has_params() {
+            return self;
+        }
+
+        let all = |types: &[IType]| types.iter().map(|ty| ty.instantiate(map)).collect();
+        Self::new(match self.kind() {
+            TypeKind::Symbol(name, generics) => TypeKind::Symbol(*name, all(generics)),
+            TypeKind::Array(ty, len) => TypeKind::Array(ty.instantiate(map), *len),
+            TypeKind::Struct { fields } => TypeKind::Struct {
+                fields: fields
+                    .iter()
+                    .map(|(name, ty)| (*name, ty.instantiate(map)))
+                    .collect(),
+            },
+            TypeKind::Ptr(ty, mutable) => TypeKind::Ptr(ty.instantiate(map), *mutable),
+            TypeKind::FnPtr {
+                params,
+                return_type,
+            } => TypeKind::FnPtr {
+                params: all(params),
+                return_type: return_type.map(|ty| ty.instantiate(map)),
+            },
+            TypeKind::Param(name) => return map.get(name).copied().unwrap_or(self),
+            _ => unreachable!(),
+        })
+    }
+}
+
+impl std::ops::Deref for IType {
+    type Target = TypeKind;
+
+    fn deref(&self) -> &Self::Target {
+        self.kind()
+    }
+}
+
+impl PartialEq for IType {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+
+impl Eq for IType {}
+
+impl std::hash::Hash for IType {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self.0, state);
+    }
+}
+
+impl PartialOrd for IType {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for IType {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        if self == other {
+            return std::cmp::Ordering::Equal;
+        }
+        self.kind().cmp(other.kind())
+    }
+}
+
+impl From<&Type> for IType {
+    fn from(value: &Type) -> Self {
+        let all = |types: &[Type]| types.iter().map(IType::from).collect();
+        Self::new(match value {
+            Type::Integer(size) => TypeKind::Integer(*size),
+            Type::Unsigned(size) => TypeKind::Unsigned(*size),
+            Type::Float(size) => TypeKind::Float(*size),
+            Type::Bool => TypeKind::Bool,
+            Type::Char(wide) => TypeKind::Char(*wide),
+            Type::Symbol(name, generics) => TypeKind::Symbol(*name, all(generics)),
+            Type::Array(ty, len) => TypeKind::Array(ty.as_ref().into(), *len),
+            Type::Struct { fields } => TypeKind::Struct {
+                fields: fields
+                    .iter()
+                    .map(|(name, ty)| (name.as_deref().map(IStr::from), ty.into()))
+                    .collect(),
+            },
+            Type::Ptr(ty, mutable) => TypeKind::Ptr(ty.as_ref().into(), *mutable),
+            Type::FnPtr {
+                params,
+                return_type,
+            } => TypeKind::FnPtr {
+                params: all(params),
+                return_type: return_type.as_deref().map(IType::from),
+            },
+            Type::Param(name) => TypeKind::Param(*name),
+            Type::Error => TypeKind::Error,
+        })
+    }
+}
+
+impl From<Type> for IType {
+    fn from(value: Type) -> Self {
+        (&value).into()
+    }
+}
+
+impl From<IType> for Type {
+    fn from(value: IType) -> Self {
+        let all = |types: &[IType]| types.iter().map(|ty| ty.to_type()).collect();
+        match value.kind() {
+            TypeKind::Integer(size) => Type::Integer(*size),
+            TypeKind::Unsigned(size) => Type::Unsigned(*size),
+            TypeKind::Float(size) => Type::Float(*size),
+            TypeKind::Bool => Type::Bool,
+            TypeKind::Char(wide) => Type::Char(*wide),
+            TypeKind::Symbol(name, generics) => Type::Symbol(*name, all(generics)),
+            TypeKind::Array(ty, len) => Type::Array(Box::new(ty.to_type()), *len),
+            TypeKind::Struct { fields } => Type::Struct {
+                fields: fields
+                    .iter()
+                    .map(|(name, ty)| (name.map(|name| name.to_string()), ty.to_type()))
+                    .collect(),
+            },
+            TypeKind::Ptr(ty, mutable) => Type::Ptr(Box::new(ty.to_type()), *mutable),
+            TypeKind::FnPtr {
+                params,
+                return_type,
+            } => Type::FnPtr {
+                params: all(params),
+                return_type: return_type.map(|ty| Box::new(ty.to_type())),
+            },
+            TypeKind::Param(name) => Type::Param(*name),
+            TypeKind::Error => Type::Error,
+        }
+    }
+}
+
+impl std::fmt::Display for IType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_type().fmt(f)
+    }
+}
+
+impl std::fmt::Debug for IType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.kind().fmt(f)
+    }
+}
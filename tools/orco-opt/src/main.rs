@@ -0,0 +1,175 @@
+//! Load textual orco IR, run passes on it and emit IR or C.
+//! Handy for regression tests and reducing bugs without going through a frontend
+use orco_ir::passes::PassManager;
+
+const USAGE: &str = "\
+Usage: orco-opt [OPTIONS] <INPUT>
+
+Reads orco IR (textual or binary) from INPUT (`-` for stdin),
+runs passes in order and prints the result.
+Passes other passes require are scheduled automatically.
+
+Options:
+  -p, --pass <NAME>    Run a pass or a pipeline, can be repeated (see --list-passes)
+  -O <LEVEL>           Run the pipeline for an optimization level (0, 1, 2, 3, s or z)
+      --verify         Verify the IR after loading it and after each pass
+      --time-passes    Print how long each pass took to stderr
+      --emit <FORMAT>  Output format: `ir` (default), `bin` or `c`
+  -o <FILE>            Write output to FILE instead of stdout
+      --list-passes    List available passes
+  -h, --help           Print this message";
+
+/// Output format, see `--emit`
+enum Emit {
+    Ir,
+    Bin,
+    C,
+}
+
+struct Options {
+    input: String,
+    output: Option<String>,
+    passes: Vec<String>,
+    verify: bool,
+    time_passes: bool,
+    emit: Emit,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut args = std::env::args().skip(1);
+    let mut input = None;
+    let mut options = Options {
+        input: String::new(),
+        output: None,
+        passes: Vec::new(),
+        verify: false,
+        time_passes: false,
+        emit: Emit::Ir,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| {
+            args.next()
+                .ok_or_else(|| format!("missing value for {option}"))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            "--list-passes" => {
+                let manager = PassManager::new();
+                println!("Passes:");
+                for pass in manager.passes() {
+                    println!("  {:16} {}", pass.name, pass.description);
+                }
+                println!("Pipelines:");
+                for (name, passes) in manager.pipelines() {
+                    println!("  {name:16} {}", passes.join(", "));
+                }
+                std::process::exit(0);
+            }
+            "-p" | "--pass" => options.passes.push(value(&arg)?),
+            "-O" => {
+                let level = value(&arg)?;
+                let pipeline = PassManager::opt_level(&level)
+                    .ok_or_else(|| format!("unknown optimization level {level:?}"))?;
+                options.passes.push(pipeline.to_owned());
+            }
+            "--verify" => options.verify = true,
+            "--time-passes" => options.time_passes = true,
+            "-o" => options.output = Some(value(&arg)?),
+            "--emit" => {
+                options.emit = match value(&arg)?.as_str() {
+                    "ir" => Emit::Ir,
+                    "bin" => Emit::Bin,
+                    "c" => Emit::C,
+                    format => return Err(format!("unknown output format {format:?}")),
+                }
+            }
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unknown option {arg:?}"));
+            }
+            _ if input.is_some() => return Err("more than one input given".to_owned()),
+            _ => input = Some(arg),
+        }
+    }
+    options.input = input.ok_or_else(|| format!("no input given\n\n{USAGE}"))?;
+    Ok(options)
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let source = match options.input.as_str() {
+        "-" => {
+            let mut source = Vec::new();
+            std::io::Read::read_to_end(&mut std::io::stdin(), &mut source).map(|_| source)
+        }
+        path => std::fs::read(path),
+    }
+    .map_err(|err| format!("can't read {}: {err}", options.input))?;
+    let mut store = if source.starts_with(orco_ir::binary::MAGIC) {
+        orco_ir::binary::read(source.as_slice())
+            .map_err(|err| format!("{}: {err}", options.input))?
+    } else {
+        let source = std::str::from_utf8(&source)
+            .map_err(|_| format!("{}: not valid UTF-8", options.input))?;
+        orco_ir::parser::parse(source).map_err(|err| format!("{}:{err}", options.input))?
+    };
+
+    let mut manager = PassManager::new();
+    manager.verify = options.verify;
+    let passes = options
+        .passes
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    let stats = manager
+        .run(&mut store, &passes)
+        .map_err(|err| format!("{}: {err}", options.input))?;
+    if options.time_passes {
+        for stats in stats {
+            eprintln!("{stats}");
+        }
+    }
+
+    let output = match options.emit {
+        Emit::Ir => store.to_string().into_bytes(),
+        Emit::Bin => {
+            let mut output = Vec::new();
+            orco_ir::binary::write(&store, &mut output).map_err(|err| err.to_string())?;
+            output
+        }
+        Emit::C => {
+            let backend = orco_cgen::Backend::new();
+            store
+                .monomorphize()
+                .and_then(|()| store.declare_mono(&backend))
+                .map_err(|errors| {
+                    let errors = errors.iter().map(ToString::to_string);
+                    errors.collect::<Vec<_>>().join("\n")
+                })?;
+            store
+                .codegen_mono(&backend)
+                .map_err(|err| err.to_string())?;
+            backend.to_string().into_bytes()
+        }
+    };
+
+    match &options.output {
+        Some(path) => {
+            std::fs::write(path, output).map_err(|err| format!("can't write {path}: {err}"))
+        }
+        None => {
+            use std::io::Write as _;
+            std::io::stdout()
+                .write_all(&output)
+                .map_err(|err| format!("can't write output: {err}"))
+        }
+    }
+}
+
+fn main() {
+    if let Err(err) = parse_args().and_then(run) {
+        eprintln!("orco-opt: {err}");
+        std::process::exit(1);
+    }
+}